@@ -0,0 +1,204 @@
+// benches/scheduler.rs
+//! Scheduler throughput and latency at raid-level message rates
+//!
+//! Compares the worker-based `Scheduler` with the previous dispatch, which spawned a
+//! thread per handler per message and handled one message at a time. Each message
+//! goes through three stages: a display-like handler, two handlers in parallel and a
+//! handler that does a little IO. Latency is measured from when a message arrives
+//! (its slot at the target rate) until the last stage is done with it, so backlog
+//! shows up as latency.
+//!
+//! Run with `cargo bench --bench scheduler`.
+
+use blivedm::client::models::BiliMessage;
+use blivedm::client::scheduler::{EventContext, EventHandler, Scheduler};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long each rate is sustained
+const RUN_TIME: Duration = Duration::from_secs(2);
+/// Messages per second to test
+const RATES: [u64; 4] = [100, 500, 2000, 5000];
+/// Messages sent at once for the burst test
+const BURST: usize = 20_000;
+
+/// Handler that does `work` worth of blocking work
+struct WorkHandler {
+    work: Duration,
+}
+
+impl EventHandler for WorkHandler {
+    fn handle(&self, _msg: &BiliMessage, _context: &EventContext) {
+        if !self.work.is_zero() {
+            thread::sleep(self.work);
+        }
+    }
+}
+
+/// Last-stage handler recording when each message finished
+struct DoneHandler {
+    work: Duration,
+    finished: Arc<Mutex<Vec<Option<Instant>>>>,
+}
+
+impl EventHandler for DoneHandler {
+    fn handle(&self, msg: &BiliMessage, _context: &EventContext) {
+        thread::sleep(self.work);
+        if let BiliMessage::Raw(json) = msg
+            && let Some(id) = json["id"].as_u64()
+        {
+            self.finished.lock().unwrap()[id as usize] = Some(Instant::now());
+        }
+    }
+}
+
+type Stages = Vec<Vec<Arc<dyn EventHandler>>>;
+
+fn stages(finished: &Arc<Mutex<Vec<Option<Instant>>>>) -> Stages {
+    vec![
+        vec![Arc::new(WorkHandler {
+            work: Duration::ZERO,
+        })],
+        vec![
+            Arc::new(WorkHandler {
+                work: Duration::ZERO,
+            }),
+            Arc::new(WorkHandler {
+                work: Duration::from_micros(50),
+            }),
+        ],
+        vec![Arc::new(DoneHandler {
+            work: Duration::from_micros(100),
+            finished: Arc::clone(finished),
+        })],
+    ]
+}
+
+fn message(id: usize) -> BiliMessage {
+    BiliMessage::Raw(serde_json::json!({"cmd": "DANMU_MSG", "id": id}))
+}
+
+/// The previous `Scheduler::trigger`: a thread per handler per message, joined per stage
+fn spawn_per_message(stages: &Stages, msg: &BiliMessage, context: &EventContext) {
+    for stage in stages {
+        let handles: Vec<_> = stage
+            .iter()
+            .map(|handler| {
+                let handler = Arc::clone(handler);
+                let msg = msg.clone();
+                let context = context.clone();
+                thread::spawn(move || handler.handle(&msg, &context))
+            })
+            .collect();
+        for handle in handles {
+            let _ = handle.join();
+        }
+    }
+}
+
+struct Report {
+    throughput: f64,
+    p50: Duration,
+    p99: Duration,
+    max: Duration,
+}
+
+fn report(arrivals: &[Instant], finished: &[Option<Instant>], elapsed: Duration) -> Report {
+    let mut latencies: Vec<Duration> = arrivals
+        .iter()
+        .zip(finished)
+        .filter_map(|(arrival, done)| done.map(|done| done.saturating_duration_since(*arrival)))
+        .collect();
+    latencies.sort();
+    let at = |q: f64| latencies[((latencies.len() - 1) as f64 * q) as usize];
+    Report {
+        throughput: latencies.len() as f64 / elapsed.as_secs_f64(),
+        p50: at(0.5),
+        p99: at(0.99),
+        max: at(1.0),
+    }
+}
+
+/// Arrival slot of message `i` at `rate` messages per second, or all at once
+fn arrivals(start: Instant, count: usize, rate: Option<u64>) -> Vec<Instant> {
+    (0..count)
+        .map(|i| match rate {
+            Some(rate) => start + Duration::from_secs_f64(i as f64 / rate as f64),
+            None => start,
+        })
+        .collect()
+}
+
+fn run_workers(count: usize, rate: Option<u64>) -> Report {
+    let finished = Arc::new(Mutex::new(vec![None; count]));
+    let mut scheduler = Scheduler::new(EventContext::new(None, 1));
+    for stage in stages(&finished) {
+        scheduler.add_stage(stage);
+    }
+    let runtime = scheduler.runtime();
+
+    let start = Instant::now() + Duration::from_millis(10);
+    let arrivals = arrivals(start, count, rate);
+    // Paced like the baseline; tokio's timer would round arrivals up to whole milliseconds
+    for (id, arrival) in arrivals.iter().enumerate() {
+        if let Some(wait) = arrival.checked_duration_since(Instant::now()) {
+            thread::sleep(wait);
+        }
+        runtime.block_on(scheduler.submit(message(id)));
+    }
+    // Messages are handled in order, so this one finishing means all have
+    scheduler.trigger(BiliMessage::Raw(serde_json::json!({})));
+
+    let finished = finished.lock().unwrap();
+    report(&arrivals, &finished, start.elapsed())
+}
+
+fn run_spawn_per_message(count: usize, rate: Option<u64>) -> Report {
+    let finished = Arc::new(Mutex::new(vec![None; count]));
+    let stages = stages(&finished);
+    let context = EventContext::new(None, 1);
+
+    let start = Instant::now() + Duration::from_millis(10);
+    let arrivals = arrivals(start, count, rate);
+    for (id, arrival) in arrivals.iter().enumerate() {
+        if let Some(wait) = arrival.checked_duration_since(Instant::now()) {
+            thread::sleep(wait);
+        }
+        spawn_per_message(&stages, &message(id), &context);
+    }
+
+    let finished = finished.lock().unwrap();
+    report(&arrivals, &finished, start.elapsed())
+}
+
+fn print_row(dispatch: &str, load: &str, report: &Report) {
+    println!(
+        "{:<18} {:>10} {:>12.0} {:>12.2?} {:>12.2?} {:>12.2?}",
+        dispatch, load, report.throughput, report.p50, report.p99, report.max
+    );
+}
+
+fn main() {
+    println!(
+        "{:<18} {:>10} {:>12} {:>12} {:>12} {:>12}",
+        "dispatch", "load", "handled/s", "p50", "p99", "max"
+    );
+    for rate in RATES {
+        let count = (rate * RUN_TIME.as_secs()) as usize;
+        let load = format!("{}/s", rate);
+        print_row("workers", &load, &run_workers(count, Some(rate)));
+        print_row(
+            "spawn per message",
+            &load,
+            &run_spawn_per_message(count, Some(rate)),
+        );
+    }
+    let load = format!("burst {}", BURST);
+    print_row("workers", &load, &run_workers(BURST, None));
+    print_row(
+        "spawn per message",
+        &load,
+        &run_spawn_per_message(BURST, None),
+    );
+}
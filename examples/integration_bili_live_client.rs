@@ -47,6 +47,7 @@ fn main() {
                 Ok(mut rec_c) => {
                     if let Err(e) = rec_c.receive() {
                         eprintln!("{}", e);
+                        break;
                     }
                 }
                 Err(e) => {
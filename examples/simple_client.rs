@@ -71,10 +71,10 @@ async fn main() {
     println!("Listening for messages...");
     while let Some(msg) = rx.next().await {
         match msg {
-            BiliMessage::Danmu { user, text } => {
+            BiliMessage::Danmu { user, text, .. } => {
                 println!("Danmu: {}: {}", user, text);
             }
-            BiliMessage::Gift { user, gift } => {
+            BiliMessage::Gift { user, gift, .. } => {
                 println!("Gift: {} sent {}", user, gift);
             }
             BiliMessage::Raw(json) => {
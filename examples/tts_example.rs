@@ -34,27 +34,30 @@ fn main() {
 
 fn test_rest_api_mode() {
     // Create scheduler for REST API TTS
-    let context = EventContext {
-        cookies: None,
-        room_id: 12345,
-    };
+    let context = EventContext::new(None, 12345);
     let mut scheduler = Scheduler::new(context);
 
     // Add TTS handler with default Chinese voice
     // Make sure the danmu-tts server is running at http://192.168.71.202:8000
     // The handler will automatically decode base64 audio data and play it
     let tts_handler = tts_handler_default("http://192.168.71.202:8000".to_string());
-    scheduler.add_sequential_handler(tts_handler);
+    scheduler.add_async_handler(tts_handler);
 
     // Simulate some danmu messages for REST API
     let messages = vec![
         BiliMessage::Danmu {
             user: "观众1".to_string(),
+            uid: 0,
             text: "REST API 模式测试".to_string(),
+            segments: Vec::new(),
+            medal: None,
         },
         BiliMessage::Danmu {
             user: "观众2".to_string(),
+            uid: 0,
             text: "神经网络语音合成".to_string(),
+            segments: Vec::new(),
+            medal: None,
         },
     ];
 
@@ -69,10 +72,7 @@ fn test_rest_api_mode() {
 
 fn test_command_mode() {
     // Create scheduler for command-line TTS
-    let context = EventContext {
-        cookies: None,
-        room_id: 12345,
-    };
+    let context = EventContext::new(None, 12345);
     let mut scheduler = Scheduler::new(context);
 
     // Choose TTS command based on platform
@@ -91,17 +91,23 @@ fn test_command_mode() {
     #[cfg(not(any(target_os = "macos", target_os = "linux")))]
     let tts_handler = tts_handler_command("echo".to_string(), vec![]);
 
-    scheduler.add_sequential_handler(tts_handler);
+    scheduler.add_async_handler(tts_handler);
 
     // Simulate some danmu messages for command-line TTS
     let messages = vec![
         BiliMessage::Danmu {
             user: "观众3".to_string(),
+            uid: 0,
             text: "命令行模式测试".to_string(),
+            segments: Vec::new(),
+            medal: None,
         },
         BiliMessage::Danmu {
             user: "观众4".to_string(),
+            uid: 0,
             text: "本地语音合成".to_string(),
+            segments: Vec::new(),
+            medal: None,
         },
     ];
 
@@ -0,0 +1,123 @@
+// src/client/accounts.rs
+//! Named accounts, so the connection and danmaku sending can use different logins
+
+use crate::browser_cookies::{self, BrowserSelection};
+use crate::credentials::Credentials;
+use std::path::{Path, PathBuf};
+
+/// Where an account's cookies come from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccountSource {
+    /// A raw cookie string
+    Cookies(String),
+    /// A cookies file exported from a browser (Netscape cookies.txt or JSON)
+    CookiesFile(PathBuf),
+    /// A credentials file written by `blivedm login`
+    Credentials(PathBuf),
+    /// A browser, optionally pinned to a profile
+    Browser(BrowserSelection),
+}
+
+/// A named Bilibili account
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Account {
+    pub name: String,
+    pub source: AccountSource,
+}
+
+impl Account {
+    pub fn new(name: impl Into<String>, source: AccountSource) -> Self {
+        Self {
+            name: name.into(),
+            source,
+        }
+    }
+
+    /// Resolve the account's cookies as a `Cookie` header value
+    pub fn resolve_cookies(&self) -> Result<String, String> {
+        let cookies = match &self.source {
+            AccountSource::Cookies(cookies) => cookies.clone(),
+            AccountSource::CookiesFile(path) => browser_cookies::bilibili_cookies_from_file(path)?,
+            AccountSource::Credentials(path) => Credentials::load(path)?.to_cookie_string(),
+            AccountSource::Browser(selection) => {
+                browser_cookies::find_bilibili_cookies_in(Some(selection)).ok_or_else(|| {
+                    format!(
+                        "No bilibili cookies found in {} (profile: {})",
+                        selection.browser.name(),
+                        selection.profile.as_deref().unwrap_or("any")
+                    )
+                })?
+            }
+        };
+
+        if cookies.contains("SESSDATA=") {
+            Ok(cookies)
+        } else {
+            Err(format!("Account '{}' has no SESSDATA cookie", self.name))
+        }
+    }
+
+    /// The credentials file backing this account, if it can be refreshed
+    pub fn credentials_path(&self) -> Option<&Path> {
+        match &self.source {
+            AccountSource::Credentials(path) => Some(path),
+            _ => None,
+        }
+    }
+}
+
+/// Find an account by name (case-insensitive)
+pub fn find_account<'a>(accounts: &'a [Account], name: &str) -> Option<&'a Account> {
+    accounts
+        .iter()
+        .find(|account| account.name.eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_account_cookies() {
+        let accounts = vec![
+            Account::new(
+                "streamer",
+                AccountSource::Cookies("SESSDATA=a; bili_jct=b".to_string()),
+            ),
+            Account::new("broken", AccountSource::Cookies("bili_jct=b".to_string())),
+        ];
+
+        let streamer = find_account(&accounts, "Streamer").unwrap();
+        assert_eq!(
+            streamer.resolve_cookies().unwrap(),
+            "SESSDATA=a; bili_jct=b"
+        );
+        assert!(streamer.credentials_path().is_none());
+        assert!(
+            find_account(&accounts, "broken")
+                .unwrap()
+                .resolve_cookies()
+                .is_err()
+        );
+        assert!(find_account(&accounts, "bot").is_none());
+    }
+
+    #[test]
+    fn test_resolve_credentials_account() {
+        let dir = std::env::temp_dir().join(format!("blivedm_accounts_{}", std::process::id()));
+        let path = dir.join("bot.json");
+        Credentials::from_cookie_pairs(vec![("SESSDATA", "bot"), ("bili_jct", "csrf")], "rt")
+            .unwrap()
+            .save(&path)
+            .unwrap();
+
+        let bot = Account::new("bot", AccountSource::Credentials(path.clone()));
+        assert_eq!(
+            bot.resolve_cookies().unwrap(),
+            "SESSDATA=bot; bili_jct=csrf"
+        );
+        assert_eq!(bot.credentials_path(), Some(path.as_path()));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
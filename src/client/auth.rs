@@ -5,16 +5,44 @@ use md5;
 use reqwest::StatusCode;
 use reqwest::header::HeaderMap;
 use serde::Deserialize;
-use std::time::{SystemTime, UNIX_EPOCH};
+use serde_json::Value;
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 // Add browser cookie support
 use crate::browser_cookies;
+use crate::credentials;
 
-/// Get Bilibili cookies from browser (preferred, newest), then fallback to provided cookie string
+/// Get Bilibili cookies from stored login credentials (written by `blivedm login`),
+/// then browser (newest), then fallback to provided cookie string.
 pub fn get_cookies_or_browser(provided_cookie: Option<&str>) -> Option<String> {
+    get_cookies_or_browser_in(provided_cookie, None)
+}
+
+/// Like [`get_cookies_or_browser`], but only searches the given browser selection.
+///
+/// A selected browser skips stored credentials, since the user asked for that browser's login.
+pub fn get_cookies_or_browser_in(
+    provided_cookie: Option<&str>,
+    selection: Option<&browser_cookies::BrowserSelection>,
+) -> Option<String> {
+    // Credentials from an explicit QR code login take precedence
+    if let Some(credentials) = credentials::load_default_credentials() {
+        match selection {
+            None => {
+                log::info!("Using stored login credentials");
+                return Some(credentials.to_cookie_string());
+            }
+            Some(selection) => log::info!(
+                "Ignoring stored login credentials, cookies are pinned to {}",
+                selection.browser.name()
+            ),
+        }
+    }
+
     // First try browser cookies as they are the newest
     log::info!("Searching for Bilibili cookies in browser (newest)...");
-    if let Some(browser_cookie) = browser_cookies::find_bilibili_cookies_as_string() {
+    if let Some(browser_cookie) = browser_cookies::find_bilibili_cookies_in(selection) {
         log::info!("Found Bilibili cookies in browser (using newest)");
         return Some(browser_cookie);
     }
@@ -31,30 +59,112 @@ pub fn get_cookies_or_browser(provided_cookie: Option<&str>) -> Option<String> {
     None
 }
 
+/// Information about the logged in user, as reported by the nav endpoint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserInfo {
+    pub uid: u64,
+    pub uname: String,
+    /// Account level (0-6)
+    pub level: u32,
+    /// Whether a (big) VIP membership is active
+    pub vip: bool,
+    /// VIP type: 0 none, 1 monthly, 2 annual
+    pub vip_type: u32,
+}
+
+impl UserInfo {
+    /// Short label for display, e.g. `name (uid 123, Lv5, VIP)`
+    pub fn display_label(&self) -> String {
+        format!(
+            "{} (uid {}, Lv{}{})",
+            self.uname,
+            self.uid,
+            self.level,
+            if self.vip { ", VIP" } else { "" }
+        )
+    }
+}
+
+/// Result of checking whether a set of cookies is logged in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthStatus {
+    LoggedIn(UserInfo),
+    /// Not logged in, with the reason reported by the API (or a local one)
+    NotLoggedIn {
+        reason: String,
+    },
+}
+
+/// Check the login status of the given cookies by calling the nav endpoint
+///
+/// Returns an error only if the request itself fails.
+pub fn check_auth_status(cookies: &str) -> Result<AuthStatus, String> {
+    if !cookies.contains("SESSDATA=") {
+        return Ok(AuthStatus::NotLoggedIn {
+            reason: "No SESSDATA cookie provided".to_string(),
+        });
+    }
+
+    let client = reqwest::blocking::Client::builder()
+        .https_only(true)
+        .user_agent(USER_AGENT)
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+    let body: serde_json::Value = client
+        .get(UID_INIT_URL)
+        .header(reqwest::header::COOKIE, cookies)
+        .send()
+        .and_then(|r| r.json())
+        .map_err(|e| format!("Failed to query login status: {}", e))?;
+
+    Ok(parse_nav_response(&body))
+}
+
+/// Interpret a nav endpoint response
+pub fn parse_nav_response(body: &serde_json::Value) -> AuthStatus {
+    let data = &body["data"];
+    if body["code"].as_i64() == Some(0)
+        && data["isLogin"].as_bool().unwrap_or(false)
+        && let Some(uid) = data["mid"].as_u64()
+    {
+        return AuthStatus::LoggedIn(UserInfo {
+            uid,
+            uname: data["uname"].as_str().unwrap_or("").to_string(),
+            level: data["level_info"]["current_level"].as_u64().unwrap_or(0) as u32,
+            vip: data["vipStatus"].as_u64() == Some(1),
+            vip_type: data["vipType"].as_u64().unwrap_or(0) as u32,
+        });
+    }
+
+    let reason = match (body["code"].as_i64(), body["message"].as_str()) {
+        (Some(-101), _) => "Not logged in - SESSDATA is invalid or expired".to_string(),
+        (Some(code), Some(message)) if code != 0 => format!("{} ({})", message, code),
+        _ => "Not logged in".to_string(),
+    };
+    AuthStatus::NotLoggedIn { reason }
+}
+
 pub fn init_uid(headers: HeaderMap) -> (StatusCode, String) {
+    try_init_uid(headers).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Like [`init_uid`], but returns an error instead of panicking when the request fails
+pub fn try_init_uid(headers: HeaderMap) -> Result<(StatusCode, String), String> {
     let client = reqwest::blocking::Client::builder()
         .https_only(true)
         .build()
-        .unwrap();
+        .map_err(|e| format!("init uid failed: {}", e))?;
 
     let mut request_headers = headers;
     request_headers.insert("user-agent", USER_AGENT.parse().unwrap());
 
     let response = client.get(UID_INIT_URL).headers(request_headers).send();
     log::debug!("init uid response: {:?}", response);
-    let stat: StatusCode;
-    let body: String;
-    match response {
-        Ok(resp) => {
-            stat = resp.status();
-            body = resp.text().unwrap();
-            log::info!("init uid response: {:?}", body);
-        }
-        Err(_) => {
-            panic!("init uid failed");
-        }
-    }
-    (stat, body)
+    let resp = response.map_err(|e| format!("init uid failed: {}", e))?;
+    let stat = resp.status();
+    let body = resp.text().map_err(|e| format!("init uid failed: {}", e))?;
+    log::info!("init uid response: {:?}", body);
+    Ok((stat, body))
 }
 
 /// Initializes the buvid by sending a request and extracting the 'buvid3' cookie.
@@ -130,10 +240,18 @@ pub fn init_room(headers: HeaderMap, temp_room_id: &str) -> (StatusCode, String)
 }
 
 pub fn init_host_server(headers: HeaderMap, room_id: u64) -> (StatusCode, String) {
+    try_init_host_server(headers, room_id).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Like [`init_host_server`], but returns an error instead of panicking when a request fails
+pub fn try_init_host_server(
+    headers: HeaderMap,
+    room_id: u64,
+) -> Result<(StatusCode, String), String> {
     let client = reqwest::blocking::Client::builder()
         .https_only(true)
         .build()
-        .unwrap();
+        .map_err(|e| format!("init host server failed: {}", e))?;
 
     let mut request_headers = headers.clone();
     request_headers.insert("user-agent", USER_AGENT.parse().unwrap());
@@ -143,7 +261,7 @@ pub fn init_host_server(headers: HeaderMap, room_id: u64) -> (StatusCode, String
         Ok(keys) => keys,
         Err(e) => {
             log::error!("Failed to get WBI keys: {:?}", e);
-            panic!("Failed to get WBI keys");
+            return Err("Failed to get WBI keys".to_string());
         }
     };
 
@@ -163,19 +281,13 @@ pub fn init_host_server(headers: HeaderMap, room_id: u64) -> (StatusCode, String
     // debug log the total request
     let response = client.get(url).headers(request_headers).send();
     log::debug!("init host server response: {:?}", response);
-    let stat: StatusCode;
-    let body: String;
-    match response {
-        Ok(resp) => {
-            stat = resp.status();
-            body = resp.text().unwrap();
-            log::info!("init host server response body: {:?}", body);
-        }
-        Err(_) => {
-            panic!("init host server failed");
-        }
-    }
-    (stat, body)
+    let resp = response.map_err(|e| format!("init host server failed: {}", e))?;
+    let stat = resp.status();
+    let body = resp
+        .text()
+        .map_err(|e| format!("init host server failed: {}", e))?;
+    log::info!("init host server response body: {:?}", body);
+    Ok((stat, body))
 }
 
 // WBI signing constants and functions
@@ -222,7 +334,9 @@ fn get_url_encoded(s: &str) -> String {
                 let encoded = c
                     .encode_utf8(&mut [0; 4])
                     .bytes()
-                    .fold("".to_string(), |acc, b| acc + &format!("%{:02X}", b));
+                    .fold("".to_string(), |acc, b| {
+                        acc + format!("%{:02X}", b).as_str()
+                    });
                 Some(encoded)
             }
         })
@@ -243,7 +357,7 @@ fn _encode_wbi(
     (img_key, sub_key): (String, String),
     timestamp: u64,
 ) -> String {
-    let mixin_key = get_mixin_key((img_key + &sub_key).as_bytes());
+    let mixin_key = get_mixin_key((img_key + sub_key.as_str()).as_bytes());
     // 添加当前时间戳
     params.push(("wts", timestamp.to_string()));
     // 重新排序
@@ -255,9 +369,9 @@ fn _encode_wbi(
         .collect::<Vec<_>>()
         .join("&");
     // 计算签名
-    let web_sign = format!("{:x}", md5::compute(query.clone() + &mixin_key));
+    let web_sign = format!("{:x}", md5::compute(query.clone() + mixin_key.as_str()));
     // 返回最终的 query
-    query + &format!("&w_rid={}", web_sign)
+    query + format!("&w_rid={}", web_sign).as_str()
 }
 
 fn get_wbi_keys(headers: HeaderMap) -> Result<(String, String), reqwest::Error> {
@@ -296,6 +410,54 @@ pub const DANMAKU_SERVER_CONF_URL: &str =
 pub const USER_AGENT: &str =
     "Mozilla/5.0 (X11; Linux x86_64; rv:138.0) Gecko/20100101 Firefox/138.0";
 
+/// Blocking client for the live room APIs, shared so requests reuse one connection pool
+fn api_client() -> &'static reqwest::blocking::Client {
+    static CLIENT: OnceLock<reqwest::blocking::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .user_agent(USER_AGENT)
+            .build()
+            .expect("Failed to create HTTP client")
+    })
+}
+
+/// GET a Bilibili API endpoint and return the response when its `code` is 0.
+///
+/// `what` names the request in errors, e.g. "History".
+pub fn get_json(
+    url: &str,
+    query: &[(&str, String)],
+    headers: &[(&str, String)],
+    what: &str,
+) -> Result<Value, String> {
+    let mut request = api_client().get(url).query(query);
+    for (name, value) in headers {
+        request = request.header(*name, value);
+    }
+    let body: Value = request
+        .send()
+        .map_err(|e| format!("{} request failed: {}", what, e))?
+        .json()
+        .map_err(|e| format!("{} response is not JSON: {}", what, e))?;
+    log::debug!("{} response from {}: {}", what, url, body);
+
+    match body["code"].as_i64() {
+        Some(0) => Ok(body),
+        Some(code) => Err(format!(
+            "{} request rejected: {} ({})",
+            what,
+            body["message"].as_str().unwrap_or(""),
+            code
+        )),
+        None => Err(format!(
+            "Malformed {} response: {}",
+            what.to_lowercase(),
+            body
+        )),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -305,6 +467,42 @@ mod tests {
         assert!(UID_INIT_URL.contains("bilibili.com"));
     }
 
+    #[test]
+    fn test_parse_nav_response() {
+        let body = serde_json::json!({
+            "code": 0,
+            "data": {
+                "isLogin": true,
+                "mid": 12345,
+                "uname": "tester",
+                "level_info": {"current_level": 5},
+                "vipStatus": 1,
+                "vipType": 2
+            }
+        });
+        let status = parse_nav_response(&body);
+        assert_eq!(
+            status,
+            AuthStatus::LoggedIn(UserInfo {
+                uid: 12345,
+                uname: "tester".to_string(),
+                level: 5,
+                vip: true,
+                vip_type: 2,
+            })
+        );
+
+        let body = serde_json::json!({
+            "code": -101,
+            "message": "账号未登录",
+            "data": {"isLogin": false}
+        });
+        assert!(matches!(
+            parse_nav_response(&body),
+            AuthStatus::NotLoggedIn { .. }
+        ));
+    }
+
     #[test]
     fn test_take_filename() {
         assert_eq!(
@@ -1,13 +1,22 @@
 // src/client/browser_cookies.rs
 //! Browser cookie reading functionality for automatic SESSDATA detection
 
+#[cfg(target_os = "linux")]
+use aes::cipher::{BlockDecryptMut, KeyIvInit, block_padding::Pkcs7};
 use chrono::{DateTime, TimeZone, Utc};
 use directories::UserDirs;
 use log::{debug, info, warn};
-use sqlite::Connection;
+#[cfg(target_os = "linux")]
+use sha1::Sha1;
+#[cfg(target_os = "linux")]
+use sha2::{Digest, Sha256};
+use sqlite::{Connection, OpenFlags, State, Statement};
 use std::collections::HashMap;
+use std::fmt;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 #[derive(Debug, Clone)]
 pub struct Cookie {
@@ -20,140 +29,228 @@ pub struct Cookie {
     pub http_only: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Browser {
     Chrome,
     Firefox,
     Edge,
     Chromium,
     Opera,
+    Brave,
+    Vivaldi,
+    LibreWolf,
+    Waterfox,
 }
 
-impl Browser {
-    pub fn get_cookie_db_path(&self) -> Option<PathBuf> {
-        let user_dirs = UserDirs::new()?;
-        let home_dir = user_dirs.home_dir();
+/// A browser profile that has a cookie database
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrowserProfile {
+    pub browser: Browser,
+    /// Profile directory name, e.g. `Default`, `Profile 2` or `abcd.default-release`
+    pub name: String,
+    pub cookie_db_path: PathBuf,
+}
 
+impl Browser {
+    /// Short lowercase name, as accepted by `--browser`
+    pub fn name(&self) -> &'static str {
         match self {
-            Browser::Chrome => {
-                #[cfg(target_os = "linux")]
-                {
-                    Some(home_dir.join(".config/google-chrome/Default/Cookies"))
-                }
-                #[cfg(target_os = "macos")]
-                {
-                    Some(home_dir.join("Library/Application Support/Google/Chrome/Default/Cookies"))
-                }
-                #[cfg(target_os = "windows")]
-                {
-                    Some(
-                        home_dir
-                            .join("AppData/Local/Google/Chrome/User Data/Default/Network/Cookies"),
-                    )
-                }
-            }
-            Browser::Firefox => {
-                #[cfg(target_os = "linux")]
-                {
-                    let firefox_dir = vec![
-                        home_dir.join(".mozilla/firefox"),
-                        home_dir.join("snap/firefox/common/.mozilla/firefox"),
-                        home_dir.join(".var/app/org.mozilla.firefox/.mozilla/firefox"),
-                        home_dir.join("snap/firefox/current/.mozilla/firefox"),
-                    ]
-                    .into_iter()
-                    .find(|p| p.exists())?;
-
-                    Self::find_firefox_profile_cookies(&firefox_dir)
-                }
-                #[cfg(target_os = "macos")]
-                {
-                    let firefox_dir = home_dir.join("Library/Application Support/Firefox/Profiles");
-                    Self::find_firefox_profile_cookies(&firefox_dir)
-                }
-                #[cfg(target_os = "windows")]
-                {
-                    let firefox_dir = home_dir.join("AppData/Roaming/Mozilla/Firefox/Profiles");
-                    Self::find_firefox_profile_cookies(&firefox_dir)
-                }
-            }
-            Browser::Edge => {
-                #[cfg(target_os = "linux")]
-                {
-                    Some(home_dir.join(".config/microsoft-edge/Default/Cookies"))
-                }
-                #[cfg(target_os = "macos")]
-                {
-                    Some(
-                        home_dir.join("Library/Application Support/Microsoft Edge/Default/Cookies"),
-                    )
-                }
-                #[cfg(target_os = "windows")]
-                {
-                    Some(
-                        home_dir
-                            .join("AppData/Local/Microsoft/Edge/User Data/Default/Network/Cookies"),
-                    )
-                }
-            }
-            Browser::Chromium => {
-                #[cfg(target_os = "linux")]
-                {
-                    Some(home_dir.join(".config/chromium/Default/Cookies"))
-                }
-                #[cfg(target_os = "macos")]
-                {
-                    Some(home_dir.join("Library/Application Support/Chromium/Default/Cookies"))
-                }
-                #[cfg(target_os = "windows")]
-                {
-                    Some(home_dir.join("AppData/Local/Chromium/User Data/Default/Network/Cookies"))
-                }
-            }
-            Browser::Opera => {
-                #[cfg(target_os = "linux")]
-                {
-                    Some(home_dir.join(".config/opera/Default/Cookies"))
-                }
-                #[cfg(target_os = "macos")]
-                {
-                    Some(home_dir.join(
-                        "Library/Application Support/com.operasoftware.Opera/Default/Cookies",
-                    ))
-                }
-                #[cfg(target_os = "windows")]
-                {
-                    Some(
-                        home_dir
-                            .join("AppData/Roaming/Opera Software/Opera Stable/Network/Cookies"),
-                    )
-                }
-            }
+            Browser::Chrome => "chrome",
+            Browser::Firefox => "firefox",
+            Browser::Edge => "edge",
+            Browser::Chromium => "chromium",
+            Browser::Opera => "opera",
+            Browser::Brave => "brave",
+            Browser::Vivaldi => "vivaldi",
+            Browser::LibreWolf => "librewolf",
+            Browser::Waterfox => "waterfox",
         }
     }
 
-    fn find_firefox_profile_cookies(firefox_dir: &Path) -> Option<PathBuf> {
-        if !firefox_dir.exists() {
-            return None;
-        }
+    /// Whether the browser uses the Firefox (`moz_cookies`) database format
+    pub fn is_firefox_based(&self) -> bool {
+        matches!(
+            self,
+            Browser::Firefox | Browser::LibreWolf | Browser::Waterfox
+        )
+    }
+
+    /// Directories that hold the browser's profiles, including Flatpak and Snap installs
+    fn data_dirs(&self, home_dir: &Path) -> Vec<PathBuf> {
+        #[cfg(target_os = "linux")]
+        let dirs: Vec<&str> = match self {
+            Browser::Chrome => vec![
+                ".config/google-chrome",
+                ".var/app/com.google.Chrome/config/google-chrome",
+            ],
+            Browser::Firefox => vec![
+                ".mozilla/firefox",
+                "snap/firefox/common/.mozilla/firefox",
+                ".var/app/org.mozilla.firefox/.mozilla/firefox",
+                "snap/firefox/current/.mozilla/firefox",
+            ],
+            Browser::Edge => vec![
+                ".config/microsoft-edge",
+                ".var/app/com.microsoft.Edge/config/microsoft-edge",
+            ],
+            Browser::Chromium => vec![
+                ".config/chromium",
+                "snap/chromium/common/chromium",
+                ".var/app/org.chromium.Chromium/config/chromium",
+            ],
+            Browser::Opera => vec![
+                ".config/opera",
+                "snap/opera/current/.config/opera",
+                ".var/app/com.opera.Opera/config/opera",
+            ],
+            Browser::Brave => vec![
+                ".config/BraveSoftware/Brave-Browser",
+                "snap/brave/current/.config/BraveSoftware/Brave-Browser",
+                ".var/app/com.brave.Browser/config/BraveSoftware/Brave-Browser",
+            ],
+            Browser::Vivaldi => vec![
+                ".config/vivaldi",
+                ".var/app/com.vivaldi.Vivaldi/config/vivaldi",
+            ],
+            Browser::LibreWolf => vec![
+                ".librewolf",
+                ".var/app/io.gitlab.librewolf-community/.librewolf",
+            ],
+            Browser::Waterfox => vec![".waterfox", ".var/app/net.waterfox.waterfox/.waterfox"],
+        };
+        #[cfg(target_os = "macos")]
+        let dirs: Vec<&str> = match self {
+            Browser::Chrome => vec!["Library/Application Support/Google/Chrome"],
+            Browser::Firefox => vec!["Library/Application Support/Firefox/Profiles"],
+            Browser::Edge => vec!["Library/Application Support/Microsoft Edge"],
+            Browser::Chromium => vec!["Library/Application Support/Chromium"],
+            Browser::Opera => vec!["Library/Application Support/com.operasoftware.Opera"],
+            Browser::Brave => vec!["Library/Application Support/BraveSoftware/Brave-Browser"],
+            Browser::Vivaldi => vec!["Library/Application Support/Vivaldi"],
+            Browser::LibreWolf => vec!["Library/Application Support/librewolf/Profiles"],
+            Browser::Waterfox => vec!["Library/Application Support/Waterfox/Profiles"],
+        };
+        #[cfg(target_os = "windows")]
+        let dirs: Vec<&str> = match self {
+            Browser::Chrome => vec!["AppData/Local/Google/Chrome/User Data"],
+            Browser::Firefox => vec!["AppData/Roaming/Mozilla/Firefox/Profiles"],
+            Browser::Edge => vec!["AppData/Local/Microsoft/Edge/User Data"],
+            Browser::Chromium => vec!["AppData/Local/Chromium/User Data"],
+            Browser::Opera => vec!["AppData/Roaming/Opera Software/Opera Stable"],
+            Browser::Brave => vec!["AppData/Local/BraveSoftware/Brave-Browser/User Data"],
+            Browser::Vivaldi => vec!["AppData/Local/Vivaldi/User Data"],
+            Browser::LibreWolf => vec!["AppData/Roaming/librewolf/Profiles"],
+            Browser::Waterfox => vec!["AppData/Roaming/Waterfox/Profiles"],
+        };
+        #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+        let dirs: Vec<&str> = Vec::new();
 
-        // Look for the default profile directory
-        let entries = fs::read_dir(firefox_dir).ok()?;
-        for entry in entries {
-            if let Ok(entry) = entry {
-                let path = entry.path();
-                if path.is_dir() {
-                    let dir_name = path.file_name()?.to_str()?;
-                    if dir_name.contains(".default") || dir_name.contains(".default-release") {
-                        let cookies_path = path.join("cookies.sqlite");
-                        if cookies_path.exists() {
-                            return Some(cookies_path);
-                        }
-                    }
+        dirs.into_iter().map(|dir| home_dir.join(dir)).collect()
+    }
+
+    /// Find every profile of this browser that has a cookie database
+    pub fn find_profiles(&self) -> Vec<BrowserProfile> {
+        let Some(user_dirs) = UserDirs::new() else {
+            return Vec::new();
+        };
+
+        self.data_dirs(user_dirs.home_dir())
+            .iter()
+            .filter(|dir| dir.is_dir())
+            .flat_map(|dir| {
+                if self.is_firefox_based() {
+                    self.find_firefox_profiles(dir)
+                } else {
+                    self.find_chromium_profiles(dir)
                 }
-            }
+            })
+            .collect()
+    }
+
+    /// Path of the first cookie database found for this browser
+    pub fn get_cookie_db_path(&self) -> Option<PathBuf> {
+        self.find_profiles()
+            .into_iter()
+            .next()
+            .map(|profile| profile.cookie_db_path)
+    }
+
+    /// Chromium keeps one directory per profile (`Default`, `Profile 1`, ...) with the
+    /// database at `Network/Cookies` (newer) or `Cookies` (older). Opera stores a single
+    /// profile directly in the data directory.
+    fn find_chromium_profiles(&self, data_dir: &Path) -> Vec<BrowserProfile> {
+        let mut candidates = vec![("Default".to_string(), data_dir.to_path_buf())];
+        let mut subdirs: Vec<(String, PathBuf)> = fs::read_dir(data_dir)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .map(|entry| entry.path())
+                    .filter(|path| path.is_dir())
+                    .filter_map(|path| {
+                        let name = path.file_name()?.to_str()?.to_string();
+                        Some((name, path))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        // Default first, then Profile 1, Profile 2, ...
+        subdirs.sort_by_key(|(name, _)| (name != "Default", name.len(), name.clone()));
+        candidates.extend(subdirs);
+
+        candidates
+            .into_iter()
+            .filter_map(|(name, dir)| {
+                let cookie_db_path = ["Network/Cookies", "Cookies"]
+                    .iter()
+                    .map(|relative| dir.join(relative))
+                    .find(|path| path.is_file())?;
+                Some(BrowserProfile {
+                    browser: *self,
+                    name,
+                    cookie_db_path,
+                })
+            })
+            .collect()
+    }
+
+    /// Firefox-based browsers keep each profile in `<random>.<name>` with `cookies.sqlite`
+    fn find_firefox_profiles(&self, profiles_dir: &Path) -> Vec<BrowserProfile> {
+        let Ok(entries) = fs::read_dir(profiles_dir) else {
+            return Vec::new();
+        };
+
+        let mut profiles: Vec<BrowserProfile> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter_map(|path| {
+                let cookie_db_path = path.join("cookies.sqlite");
+                if !cookie_db_path.is_file() {
+                    return None;
+                }
+                Some(BrowserProfile {
+                    browser: *self,
+                    name: path.file_name()?.to_str()?.to_string(),
+                    cookie_db_path,
+                })
+            })
+            .collect();
+        // Prefer the default profiles
+        profiles.sort_by_key(|profile| (!profile.name.contains(".default"), profile.name.clone()));
+        profiles
+    }
+
+    /// Application name under which the browser stores its cookie encryption secret
+    /// in the desktop keyring (libsecret)
+    pub fn keyring_application(&self) -> Option<&'static str> {
+        match self {
+            Browser::Chrome => Some("chrome"),
+            Browser::Chromium => Some("chromium"),
+            Browser::Edge => Some("microsoft-edge"),
+            Browser::Opera => Some("opera"),
+            Browser::Brave => Some("brave"),
+            Browser::Vivaldi => Some("vivaldi"),
+            Browser::Firefox | Browser::LibreWolf | Browser::Waterfox => None,
         }
-        None
     }
 
     pub fn get_all_supported() -> Vec<Browser> {
@@ -163,177 +260,661 @@ impl Browser {
             Browser::Edge,
             Browser::Chromium,
             Browser::Opera,
+            Browser::Brave,
+            Browser::Vivaldi,
+            Browser::LibreWolf,
+            Browser::Waterfox,
         ]
     }
 }
 
-/// Read cookies from a browser's cookie database
+impl FromStr for Browser {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_lowercase();
+        Browser::get_all_supported()
+            .into_iter()
+            .find(|browser| browser.name() == name)
+            .ok_or_else(|| {
+                format!(
+                    "Unknown browser '{}', expected one of: {}",
+                    s,
+                    Browser::get_all_supported()
+                        .iter()
+                        .map(|b| b.name())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })
+    }
+}
+
+impl BrowserProfile {
+    /// Whether this profile matches a user supplied profile name. Firefox profiles also
+    /// match by the part after the random prefix (e.g. `default-release`).
+    pub fn matches_name(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+            || (self.browser.is_firefox_based()
+                && self
+                    .name
+                    .split_once('.')
+                    .is_some_and(|(_, suffix)| suffix.eq_ignore_ascii_case(name)))
+    }
+}
+
+/// A user pinned browser and optional profile, parsed from `chrome` or `chrome:Profile 2`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrowserSelection {
+    pub browser: Browser,
+    pub profile: Option<String>,
+}
+
+impl BrowserSelection {
+    /// Profiles matching this selection
+    pub fn find_profiles(&self) -> Vec<BrowserProfile> {
+        self.browser
+            .find_profiles()
+            .into_iter()
+            .filter(|profile| match &self.profile {
+                Some(name) => profile.matches_name(name),
+                None => true,
+            })
+            .collect()
+    }
+}
+
+impl FromStr for BrowserSelection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (browser, profile) = match s.split_once(':') {
+            Some((browser, profile)) => (browser, Some(profile.trim().to_string())),
+            None => (s, None),
+        };
+        Ok(BrowserSelection {
+            browser: browser.parse()?,
+            profile: profile.filter(|p| !p.is_empty()),
+        })
+    }
+}
+
+/// Profiles to scan: those of the given selection, or every profile of every supported browser
+fn profiles_to_scan(selection: Option<&BrowserSelection>) -> Vec<BrowserProfile> {
+    match selection {
+        Some(selection) => {
+            let profiles = selection.find_profiles();
+            if profiles.is_empty() {
+                warn!(
+                    "No cookie database found for pinned browser {} (profile: {})",
+                    selection.browser.name(),
+                    selection.profile.as_deref().unwrap_or("any")
+                );
+            }
+            profiles
+        }
+        None => Browser::get_all_supported()
+            .iter()
+            .flat_map(|browser| browser.find_profiles())
+            .collect(),
+    }
+}
+
+/// Read cookies from every profile of a browser
 pub fn read_cookies_from_browser(
     browser: &Browser,
     domain_filter: Option<&str>,
 ) -> Result<Vec<Cookie>, String> {
-    let db_path = browser
-        .get_cookie_db_path()
-        .ok_or_else(|| "Could not determine cookie database path".to_string())?;
+    let profiles = browser.find_profiles();
+    if profiles.is_empty() {
+        return Err(format!("No cookie database found for {}", browser.name()));
+    }
 
-    if !db_path.exists() {
-        return Err(format!("Cookie database not found at: {:?}", db_path));
+    let mut cookies = Vec::new();
+    let mut errors = Vec::new();
+    for profile in &profiles {
+        match read_cookies_from_profile(profile, domain_filter) {
+            Ok(profile_cookies) => cookies.extend(profile_cookies),
+            Err(e) => errors.push(e.to_string()),
+        }
     }
 
-    debug!("Reading cookies from: {:?}", db_path);
+    if errors.len() == profiles.len() {
+        return Err(errors.join("; "));
+    }
+    Ok(cookies)
+}
+
+/// Why a cookie database could not be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CookieReadError {
+    /// The database file does not exist
+    NotFound(PathBuf),
+    /// The database is locked by the running browser
+    Locked(String),
+    /// Any other I/O or SQLite failure
+    Failed(String),
+    /// Only encrypted cookies were found, and none of them could be decrypted
+    Encrypted(usize),
+}
 
-    // Create a temporary copy of the database since browsers might have it locked
-    let temp_path = std::env::temp_dir().join(format!("temp_cookies_{}.db", std::process::id()));
-    if let Err(e) = fs::copy(&db_path, &temp_path) {
-        return Err(format!("Failed to copy cookie database: {}", e));
+impl fmt::Display for CookieReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CookieReadError::NotFound(path) => {
+                write!(f, "Cookie database not found at: {:?}", path)
+            }
+            CookieReadError::Locked(e) => write!(f, "Cookie database is locked: {}", e),
+            CookieReadError::Failed(e) => write!(f, "{}", e),
+            CookieReadError::Encrypted(count) => {
+                write!(f, "{} encrypted cookies could not be decrypted", count)
+            }
+        }
     }
+}
 
-    let result = match browser {
-        Browser::Firefox => read_firefox_cookies(&temp_path, domain_filter),
-        _ => read_chromium_cookies(&temp_path, domain_filter),
-    };
+impl CookieReadError {
+    fn from_sqlite(e: sqlite::Error, what: &str) -> Self {
+        // SQLITE_BUSY (5) and SQLITE_LOCKED (6), including their extended codes
+        match e.code.map(|code| code & 0xff) {
+            Some(5) | Some(6) => CookieReadError::Locked(format!("{}: {}", what, e)),
+            _ => CookieReadError::Failed(format!("{}: {}", what, e)),
+        }
+    }
+}
 
-    // Clean up temporary file
-    let _ = fs::remove_file(&temp_path);
+/// Read cookies from a single browser profile's cookie database
+pub fn read_cookies_from_profile(
+    profile: &BrowserProfile,
+    domain_filter: Option<&str>,
+) -> Result<Vec<Cookie>, CookieReadError> {
+    let database = CookieDatabase::open(&profile.cookie_db_path)?;
 
-    result
+    if profile.browser.is_firefox_based() {
+        read_firefox_cookies(&database.connection, domain_filter)
+    } else {
+        read_chromium_cookies(
+            &database.connection,
+            domain_filter,
+            chromium_decrypt(&profile.browser),
+        )
+    }
+}
+
+/// Decrypts the browser's `encrypted_value` columns with [`ChromiumDecryptor`]
+#[cfg(target_os = "linux")]
+fn chromium_decrypt(browser: &Browser) -> impl Fn(&[u8], &str, bool) -> Result<String, String> {
+    let decryptor = ChromiumDecryptor::for_browser(browser);
+    move |encrypted_value, host_key, strip_domain_hash| {
+        decryptor.decrypt(encrypted_value, host_key, strip_domain_hash)
+    }
+}
+
+/// Chromium encrypts cookies with the Keychain on macOS and DPAPI on Windows, which
+/// are not supported; encrypted values are skipped
+#[cfg(not(target_os = "linux"))]
+fn chromium_decrypt(_browser: &Browser) -> impl Fn(&[u8], &str, bool) -> Result<String, String> {
+    |_, _, _| Err("encrypted cookies can only be read on Linux".to_string())
+}
+
+/// A connection to a snapshot of a browser cookie database.
+///
+/// Browsers keep the database open (and on Windows exclusively locked), and recent
+/// writes may still live in the `-wal` file. The database is copied together with its
+/// WAL/SHM/journal files into a private temp directory; if that fails, the original is
+/// opened read-only with `immutable=1` so SQLite ignores the browser's locks.
+struct CookieDatabase {
+    // Declared first so the connection is closed before the temp directory is removed
+    connection: Connection,
+    _temp_dir: Option<TempCopyDir>,
+}
+
+/// Temp directory holding a database copy, removed on drop
+struct TempCopyDir(PathBuf);
+
+impl Drop for TempCopyDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+impl CookieDatabase {
+    fn open(db_path: &Path) -> Result<Self, CookieReadError> {
+        if !db_path.is_file() {
+            return Err(CookieReadError::NotFound(db_path.to_path_buf()));
+        }
+
+        debug!("Reading cookies from: {:?}", db_path);
+
+        match Self::open_copy(db_path) {
+            Ok(database) => Ok(database),
+            Err(e) => {
+                debug!("{}, opening the original database as immutable", e);
+                Self::open_immutable(db_path)
+            }
+        }
+    }
+
+    fn open_copy(db_path: &Path) -> Result<Self, CookieReadError> {
+        static COPY_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let temp_dir = std::env::temp_dir().join(format!(
+            "blivedm_cookies_{}_{}",
+            std::process::id(),
+            COPY_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&temp_dir).map_err(|e| {
+            CookieReadError::Failed(format!("Failed to create temp directory: {}", e))
+        })?;
+
+        let temp_dir = TempCopyDir(temp_dir);
+        let copy_path = Self::copy_database_files(db_path, &temp_dir.0)?;
+        let connection = Connection::open(copy_path)
+            .map_err(|e| CookieReadError::from_sqlite(e, "Failed to open cookie database"))?;
+        Ok(CookieDatabase {
+            connection,
+            _temp_dir: Some(temp_dir),
+        })
+    }
+
+    /// Copy the database and any WAL/SHM/journal sidecar files, returning the copy's path
+    fn copy_database_files(db_path: &Path, temp_dir: &Path) -> Result<PathBuf, CookieReadError> {
+        let file_name = db_path.file_name().ok_or_else(|| {
+            CookieReadError::Failed(format!("Invalid database path {:?}", db_path))
+        })?;
+        let copy_path = temp_dir.join(file_name);
+
+        fs::copy(db_path, &copy_path).map_err(|e| {
+            // Windows reports the exclusive lock held by a running browser as a sharing violation
+            if e.raw_os_error() == Some(32) || e.raw_os_error() == Some(33) {
+                CookieReadError::Locked(format!("Failed to copy cookie database: {}", e))
+            } else {
+                CookieReadError::Failed(format!("Failed to copy cookie database: {}", e))
+            }
+        })?;
+
+        for suffix in ["-wal", "-shm", "-journal"] {
+            let mut sidecar = db_path.as_os_str().to_owned();
+            sidecar.push(suffix);
+            let sidecar = PathBuf::from(sidecar);
+            if sidecar.is_file() {
+                let mut target = copy_path.as_os_str().to_owned();
+                target.push(suffix);
+                if let Err(e) = fs::copy(&sidecar, PathBuf::from(target)) {
+                    // Without the WAL the snapshot is just older, so keep going
+                    warn!("Failed to copy {:?}: {}", sidecar, e);
+                }
+            }
+        }
+
+        Ok(copy_path)
+    }
+
+    fn open_immutable(db_path: &Path) -> Result<Self, CookieReadError> {
+        let connection = Connection::open_with_flags(
+            sqlite_immutable_uri(db_path),
+            OpenFlags::new().with_read_only().with_uri(),
+        )
+        .map_err(|e| CookieReadError::from_sqlite(e, "Failed to open cookie database"))?;
+        Ok(CookieDatabase {
+            connection,
+            _temp_dir: None,
+        })
+    }
+}
+
+/// Build a read-only `file:` URI with `immutable=1` for the given path
+fn sqlite_immutable_uri(path: &Path) -> String {
+    let mut path = path.to_string_lossy().replace('\\', "/");
+    if !path.starts_with('/') {
+        // Windows drive paths become file:///C:/...
+        path.insert(0, '/');
+    }
+    let escaped: String = path
+        .chars()
+        .map(|c| match c {
+            '%' => "%25".to_string(),
+            '?' => "%3f".to_string(),
+            '#' => "%23".to_string(),
+            ' ' => "%20".to_string(),
+            c => c.to_string(),
+        })
+        .collect();
+    format!("file://{}?immutable=1", escaped)
+}
+
+/// Bind a domain filter to a `(column = ? OR column LIKE ?)` clause, matching the
+/// domain itself and all of its subdomains
+fn bind_domain_filter(statement: &mut Statement<'_>, domain: &str) -> Result<(), CookieReadError> {
+    let domain = domain.trim_start_matches('.');
+    let subdomains = format!("%.{}", domain);
+    statement
+        .bind(&[(1, domain), (2, subdomains.as_str())][..])
+        .map_err(|e| CookieReadError::from_sqlite(e, "Failed to bind domain filter"))
 }
 
+/// Read a Chromium cookie database, decrypting `encrypted_value` with `decrypt(value,
+/// host_key, strip_domain_hash)`.
+///
+/// Values that do not decrypt are skipped; if nothing else is left, this fails with
+/// [`CookieReadError::Encrypted`].
 fn read_chromium_cookies(
-    db_path: &Path,
+    connection: &Connection,
     domain_filter: Option<&str>,
-) -> Result<Vec<Cookie>, String> {
-    let connection =
-        Connection::open(db_path).map_err(|e| format!("Failed to open cookie database: {}", e))?;
+    decrypt: impl Fn(&[u8], &str, bool) -> Result<String, String>,
+) -> Result<Vec<Cookie>, CookieReadError> {
+    // Databases from version 24 on prefix decrypted values with SHA256(host_key)
+    let db_version = read_chromium_db_version(connection);
 
-    let mut query =
-        "SELECT name, value, host_key, path, expires_utc, is_secure, is_httponly FROM cookies"
-            .to_string();
+    let mut query = "SELECT name, value, encrypted_value, host_key, path, expires_utc, is_secure, is_httponly FROM cookies"
+        .to_string();
+    if domain_filter.is_some() {
+        query.push_str(" WHERE host_key = ? OR host_key LIKE ?");
+    }
 
+    let mut statement = connection
+        .prepare(query)
+        .map_err(|e| CookieReadError::from_sqlite(e, "Failed to query cookies"))?;
     if let Some(domain) = domain_filter {
-        query.push_str(&format!(" WHERE host_key LIKE '%{}'", domain));
+        bind_domain_filter(&mut statement, domain)?;
     }
 
     let mut cookies = Vec::new();
+    let mut undecrypted = 0;
 
-    connection
-        .iterate(query, |pairs| {
-            let mut cookie_data = HashMap::new();
-            for &(column, value) in pairs.iter() {
-                cookie_data.insert(column, value.unwrap_or(""));
-            }
+    while let State::Row = statement
+        .next()
+        .map_err(|e| CookieReadError::from_sqlite(e, "Failed to query cookies"))?
+    {
+        let name = statement.read::<String, _>("name").unwrap_or_default();
+        let host_key = statement.read::<String, _>("host_key").unwrap_or_default();
+        let mut value = statement.read::<String, _>("value").unwrap_or_default();
+        let encrypted_value = statement
+            .read::<Vec<u8>, _>("encrypted_value")
+            .unwrap_or_default();
 
-            let expires = if let Some(expires_str) = cookie_data.get("expires_utc") {
-                if let Ok(expires_microseconds) = expires_str.parse::<i64>() {
-                    // Chrome stores time as microseconds since Windows epoch (1601-01-01)
-                    // Convert to Unix timestamp (seconds since 1970-01-01)
-                    let windows_epoch_offset = 11644473600_i64; // seconds between 1601 and 1970
-                    let unix_timestamp = (expires_microseconds / 1_000_000) - windows_epoch_offset;
-                    Utc.timestamp_opt(unix_timestamp, 0).single()
-                } else {
-                    None
+        if value.is_empty() && !encrypted_value.is_empty() {
+            match decrypt(&encrypted_value, &host_key, db_version >= 24) {
+                Ok(decrypted) => value = decrypted,
+                Err(e) => {
+                    debug!("Skipping cookie {} for {}: {}", name, host_key, e);
+                    undecrypted += 1;
+                    continue;
                 }
+            }
+        }
+
+        // Chrome stores time as microseconds since Windows epoch (1601-01-01)
+        // Convert to Unix timestamp (seconds since 1970-01-01)
+        let expires_microseconds = statement.read::<i64, _>("expires_utc").unwrap_or(0);
+        let expires = if expires_microseconds > 0 {
+            let windows_epoch_offset = 11644473600_i64; // seconds between 1601 and 1970
+            let unix_timestamp = (expires_microseconds / 1_000_000) - windows_epoch_offset;
+            Utc.timestamp_opt(unix_timestamp, 0).single()
+        } else {
+            None
+        };
+
+        cookies.push(Cookie {
+            name,
+            value,
+            domain: host_key,
+            path: statement.read::<String, _>("path").unwrap_or_default(),
+            expires,
+            secure: statement.read::<i64, _>("is_secure").unwrap_or(0) == 1,
+            http_only: statement.read::<i64, _>("is_httponly").unwrap_or(0) == 1,
+        });
+    }
+
+    if cookies.is_empty() && undecrypted > 0 {
+        return Err(CookieReadError::Encrypted(undecrypted));
+    }
+    Ok(cookies)
+}
+
+/// Read the schema version from the Chromium `meta` table (0 if unavailable)
+fn read_chromium_db_version(connection: &Connection) -> i64 {
+    let mut version = 0;
+    let _ = connection.iterate("SELECT value FROM meta WHERE key = 'version'", |pairs| {
+        if let Some(&(_, Some(value))) = pairs.first() {
+            version = value.parse().unwrap_or(0);
+        }
+        true
+    });
+    version
+}
+
+/// Decrypts Chromium `encrypted_value` columns as stored on Linux.
+///
+/// Values are prefixed with `v10` (key derived from the hard-coded "peanuts" password)
+/// or `v11` (key derived from a secret stored in the desktop keyring), followed by
+/// AES-128-CBC ciphertext with a fixed IV of 16 spaces.
+#[cfg(target_os = "linux")]
+pub struct ChromiumDecryptor {
+    v10_key: [u8; 16],
+    v11_keys: Vec<[u8; 16]>,
+}
+
+#[cfg(target_os = "linux")]
+impl ChromiumDecryptor {
+    /// Create a decryptor, optionally with the keyring secret used for `v11` values
+    pub fn new(keyring_secret: Option<&[u8]>) -> Self {
+        let mut v11_keys = Vec::new();
+        if let Some(secret) = keyring_secret {
+            v11_keys.push(derive_chromium_key(secret));
+        }
+        // Chromium falls back to an empty password when the keyring has no secret
+        v11_keys.push(derive_chromium_key(b""));
+
+        Self {
+            v10_key: derive_chromium_key(b"peanuts"),
+            v11_keys,
+        }
+    }
+
+    /// Create a decryptor for the given browser, looking up its keyring secret
+    pub fn for_browser(browser: &Browser) -> Self {
+        let secret = browser
+            .keyring_application()
+            .and_then(lookup_keyring_secret);
+        if secret.is_none() {
+            debug!(
+                "No keyring secret found for {:?}, v11 cookies may not decrypt",
+                browser
+            );
+        }
+        Self::new(secret.as_deref())
+    }
+
+    /// Decrypt an `encrypted_value`, stripping the SHA256(host_key) prefix used by newer
+    /// databases when `strip_domain_hash` is set or the prefix is detected
+    pub fn decrypt(
+        &self,
+        encrypted_value: &[u8],
+        host_key: &str,
+        strip_domain_hash: bool,
+    ) -> Result<String, String> {
+        let (prefix, ciphertext) = encrypted_value.split_at(3.min(encrypted_value.len()));
+        let keys: Vec<&[u8; 16]> = match prefix {
+            b"v10" => vec![&self.v10_key],
+            b"v11" => self.v11_keys.iter().collect(),
+            _ => return Err("unsupported encryption scheme".to_string()),
+        };
+
+        let plaintext = keys
+            .into_iter()
+            .find_map(|key| decrypt_aes128_cbc(key, ciphertext))
+            .ok_or_else(|| "decryption failed".to_string())?;
+
+        let domain_hash = Sha256::digest(host_key.as_bytes());
+        let plaintext =
+            if plaintext.len() >= 32 && (strip_domain_hash || plaintext[..32] == domain_hash[..]) {
+                &plaintext[32..]
             } else {
-                None
+                &plaintext[..]
             };
 
-            let cookie = Cookie {
-                name: cookie_data.get("name").unwrap_or(&"").to_string(),
-                value: cookie_data.get("value").unwrap_or(&"").to_string(),
-                domain: cookie_data.get("host_key").unwrap_or(&"").to_string(),
-                path: cookie_data.get("path").unwrap_or(&"").to_string(),
-                expires,
-                secure: cookie_data.get("is_secure").unwrap_or(&"0") == &"1",
-                http_only: cookie_data.get("is_httponly").unwrap_or(&"0") == &"1",
-            };
+        String::from_utf8(plaintext.to_vec())
+            .map_err(|_| "decrypted value is not UTF-8".to_string())
+    }
+}
 
-            cookies.push(cookie);
-            true
-        })
-        .map_err(|e| format!("Failed to query cookies: {}", e))?;
+/// PBKDF2-HMAC-SHA1 with the "saltysalt" salt and a single iteration, as used on Linux
+#[cfg(target_os = "linux")]
+fn derive_chromium_key(password: &[u8]) -> [u8; 16] {
+    let mut key = [0u8; 16];
+    pbkdf2::pbkdf2_hmac::<Sha1>(password, b"saltysalt", 1, &mut key);
+    key
+}
 
-    Ok(cookies)
+#[cfg(target_os = "linux")]
+fn decrypt_aes128_cbc(key: &[u8; 16], ciphertext: &[u8]) -> Option<Vec<u8>> {
+    let iv = [b' '; 16];
+    cbc::Decryptor::<aes::Aes128>::new(key.into(), &iv.into())
+        .decrypt_padded_vec_mut::<Pkcs7>(ciphertext)
+        .ok()
+}
+
+/// Look up the "Safe Storage" secret for a Chromium-based browser via libsecret's `secret-tool`
+#[cfg(target_os = "linux")]
+fn lookup_keyring_secret(application: &str) -> Option<Vec<u8>> {
+    let output = std::process::Command::new("secret-tool")
+        .args(["lookup", "application", application])
+        .output()
+        .ok()?;
+    if !output.status.success() || output.stdout.is_empty() {
+        return None;
+    }
+    let mut secret = output.stdout;
+    while secret.last() == Some(&b'\n') {
+        secret.pop();
+    }
+    Some(secret)
 }
 
 fn read_firefox_cookies(
-    db_path: &Path,
+    connection: &Connection,
     domain_filter: Option<&str>,
-) -> Result<Vec<Cookie>, String> {
-    let connection =
-        Connection::open(db_path).map_err(|e| format!("Failed to open cookie database: {}", e))?;
-
+) -> Result<Vec<Cookie>, CookieReadError> {
     let mut query =
         "SELECT name, value, host, path, expiry, isSecure, isHttpOnly FROM moz_cookies".to_string();
+    if domain_filter.is_some() {
+        query.push_str(" WHERE host = ? OR host LIKE ?");
+    }
 
+    let mut statement = connection
+        .prepare(query)
+        .map_err(|e| CookieReadError::from_sqlite(e, "Failed to query cookies"))?;
     if let Some(domain) = domain_filter {
-        query.push_str(&format!(" WHERE host LIKE '%{}'", domain));
+        bind_domain_filter(&mut statement, domain)?;
     }
 
     let mut cookies = Vec::new();
 
-    connection
-        .iterate(query, |pairs| {
-            let mut cookie_data = HashMap::new();
-            for &(column, value) in pairs.iter() {
-                cookie_data.insert(column, value.unwrap_or(""));
-            }
-
-            let expires = if let Some(expires_str) = cookie_data.get("expiry") {
-                if let Ok(expires_timestamp) = expires_str.parse::<i64>() {
-                    Utc.timestamp_opt(expires_timestamp, 0).single()
-                } else {
-                    None
-                }
-            } else {
-                None
-            };
-
-            let cookie = Cookie {
-                name: cookie_data.get("name").unwrap_or(&"").to_string(),
-                value: cookie_data.get("value").unwrap_or(&"").to_string(),
-                domain: cookie_data.get("host").unwrap_or(&"").to_string(),
-                path: cookie_data.get("path").unwrap_or(&"").to_string(),
-                expires,
-                secure: cookie_data.get("isSecure").unwrap_or(&"0") == &"1",
-                http_only: cookie_data.get("isHttpOnly").unwrap_or(&"0") == &"1",
-            };
+    while let State::Row = statement
+        .next()
+        .map_err(|e| CookieReadError::from_sqlite(e, "Failed to query cookies"))?
+    {
+        // Firefox stores the expiry in seconds since the Unix epoch
+        let expiry = statement.read::<i64, _>("expiry").unwrap_or(0);
+        let expires = if expiry > 0 {
+            Utc.timestamp_opt(expiry, 0).single()
+        } else {
+            None
+        };
 
-            cookies.push(cookie);
-            true
-        })
-        .map_err(|e| format!("Failed to query cookies: {}", e))?;
+        cookies.push(Cookie {
+            name: statement.read::<String, _>("name").unwrap_or_default(),
+            value: statement.read::<String, _>("value").unwrap_or_default(),
+            domain: statement.read::<String, _>("host").unwrap_or_default(),
+            path: statement.read::<String, _>("path").unwrap_or_default(),
+            expires,
+            secure: statement.read::<i64, _>("isSecure").unwrap_or(0) == 1,
+            http_only: statement.read::<i64, _>("isHttpOnly").unwrap_or(0) == 1,
+        });
+    }
 
     Ok(cookies)
 }
 
-/// Find SESSDATA cookie from all supported browsers
+/// Find bilibili cookies (including SESSDATA) in all supported browsers.
+///
+/// Cookies are taken from a single profile, the one whose SESSDATA expires last, so cookies
+/// from different accounts are never mixed.
 pub fn find_bilibili_cookies_as_string() -> Option<String> {
-    let browsers = Browser::get_all_supported();
-    let mut all_cookies = vec![];
+    find_bilibili_cookies_in(None)
+}
+
+/// Find bilibili cookies in the given browser selection, or all supported browsers if None.
+///
+/// The selection is passed per call, so different accounts can read from different
+/// browsers or profiles.
+pub fn find_bilibili_cookies_in(selection: Option<&BrowserSelection>) -> Option<String> {
+    let mut best: Option<(Option<DateTime<Utc>>, Vec<Cookie>)> = None;
+
+    for profile in profiles_to_scan(selection) {
+        info!(
+            "Checking browser: {} (profile: {})",
+            profile.browser.name(),
+            profile.name
+        );
+
+        let cookies = match read_cookies_from_profile(&profile, Some("bilibili.com")) {
+            Ok(cookies) => valid_cookies(cookies),
+            Err(e) => {
+                debug!("{}", e);
+                continue;
+            }
+        };
+
+        let Some(sessdata_expiry) = cookies
+            .iter()
+            .find(|c| c.name == "SESSDATA")
+            .map(|c| c.expires)
+        else {
+            continue;
+        };
 
-    for browser in browsers {
-        info!("Checking browser: {:?}", browser);
+        if best
+            .as_ref()
+            .is_none_or(|(expiry, _)| sessdata_expiry > *expiry)
+        {
+            best = Some((sessdata_expiry, cookies));
+        }
+    }
 
-        if let Ok(cookies) = read_cookies_from_browser(&browser, Some("bilibili.com")) {
-            all_cookies.extend(cookies);
+    match best {
+        Some((_, cookies)) => bilibili_cookie_string(cookies),
+        None => {
+            warn!("No valid bilibili cookies found in any browser");
+            None
         }
     }
+}
 
-    let mut valid_cookies = all_cookies
+/// Drop expired cookies
+fn valid_cookies(cookies: Vec<Cookie>) -> Vec<Cookie> {
+    cookies
         .into_iter()
         .filter(|cookie| {
-            if let Some(expires) = cookie.expires {
-                if Utc::now() > expires {
-                    warn!(
-                        "Found expired {} cookie, expires: {:?}",
-                        cookie.name, expires
-                    );
-                    return false;
-                }
+            if let Some(expires) = cookie.expires
+                && Utc::now() > expires
+            {
+                warn!(
+                    "Found expired {} cookie, expires: {:?}",
+                    cookie.name, expires
+                );
+                return false;
             }
             true
         })
-        .collect::<Vec<_>>();
+        .collect()
+}
+
+/// Build a `Cookie` header value from cookies, dropping expired ones and keeping the
+/// latest-expiring cookie per name. Returns None if no SESSDATA is present.
+pub fn bilibili_cookie_string(cookies: Vec<Cookie>) -> Option<String> {
+    let mut valid_cookies = valid_cookies(cookies);
 
     // Deduplicate cookies, keeping the one with the latest expiry
     valid_cookies.sort_by(|a, b| {
@@ -346,7 +927,7 @@ pub fn find_bilibili_cookies_as_string() -> Option<String> {
     valid_cookies.dedup_by(|a, b| a.name == b.name);
 
     if valid_cookies.is_empty() {
-        warn!("No valid bilibili cookies found in any browser");
+        warn!("No valid bilibili cookies found");
         return None;
     }
 
@@ -358,7 +939,7 @@ pub fn find_bilibili_cookies_as_string() -> Option<String> {
         .collect::<Vec<String>>()
         .join("; ");
 
-    if cookie_string.contains("SESSDATA") {
+    if valid_cookies.iter().any(|c| c.name == "SESSDATA") {
         Some(cookie_string)
     } else {
         warn!("No SESSDATA cookie found among the valid cookies");
@@ -366,21 +947,141 @@ pub fn find_bilibili_cookies_as_string() -> Option<String> {
     }
 }
 
-/// Get all bilibili cookies from browsers for debugging
-pub fn get_all_bilibili_cookies() -> HashMap<String, String> {
+/// Parse a Netscape/Mozilla `cookies.txt` file (as written by curl, wget and most
+/// "export cookies" browser extensions).
+///
+/// Each line holds seven tab-separated fields: domain, include-subdomains flag, path,
+/// secure flag, expiry (Unix seconds, 0 for session cookies), name and value. Lines
+/// prefixed with `#HttpOnly_` are HttpOnly cookies; other `#` lines are comments.
+pub fn parse_netscape_cookies(content: &str) -> Vec<Cookie> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim_end_matches(['\r', '\n']);
+            let (line, http_only) = match line.strip_prefix("#HttpOnly_") {
+                Some(rest) => (rest, true),
+                None => (line, false),
+            };
+            if line.trim().is_empty() || line.starts_with('#') {
+                return None;
+            }
+
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() < 7 {
+                debug!("Skipping malformed cookies.txt line: {}", line);
+                return None;
+            }
+
+            let expiry = fields[4].trim().parse::<i64>().unwrap_or(0);
+            Some(Cookie {
+                name: fields[5].to_string(),
+                // Values may contain tabs in theory, keep everything after the name
+                value: fields[6..].join("\t"),
+                domain: fields[0].to_string(),
+                path: fields[2].to_string(),
+                expires: if expiry > 0 {
+                    Utc.timestamp_opt(expiry, 0).single()
+                } else {
+                    None
+                },
+                secure: fields[3].eq_ignore_ascii_case("TRUE"),
+                http_only,
+            })
+        })
+        .collect()
+}
+
+/// Parse cookies exported as JSON.
+///
+/// Accepts an array of cookie objects (EditThisCookie / Cookie-Editor / Puppeteer style)
+/// or an object with a `cookies` array. The expiry is read from `expirationDate`,
+/// `expires` or `expiry` in Unix seconds; missing, zero or negative values and
+/// `"session": true` mean a session cookie.
+pub fn parse_json_cookies(content: &str) -> Result<Vec<Cookie>, String> {
+    let json: serde_json::Value =
+        serde_json::from_str(content).map_err(|e| format!("Invalid cookie JSON: {}", e))?;
+    let entries = match &json {
+        serde_json::Value::Array(entries) => entries,
+        serde_json::Value::Object(object) => object
+            .get("cookies")
+            .and_then(|c| c.as_array())
+            .ok_or_else(|| "Cookie JSON object has no \"cookies\" array".to_string())?,
+        _ => return Err("Cookie JSON must be an array of cookies".to_string()),
+    };
+
+    Ok(entries
+        .iter()
+        .filter_map(|entry| {
+            let name = entry["name"].as_str()?;
+            let expiry = ["expirationDate", "expires", "expiry"]
+                .iter()
+                .find_map(|key| entry[*key].as_f64())
+                .filter(|expiry| *expiry > 0.0 && !entry["session"].as_bool().unwrap_or(false));
+
+            Some(Cookie {
+                name: name.to_string(),
+                value: entry["value"].as_str().unwrap_or("").to_string(),
+                domain: entry["domain"]
+                    .as_str()
+                    .or_else(|| entry["host"].as_str())
+                    .unwrap_or("")
+                    .to_string(),
+                path: entry["path"].as_str().unwrap_or("/").to_string(),
+                expires: expiry.and_then(|e| Utc.timestamp_opt(e as i64, 0).single()),
+                secure: entry["secure"].as_bool().unwrap_or(false),
+                http_only: entry["httpOnly"].as_bool().unwrap_or(false),
+            })
+        })
+        .collect())
+}
+
+/// Read a cookie export file, detecting JSON vs Netscape `cookies.txt` by its content
+pub fn read_cookies_file(path: &Path) -> Result<Vec<Cookie>, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read cookies file {:?}: {}", path, e))?;
+    // Strip a UTF-8 BOM some Windows tools write
+    let content = content.trim_start_matches('\u{feff}');
+
+    if content.trim_start().starts_with(['[', '{']) {
+        parse_json_cookies(content).map_err(|e| format!("{} ({:?})", e, path))
+    } else {
+        Ok(parse_netscape_cookies(content))
+    }
+}
+
+/// Load bilibili cookies from an exported cookies file as a `Cookie` header value.
+///
+/// Uses the same expiry and dedup rules as [`find_bilibili_cookies_as_string`]; fails if
+/// the file has no valid SESSDATA.
+pub fn bilibili_cookies_from_file(path: &Path) -> Result<String, String> {
+    let cookies: Vec<Cookie> = read_cookies_file(path)?
+        .into_iter()
+        .filter(|cookie| domain_matches(&cookie.domain, "bilibili.com"))
+        .collect();
+    if cookies.is_empty() {
+        return Err(format!("No bilibili.com cookies found in {:?}", path));
+    }
+
+    bilibili_cookie_string(cookies)
+        .ok_or_else(|| format!("No valid SESSDATA cookie found in {:?}", path))
+}
+
+/// Whether a cookie domain is `domain` or one of its subdomains
+fn domain_matches(cookie_domain: &str, domain: &str) -> bool {
+    let cookie_domain = cookie_domain.trim_start_matches('.');
+    cookie_domain == domain
+        || cookie_domain
+            .strip_suffix(domain)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+/// Get all bilibili cookies from the selected browser, or all browsers, for debugging
+pub fn get_all_bilibili_cookies(selection: Option<&BrowserSelection>) -> HashMap<String, String> {
     let mut all_cookies = HashMap::new();
-    let browsers = Browser::get_all_supported();
-
-    for browser in browsers {
-        if let Ok(cookies) = read_cookies_from_browser(&browser, Some("bilibili.com")) {
-            for cookie in cookies {
-                // Only include non-expired cookies
-                if let Some(expires) = cookie.expires {
-                    if Utc::now() > expires {
-                        continue;
-                    }
-                }
 
+    for profile in profiles_to_scan(selection) {
+        if let Ok(cookies) = read_cookies_from_profile(&profile, Some("bilibili.com")) {
+            for cookie in valid_cookies(cookies) {
                 // Use the most recent cookie if duplicates exist
                 let key = format!("{}_{}", cookie.name, cookie.domain);
                 all_cookies.insert(key, cookie.value);
@@ -391,6 +1092,166 @@ pub fn get_all_bilibili_cookies() -> HashMap<String, String> {
     all_cookies
 }
 
+/// Outcome of checking one browser (profile) for bilibili cookies
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CookieDiagnosis {
+    /// The browser is not installed, or has no cookie database
+    NotFound,
+    /// The cookie database is locked by the running browser
+    Locked(String),
+    /// The cookie database could not be read
+    Unreadable(String),
+    /// The database has no bilibili.com cookies
+    NoBilibiliCookies,
+    /// The cookies are encrypted and could not be decrypted, e.g. Chromium on macOS or
+    /// Windows, or a missing keyring secret on Linux
+    Encrypted { cookie_count: usize },
+    /// bilibili cookies exist, but no SESSDATA (logged out)
+    NoSessdata { cookie_count: usize },
+    /// SESSDATA exists but has expired
+    Expired { expired_at: DateTime<Utc> },
+    /// A valid SESSDATA was found
+    Ok {
+        cookie_count: usize,
+        sessdata_expires: Option<DateTime<Utc>>,
+    },
+}
+
+impl CookieDiagnosis {
+    /// Classify the bilibili cookies read from a profile
+    pub fn from_cookies(cookies: &[Cookie]) -> Self {
+        if cookies.is_empty() {
+            return CookieDiagnosis::NoBilibiliCookies;
+        }
+
+        // Keep the latest-expiring SESSDATA, None (session cookie) counts as valid
+        let sessdata = cookies
+            .iter()
+            .filter(|c| c.name == "SESSDATA")
+            .max_by_key(|c| c.expires.map_or(i64::MAX, |e| e.timestamp()));
+        match sessdata {
+            None => CookieDiagnosis::NoSessdata {
+                cookie_count: cookies.len(),
+            },
+            Some(cookie) => match cookie.expires {
+                Some(expires) if expires < Utc::now() => CookieDiagnosis::Expired {
+                    expired_at: expires,
+                },
+                expires => CookieDiagnosis::Ok {
+                    cookie_count: cookies.len(),
+                    sessdata_expires: expires,
+                },
+            },
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        matches!(self, CookieDiagnosis::Ok { .. })
+    }
+}
+
+impl fmt::Display for CookieDiagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CookieDiagnosis::NotFound => write!(f, "not found"),
+            CookieDiagnosis::Locked(e) => write!(f, "locked ({})", e),
+            CookieDiagnosis::Unreadable(e) => write!(f, "unreadable ({})", e),
+            CookieDiagnosis::NoBilibiliCookies => write!(f, "no bilibili cookies"),
+            CookieDiagnosis::Encrypted { cookie_count } => write!(
+                f,
+                "encrypted ({} cookies could not be decrypted)",
+                cookie_count
+            ),
+            CookieDiagnosis::NoSessdata { cookie_count } => write!(
+                f,
+                "{} bilibili cookies but no SESSDATA (logged out?)",
+                cookie_count
+            ),
+            CookieDiagnosis::Expired { expired_at } => {
+                write!(
+                    f,
+                    "SESSDATA expired at {}",
+                    expired_at.format("%Y-%m-%d %H:%M")
+                )
+            }
+            CookieDiagnosis::Ok {
+                cookie_count,
+                sessdata_expires,
+            } => match sessdata_expires {
+                Some(expires) => write!(
+                    f,
+                    "ok, {} cookies, SESSDATA expires {}",
+                    cookie_count,
+                    expires.format("%Y-%m-%d %H:%M")
+                ),
+                None => write!(
+                    f,
+                    "ok, {} cookies, SESSDATA is a session cookie",
+                    cookie_count
+                ),
+            },
+        }
+    }
+}
+
+/// Diagnostics for one browser profile (or a browser without any profile)
+#[derive(Debug, Clone)]
+pub struct CookieSourceReport {
+    pub browser: Browser,
+    /// Profile name, None if the browser has no cookie database
+    pub profile: Option<String>,
+    pub cookie_db_path: Option<PathBuf>,
+    pub diagnosis: CookieDiagnosis,
+}
+
+/// Check a single profile for bilibili cookies
+pub fn diagnose_profile(profile: &BrowserProfile) -> CookieSourceReport {
+    let diagnosis = match read_cookies_from_profile(profile, Some("bilibili.com")) {
+        Ok(cookies) => CookieDiagnosis::from_cookies(&cookies),
+        Err(CookieReadError::NotFound(_)) => CookieDiagnosis::NotFound,
+        Err(CookieReadError::Locked(e)) => CookieDiagnosis::Locked(e),
+        Err(CookieReadError::Failed(e)) => CookieDiagnosis::Unreadable(e),
+        Err(CookieReadError::Encrypted(cookie_count)) => {
+            CookieDiagnosis::Encrypted { cookie_count }
+        }
+    };
+    CookieSourceReport {
+        browser: profile.browser,
+        profile: Some(profile.name.clone()),
+        cookie_db_path: Some(profile.cookie_db_path.clone()),
+        diagnosis,
+    }
+}
+
+/// Check every supported browser (or only the selected one) for bilibili cookies.
+///
+/// Browsers without a cookie database are reported as [`CookieDiagnosis::NotFound`].
+pub fn diagnose_cookie_sources(selection: Option<&BrowserSelection>) -> Vec<CookieSourceReport> {
+    let browsers = match selection {
+        Some(selection) => vec![selection.browser],
+        None => Browser::get_all_supported(),
+    };
+
+    browsers
+        .into_iter()
+        .flat_map(|browser| {
+            let profiles = match selection {
+                Some(selection) => selection.find_profiles(),
+                None => browser.find_profiles(),
+            };
+            if profiles.is_empty() {
+                return vec![CookieSourceReport {
+                    browser,
+                    profile: None,
+                    cookie_db_path: None,
+                    diagnosis: CookieDiagnosis::NotFound,
+                }];
+            }
+            profiles.iter().map(diagnose_profile).collect()
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -413,4 +1274,457 @@ mod tests {
             println!("No SESSDATA found - this is normal if you're not logged into bilibili");
         }
     }
+
+    #[cfg(target_os = "linux")]
+    fn encrypt_chromium_value(prefix: &[u8], password: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        use aes::cipher::BlockEncryptMut;
+
+        let key = derive_chromium_key(password);
+        let iv = [b' '; 16];
+        let ciphertext = cbc::Encryptor::<aes::Aes128>::new(&key.into(), &iv.into())
+            .encrypt_padded_vec_mut::<Pkcs7>(plaintext);
+        [prefix, &ciphertext[..]].concat()
+    }
+
+    fn create_chromium_fixture(path: &Path, version: i64, rows: &[(&str, &str, Vec<u8>)]) {
+        let _ = fs::remove_file(path);
+        let connection = Connection::open(path).unwrap();
+        connection
+            .execute(
+                "CREATE TABLE meta (key TEXT NOT NULL UNIQUE PRIMARY KEY, value TEXT);
+                 CREATE TABLE cookies (host_key TEXT, name TEXT, value TEXT, encrypted_value BLOB,
+                     path TEXT, expires_utc INTEGER, is_secure INTEGER, is_httponly INTEGER);",
+            )
+            .unwrap();
+        connection
+            .execute(format!(
+                "INSERT INTO meta (key, value) VALUES ('version', '{}')",
+                version
+            ))
+            .unwrap();
+        for (host_key, name, encrypted_value) in rows {
+            let mut statement = connection
+                .prepare("INSERT INTO cookies VALUES (?, ?, '', ?, '/', 0, 1, 1)")
+                .unwrap();
+            statement.bind((1, *host_key)).unwrap();
+            statement.bind((2, *name)).unwrap();
+            statement.bind((3, &encrypted_value[..])).unwrap();
+            statement.next().unwrap();
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_decrypt_v10_value() {
+        let decryptor = ChromiumDecryptor::new(None);
+        let encrypted = encrypt_chromium_value(b"v10", b"peanuts", b"sessdata_value");
+        assert_eq!(
+            decryptor.decrypt(&encrypted, ".bilibili.com", false),
+            Ok("sessdata_value".to_string())
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_decrypt_v11_value() {
+        let encrypted = encrypt_chromium_value(b"v11", b"keyring_secret", b"sessdata_value");
+        assert!(
+            ChromiumDecryptor::new(None)
+                .decrypt(&encrypted, ".bilibili.com", false)
+                .is_err()
+        );
+        assert_eq!(
+            ChromiumDecryptor::new(Some(b"keyring_secret")).decrypt(
+                &encrypted,
+                ".bilibili.com",
+                false
+            ),
+            Ok("sessdata_value".to_string())
+        );
+
+        // Without a keyring secret Chromium uses an empty password
+        let encrypted = encrypt_chromium_value(b"v11", b"", b"sessdata_value");
+        assert_eq!(
+            ChromiumDecryptor::new(None).decrypt(&encrypted, ".bilibili.com", false),
+            Ok("sessdata_value".to_string())
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_decrypt_strips_domain_hash() {
+        let decryptor = ChromiumDecryptor::new(None);
+        let plaintext = [
+            &Sha256::digest(b".bilibili.com")[..],
+            b"sessdata_value".as_slice(),
+        ]
+        .concat();
+        let encrypted = encrypt_chromium_value(b"v10", b"peanuts", &plaintext);
+        assert_eq!(
+            decryptor.decrypt(&encrypted, ".bilibili.com", true),
+            Ok("sessdata_value".to_string())
+        );
+        // The prefix is detected even without the database version
+        assert_eq!(
+            decryptor.decrypt(&encrypted, ".bilibili.com", false),
+            Ok("sessdata_value".to_string())
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_read_chromium_cookies_fixture_database() {
+        let path = std::env::temp_dir().join(format!(
+            "blivedm_chromium_fixture_{}.db",
+            std::process::id()
+        ));
+        let hashed = [
+            &Sha256::digest(b".bilibili.com")[..],
+            b"csrf_value".as_slice(),
+        ]
+        .concat();
+        create_chromium_fixture(
+            &path,
+            24,
+            &[
+                (
+                    ".bilibili.com",
+                    "SESSDATA",
+                    encrypt_chromium_value(b"v10", b"peanuts", b"sessdata_value"),
+                ),
+                (
+                    ".bilibili.com",
+                    "bili_jct",
+                    encrypt_chromium_value(b"v11", b"secret", &hashed),
+                ),
+                (
+                    ".bilibili.com",
+                    "broken",
+                    encrypt_chromium_value(b"v11", b"other", b"unreadable"),
+                ),
+                (
+                    ".example.com",
+                    "other",
+                    encrypt_chromium_value(b"v10", b"peanuts", b"other_value"),
+                ),
+            ],
+        );
+
+        let decryptor = ChromiumDecryptor::new(Some(b"secret"));
+        let cookies = read_chromium_cookies(
+            &Connection::open(&path).unwrap(),
+            Some("bilibili.com"),
+            |value: &[u8], host_key: &str, strip: bool| decryptor.decrypt(value, host_key, strip),
+        )
+        .unwrap();
+        let _ = fs::remove_file(&path);
+
+        let values: HashMap<_, _> = cookies
+            .iter()
+            .map(|c| (c.name.as_str(), c.value.as_str()))
+            .collect();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values["SESSDATA"], "sessdata_value");
+        assert_eq!(values["bili_jct"], "csrf_value");
+    }
+
+    #[test]
+    fn test_read_chromium_cookies_reports_undecryptable_values() {
+        let path = std::env::temp_dir().join(format!(
+            "blivedm_chromium_encrypted_{}.db",
+            std::process::id()
+        ));
+        create_chromium_fixture(
+            &path,
+            24,
+            &[
+                (".bilibili.com", "SESSDATA", b"v20 ciphertext".to_vec()),
+                (".bilibili.com", "bili_jct", b"v20 ciphertext".to_vec()),
+            ],
+        );
+
+        let result = read_chromium_cookies(
+            &Connection::open(&path).unwrap(),
+            Some("bilibili.com"),
+            |_: &[u8], _: &str, _: bool| Err("unsupported".to_string()),
+        );
+        let _ = fs::remove_file(&path);
+
+        assert!(matches!(result, Err(CookieReadError::Encrypted(2))));
+    }
+
+    #[test]
+    fn test_parse_browser_selection() {
+        let selection: BrowserSelection = "chrome".parse().unwrap();
+        assert_eq!(selection.browser, Browser::Chrome);
+        assert_eq!(selection.profile, None);
+
+        let selection: BrowserSelection = "Brave:Profile 2".parse().unwrap();
+        assert_eq!(selection.browser, Browser::Brave);
+        assert_eq!(selection.profile.as_deref(), Some("Profile 2"));
+
+        assert!("netscape".parse::<BrowserSelection>().is_err());
+    }
+
+    #[test]
+    fn test_find_profiles_in_data_dir() {
+        let dir = std::env::temp_dir().join(format!("blivedm_profiles_{}", std::process::id()));
+        let touch = |relative: &str| {
+            let path = dir.join(relative);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"").unwrap();
+        };
+        touch("chrome/Default/Network/Cookies");
+        touch("chrome/Profile 2/Cookies");
+        touch("chrome/Profile 10/Network/Cookies");
+        fs::create_dir_all(dir.join("chrome/System Profile")).unwrap();
+        touch("firefox/abcd.work/cookies.sqlite");
+        touch("firefox/efgh.default-release/cookies.sqlite");
+
+        let chrome = Browser::Chrome.find_chromium_profiles(&dir.join("chrome"));
+        let names: Vec<_> = chrome.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["Default", "Profile 2", "Profile 10"]);
+        assert!(chrome[0].cookie_db_path.ends_with("Network/Cookies"));
+        assert!(chrome[1].cookie_db_path.ends_with("Profile 2/Cookies"));
+
+        let firefox = Browser::Firefox.find_firefox_profiles(&dir.join("firefox"));
+        let names: Vec<_> = firefox.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["efgh.default-release", "abcd.work"]);
+        assert!(firefox[0].matches_name("default-release"));
+        assert!(firefox[1].matches_name("abcd.WORK"));
+        assert!(!chrome[1].matches_name("2"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_bilibili_cookie_string() {
+        let cookie = |name: &str, value: &str, expires: Option<i64>| Cookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            domain: ".bilibili.com".to_string(),
+            path: "/".to_string(),
+            expires: expires.and_then(|t| Utc.timestamp_opt(t, 0).single()),
+            secure: true,
+            http_only: true,
+        };
+        let later = Utc::now().timestamp() + 3600;
+
+        let cookies = vec![
+            cookie("SESSDATA", "old", Some(later)),
+            cookie("SESSDATA", "new", Some(later + 60)),
+            cookie("bili_jct", "csrf", None),
+            cookie("expired", "x", Some(1)),
+        ];
+        assert_eq!(
+            bilibili_cookie_string(cookies).as_deref(),
+            Some("SESSDATA=new; bili_jct=csrf")
+        );
+        assert_eq!(
+            bilibili_cookie_string(vec![cookie("bili_jct", "csrf", None)]),
+            None
+        );
+    }
+
+    fn create_firefox_fixture(connection: &Connection, rows: &[(&str, &str, &str)]) {
+        connection
+            .execute(
+                "CREATE TABLE moz_cookies (name TEXT, value TEXT, host TEXT, path TEXT,
+                     expiry INTEGER, isSecure INTEGER, isHttpOnly INTEGER);",
+            )
+            .unwrap();
+        for (host, name, value) in rows {
+            let mut statement = connection
+                .prepare("INSERT INTO moz_cookies VALUES (?, ?, ?, '/', 0, 1, 1)")
+                .unwrap();
+            statement
+                .bind(&[(1, *name), (2, *value), (3, *host)][..])
+                .unwrap();
+            statement.next().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_read_firefox_cookies_with_bound_domain_filter() {
+        let connection = Connection::open(":memory:").unwrap();
+        create_firefox_fixture(
+            &connection,
+            &[
+                (".bilibili.com", "SESSDATA", "sess"),
+                ("live.bilibili.com", "LIVE_BUVID", "buvid"),
+                ("bilibili.com", "host_only", "x"),
+                ("notbilibili.com", "spoofed", "x"),
+            ],
+        );
+
+        let mut names: Vec<_> = read_firefox_cookies(&connection, Some("bilibili.com"))
+            .unwrap()
+            .into_iter()
+            .map(|c| c.name)
+            .collect();
+        names.sort();
+        assert_eq!(names, ["LIVE_BUVID", "SESSDATA", "host_only"]);
+
+        // Quotes in the filter are data, not SQL
+        assert!(
+            read_firefox_cookies(&connection, Some("x' OR '1'='1"))
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_cookie_database_reads_uncheckpointed_wal() {
+        let dir = std::env::temp_dir().join(format!("blivedm_wal_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cookies.sqlite");
+
+        // Keep the writer open with checkpointing disabled, like a running browser
+        let writer = Connection::open(&path).unwrap();
+        writer
+            .execute("PRAGMA journal_mode = WAL; PRAGMA wal_autocheckpoint = 0;")
+            .unwrap();
+        create_firefox_fixture(&writer, &[(".bilibili.com", "SESSDATA", "sess")]);
+        assert!(dir.join("cookies.sqlite-wal").is_file());
+
+        let database = CookieDatabase::open(&path).unwrap();
+        let cookies = read_firefox_cookies(&database.connection, Some("bilibili.com")).unwrap();
+        assert_eq!(cookies.len(), 1);
+        assert_eq!(cookies[0].value, "sess");
+
+        drop(database);
+        drop(writer);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_open_immutable_database() {
+        let dir = std::env::temp_dir().join(format!("blivedm_immutable_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("Cookies 100%.db");
+        create_firefox_fixture(
+            &Connection::open(&path).unwrap(),
+            &[(".bilibili.com", "SESSDATA", "sess")],
+        );
+
+        assert!(sqlite_immutable_uri(&path).ends_with("Cookies%20100%25.db?immutable=1"));
+        let database = CookieDatabase::open_immutable(&path).unwrap();
+        assert_eq!(
+            read_firefox_cookies(&database.connection, None)
+                .unwrap()
+                .len(),
+            1
+        );
+
+        drop(database);
+        let _ = fs::remove_dir_all(&dir);
+        assert!(matches!(
+            CookieDatabase::open(&path),
+            Err(CookieReadError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_cookie_diagnosis_from_cookies() {
+        let cookie = |name: &str, expires: i64| Cookie {
+            name: name.to_string(),
+            value: "value".to_string(),
+            domain: ".bilibili.com".to_string(),
+            path: "/".to_string(),
+            expires: Utc.timestamp_opt(expires, 0).single(),
+            secure: true,
+            http_only: true,
+        };
+        let later = Utc::now().timestamp() + 3600;
+
+        assert_eq!(
+            CookieDiagnosis::from_cookies(&[]),
+            CookieDiagnosis::NoBilibiliCookies
+        );
+        assert_eq!(
+            CookieDiagnosis::from_cookies(&[cookie("buvid3", later)]),
+            CookieDiagnosis::NoSessdata { cookie_count: 1 }
+        );
+        assert!(matches!(
+            CookieDiagnosis::from_cookies(&[cookie("SESSDATA", 1)]),
+            CookieDiagnosis::Expired { .. }
+        ));
+        assert!(
+            CookieDiagnosis::from_cookies(&[cookie("SESSDATA", 1), cookie("SESSDATA", later)])
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_parse_netscape_cookies() {
+        let content = "# Netscape HTTP Cookie File\n\
+                       \n\
+                       .bilibili.com\tTRUE\t/\tFALSE\t4102444800\tbuvid3\tabc\n\
+                       #HttpOnly_.bilibili.com\tTRUE\t/\tTRUE\t4102444800\tSESSDATA\tsess%2Cdata\r\n\
+                       www.bilibili.com\tFALSE\t/video\tFALSE\t0\tsession\tx\n\
+                       malformed line\n";
+        let cookies = parse_netscape_cookies(content);
+        assert_eq!(cookies.len(), 3);
+
+        assert_eq!(cookies[0].name, "buvid3");
+        assert!(!cookies[0].http_only);
+        assert_eq!(cookies[0].expires.unwrap().timestamp(), 4102444800);
+
+        assert_eq!(cookies[1].name, "SESSDATA");
+        assert_eq!(cookies[1].value, "sess%2Cdata");
+        assert_eq!(cookies[1].domain, ".bilibili.com");
+        assert!(cookies[1].secure && cookies[1].http_only);
+
+        assert_eq!(cookies[2].path, "/video");
+        assert_eq!(cookies[2].expires, None);
+    }
+
+    #[test]
+    fn test_parse_json_cookies() {
+        let content = r#"[
+            {"domain": ".bilibili.com", "name": "SESSDATA", "value": "sess", "path": "/",
+             "expirationDate": 4102444800.5, "secure": true, "httpOnly": true, "session": false},
+            {"domain": ".bilibili.com", "name": "bili_jct", "value": "csrf", "expires": -1},
+            {"value": "no name"}
+        ]"#;
+        let cookies = parse_json_cookies(content).unwrap();
+        assert_eq!(cookies.len(), 2);
+        assert_eq!(cookies[0].expires.unwrap().timestamp(), 4102444800);
+        assert!(cookies[0].http_only);
+        assert_eq!(cookies[1].expires, None);
+
+        let wrapped = r#"{"cookies": [{"domain": "bilibili.com", "name": "a", "value": "b"}]}"#;
+        assert_eq!(parse_json_cookies(wrapped).unwrap()[0].path, "/");
+        assert!(parse_json_cookies("\"cookies\"").is_err());
+    }
+
+    #[test]
+    fn test_bilibili_cookies_from_file() {
+        let dir = std::env::temp_dir().join(format!("blivedm_cookie_files_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let txt = dir.join("cookies.txt");
+        fs::write(
+            &txt,
+            ".bilibili.com\tTRUE\t/\tTRUE\t1\tSESSDATA\texpired\n\
+             .bilibili.com\tTRUE\t/\tTRUE\t4102444800\tSESSDATA\tfresh\n\
+             .notbilibili.com\tTRUE\t/\tTRUE\t4102444800\tevil\tx\n",
+        )
+        .unwrap();
+        assert_eq!(bilibili_cookies_from_file(&txt).unwrap(), "SESSDATA=fresh");
+
+        let json = dir.join("cookies.json");
+        fs::write(
+            &json,
+            "\u{feff}[{\"domain\": \".bilibili.com\", \"name\": \"bili_jct\", \"value\": \"csrf\"}]",
+        )
+        .unwrap();
+        assert!(
+            bilibili_cookies_from_file(&json)
+                .unwrap_err()
+                .contains("SESSDATA")
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
 }
@@ -0,0 +1,256 @@
+// src/client/cookie_refresh.rs
+//! Web cookie refresh flow (cookie/info → correspond path → refresh → confirm)
+//!
+//! See [the API docs](https://github.com/SocialSisterYi/bilibili-API-collect/blob/master/docs/login/cookie_refresh.md).
+
+use crate::auth::USER_AGENT;
+use crate::credentials::Credentials;
+use rsa::pkcs8::DecodePublicKey;
+use rsa::sha2::Sha256;
+use rsa::{Oaep, RsaPublicKey};
+use serde_json::Value;
+use std::path::Path;
+use std::time::Duration;
+
+pub const COOKIE_INFO_URL: &str = "https://passport.bilibili.com/x/passport-login/web/cookie/info";
+pub const CORRESPOND_URL: &str = "https://www.bilibili.com/correspond/1";
+pub const COOKIE_REFRESH_URL: &str =
+    "https://passport.bilibili.com/x/passport-login/web/cookie/refresh";
+pub const CONFIRM_REFRESH_URL: &str =
+    "https://passport.bilibili.com/x/passport-login/web/confirm/refresh";
+
+/// Public key used to encrypt the correspond path
+const CORRESPOND_PUBLIC_KEY: &str = "-----BEGIN PUBLIC KEY-----
+MIGfMA0GCSqGSIb3DQEBAQUAA4GNADCBiQKBgQDLgd2OAkcGVtoE3ThUREbio0Eg
+Uc/prcajMKXvkCKFCWhJYJcLkcM2DKKcSeFpD/j6Boy538YXnR6VhcuUJOhH2x71
+nzPjfdTcqMz7djHum0qSZA0AyCBDABUqCrfNgCiJ00Ra7GmRj+YCK1NJEuewlb40
+JNrRuoEUXpabUzGB8QIDAQAB
+-----END PUBLIC KEY-----";
+
+/// Result of the cookie/info check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CookieInfo {
+    /// Whether the server asks for the cookies to be refreshed
+    pub refresh: bool,
+    /// Server timestamp in milliseconds, used to build the correspond path
+    pub timestamp: u64,
+}
+
+/// Ask the server whether the cookies need refreshing
+pub fn check_cookie_info(credentials: &Credentials) -> Result<CookieInfo, String> {
+    let client = http_client()?;
+    let body: Value = client
+        .get(COOKIE_INFO_URL)
+        .query(&[("csrf", credentials.bili_jct.as_str())])
+        .header(reqwest::header::COOKIE, credentials.to_cookie_string())
+        .send()
+        .and_then(|r| r.json())
+        .map_err(|e| format!("Failed to query cookie info: {}", e))?;
+
+    parse_cookie_info(&body)
+}
+
+fn parse_cookie_info(body: &Value) -> Result<CookieInfo, String> {
+    check_code(body, "cookie info")?;
+    Ok(CookieInfo {
+        refresh: body["data"]["refresh"].as_bool().unwrap_or(false),
+        timestamp: body["data"]["timestamp"].as_u64().unwrap_or(0),
+    })
+}
+
+/// Build the correspond path: RSA-OAEP(SHA-256) encryption of `refresh_{timestamp}`, hex encoded
+pub fn correspond_path(timestamp: u64) -> Result<String, String> {
+    let key = RsaPublicKey::from_public_key_pem(CORRESPOND_PUBLIC_KEY)
+        .map_err(|e| format!("Invalid correspond public key: {}", e))?;
+    let plaintext = format!("refresh_{}", timestamp);
+    let encrypted = key
+        .encrypt(
+            &mut rsa::rand_core::OsRng,
+            Oaep::new::<Sha256>(),
+            plaintext.as_bytes(),
+        )
+        .map_err(|e| format!("Failed to encrypt correspond path: {}", e))?;
+    Ok(encrypted.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Extract the refresh_csrf from the correspond page (`<div id="1-name">...</div>`)
+fn extract_refresh_csrf(html: &str) -> Option<String> {
+    let marker = "<div id=\"1-name\">";
+    let start = html.find(marker)? + marker.len();
+    let end = html[start..].find("</div>")? + start;
+    let value = html[start..end].trim();
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
+/// Fetch the refresh_csrf for the given server timestamp
+fn fetch_refresh_csrf(
+    client: &reqwest::blocking::Client,
+    credentials: &Credentials,
+    timestamp: u64,
+) -> Result<String, String> {
+    let url = format!("{}/{}", CORRESPOND_URL, correspond_path(timestamp)?);
+    let html = client
+        .get(url)
+        .header(reqwest::header::COOKIE, credentials.to_cookie_string())
+        .send()
+        .and_then(|r| r.text())
+        .map_err(|e| format!("Failed to fetch correspond page: {}", e))?;
+    extract_refresh_csrf(&html)
+        .ok_or_else(|| "refresh_csrf not found in correspond page".to_string())
+}
+
+/// Refresh the cookies unconditionally, returning the new credentials
+///
+/// The old credentials are invalidated once this returns successfully.
+pub fn refresh_credentials(
+    credentials: &Credentials,
+    timestamp: u64,
+) -> Result<Credentials, String> {
+    if credentials.refresh_token.is_empty() {
+        return Err("No refresh token available, please run `blivedm login`".to_string());
+    }
+
+    let client = http_client()?;
+    let refresh_csrf = fetch_refresh_csrf(&client, credentials, timestamp)?;
+
+    let response = client
+        .post(COOKIE_REFRESH_URL)
+        .header(reqwest::header::COOKIE, credentials.to_cookie_string())
+        .form(&[
+            ("csrf", credentials.bili_jct.as_str()),
+            ("refresh_csrf", refresh_csrf.as_str()),
+            ("source", "main_web"),
+            ("refresh_token", credentials.refresh_token.as_str()),
+        ])
+        .send()
+        .map_err(|e| format!("Failed to refresh cookies: {}", e))?;
+
+    let cookies: Vec<(String, String)> = response
+        .cookies()
+        .map(|c| (c.name().to_string(), c.value().to_string()))
+        .collect();
+    let body: Value = response
+        .json()
+        .map_err(|e| format!("Failed to parse cookie refresh response: {}", e))?;
+    check_code(&body, "cookie refresh")?;
+
+    let new_refresh_token = body["data"]["refresh_token"].as_str().unwrap_or("");
+    let mut refreshed = Credentials::from_cookie_pairs(cookies, new_refresh_token)
+        .ok_or_else(|| "Cookie refresh returned no SESSDATA".to_string())?;
+    // Keep cookies that the refresh endpoint did not reissue (e.g. buvid3)
+    for (name, value) in &credentials.extra_cookies {
+        if !refreshed.extra_cookies.iter().any(|(n, _)| n == name) {
+            refreshed.extra_cookies.push((name.clone(), value.clone()));
+        }
+    }
+
+    // Confirm with the new cookies so the old refresh token is retired
+    let body: Value = client
+        .post(CONFIRM_REFRESH_URL)
+        .header(reqwest::header::COOKIE, refreshed.to_cookie_string())
+        .form(&[
+            ("csrf", refreshed.bili_jct.as_str()),
+            ("refresh_token", credentials.refresh_token.as_str()),
+        ])
+        .send()
+        .and_then(|r| r.json())
+        .map_err(|e| format!("Failed to confirm cookie refresh: {}", e))?;
+    if let Err(e) = check_code(&body, "refresh confirmation") {
+        // The new cookies are already valid, so only warn
+        log::warn!("{}", e);
+    }
+
+    Ok(refreshed)
+}
+
+/// Refresh the credentials if the server asks for it
+///
+/// Returns `Ok(None)` when no refresh was needed.
+pub fn refresh_if_needed(credentials: &Credentials) -> Result<Option<Credentials>, String> {
+    let info = check_cookie_info(credentials)?;
+    if !info.refresh {
+        log::debug!("Cookies are still fresh, no refresh needed");
+        return Ok(None);
+    }
+    log::info!("Server requested a cookie refresh, refreshing...");
+    refresh_credentials(credentials, info.timestamp).map(Some)
+}
+
+/// Refresh the credentials stored at `path` if needed and persist the result
+pub fn refresh_stored_credentials(path: &Path) -> Result<Option<Credentials>, String> {
+    let credentials = Credentials::load(path)?;
+    let refreshed = refresh_if_needed(&credentials)?;
+    if let Some(new_credentials) = &refreshed {
+        new_credentials.save(path)?;
+        log::info!("Refreshed cookies saved to {:?}", path);
+    }
+    Ok(refreshed)
+}
+
+fn check_code(body: &Value, what: &str) -> Result<(), String> {
+    match body["code"].as_i64() {
+        Some(0) => Ok(()),
+        Some(code) => Err(format!(
+            "{} failed: {} ({})",
+            what,
+            body["message"].as_str().unwrap_or(""),
+            code
+        )),
+        None => Err(format!("{} failed: malformed response", what)),
+    }
+}
+
+fn http_client() -> Result<reqwest::blocking::Client, String> {
+    reqwest::blocking::Client::builder()
+        .https_only(true)
+        .user_agent(USER_AGENT)
+        .timeout(Duration::from_secs(10))
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_correspond_path() {
+        let path = correspond_path(1684468084078).unwrap();
+        // 1024-bit key -> 128 bytes -> 256 hex characters
+        assert_eq!(path.len(), 256);
+        assert!(path.chars().all(|c| c.is_ascii_hexdigit()));
+        // OAEP is randomized
+        assert_ne!(path, correspond_path(1684468084078).unwrap());
+    }
+
+    #[test]
+    fn test_extract_refresh_csrf() {
+        let html =
+            r#"<html><body><div id="1-name">b0cc8411ded2f9db2cff2edb3123acac</div></body></html>"#;
+        assert_eq!(
+            extract_refresh_csrf(html),
+            Some("b0cc8411ded2f9db2cff2edb3123acac".to_string())
+        );
+        assert_eq!(extract_refresh_csrf("<html></html>"), None);
+    }
+
+    #[test]
+    fn test_parse_cookie_info() {
+        let body = json!({"code": 0, "data": {"refresh": true, "timestamp": 1684466082813u64}});
+        assert_eq!(
+            parse_cookie_info(&body).unwrap(),
+            CookieInfo {
+                refresh: true,
+                timestamp: 1684466082813
+            }
+        );
+
+        let body = json!({"code": -101, "message": "账号未登录"});
+        assert!(parse_cookie_info(&body).is_err());
+    }
+}
@@ -0,0 +1,200 @@
+// src/client/credentials.rs
+//! Persistent login credentials produced by `blivedm login`
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Login credentials obtained from the passport login flow
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct Credentials {
+    /// SESSDATA cookie used for authentication
+    pub sessdata: String,
+    /// bili_jct cookie, doubles as the CSRF token
+    pub bili_jct: String,
+    /// DedeUserID cookie (the uid of the logged in account)
+    pub dede_user_id: String,
+    /// Refresh token used to renew the cookies
+    #[serde(default)]
+    pub refresh_token: String,
+    /// Any additional cookies returned by the login endpoint
+    #[serde(default)]
+    pub extra_cookies: Vec<(String, String)>,
+    /// Unix timestamp (seconds) of when the credentials were obtained
+    #[serde(default)]
+    pub obtained_at: u64,
+}
+
+impl Credentials {
+    /// Build credentials from a list of cookie name/value pairs
+    ///
+    /// Returns None if SESSDATA is missing.
+    pub fn from_cookie_pairs<I, K, V>(pairs: I, refresh_token: &str) -> Option<Self>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let mut credentials = Credentials {
+            refresh_token: refresh_token.to_string(),
+            obtained_at: now_secs(),
+            ..Default::default()
+        };
+
+        for (name, value) in pairs {
+            let (name, value) = (name.into(), value.into());
+            match name.as_str() {
+                "SESSDATA" => credentials.sessdata = value,
+                "bili_jct" => credentials.bili_jct = value,
+                "DedeUserID" => credentials.dede_user_id = value,
+                _ => {
+                    if !credentials.extra_cookies.iter().any(|(n, _)| *n == name) {
+                        credentials.extra_cookies.push((name, value));
+                    }
+                }
+            }
+        }
+
+        if credentials.sessdata.is_empty() {
+            None
+        } else {
+            Some(credentials)
+        }
+    }
+
+    /// Format the credentials as a `Cookie` header value
+    pub fn to_cookie_string(&self) -> String {
+        let mut parts = vec![format!("SESSDATA={}", self.sessdata)];
+        if !self.bili_jct.is_empty() {
+            parts.push(format!("bili_jct={}", self.bili_jct));
+        }
+        if !self.dede_user_id.is_empty() {
+            parts.push(format!("DedeUserID={}", self.dede_user_id));
+        }
+        for (name, value) in &self.extra_cookies {
+            parts.push(format!("{}={}", name, value));
+        }
+        parts.join("; ")
+    }
+
+    /// Load credentials from a JSON file
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read credentials file {:?}: {}", path, e))?;
+        serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse credentials file {:?}: {}", path, e))
+    }
+
+    /// Save credentials to a JSON file readable only by the current user (0600)
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+            && !parent.exists()
+        {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create directory {:?}: {}", parent, e))?;
+        }
+
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize credentials: {}", e))?;
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        let mut file = options
+            .open(path)
+            .map_err(|e| format!("Failed to open credentials file {:?}: {}", path, e))?;
+
+        // The mode above only applies to newly created files, so tighten existing ones too
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(fs::Permissions::from_mode(0o600))
+                .map_err(|e| format!("Failed to set permissions on {:?}: {}", path, e))?;
+        }
+
+        file.write_all(content.as_bytes())
+            .map_err(|e| format!("Failed to write credentials file {:?}: {}", path, e))
+    }
+}
+
+/// Get the default credentials file path (~/.config/blivedm_rs/credentials.json)
+pub fn default_credentials_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("blivedm_rs").join("credentials.json"))
+}
+
+/// Load credentials from the default location, if present and valid
+pub fn load_default_credentials() -> Option<Credentials> {
+    let path = default_credentials_path()?;
+    if !path.exists() {
+        return None;
+    }
+    match Credentials::load(&path) {
+        Ok(credentials) => Some(credentials),
+        Err(e) => {
+            log::warn!("{}", e);
+            None
+        }
+    }
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_credentials_from_cookie_pairs() {
+        let pairs = vec![
+            ("SESSDATA", "sess"),
+            ("bili_jct", "csrf"),
+            ("DedeUserID", "12345"),
+            ("sid", "abc"),
+        ];
+        let credentials = Credentials::from_cookie_pairs(pairs, "refresh").unwrap();
+        assert_eq!(credentials.sessdata, "sess");
+        assert_eq!(credentials.bili_jct, "csrf");
+        assert_eq!(credentials.dede_user_id, "12345");
+        assert_eq!(credentials.refresh_token, "refresh");
+        assert_eq!(
+            credentials.to_cookie_string(),
+            "SESSDATA=sess; bili_jct=csrf; DedeUserID=12345; sid=abc"
+        );
+
+        assert!(Credentials::from_cookie_pairs(vec![("bili_jct", "csrf")], "").is_none());
+    }
+
+    #[test]
+    fn test_credentials_save_and_load() {
+        let dir = std::env::temp_dir().join(format!("blivedm_credentials_{}", std::process::id()));
+        let path = dir.join("credentials.json");
+        let credentials =
+            Credentials::from_cookie_pairs(vec![("SESSDATA", "sess"), ("bili_jct", "csrf")], "rt")
+                .unwrap();
+
+        credentials.save(&path).unwrap();
+        let loaded = Credentials::load(&path).unwrap();
+        assert_eq!(loaded, credentials);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,879 @@
+// src/client/danmaku.rs
+//! Sending danmaku to a live room via `/msg/send`
+
+use crate::auth::USER_AGENT;
+use crate::scheduler::EventContext;
+use log::{debug, error, info, warn};
+use reqwest::header::{HeaderMap, HeaderValue};
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+pub const SEND_URL: &str = "https://api.live.bilibili.com/msg/send";
+
+/// Parameters for sending a danmaku message to Bilibili API
+#[derive(Serialize, Debug)]
+struct SendDanmakuRequest {
+    csrf: String,
+    roomid: u64,
+    msg: String,
+    rnd: u64,
+    fontsize: u32,
+    color: u32,
+    mode: u32,
+    /// 1 when `msg` is an emoticon unique id
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dm_type: Option<u32>,
+    bubble: u32,
+    room_type: u32,
+    jumpfrom: u32,
+    reply_mid: u64,
+    reply_attr: u32,
+    reply_uname: String,
+    replay_dmid: String,
+    statistics: String,
+    csrf_token: String,
+}
+
+/// Where a danmaku is displayed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DanmakuMode {
+    /// Scrolls across the player
+    #[default]
+    Scroll,
+    /// Fixed at the bottom
+    Bottom,
+    /// Fixed at the top
+    Top,
+}
+
+impl DanmakuMode {
+    fn code(self) -> u32 {
+        match self {
+            DanmakuMode::Scroll => 1,
+            DanmakuMode::Bottom => 4,
+            DanmakuMode::Top => 5,
+        }
+    }
+}
+
+/// The user a danmaku replies to (shown as an @ mention)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplyTarget {
+    pub uid: u64,
+    pub uname: String,
+}
+
+impl ReplyTarget {
+    pub fn new(uid: u64, uname: impl Into<String>) -> Self {
+        Self {
+            uid,
+            uname: uname.into(),
+        }
+    }
+}
+
+/// How a danmaku is sent
+///
+/// ```
+/// use blivedm::client::danmaku::{DanmakuMode, ReplyTarget, SendOptions};
+///
+/// let options = SendOptions::new()
+///     .with_color(0xFF6868)
+///     .with_mode(DanmakuMode::Top)
+///     .with_reply_to(ReplyTarget::new(12345, "viewer"));
+/// assert_eq!(options.color, 0xFF6868);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SendOptions {
+    /// RGB color, white by default
+    pub color: u32,
+    pub mode: DanmakuMode,
+    pub fontsize: u32,
+    /// The message is an emoticon unique id (e.g. `official_147`) rather than text
+    pub emoticon: bool,
+    pub reply_to: Option<ReplyTarget>,
+}
+
+impl Default for SendOptions {
+    fn default() -> Self {
+        Self {
+            color: 0xFFFFFF,
+            mode: DanmakuMode::Scroll,
+            fontsize: 25,
+            emoticon: false,
+            reply_to: None,
+        }
+    }
+}
+
+impl SendOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_color(mut self, color: u32) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_mode(mut self, mode: DanmakuMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn with_fontsize(mut self, fontsize: u32) -> Self {
+        self.fontsize = fontsize;
+        self
+    }
+
+    /// Send the message as an emoticon; the message must be the emoticon's unique id
+    pub fn with_emoticon(mut self) -> Self {
+        self.emoticon = true;
+        self
+    }
+
+    /// Reply to (@) a user
+    pub fn with_reply_to(mut self, target: ReplyTarget) -> Self {
+        self.reply_to = Some(target);
+        self
+    }
+}
+
+/// A danmaku accepted by the server
+#[derive(Debug, Clone, PartialEq)]
+pub struct SentDanmaku {
+    /// The text that was sent
+    pub message: String,
+    /// Danmaku id (`id_str` from `mode_info.extra`), if echoed by the server
+    pub dm_id: Option<String>,
+    /// Raw `mode_info` object echoed by the server
+    pub mode_info: Option<Value>,
+}
+
+/// Why a request to a live room API as the sending account failed; shared by danmaku
+/// sending and moderation, which add their own errors on top
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LiveApiError {
+    /// No cookies in the context
+    MissingCookies,
+    /// The cookies have no bili_jct (CSRF token)
+    MissingCsrf,
+    /// Not logged in or the cookies expired (-101)
+    NotLoggedIn,
+    /// Any other API error code
+    Api { code: i64, message: String },
+    /// Network failure, non-200 status or an unparseable response
+    Http(String),
+}
+
+impl LiveApiError {
+    /// Map an API error code that has no more specific error
+    pub fn from_code(code: i64, message: &str) -> Self {
+        match code {
+            -101 => LiveApiError::NotLoggedIn,
+            _ => LiveApiError::Api {
+                code,
+                message: message.to_string(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for LiveApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LiveApiError::MissingCookies => write!(f, "No cookies available"),
+            LiveApiError::MissingCsrf => {
+                write!(f, "Could not extract CSRF token (bili_jct) from cookies")
+            }
+            LiveApiError::NotLoggedIn => write!(f, "Not logged in or cookies expired (-101)"),
+            LiveApiError::Api { code, message } => {
+                write!(f, "Request rejected: {} ({})", message, code)
+            }
+            LiveApiError::Http(e) => write!(f, "Request failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LiveApiError {}
+
+/// Why a danmaku was not sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DanmakuSendError {
+    /// Missing credentials, network failure or an error code not specific to sending
+    Request(LiveApiError),
+    /// Sending too frequently (10030)
+    TooFrequent,
+    /// Same message sent twice in a row (10031)
+    Duplicate,
+    /// Blocked by the content filter (11000, or code 0 with message `f`/`k`)
+    Filtered,
+    /// The room is silenced or the user is muted (1003)
+    RoomSilenced,
+}
+
+impl DanmakuSendError {
+    /// Map a `/msg/send` error code to an error
+    pub fn from_code(code: i64, message: &str) -> Self {
+        match code {
+            10030 => DanmakuSendError::TooFrequent,
+            10031 => DanmakuSendError::Duplicate,
+            11000 => DanmakuSendError::Filtered,
+            1003 => DanmakuSendError::RoomSilenced,
+            _ => DanmakuSendError::Request(LiveApiError::from_code(code, message)),
+        }
+    }
+}
+
+impl From<LiveApiError> for DanmakuSendError {
+    fn from(error: LiveApiError) -> Self {
+        DanmakuSendError::Request(error)
+    }
+}
+
+impl fmt::Display for DanmakuSendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DanmakuSendError::Request(e) => write!(f, "{}", e),
+            DanmakuSendError::TooFrequent => write!(f, "Sending too frequently (10030)"),
+            DanmakuSendError::Duplicate => write!(f, "Duplicate message rejected (10031)"),
+            DanmakuSendError::Filtered => write!(f, "Message blocked by the content filter"),
+            DanmakuSendError::RoomSilenced => write!(f, "Room is silenced or you are muted (1003)"),
+        }
+    }
+}
+
+impl std::error::Error for DanmakuSendError {}
+
+/// Extract CSRF token from cookies string
+pub fn extract_csrf_token(cookies: &str) -> Option<String> {
+    for cookie in cookies.split(';') {
+        let cookie = cookie.trim();
+        if let Some(token) = cookie.strip_prefix("bili_jct=") {
+            return Some(token.to_string());
+        }
+    }
+    None
+}
+
+/// HTTP client for the live room APIs; clones share one connection pool
+pub(crate) fn live_http_client() -> reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT
+        .get_or_init(|| {
+            reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .expect("Failed to create HTTP client")
+        })
+        .clone()
+}
+
+/// Cookies of the context's sending account, with the headers to send them in
+pub(crate) fn sending_headers(context: &EventContext) -> Result<(&str, HeaderMap), LiveApiError> {
+    let cookies = context
+        .sending_cookies()
+        .ok_or(LiveApiError::MissingCookies)?;
+    let headers = live_request_headers(cookies, context.room_id).map_err(LiveApiError::Http)?;
+    Ok((cookies, headers))
+}
+
+/// The JSON body of a live room API response, or an error for a failed status
+pub(crate) async fn read_json(response: reqwest::Response) -> Result<Value, LiveApiError> {
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    if !status.is_success() {
+        debug!("Response body: {}", body);
+        return Err(LiveApiError::Http(format!("{} - {}", status, body)));
+    }
+    serde_json::from_str(&body).map_err(|e| LiveApiError::Http(format!("invalid response: {}", e)))
+}
+
+/// The `code` of a live room API response and its message
+pub(crate) fn response_code(body: &Value) -> Result<(i64, &str), LiveApiError> {
+    let code = body["code"]
+        .as_i64()
+        .ok_or_else(|| LiveApiError::Http(format!("malformed response: {}", body)))?;
+    let message = body["message"]
+        .as_str()
+        .or_else(|| body["msg"].as_str())
+        .unwrap_or("");
+    Ok((code, message))
+}
+
+/// Headers for live room write APIs: the account's cookies and the room as referer
+fn live_request_headers(cookies: &str, room_id: u64) -> Result<HeaderMap, String> {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "Cookie",
+        HeaderValue::from_str(cookies).map_err(|e| format!("invalid cookies: {}", e))?,
+    );
+    headers.insert("User-Agent", HeaderValue::from_static(USER_AGENT));
+    headers.insert(
+        "Referer",
+        HeaderValue::from_str(&format!("https://live.bilibili.com/{}", room_id))
+            .map_err(|e| e.to_string())?,
+    );
+    Ok(headers)
+}
+
+/// Interpret a `/msg/send` response body
+pub fn parse_send_response(message: &str, body: &Value) -> Result<SentDanmaku, DanmakuSendError> {
+    let (code, api_message) = response_code(body)?;
+    if code != 0 {
+        return Err(DanmakuSendError::from_code(code, api_message));
+    }
+    // Filtered messages are acknowledged with code 0 but never shown
+    if api_message == "f" || api_message == "k" {
+        return Err(DanmakuSendError::Filtered);
+    }
+
+    let mode_info = body["data"]
+        .get("mode_info")
+        .filter(|v| !v.is_null())
+        .cloned();
+    let dm_id = mode_info
+        .as_ref()
+        .and_then(|info| info["extra"].as_str())
+        .and_then(|extra| serde_json::from_str::<Value>(extra).ok())
+        .and_then(|extra| extra["id_str"].as_str().map(str::to_string));
+
+    Ok(SentDanmaku {
+        message: message.to_string(),
+        dm_id,
+        mode_info,
+    })
+}
+
+/// Sends danmaku with a reusable HTTP client
+#[derive(Debug, Clone)]
+pub struct DanmakuSender {
+    http_client: reqwest::Client,
+}
+
+impl Default for DanmakuSender {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DanmakuSender {
+    pub fn new() -> Self {
+        Self {
+            http_client: live_http_client(),
+        }
+    }
+
+    /// Process-wide sender
+    pub fn shared() -> &'static DanmakuSender {
+        static SHARED: OnceLock<DanmakuSender> = OnceLock::new();
+        SHARED.get_or_init(DanmakuSender::new)
+    }
+
+    /// Send a danmaku to the context's room, as the context's sending account
+    pub async fn send(
+        &self,
+        message: &str,
+        context: &EventContext,
+    ) -> Result<SentDanmaku, DanmakuSendError> {
+        self.send_with_options(message, context, &SendOptions::default())
+            .await
+    }
+
+    /// Send a danmaku with custom color, mode, emoticon or reply target
+    pub async fn send_with_options(
+        &self,
+        message: &str,
+        context: &EventContext,
+        options: &SendOptions,
+    ) -> Result<SentDanmaku, DanmakuSendError> {
+        let (cookies, headers) = sending_headers(context)?;
+        let csrf_token = extract_csrf_token(cookies).ok_or(LiveApiError::MissingCsrf)?;
+
+        // Current timestamp
+        let rnd = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        let request = SendDanmakuRequest {
+            csrf: csrf_token.clone(),
+            roomid: context.room_id,
+            msg: message.to_string(),
+            rnd,
+            fontsize: options.fontsize,
+            color: options.color,
+            mode: options.mode.code(),
+            dm_type: options.emoticon.then_some(1),
+            bubble: 0,
+            room_type: 0,
+            jumpfrom: 0,
+            reply_mid: options.reply_to.as_ref().map_or(0, |r| r.uid),
+            reply_attr: 0,
+            reply_uname: options
+                .reply_to
+                .as_ref()
+                .map_or_else(String::new, |r| r.uname.clone()),
+            replay_dmid: String::new(),
+            statistics: r#"{"appId":100,"platform":5}"#.to_string(),
+            csrf_token,
+        };
+
+        debug!("Sending danmaku: {}", message);
+
+        let response = self
+            .http_client
+            .post(SEND_URL)
+            .headers(headers)
+            .form(&request)
+            .send()
+            .await
+            .map_err(|e| LiveApiError::Http(e.to_string()))?;
+
+        let json = read_json(response).await.inspect_err(|e| {
+            warn!("Failed to send danmaku: {}", e);
+        })?;
+        match parse_send_response(message, &json) {
+            Ok(sent) => {
+                info!("Successfully sent danmaku: {}", message);
+                Ok(sent)
+            }
+            Err(e) => {
+                warn!("Danmaku '{}' rejected: {}", message, e);
+                debug!("Response body: {}", json);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Characters that make a good place to split an over-length message
+const SPLIT_BOUNDARIES: &[char] = &[
+    '。', '！', '？', '，', '、', '；', '：', '…', '～', '.', '!', '?', ',', ';', ':', '~',
+];
+
+/// Split a message into parts of at most `max_chars` characters
+///
+/// Parts end at punctuation or whitespace in the second half of the window when
+/// possible, otherwise the text is cut at exactly `max_chars` characters.
+pub fn split_message(text: &str, max_chars: usize) -> Vec<String> {
+    let max_chars = max_chars.max(1);
+    let mut parts = Vec::new();
+    let mut rest: Vec<char> = text.trim().chars().collect();
+
+    while rest.len() > max_chars {
+        let window = &rest[..max_chars];
+        let boundary = window
+            .iter()
+            .rposition(|c| c.is_whitespace() || SPLIT_BOUNDARIES.contains(c))
+            .filter(|&i| i + 1 >= max_chars / 2);
+        // Keep punctuation with the part it ends, drop the whitespace
+        let (end, next) = match boundary {
+            Some(i) if window[i].is_whitespace() => (i, i + 1),
+            Some(i) => (i + 1, i + 1),
+            None => (max_chars, max_chars),
+        };
+        let part: String = rest[..end].iter().collect();
+        if !part.trim().is_empty() {
+            parts.push(part.trim().to_string());
+        }
+        rest.drain(..next);
+        while rest.first().is_some_and(|c| c.is_whitespace()) {
+            rest.remove(0);
+        }
+    }
+
+    if !rest.is_empty() {
+        parts.push(rest.into_iter().collect());
+    }
+    parts
+}
+
+/// Settings for [`DanmakuQueue`]
+#[derive(Debug, Clone)]
+pub struct DanmakuQueueConfig {
+    /// Minimum time between two sends
+    pub interval: Duration,
+    /// Maximum characters per danmaku (20, 30 or 40 depending on the account level)
+    pub max_length: usize,
+    /// How often a message rejected as too frequent is retried
+    pub max_retries: u32,
+    /// A message identical to the previous one sent within this window is dropped
+    pub duplicate_window: Duration,
+}
+
+impl Default for DanmakuQueueConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(1000),
+            max_length: 20,
+            max_retries: 3,
+            duplicate_window: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Called with each queued message and its outcome
+pub type SendListener = dyn Fn(&str, &Result<SentDanmaku, DanmakuSendError>) + Send + Sync;
+
+struct QueuedDanmaku {
+    message: String,
+    context: EventContext,
+    options: SendOptions,
+}
+
+/// Outbound danmaku queue shared by every sender in the process
+///
+/// Messages are split to `max_length`, sent one at a time at least `interval`
+/// apart, retried when Bilibili answers "too frequent", and exact repeats of the
+/// previous message are dropped instead of being rejected by the server.
+#[derive(Clone)]
+pub struct DanmakuQueue {
+    tx: mpsc::UnboundedSender<QueuedDanmaku>,
+    pending: Arc<AtomicUsize>,
+    listener: Arc<Mutex<Option<Arc<SendListener>>>>,
+    max_length: usize,
+}
+
+impl DanmakuQueue {
+    /// Start the queue; sending happens on a dedicated background thread
+    pub fn start(sender: DanmakuSender, config: DanmakuQueueConfig) -> Self {
+        let (queue, worker, rx) = Self::build(sender, config);
+        thread::spawn(move || {
+            match tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+            {
+                Ok(runtime) => runtime.block_on(worker.run(rx)),
+                Err(e) => error!("Failed to start danmaku queue: {}", e),
+            }
+        });
+        queue
+    }
+
+    /// Start the queue's worker as a task on an existing runtime, e.g. the scheduler's
+    pub fn start_on(
+        runtime: &tokio::runtime::Handle,
+        sender: DanmakuSender,
+        config: DanmakuQueueConfig,
+    ) -> Self {
+        let (queue, worker, rx) = Self::build(sender, config);
+        runtime.spawn(worker.run(rx));
+        queue
+    }
+
+    fn build(
+        sender: DanmakuSender,
+        config: DanmakuQueueConfig,
+    ) -> (Self, QueueWorker, mpsc::UnboundedReceiver<QueuedDanmaku>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let listener: Arc<Mutex<Option<Arc<SendListener>>>> = Arc::new(Mutex::new(None));
+        let max_length = config.max_length;
+
+        let worker = QueueWorker {
+            sender,
+            config,
+            pending: Arc::clone(&pending),
+            listener: Arc::clone(&listener),
+            last_send_at: None,
+            last_sent: None,
+        };
+        let queue = Self {
+            tx,
+            pending,
+            listener,
+            max_length,
+        };
+        (queue, worker, rx)
+    }
+
+    /// Report the outcome of every queued message to `listener`
+    pub fn set_listener<F>(&self, listener: F)
+    where
+        F: Fn(&str, &Result<SentDanmaku, DanmakuSendError>) + Send + Sync + 'static,
+    {
+        if let Ok(mut current) = self.listener.lock() {
+            *current = Some(Arc::new(listener));
+        }
+    }
+
+    /// Queue a message for the context's room and sending account
+    ///
+    /// Returns the number of danmaku the message was split into.
+    pub fn enqueue(&self, message: &str, context: &EventContext) -> usize {
+        self.enqueue_with_options(message, context, SendOptions::default())
+    }
+
+    /// Queue a message with custom send options; emoticons are never split
+    pub fn enqueue_with_options(
+        &self,
+        message: &str,
+        context: &EventContext,
+        options: SendOptions,
+    ) -> usize {
+        let parts = if options.emoticon {
+            vec![message.trim().to_string()]
+        } else {
+            split_message(message, self.max_length)
+        };
+        let mut queued = 0;
+        for part in parts {
+            self.pending.fetch_add(1, Ordering::SeqCst);
+            let item = QueuedDanmaku {
+                message: part,
+                context: context.clone(),
+                options: options.clone(),
+            };
+            if self.tx.send(item).is_err() {
+                self.pending.fetch_sub(1, Ordering::SeqCst);
+                warn!("Danmaku queue is not running, message dropped");
+                break;
+            }
+            queued += 1;
+        }
+        queued
+    }
+
+    /// Number of danmaku waiting to be sent
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::SeqCst)
+    }
+
+    /// Shared counter of waiting danmaku, for status displays
+    pub fn pending_handle(&self) -> Arc<AtomicUsize> {
+        Arc::clone(&self.pending)
+    }
+}
+
+struct QueueWorker {
+    sender: DanmakuSender,
+    config: DanmakuQueueConfig,
+    pending: Arc<AtomicUsize>,
+    listener: Arc<Mutex<Option<Arc<SendListener>>>>,
+    last_send_at: Option<Instant>,
+    /// Room, sending cookies and text of the last accepted danmaku
+    last_sent: Option<(u64, Option<String>, String, Instant)>,
+}
+
+impl QueueWorker {
+    async fn run(mut self, mut rx: mpsc::UnboundedReceiver<QueuedDanmaku>) {
+        while let Some(item) = rx.recv().await {
+            let result = self.deliver(&item).await;
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+
+            let listener = self.listener.lock().ok().and_then(|l| l.clone());
+            if let Some(listener) = listener {
+                listener(&item.message, &result);
+            }
+        }
+    }
+
+    fn is_duplicate(&self, item: &QueuedDanmaku) -> bool {
+        match &self.last_sent {
+            Some((room_id, cookies, message, at)) => {
+                *room_id == item.context.room_id
+                    && cookies.as_deref() == item.context.sending_cookies()
+                    && *message == item.message
+                    && at.elapsed() < self.config.duplicate_window
+            }
+            None => false,
+        }
+    }
+
+    async fn wait_for_slot(&self, interval: Duration) {
+        if let Some(last) = self.last_send_at {
+            let elapsed = last.elapsed();
+            if elapsed < interval {
+                tokio::time::sleep(interval - elapsed).await;
+            }
+        }
+    }
+
+    async fn deliver(&mut self, item: &QueuedDanmaku) -> Result<SentDanmaku, DanmakuSendError> {
+        if self.is_duplicate(item) {
+            debug!("Dropping duplicate danmaku: {}", item.message);
+            return Err(DanmakuSendError::Duplicate);
+        }
+
+        let mut attempt = 0;
+        let mut interval = self.config.interval;
+        loop {
+            self.wait_for_slot(interval).await;
+            let result = self
+                .sender
+                .send_with_options(&item.message, &item.context, &item.options)
+                .await;
+            self.last_send_at = Some(Instant::now());
+
+            match result {
+                Err(DanmakuSendError::TooFrequent) if attempt < self.config.max_retries => {
+                    attempt += 1;
+                    // Back off a little more on every retry
+                    interval = self.config.interval * (attempt + 1);
+                    debug!(
+                        "Sending too frequently, retrying '{}' in {:?}",
+                        item.message, interval
+                    );
+                }
+                result => {
+                    if result.is_ok() {
+                        self.last_sent = Some((
+                            item.context.room_id,
+                            item.context.sending_cookies().map(str::to_string),
+                            item.message.clone(),
+                            Instant::now(),
+                        ));
+                    }
+                    return result;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_send_response_success() {
+        let body = json!({
+            "code": 0,
+            "message": "",
+            "msg": "",
+            "data": {
+                "mode_info": {
+                    "mode": 0,
+                    "show_player_type": 0,
+                    "extra": "{\"send_from_me\":true,\"content\":\"hi\",\"id_str\":\"abc123\"}"
+                }
+            }
+        });
+        let sent = parse_send_response("hi", &body).unwrap();
+        assert_eq!(sent.message, "hi");
+        assert_eq!(sent.dm_id.as_deref(), Some("abc123"));
+        assert_eq!(sent.mode_info.unwrap()["mode"], 0);
+
+        let sent = parse_send_response("hi", &json!({"code": 0, "data": []})).unwrap();
+        assert_eq!(sent.dm_id, None);
+    }
+
+    #[test]
+    fn test_parse_send_response_errors() {
+        let error = |code: i64| {
+            parse_send_response("hi", &json!({"code": code, "message": "msg"})).unwrap_err()
+        };
+        assert_eq!(
+            error(-101),
+            DanmakuSendError::Request(LiveApiError::NotLoggedIn)
+        );
+        assert_eq!(error(10030), DanmakuSendError::TooFrequent);
+        assert_eq!(error(10031), DanmakuSendError::Duplicate);
+        assert_eq!(error(11000), DanmakuSendError::Filtered);
+        assert_eq!(error(1003), DanmakuSendError::RoomSilenced);
+        assert_eq!(
+            error(10024),
+            DanmakuSendError::Request(LiveApiError::Api {
+                code: 10024,
+                message: "msg".to_string()
+            })
+        );
+
+        assert_eq!(
+            parse_send_response("hi", &json!({"code": 0, "message": "f", "data": {}})),
+            Err(DanmakuSendError::Filtered)
+        );
+        assert!(matches!(
+            parse_send_response("hi", &json!({"unexpected": true})),
+            Err(DanmakuSendError::Request(LiveApiError::Http(_)))
+        ));
+    }
+
+    #[test]
+    fn test_split_message() {
+        assert_eq!(split_message("  hello  ", 20), vec!["hello"]);
+        assert!(split_message("", 20).is_empty());
+
+        // Hard cut when there is no boundary
+        let parts = split_message(&"啊".repeat(45), 20);
+        assert_eq!(
+            parts.iter().map(|p| p.chars().count()).collect::<Vec<_>>(),
+            vec![20, 20, 5]
+        );
+
+        // Punctuation stays with the part it ends
+        let parts = split_message("今天的直播真好看，主播唱歌也好听。下次还来", 10);
+        assert_eq!(
+            parts,
+            vec!["今天的直播真好看，", "主播唱歌也好听。", "下次还来"]
+        );
+
+        // Whitespace is dropped at the split
+        let parts = split_message("hello world this is long", 12);
+        assert_eq!(parts, vec!["hello world", "this is long"]);
+        assert!(parts.iter().all(|p| p.chars().count() <= 12));
+    }
+
+    #[test]
+    fn test_queue_reports_results() {
+        let queue = DanmakuQueue::start(
+            DanmakuSender::new(),
+            DanmakuQueueConfig {
+                interval: Duration::from_millis(1),
+                max_length: 5,
+                ..Default::default()
+            },
+        );
+        let (tx, rx) = std::sync::mpsc::channel();
+        queue.set_listener(move |message, result| {
+            tx.send((message.to_string(), result.clone())).unwrap();
+        });
+
+        // Without cookies every part fails before reaching the network
+        let context = EventContext::new(None, 1);
+        assert_eq!(queue.enqueue("abcdefghij", &context), 2);
+
+        let timeout = Duration::from_secs(5);
+        assert_eq!(
+            rx.recv_timeout(timeout).unwrap(),
+            (
+                "abcde".to_string(),
+                Err(DanmakuSendError::Request(LiveApiError::MissingCookies))
+            )
+        );
+        assert_eq!(
+            rx.recv_timeout(timeout).unwrap(),
+            (
+                "fghij".to_string(),
+                Err(DanmakuSendError::Request(LiveApiError::MissingCookies))
+            )
+        );
+        assert_eq!(queue.pending(), 0);
+    }
+
+    #[test]
+    fn test_send_without_credentials() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let sender = DanmakuSender::new();
+
+        let context = EventContext::new(None, 1);
+        assert_eq!(
+            runtime.block_on(sender.send("hi", &context)),
+            Err(DanmakuSendError::Request(LiveApiError::MissingCookies))
+        );
+
+        let context = EventContext::new(Some("SESSDATA=abc".to_string()), 1);
+        assert_eq!(
+            runtime.block_on(sender.send("hi", &context)),
+            Err(DanmakuSendError::Request(LiveApiError::MissingCsrf))
+        );
+    }
+}
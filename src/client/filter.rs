@@ -0,0 +1,370 @@
+// src/client/filter.rs
+//! Message filters for routing messages to handlers, in code or in the config file
+
+use crate::models::BiliMessage;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+
+/// Kind of a [`BiliMessage`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageKind {
+    Danmu,
+    Gift,
+    SuperChat,
+    OnlineRankCount,
+    Raw,
+}
+
+impl MessageKind {
+    /// Kind of a message; historical messages have the kind of the message they wrap
+    pub fn of(msg: &BiliMessage) -> Option<Self> {
+        match msg.inner() {
+            BiliMessage::Danmu { .. } => Some(Self::Danmu),
+            BiliMessage::Gift { .. } => Some(Self::Gift),
+            BiliMessage::SuperChat { .. } => Some(Self::SuperChat),
+            BiliMessage::OnlineRankCount { .. } => Some(Self::OnlineRankCount),
+            BiliMessage::Raw(_) => Some(Self::Raw),
+            _ => None,
+        }
+    }
+}
+
+/// Bilibili `cmd` a message came from, e.g. `DANMU_MSG`
+pub fn message_cmd(msg: &BiliMessage) -> Option<&str> {
+    match msg.inner() {
+        BiliMessage::Danmu { .. } => Some("DANMU_MSG"),
+        BiliMessage::Gift { .. } => Some("SEND_GIFT"),
+        BiliMessage::SuperChat { .. } => Some("SUPER_CHAT_MESSAGE"),
+        BiliMessage::OnlineRankCount { .. } => Some("ONLINE_RANK_COUNT"),
+        BiliMessage::Raw(json) => json["cmd"].as_str(),
+        _ => None,
+    }
+}
+
+type Predicate = Arc<dyn Fn(&BiliMessage) -> bool + Send + Sync>;
+
+/// Which messages a handler wants
+///
+/// Every condition that is set must hold; unset conditions (empty lists, `None`)
+/// accept everything, so the default filter accepts all messages. In the config file
+/// the same fields are written as a table, e.g. gifts worth at least 10 CNY:
+///
+/// ```toml
+/// [tts.filter]
+/// kinds = ["gift"]
+/// min_value = 10.0
+/// ```
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MessageFilter {
+    /// Message kinds to accept
+    pub kinds: Vec<MessageKind>,
+    /// Bilibili `cmd`s to accept, e.g. `GUARD_BUY`; a message matching either `kinds`
+    /// or `cmds` is accepted
+    pub cmds: Vec<String>,
+    /// Only messages from these senders
+    pub uids: Vec<u64>,
+    /// No messages from these senders
+    pub exclude_uids: Vec<u64>,
+    /// Only danmaku whose sender wears one of these fan medals
+    pub medals: Vec<String>,
+    /// Only danmaku whose sender's fan medal is at least this level
+    pub min_medal_level: Option<u32>,
+    /// Only gifts and super chats worth at least this much CNY
+    pub min_value: Option<f64>,
+    /// Only danmaku and super chats containing one of these keywords
+    pub keywords: Vec<String>,
+    /// Whether historical (backfilled) messages are accepted
+    pub history: Option<bool>,
+    /// At least one of these filters must match as well
+    pub any: Vec<MessageFilter>,
+    /// Extra condition, only available in code
+    #[serde(skip)]
+    predicate: Option<Predicate>,
+}
+
+impl fmt::Debug for MessageFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MessageFilter")
+            .field("kinds", &self.kinds)
+            .field("cmds", &self.cmds)
+            .field("uids", &self.uids)
+            .field("exclude_uids", &self.exclude_uids)
+            .field("medals", &self.medals)
+            .field("min_medal_level", &self.min_medal_level)
+            .field("min_value", &self.min_value)
+            .field("keywords", &self.keywords)
+            .field("history", &self.history)
+            .field("any", &self.any)
+            .field("predicate", &self.predicate.as_ref().map(|_| ".."))
+            .finish()
+    }
+}
+
+impl MessageFilter {
+    /// A filter accepting all messages
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept only these kinds of messages
+    pub fn with_kinds(mut self, kinds: impl IntoIterator<Item = MessageKind>) -> Self {
+        self.kinds = kinds.into_iter().collect();
+        self
+    }
+
+    /// Accept only these Bilibili `cmd`s (or the `kinds` set with [`Self::with_kinds`])
+    pub fn with_cmds<S: Into<String>>(mut self, cmds: impl IntoIterator<Item = S>) -> Self {
+        self.cmds = cmds.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Accept only messages from these senders
+    pub fn with_uids(mut self, uids: impl IntoIterator<Item = u64>) -> Self {
+        self.uids = uids.into_iter().collect();
+        self
+    }
+
+    /// Reject messages from these senders
+    pub fn with_exclude_uids(mut self, uids: impl IntoIterator<Item = u64>) -> Self {
+        self.exclude_uids = uids.into_iter().collect();
+        self
+    }
+
+    /// Accept only danmaku whose sender wears one of these fan medals
+    pub fn with_medals<S: Into<String>>(mut self, medals: impl IntoIterator<Item = S>) -> Self {
+        self.medals = medals.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Accept only danmaku whose sender's fan medal is at least `level`
+    pub fn with_min_medal_level(mut self, level: u32) -> Self {
+        self.min_medal_level = Some(level);
+        self
+    }
+
+    /// Accept only gifts and super chats worth at least `cny`
+    pub fn with_min_value(mut self, cny: f64) -> Self {
+        self.min_value = Some(cny);
+        self
+    }
+
+    /// Accept only danmaku and super chats containing one of `keywords`
+    pub fn with_keywords<S: Into<String>>(mut self, keywords: impl IntoIterator<Item = S>) -> Self {
+        self.keywords = keywords.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Accept or reject historical (backfilled) messages
+    pub fn with_history(mut self, history: bool) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    /// Additionally require one of `filters` to match
+    pub fn with_any(mut self, filters: impl IntoIterator<Item = MessageFilter>) -> Self {
+        self.any = filters.into_iter().collect();
+        self
+    }
+
+    /// Additionally require `predicate` to hold
+    pub fn with_predicate<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&BiliMessage) -> bool + Send + Sync + 'static,
+    {
+        self.predicate = Some(Arc::new(predicate));
+        self
+    }
+
+    /// Whether the filter accepts `msg`
+    pub fn matches(&self, msg: &BiliMessage) -> bool {
+        if self
+            .history
+            .is_some_and(|history| history != msg.is_historical())
+        {
+            return false;
+        }
+        let inner = msg.inner();
+
+        if !self.kinds.is_empty() || !self.cmds.is_empty() {
+            let kind_matches = MessageKind::of(msg).is_some_and(|kind| self.kinds.contains(&kind));
+            let cmd_matches =
+                message_cmd(msg).is_some_and(|cmd| self.cmds.iter().any(|c| c == cmd));
+            if !kind_matches && !cmd_matches {
+                return false;
+            }
+        }
+
+        let uid = sender_uid(inner);
+        if !self.uids.is_empty() && !uid.is_some_and(|uid| self.uids.contains(&uid)) {
+            return false;
+        }
+        if uid.is_some_and(|uid| self.exclude_uids.contains(&uid)) {
+            return false;
+        }
+
+        if !self.medals.is_empty() || self.min_medal_level.is_some() {
+            let BiliMessage::Danmu {
+                medal: Some(medal), ..
+            } = inner
+            else {
+                return false;
+            };
+            if !self.medals.is_empty() && !self.medals.contains(&medal.name) {
+                return false;
+            }
+            if self
+                .min_medal_level
+                .is_some_and(|level| medal.level < level)
+            {
+                return false;
+            }
+        }
+
+        if let Some(min_value) = self.min_value
+            && !value_cny(inner).is_some_and(|value| value >= min_value)
+        {
+            return false;
+        }
+
+        if !self.keywords.is_empty() {
+            let text = match inner {
+                BiliMessage::Danmu { text, .. } => text,
+                BiliMessage::SuperChat { message, .. } => message,
+                _ => return false,
+            };
+            if !self
+                .keywords
+                .iter()
+                .any(|keyword| text.contains(keyword.as_str()))
+            {
+                return false;
+            }
+        }
+
+        if !self.any.is_empty() && !self.any.iter().any(|filter| filter.matches(msg)) {
+            return false;
+        }
+
+        self.predicate
+            .as_ref()
+            .is_none_or(|predicate| predicate(msg))
+    }
+}
+
+/// Sender of a message, when it has one
+fn sender_uid(msg: &BiliMessage) -> Option<u64> {
+    match msg {
+        BiliMessage::Danmu { uid, .. }
+        | BiliMessage::Gift { uid, .. }
+        | BiliMessage::SuperChat { uid, .. } => Some(*uid),
+        _ => None,
+    }
+}
+
+/// What a gift or super chat was worth in CNY
+pub(crate) fn value_cny(msg: &BiliMessage) -> Option<f64> {
+    match msg {
+        BiliMessage::Gift { value, num, .. } => value.map(|value| value.paid_cny(*num)),
+        BiliMessage::SuperChat { price, .. } => Some(*price as f64),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CoinType, FanMedal, GiftValue};
+
+    fn danmu(uid: u64, text: &str, medal: Option<(&str, u32)>) -> BiliMessage {
+        BiliMessage::Danmu {
+            user: "user".to_string(),
+            uid,
+            text: text.to_string(),
+            segments: Vec::new(),
+            medal: medal.map(|(name, level)| FanMedal {
+                name: name.to_string(),
+                level,
+                room_id: 1,
+            }),
+        }
+    }
+
+    fn gift(price: u64, num: u64) -> BiliMessage {
+        BiliMessage::Gift {
+            user: "user".to_string(),
+            uid: 7,
+            gift: "gift".to_string(),
+            gift_id: 1,
+            num,
+            value: Some(GiftValue {
+                price,
+                coin_type: CoinType::Gold,
+                blind_box_price: None,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_filter_conditions() {
+        let raw = BiliMessage::Raw(serde_json::json!({"cmd": "GUARD_BUY"}));
+        assert!(MessageFilter::new().matches(&raw));
+
+        let kinds = MessageFilter::new()
+            .with_kinds([MessageKind::Danmu])
+            .with_cmds(["GUARD_BUY"]);
+        assert!(kinds.matches(&danmu(1, "hi", None)));
+        assert!(kinds.matches(&raw));
+        assert!(!kinds.matches(&gift(1000, 1)));
+
+        let uids = MessageFilter::new().with_uids([1]).with_exclude_uids([2]);
+        assert!(uids.matches(&danmu(1, "hi", None)));
+        assert!(!uids.matches(&danmu(2, "hi", None)));
+        assert!(!uids.matches(&raw));
+
+        let medal = MessageFilter::new()
+            .with_medals(["粉丝团"])
+            .with_min_medal_level(10);
+        assert!(medal.matches(&danmu(1, "hi", Some(("粉丝团", 12)))));
+        assert!(!medal.matches(&danmu(1, "hi", Some(("粉丝团", 3)))));
+        assert!(!medal.matches(&danmu(1, "hi", Some(("other", 20)))));
+        assert!(!medal.matches(&danmu(1, "hi", None)));
+
+        let value = MessageFilter::new().with_min_value(10.0);
+        assert!(value.matches(&gift(1000, 10)));
+        assert!(!value.matches(&gift(1000, 9)));
+        assert!(!value.matches(&danmu(1, "hi", None)));
+
+        let keywords = MessageFilter::new().with_keywords(["点歌"]);
+        assert!(keywords.matches(&danmu(1, "我要点歌", None)));
+        assert!(!keywords.matches(&danmu(1, "hello", None)));
+
+        let live_only = MessageFilter::new().with_history(false);
+        assert!(live_only.matches(&danmu(1, "hi", None)));
+        assert!(!live_only.matches(&danmu(1, "hi", None).historical()));
+
+        let predicate = MessageFilter::new().with_predicate(|msg| !msg.is_historical());
+        assert!(!predicate.matches(&danmu(1, "hi", None).historical()));
+    }
+
+    #[test]
+    fn test_filter_from_config() {
+        let filter: MessageFilter = toml::from_str(
+            r#"
+            any = [
+                { kinds = ["danmu"] },
+                { kinds = ["gift", "super_chat"], min_value = 10.0 },
+            ]
+            exclude_uids = [42]
+            "#,
+        )
+        .unwrap();
+        assert!(filter.matches(&danmu(1, "hi", None)));
+        assert!(!filter.matches(&danmu(42, "hi", None)));
+        assert!(filter.matches(&gift(1000, 10)));
+        assert!(!filter.matches(&gift(100, 1)));
+        assert!(!filter.matches(&BiliMessage::Raw(serde_json::json!({"cmd": "X"}))));
+    }
+}
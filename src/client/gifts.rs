@@ -0,0 +1,377 @@
+// src/client/gifts.rs
+//! Room gift catalog: names, prices, coin types and blind-box contents, cached on disk
+
+use crate::auth::get_json;
+use crate::models::{BiliMessage, CoinType, GiftValue};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const GIFT_CONFIG_URL: &str =
+    "https://api.live.bilibili.com/xlive/web-room/v1/giftPanel/giftConfig";
+pub const BLIND_BOX_URL: &str =
+    "https://api.live.bilibili.com/xlive/general-interface/v1/blindFirstWin/getInfo";
+
+/// How long a cached catalog is used before it is fetched again
+pub const DEFAULT_CACHE_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A gift that can come out of a blind box
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BlindBoxItem {
+    pub gift_id: u64,
+    pub name: String,
+    /// Price in gold coins
+    pub price: u64,
+    /// Chance as shown by Bilibili, e.g. "12.5%"
+    pub chance: Option<String>,
+}
+
+/// A gift from the room's gift config
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GiftInfo {
+    pub id: u64,
+    pub name: String,
+    /// Price of one gift in coins
+    pub price: u64,
+    pub coin_type: CoinType,
+    pub icon_url: Option<String>,
+    /// Possible contents, for blind boxes
+    #[serde(default)]
+    pub blind_box: Vec<BlindBoxItem>,
+}
+
+impl GiftInfo {
+    /// Price of one gift in CNY; free gifts are worth nothing
+    pub fn price_cny(&self) -> f64 {
+        self.value().value_cny(1)
+    }
+
+    pub fn is_paid(&self) -> bool {
+        self.value().is_paid()
+    }
+
+    fn value(&self) -> GiftValue {
+        GiftValue {
+            price: self.price,
+            coin_type: self.coin_type,
+            blind_box_price: None,
+        }
+    }
+}
+
+/// Gift catalog of a room
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct GiftCatalog {
+    pub room_id: u64,
+    /// Unix timestamp of when the catalog was fetched
+    pub fetched_at: i64,
+    pub gifts: HashMap<u64, GiftInfo>,
+}
+
+impl GiftCatalog {
+    /// Load the catalog from the cache, fetching it when missing or older than `max_age`
+    ///
+    /// A stale cache is still used when fetching fails.
+    pub fn load(room_id: u64, max_age: Duration) -> Result<Self, String> {
+        let cache_path = default_cache_path(room_id);
+        let cached = cache_path
+            .as_deref()
+            .and_then(|path| Self::read_cache(path).ok());
+
+        if let Some(catalog) = &cached
+            && catalog.age() < max_age
+        {
+            debug!("Using cached gift catalog for room {}", room_id);
+            return Ok(catalog.clone());
+        }
+
+        match Self::fetch(room_id) {
+            Ok(catalog) => {
+                if let Some(path) = &cache_path
+                    && let Err(e) = catalog.write_cache(path)
+                {
+                    warn!("Failed to cache gift catalog: {}", e);
+                }
+                Ok(catalog)
+            }
+            Err(e) => match cached {
+                Some(catalog) => {
+                    warn!("{}; using cached gift catalog", e);
+                    Ok(catalog)
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    /// Fetch the room's gift config and the contents of its blind boxes
+    pub fn fetch(room_id: u64) -> Result<Self, String> {
+        let body = get_json(
+            GIFT_CONFIG_URL,
+            &[
+                ("platform", "pc".to_string()),
+                ("room_id", room_id.to_string()),
+            ],
+            &[],
+            "Gift catalog",
+        )?;
+        let mut gifts = parse_gift_config(&body);
+
+        for gift in gifts.values_mut().filter(|g| is_blind_box(&g.name)) {
+            let query = [("gift_id", gift.id.to_string())];
+            match get_json(BLIND_BOX_URL, &query, &[], "Blind box") {
+                Ok(body) => gift.blind_box = parse_blind_box(&body),
+                Err(e) => warn!("Failed to load contents of {}: {}", gift.name, e),
+            }
+        }
+
+        info!("Loaded {} gifts for room {}", gifts.len(), room_id);
+        Ok(Self {
+            room_id,
+            fetched_at: chrono::Utc::now().timestamp(),
+            gifts,
+        })
+    }
+
+    pub fn get(&self, gift_id: u64) -> Option<&GiftInfo> {
+        self.gifts.get(&gift_id)
+    }
+
+    pub fn find_by_name(&self, name: &str) -> Option<&GiftInfo> {
+        self.gifts.values().find(|g| g.name == name)
+    }
+
+    /// Fill in the value of a gift event the catalog knows about
+    pub fn enrich(&self, msg: BiliMessage) -> BiliMessage {
+        match msg {
+            BiliMessage::Gift {
+                user,
+                uid,
+                gift,
+                gift_id,
+                num,
+                value,
+            } => {
+                let info = self.get(gift_id).or_else(|| self.find_by_name(&gift));
+                let value = match (value, info) {
+                    (Some(value), _) if value.price > 0 => Some(value),
+                    (value, Some(info)) => Some(GiftValue {
+                        blind_box_price: value.and_then(|v| v.blind_box_price),
+                        ..info.value()
+                    }),
+                    (value, None) => value,
+                };
+                BiliMessage::Gift {
+                    user,
+                    uid,
+                    gift,
+                    gift_id: info.map_or(gift_id, |i| i.id),
+                    num,
+                    value,
+                }
+            }
+            BiliMessage::Historical(inner) => self.enrich(*inner).historical(),
+            msg => msg,
+        }
+    }
+
+    fn age(&self) -> Duration {
+        let age = chrono::Utc::now().timestamp() - self.fetched_at;
+        Duration::from_secs(age.max(0) as u64)
+    }
+
+    fn read_cache(path: &Path) -> Result<Self, String> {
+        let content =
+            fs::read_to_string(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+        serde_json::from_str(&content).map_err(|e| format!("Invalid gift cache {:?}: {}", path, e))
+    }
+
+    fn write_cache(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {:?}: {}", parent, e))?;
+        }
+        let content = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        fs::write(path, content).map_err(|e| format!("Failed to write {:?}: {}", path, e))
+    }
+}
+
+/// Cache file for a room's catalog, e.g. `~/.cache/blivedm_rs/gifts_12345.json`
+pub fn default_cache_path(room_id: u64) -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| {
+        dir.join("blivedm_rs")
+            .join(format!("gifts_{}.json", room_id))
+    })
+}
+
+fn is_blind_box(name: &str) -> bool {
+    name.contains("盲盒")
+}
+
+/// Parse a `giftPanel/giftConfig` response
+pub fn parse_gift_config(body: &Value) -> HashMap<u64, GiftInfo> {
+    body["data"]["list"]
+        .as_array()
+        .map(|list| {
+            list.iter()
+                .filter_map(|entry| {
+                    let id = entry["id"].as_u64()?;
+                    let coin_type = CoinType::parse(entry["coin_type"].as_str()?)?;
+                    let icon_url = entry["img_basic"]
+                        .as_str()
+                        .filter(|url| !url.is_empty())
+                        .map(str::to_string);
+                    Some((
+                        id,
+                        GiftInfo {
+                            id,
+                            name: entry["name"].as_str().unwrap_or("").to_string(),
+                            price: entry["price"].as_u64().unwrap_or(0),
+                            coin_type,
+                            icon_url,
+                            blind_box: Vec::new(),
+                        },
+                    ))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Parse a `blindFirstWin/getInfo` response
+pub fn parse_blind_box(body: &Value) -> Vec<BlindBoxItem> {
+    body["data"]["gifts"]
+        .as_array()
+        .map(|list| {
+            list.iter()
+                .map(|entry| BlindBoxItem {
+                    gift_id: entry["gift_id"].as_u64().unwrap_or(0),
+                    name: entry["gift_name"].as_str().unwrap_or("").to_string(),
+                    price: entry["price"].as_u64().unwrap_or(0),
+                    chance: entry["chance"].as_str().map(str::to_string),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn catalog() -> GiftCatalog {
+        let body = json!({
+            "code": 0,
+            "data": {"list": [
+                {"id": 31036, "name": "小花花", "price": 100, "coin_type": "gold", "img_basic": "https://i0.hdslb.com/flower.png"},
+                {"id": 1, "name": "辣条", "price": 100, "coin_type": "silver", "img_basic": ""},
+                {"id": 32251, "name": "心动盲盒", "price": 15000, "coin_type": "gold"},
+                {"name": "no id", "price": 1, "coin_type": "gold"}
+            ]}
+        });
+        GiftCatalog {
+            room_id: 1,
+            fetched_at: 0,
+            gifts: parse_gift_config(&body),
+        }
+    }
+
+    fn gift(gift_id: u64, name: &str, value: Option<GiftValue>) -> BiliMessage {
+        BiliMessage::Gift {
+            user: "user".to_string(),
+            uid: 1,
+            gift: name.to_string(),
+            gift_id,
+            num: 2,
+            value,
+        }
+    }
+
+    #[test]
+    fn test_parse_gift_config() {
+        let catalog = catalog();
+        assert_eq!(catalog.gifts.len(), 3);
+
+        let flower = catalog.get(31036).unwrap();
+        assert!(flower.is_paid());
+        assert_eq!(flower.price_cny(), 0.1);
+        assert_eq!(
+            flower.icon_url.as_deref(),
+            Some("https://i0.hdslb.com/flower.png")
+        );
+
+        let latiao = catalog.get(1).unwrap();
+        assert!(!latiao.is_paid());
+        assert_eq!(latiao.icon_url, None);
+    }
+
+    #[test]
+    fn test_parse_blind_box() {
+        let body = json!({"code": 0, "data": {"gifts": [
+            {"gift_id": 32124, "gift_name": "浪漫城堡", "price": 2000000, "chance": "0.06%"}
+        ]}});
+        assert_eq!(
+            parse_blind_box(&body),
+            vec![BlindBoxItem {
+                gift_id: 32124,
+                name: "浪漫城堡".to_string(),
+                price: 2000000,
+                chance: Some("0.06%".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_enrich_gift() {
+        let catalog = catalog();
+
+        // Missing value is filled from the catalog, by id or by name
+        let enriched = catalog.enrich(gift(31036, "小花花", None));
+        let expected_value = Some(GiftValue {
+            price: 100,
+            coin_type: CoinType::Gold,
+            blind_box_price: None,
+        });
+        assert_eq!(enriched, gift(31036, "小花花", expected_value));
+        assert_eq!(
+            catalog.enrich(gift(0, "小花花", None)),
+            gift(31036, "小花花", expected_value)
+        );
+
+        // Values from the event win, unknown gifts are unchanged
+        let event_value = Some(GiftValue {
+            price: 5000,
+            coin_type: CoinType::Gold,
+            blind_box_price: Some(15000),
+        });
+        assert_eq!(
+            catalog.enrich(gift(31036, "小花花", event_value)),
+            gift(31036, "小花花", event_value)
+        );
+        assert_eq!(
+            catalog.enrich(gift(9, "unknown", None)),
+            gift(9, "unknown", None)
+        );
+
+        assert!(
+            catalog
+                .enrich(gift(1, "辣条", None).historical())
+                .is_historical()
+        );
+    }
+
+    #[test]
+    fn test_cache_round_trip() {
+        let dir = std::env::temp_dir().join(format!("blivedm_gifts_test_{}", std::process::id()));
+        let path = dir.join("gifts.json");
+        let catalog = catalog();
+        catalog.write_cache(&path).unwrap();
+        assert_eq!(GiftCatalog::read_cache(&path).unwrap(), catalog);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
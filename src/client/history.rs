@@ -0,0 +1,158 @@
+// src/client/history.rs
+//! Recent room history (danmaku and super chats) for backfilling on connect
+
+use crate::auth::get_json;
+use crate::models::{BiliMessage, FanMedal, parse_danmu_segments};
+use log::info;
+use serde_json::Value;
+
+pub const HISTORY_URL: &str = "https://api.live.bilibili.com/xlive/web-room/v1/dM/gethistory";
+pub const SUPER_CHAT_LIST_URL: &str =
+    "https://api.live.bilibili.com/av/v1/SuperChat/getMessageList";
+
+/// Fetch the room's recent messages, oldest first, marked as historical
+///
+/// Super chats still on display come first, followed by the recent danmaku from
+/// the admin and room lists.
+pub fn fetch_history(
+    room_id: u64,
+    cookies: Option<&str>,
+    include_super_chats: bool,
+) -> Result<Vec<BiliMessage>, String> {
+    let mut headers = vec![("Referer", format!("https://live.bilibili.com/{}", room_id))];
+    if let Some(cookies) = cookies {
+        headers.push(("Cookie", cookies.to_string()));
+    }
+
+    let mut messages = Vec::new();
+    if include_super_chats {
+        let query = [("room_id", room_id.to_string())];
+        let body = get_json(SUPER_CHAT_LIST_URL, &query, &headers, "History")?;
+        messages.extend(parse_super_chats(&body));
+    }
+    let query = [("roomid", room_id.to_string())];
+    let body = get_json(HISTORY_URL, &query, &headers, "History")?;
+    messages.extend(parse_history(&body));
+
+    info!(
+        "Loaded {} historical messages for room {}",
+        messages.len(),
+        room_id
+    );
+    Ok(messages.into_iter().map(BiliMessage::historical).collect())
+}
+
+/// Parse a `dM/gethistory` response into danmaku, oldest first
+pub fn parse_history(body: &Value) -> Vec<BiliMessage> {
+    let mut entries: Vec<&Value> = ["admin", "room"]
+        .iter()
+        .filter_map(|list| body["data"][*list].as_array())
+        .flatten()
+        .collect();
+    // Both lists are in chronological order; merge them by timestamp
+    entries.sort_by_key(|entry| entry["timeline"].as_str().unwrap_or(""));
+    entries.dedup_by(|a, b| {
+        a["uid"] == b["uid"] && a["text"] == b["text"] && a["timeline"] == b["timeline"]
+    });
+
+    entries
+        .into_iter()
+        .map(|entry| {
+            let text = entry["text"].as_str().unwrap_or("");
+            BiliMessage::Danmu {
+                user: entry["nickname"]
+                    .as_str()
+                    .unwrap_or("<unknown>")
+                    .to_string(),
+                uid: entry["uid"].as_u64().unwrap_or(0),
+                text: text.to_string(),
+                segments: parse_danmu_segments(text, &entry["emoticon"], &entry["emots"]),
+                medal: FanMedal::parse(&entry["medal"]),
+            }
+        })
+        .collect()
+}
+
+/// Parse a super chat list response, oldest first
+pub fn parse_super_chats(body: &Value) -> Vec<BiliMessage> {
+    let mut entries: Vec<&Value> = body["data"]["list"]
+        .as_array()
+        .map(|list| list.iter().collect())
+        .unwrap_or_default();
+    entries.sort_by_key(|entry| entry["start_time"].as_u64().unwrap_or(0));
+
+    entries
+        .into_iter()
+        .map(|entry| BiliMessage::SuperChat {
+            user: entry["user_info"]["uname"]
+                .as_str()
+                .unwrap_or("<unknown>")
+                .to_string(),
+            uid: entry["uid"].as_u64().unwrap_or(0),
+            message: entry["message"].as_str().unwrap_or("").to_string(),
+            price: entry["price"].as_u64().unwrap_or(0),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_history_merges_lists() {
+        let body = json!({
+            "code": 0,
+            "data": {
+                "admin": [
+                    {"text": "admin says", "uid": 1, "nickname": "admin", "timeline": "2026-10-18 12:00:02"}
+                ],
+                "room": [
+                    {"text": "first", "uid": 2, "nickname": "alice", "timeline": "2026-10-18 12:00:01"},
+                    {"text": "admin says", "uid": 1, "nickname": "admin", "timeline": "2026-10-18 12:00:02"},
+                    {"text": "last", "uid": 3, "nickname": "bob", "timeline": "2026-10-18 12:00:03"}
+                ]
+            }
+        });
+        let texts: Vec<String> = parse_history(&body)
+            .into_iter()
+            .map(|msg| match msg {
+                BiliMessage::Danmu { text, .. } => text,
+                other => panic!("unexpected message {:?}", other),
+            })
+            .collect();
+        assert_eq!(texts, vec!["first", "admin says", "last"]);
+        assert!(parse_history(&json!({"code": 0, "data": {}})).is_empty());
+    }
+
+    #[test]
+    fn test_parse_super_chats() {
+        let body = json!({
+            "code": 0,
+            "data": {
+                "list": [
+                    {"uid": 9, "price": 50, "message": "newer", "start_time": 200, "user_info": {"uname": "b"}},
+                    {"uid": 8, "price": 30, "message": "older", "start_time": 100, "user_info": {"uname": "a"}}
+                ]
+            }
+        });
+        assert_eq!(
+            parse_super_chats(&body),
+            vec![
+                BiliMessage::SuperChat {
+                    user: "a".to_string(),
+                    uid: 8,
+                    message: "older".to_string(),
+                    price: 30,
+                },
+                BiliMessage::SuperChat {
+                    user: "b".to_string(),
+                    uid: 9,
+                    message: "newer".to_string(),
+                    price: 50,
+                },
+            ]
+        );
+    }
+}
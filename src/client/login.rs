@@ -0,0 +1,256 @@
+// src/client/login.rs
+//! QR-code login via the Bilibili passport API
+
+use crate::auth::USER_AGENT;
+use crate::credentials::Credentials;
+use qrcode::QrCode;
+use qrcode::render::unicode;
+use serde::Deserialize;
+use serde_json::Value;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const QRCODE_GENERATE_URL: &str =
+    "https://passport.bilibili.com/x/passport-login/web/qrcode/generate";
+pub const QRCODE_POLL_URL: &str = "https://passport.bilibili.com/x/passport-login/web/qrcode/poll";
+
+/// How often the poll endpoint is queried while waiting for confirmation
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// QR codes issued by the passport API expire after 180 seconds
+const QRCODE_LIFETIME: Duration = Duration::from_secs(180);
+/// Query parameters of the confirmation URL that are actual cookies
+const URL_COOKIE_NAMES: [&str; 5] = [
+    "SESSDATA",
+    "bili_jct",
+    "DedeUserID",
+    "DedeUserID__ckMd5",
+    "sid",
+];
+
+/// A QR code login session returned by the generate endpoint
+#[derive(Debug, Clone, Deserialize)]
+pub struct QrCodeSession {
+    /// URL encoded into the QR code, opened by the mobile app
+    pub url: String,
+    /// Key used to poll the login status
+    pub qrcode_key: String,
+}
+
+#[derive(Deserialize)]
+struct GenerateResponse {
+    code: i64,
+    #[serde(default)]
+    message: String,
+    data: Option<QrCodeSession>,
+}
+
+/// Status of a QR code login session
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QrLoginStatus {
+    /// The QR code has not been scanned yet (86101)
+    WaitingForScan,
+    /// The QR code was scanned but the login is not confirmed yet (86090)
+    WaitingForConfirm,
+    /// The QR code expired (86038)
+    Expired,
+    /// Login confirmed
+    Confirmed(Credentials),
+}
+
+/// Request a new QR code login session
+pub fn generate_qrcode() -> Result<QrCodeSession, String> {
+    let client = passport_client()?;
+    let response: GenerateResponse = client
+        .get(QRCODE_GENERATE_URL)
+        .send()
+        .and_then(|r| r.json())
+        .map_err(|e| format!("Failed to request QR code: {}", e))?;
+
+    if response.code != 0 {
+        return Err(format!(
+            "QR code generation failed: {} ({})",
+            response.message, response.code
+        ));
+    }
+    response
+        .data
+        .ok_or_else(|| "QR code generation returned no data".to_string())
+}
+
+/// Poll the login status of a QR code session once
+pub fn poll_qrcode(qrcode_key: &str) -> Result<QrLoginStatus, String> {
+    let client = passport_client()?;
+    let response = client
+        .get(QRCODE_POLL_URL)
+        .query(&[("qrcode_key", qrcode_key)])
+        .send()
+        .map_err(|e| format!("Failed to poll QR code status: {}", e))?;
+
+    let cookies: Vec<(String, String)> = response
+        .cookies()
+        .map(|c| (c.name().to_string(), c.value().to_string()))
+        .collect();
+    let body: Value = response
+        .json()
+        .map_err(|e| format!("Failed to parse QR code status: {}", e))?;
+
+    parse_poll_response(&body, cookies)
+}
+
+/// Interpret a poll response body together with the cookies set by it
+fn parse_poll_response(
+    body: &Value,
+    mut cookies: Vec<(String, String)>,
+) -> Result<QrLoginStatus, String> {
+    if body["code"].as_i64() != Some(0) {
+        return Err(format!(
+            "QR code poll failed: {} ({})",
+            body["message"].as_str().unwrap_or(""),
+            body["code"]
+        ));
+    }
+
+    let data = &body["data"];
+    match data["code"].as_i64() {
+        Some(0) => {
+            // Cookies are normally delivered via Set-Cookie, but the redirect URL carries them too
+            if let Some(url) = data["url"].as_str()
+                && let Ok(parsed) = url::Url::parse(url)
+            {
+                for (name, value) in parsed.query_pairs() {
+                    if URL_COOKIE_NAMES.contains(&name.as_ref())
+                        && !cookies.iter().any(|(n, _)| *n == name)
+                    {
+                        cookies.push((name.to_string(), value.to_string()));
+                    }
+                }
+            }
+            let refresh_token = data["refresh_token"].as_str().unwrap_or("");
+            Credentials::from_cookie_pairs(cookies, refresh_token)
+                .map(QrLoginStatus::Confirmed)
+                .ok_or_else(|| "Login confirmed but no SESSDATA was returned".to_string())
+        }
+        Some(86101) => Ok(QrLoginStatus::WaitingForScan),
+        Some(86090) => Ok(QrLoginStatus::WaitingForConfirm),
+        Some(86038) => Ok(QrLoginStatus::Expired),
+        _ => Err(format!(
+            "Unexpected QR code status: {} ({})",
+            data["message"].as_str().unwrap_or(""),
+            data["code"]
+        )),
+    }
+}
+
+/// Render a string as a QR code using Unicode half blocks for terminal display
+pub fn render_qrcode(content: &str) -> Result<String, String> {
+    let code =
+        QrCode::new(content.as_bytes()).map_err(|e| format!("Failed to encode QR code: {}", e))?;
+    Ok(code
+        .render::<unicode::Dense1x2>()
+        .dark_color(unicode::Dense1x2::Light)
+        .light_color(unicode::Dense1x2::Dark)
+        .quiet_zone(true)
+        .build())
+}
+
+/// Run the full QR code login flow
+///
+/// `on_qrcode` is called with the rendered QR code and the login URL, `on_status`
+/// with every status change. Blocks until the login is confirmed or the QR code expires.
+pub fn login_with_qrcode<Q, S>(mut on_qrcode: Q, mut on_status: S) -> Result<Credentials, String>
+where
+    Q: FnMut(&str, &str),
+    S: FnMut(&QrLoginStatus),
+{
+    let session = generate_qrcode()?;
+    let rendered = render_qrcode(&session.url)?;
+    on_qrcode(&rendered, &session.url);
+
+    let started = Instant::now();
+    let mut last_status = None;
+    loop {
+        let status = poll_qrcode(&session.qrcode_key)?;
+        if last_status.as_ref() != Some(&status) {
+            on_status(&status);
+        }
+
+        match status {
+            QrLoginStatus::Confirmed(credentials) => return Ok(credentials),
+            QrLoginStatus::Expired => return Err("QR code expired before login".to_string()),
+            _ => {}
+        }
+
+        if started.elapsed() > QRCODE_LIFETIME {
+            return Err("Timed out waiting for QR code login".to_string());
+        }
+        last_status = Some(status);
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn passport_client() -> Result<reqwest::blocking::Client, String> {
+    reqwest::blocking::Client::builder()
+        .https_only(true)
+        .user_agent(USER_AGENT)
+        .timeout(Duration::from_secs(10))
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_poll_waiting_states() {
+        let body = json!({"code": 0, "data": {"code": 86101, "message": "未扫码"}});
+        assert_eq!(
+            parse_poll_response(&body, vec![]).unwrap(),
+            QrLoginStatus::WaitingForScan
+        );
+
+        let body = json!({"code": 0, "data": {"code": 86090, "message": "二维码已扫码未确认"}});
+        assert_eq!(
+            parse_poll_response(&body, vec![]).unwrap(),
+            QrLoginStatus::WaitingForConfirm
+        );
+
+        let body = json!({"code": 0, "data": {"code": 86038, "message": "二维码已失效"}});
+        assert_eq!(
+            parse_poll_response(&body, vec![]).unwrap(),
+            QrLoginStatus::Expired
+        );
+    }
+
+    #[test]
+    fn test_parse_poll_confirmed_from_url() {
+        let body = json!({
+            "code": 0,
+            "data": {
+                "code": 0,
+                "url": "https://passport.biligame.com/x/passport-login/web/crossDomain?DedeUserID=12345&DedeUserID__ckMd5=abc&Expires=1&SESSDATA=sess%2Cdata&bili_jct=csrf&gourl=https%3A%2F%2Fwww.bilibili.com",
+                "refresh_token": "refresh",
+                "timestamp": 1700000000000u64
+            }
+        });
+        let status = parse_poll_response(&body, vec![]).unwrap();
+        match status {
+            QrLoginStatus::Confirmed(credentials) => {
+                assert_eq!(credentials.sessdata, "sess,data");
+                assert_eq!(credentials.bili_jct, "csrf");
+                assert_eq!(credentials.dede_user_id, "12345");
+                assert_eq!(credentials.refresh_token, "refresh");
+            }
+            other => panic!("unexpected status: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_render_qrcode() {
+        let rendered =
+            render_qrcode("https://account.bilibili.com/h5/account-h5/auth/scan-web").unwrap();
+        assert!(rendered.lines().count() > 10);
+        assert!(rendered.contains('█') || rendered.contains('▀') || rendered.contains('▄'));
+    }
+}
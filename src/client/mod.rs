@@ -1,11 +1,20 @@
 // src/client/lib.rs
 //! Library entry for the client package
 
+pub mod accounts;
 pub mod auth;
 pub mod browser_cookies;
+pub mod cookie_refresh;
+pub mod credentials;
+pub mod danmaku;
+pub mod filter;
+pub mod gifts;
+pub mod history;
+pub mod login;
 pub mod models;
+pub mod moderation;
 pub mod scheduler;
 pub mod websocket;
 
 // Re-export commonly used functions
-pub use auth::get_cookies_or_browser;
+pub use auth::{get_cookies_or_browser, get_cookies_or_browser_in};
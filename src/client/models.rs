@@ -55,15 +55,227 @@ impl AuthMessage {
     }
 }
 
+/// Currency a gift is bought with
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum CoinType {
+    /// Paid with real money (1000 gold coins = 1 CNY)
+    Gold,
+    /// Free gifts bought with silver coins
+    Silver,
+}
+
+impl CoinType {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "gold" => Some(CoinType::Gold),
+            "silver" => Some(CoinType::Silver),
+            _ => None,
+        }
+    }
+}
+
+/// Price of a single gift
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GiftValue {
+    /// Price in coins
+    pub price: u64,
+    pub coin_type: CoinType,
+    /// For a gift that came out of a blind box, the price of the box
+    pub blind_box_price: Option<u64>,
+}
+
+impl GiftValue {
+    /// Whether the gift was paid for with real money
+    pub fn is_paid(&self) -> bool {
+        self.coin_type == CoinType::Gold && self.price > 0
+    }
+
+    /// Worth of `num` gifts in CNY; free gifts are worth nothing
+    pub fn value_cny(&self, num: u64) -> f64 {
+        match self.coin_type {
+            CoinType::Gold => (self.price * num) as f64 / 1000.0,
+            CoinType::Silver => 0.0,
+        }
+    }
+
+    /// What the sender spent on `num` gifts in CNY (the box price for blind boxes)
+    pub fn paid_cny(&self, num: u64) -> f64 {
+        match self.coin_type {
+            CoinType::Gold => (self.blind_box_price.unwrap_or(self.price) * num) as f64 / 1000.0,
+            CoinType::Silver => 0.0,
+        }
+    }
+}
+
+/// An emoticon in a danmaku: a sticker or an inline emote such as `[dog]`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Emote {
+    /// Name without brackets, e.g. `dog`
+    pub name: String,
+    /// Description suitable for reading aloud
+    pub description: String,
+    /// Emoticon unique id, e.g. `emoji_208`
+    pub unique_id: Option<String>,
+    pub url: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Emote {
+    /// Badge shown in place of the emote, e.g. `[dog]`
+    pub fn badge(&self) -> String {
+        format!("[{}]", self.name)
+    }
+}
+
+/// Part of a danmaku's text
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum DanmuSegment {
+    Text(String),
+    Emote(Emote),
+}
+
+/// Split danmaku text into text and emote segments
+///
+/// `sticker` is the emoticon object of a sticker danmaku, `emots` the map of inline
+/// emote tokens (`{"[dog]": {...}}`). Plain text danmaku have no segments.
+pub fn parse_danmu_segments(
+    text: &str,
+    sticker: &serde_json::Value,
+    emots: &serde_json::Value,
+) -> Vec<DanmuSegment> {
+    if let Some(url) = sticker["url"].as_str().filter(|url| !url.is_empty()) {
+        return vec![DanmuSegment::Emote(Emote {
+            name: text.to_string(),
+            description: text.to_string(),
+            unique_id: sticker["emoticon_unique"].as_str().map(str::to_string),
+            url: url.to_string(),
+            width: sticker["width"].as_u64().unwrap_or(0) as u32,
+            height: sticker["height"].as_u64().unwrap_or(0) as u32,
+        })];
+    }
+
+    let Some(emots) = emots.as_object().filter(|emots| !emots.is_empty()) else {
+        return Vec::new();
+    };
+
+    let mut segments = Vec::new();
+    let mut plain = String::new();
+    let mut rest = text;
+    while let Some(ch) = rest.chars().next() {
+        let token = emots
+            .iter()
+            .filter(|(token, _)| !token.is_empty() && rest.starts_with(token.as_str()))
+            .max_by_key(|(token, _)| token.len());
+        match token {
+            Some((token, emote)) => {
+                if !plain.is_empty() {
+                    segments.push(DanmuSegment::Text(std::mem::take(&mut plain)));
+                }
+                segments.push(DanmuSegment::Emote(parse_inline_emote(token, emote)));
+                rest = &rest[token.len()..];
+            }
+            None => {
+                plain.push(ch);
+                rest = &rest[ch.len_utf8()..];
+            }
+        }
+    }
+    if !plain.is_empty() {
+        segments.push(DanmuSegment::Text(plain));
+    }
+    segments
+}
+
+fn parse_inline_emote(token: &str, emote: &serde_json::Value) -> Emote {
+    let strip = |s: &str| s.trim_start_matches('[').trim_end_matches(']').to_string();
+    let name = strip(token);
+    Emote {
+        description: emote["descript"]
+            .as_str()
+            .map(strip)
+            .filter(|d| !d.is_empty())
+            .unwrap_or_else(|| name.clone()),
+        name,
+        unique_id: emote["emoticon_unique"].as_str().map(str::to_string),
+        url: emote["url"].as_str().unwrap_or("").to_string(),
+        width: emote["width"].as_u64().unwrap_or(0) as u32,
+        height: emote["height"].as_u64().unwrap_or(0) as u32,
+    }
+}
+
+/// Render a danmaku, replacing each emote with `render(emote)` or dropping it on `None`
+///
+/// Falls back to `text` for plain danmaku.
+pub fn render_danmu(
+    text: &str,
+    segments: &[DanmuSegment],
+    render: impl Fn(&Emote) -> Option<String>,
+) -> String {
+    if segments.is_empty() {
+        return text.to_string();
+    }
+    segments
+        .iter()
+        .filter_map(|segment| match segment {
+            DanmuSegment::Text(text) => Some(text.clone()),
+            DanmuSegment::Emote(emote) => render(emote),
+        })
+        .collect()
+}
+
+/// Fan medal worn by a sender
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FanMedal {
+    pub name: String,
+    pub level: u32,
+    /// Room the medal belongs to
+    pub room_id: u64,
+}
+
+impl FanMedal {
+    /// Parse the `[level, name, anchor, room_id, ...]` medal array of a danmaku
+    pub fn parse(medal: &serde_json::Value) -> Option<Self> {
+        let name = medal[1].as_str().filter(|name| !name.is_empty())?;
+        Some(Self {
+            name: name.to_string(),
+            level: medal[0].as_u64().unwrap_or(0) as u32,
+            room_id: medal[3].as_u64().unwrap_or(0),
+        })
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum BiliMessage {
     Danmu {
         user: String,
+        /// Sender's uid, 0 when hidden or unknown
+        uid: u64,
         text: String,
+        /// Text and emotes in order; empty for plain text
+        segments: Vec<DanmuSegment>,
+        /// Fan medal the sender is wearing
+        medal: Option<FanMedal>,
     },
     Gift {
         user: String,
+        /// Sender's uid, 0 when unknown
+        uid: u64,
         gift: String,
+        /// Gift id, 0 when unknown
+        gift_id: u64,
+        /// Number of gifts sent at once
+        num: u64,
+        /// Price of one gift, from the event or the gift catalog
+        value: Option<GiftValue>,
+    },
+    /// Paid super chat (SUPER_CHAT_MESSAGE)
+    SuperChat {
+        user: String,
+        uid: u64,
+        message: String,
+        /// Price in CNY
+        price: u64,
     },
     /// Online rank count message (ONLINE_RANK_COUNT)
     OnlineRankCount {
@@ -72,12 +284,38 @@ pub enum BiliMessage {
         /// Number of online users in the live room
         online_count: u64,
     },
+    /// A message from before the connection (history backfill); handlers that only
+    /// react to live events can ignore it
+    Historical(Box<BiliMessage>),
     // Add more variants as needed
     Raw(serde_json::Value),
     #[deprecated(note = "Use Raw variant instead")]
     Unsupported,
 }
 
+impl BiliMessage {
+    /// Mark a message as historical
+    pub fn historical(self) -> Self {
+        match self {
+            BiliMessage::Historical(_) => self,
+            msg => BiliMessage::Historical(Box::new(msg)),
+        }
+    }
+
+    /// Whether the message was backfilled from history rather than received live
+    pub fn is_historical(&self) -> bool {
+        matches!(self, BiliMessage::Historical(_))
+    }
+
+    /// The message itself, without the historical marker
+    pub fn inner(&self) -> &BiliMessage {
+        match self {
+            BiliMessage::Historical(msg) => msg.inner(),
+            msg => msg,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -93,4 +331,73 @@ mod tests {
         assert_eq!(auth.roomid, 67890);
         assert_eq!(auth.key, "test_token");
     }
+
+    #[test]
+    fn test_historical_marker() {
+        let msg = BiliMessage::Gift {
+            user: "user".to_string(),
+            uid: 1,
+            gift: "rocket".to_string(),
+            gift_id: 0,
+            num: 1,
+            value: None,
+        };
+        assert!(!msg.is_historical());
+
+        let historical = msg.clone().historical().historical();
+        assert!(historical.is_historical());
+        assert_eq!(historical.inner(), &msg);
+        assert_eq!(historical, BiliMessage::Historical(Box::new(msg)));
+    }
+
+    #[test]
+    fn test_parse_danmu_segments() {
+        let emots = serde_json::json!({
+            "[dog]": {"descript": "[dog]", "emoticon_unique": "emoji_208", "url": "https://i0.hdslb.com/dog.png", "width": 20, "height": 20},
+            "[妙]": {"descript": "[妙]", "url": "https://i0.hdslb.com/miao.png", "width": 20, "height": 20}
+        });
+        let segments =
+            parse_danmu_segments("hi[dog][dog] 妙[妙]", &serde_json::json!("{}"), &emots);
+        assert_eq!(segments.len(), 5);
+        assert_eq!(segments[0], DanmuSegment::Text("hi".to_string()));
+        assert!(
+            matches!(&segments[1], DanmuSegment::Emote(e) if e.name == "dog" && e.unique_id.as_deref() == Some("emoji_208"))
+        );
+        assert_eq!(segments[3], DanmuSegment::Text(" 妙".to_string()));
+        assert_eq!(
+            render_danmu("", &segments, |e| Some(format!("<{}>", e.description))),
+            "hi<dog><dog> 妙<妙>"
+        );
+        assert_eq!(render_danmu("", &segments, |_| None), "hi 妙");
+
+        let sticker = serde_json::json!({"emoticon_unique": "upower_1", "url": "https://i0.hdslb.com/zan.png", "width": 183, "height": 162});
+        let segments = parse_danmu_segments("赞", &sticker, &serde_json::Value::Null);
+        assert_eq!(render_danmu("赞", &segments, |e| Some(e.badge())), "[赞]");
+
+        assert!(
+            parse_danmu_segments("plain", &serde_json::Value::Null, &serde_json::Value::Null)
+                .is_empty()
+        );
+        assert_eq!(render_danmu("plain", &[], |_| None), "plain");
+    }
+
+    #[test]
+    fn test_gift_value() {
+        let paid = GiftValue {
+            price: 1000,
+            coin_type: CoinType::Gold,
+            blind_box_price: Some(1500),
+        };
+        assert!(paid.is_paid());
+        assert_eq!(paid.value_cny(3), 3.0);
+        assert_eq!(paid.paid_cny(2), 3.0);
+
+        let free = GiftValue {
+            price: 100,
+            coin_type: CoinType::Silver,
+            blind_box_price: None,
+        };
+        assert!(!free.is_paid());
+        assert_eq!(free.value_cny(10), 0.0);
+    }
 }
@@ -0,0 +1,461 @@
+// src/client/moderation.rs
+//! Room moderation for admins: silencing users, shield keywords and room-wide silent mode
+
+use crate::client::danmaku::{
+    LiveApiError, extract_csrf_token, live_http_client, read_json, response_code, sending_headers,
+};
+use crate::scheduler::EventContext;
+use log::{debug, info, warn};
+use serde_json::Value;
+use std::fmt;
+
+const ADD_SILENT_USER_URL: &str =
+    "https://api.live.bilibili.com/xlive/web-ucenter/v1/banned/AddSilentUser";
+const SILENT_USER_LIST_URL: &str =
+    "https://api.live.bilibili.com/xlive/web-ucenter/v1/banned/GetSilentUserList";
+const DEL_SILENT_USER_URL: &str =
+    "https://api.live.bilibili.com/banned_service/v1/Silent/del_room_block_user";
+const ADD_SHIELD_KEYWORD_URL: &str =
+    "https://api.live.bilibili.com/xlive/web-ucenter/v1/banned/AddShieldKeyword";
+const DEL_SHIELD_KEYWORD_URL: &str =
+    "https://api.live.bilibili.com/xlive/web-ucenter/v1/banned/DelShieldKeyword";
+const SHIELD_KEYWORD_LIST_URL: &str =
+    "https://api.live.bilibili.com/xlive/web-ucenter/v1/banned/GetShieldKeywordList";
+const ROOM_SILENT_URL: &str =
+    "https://api.live.bilibili.com/xlive/web-ucenter/v1/banned/RoomSilent";
+
+/// How long a user stays silenced
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SilenceDuration {
+    /// Until the current live session ends
+    ThisLive,
+    Hours(u32),
+    Forever,
+}
+
+impl SilenceDuration {
+    fn hours(self) -> i64 {
+        match self {
+            SilenceDuration::ThisLive => 0,
+            SilenceDuration::Hours(hours) => i64::from(hours),
+            SilenceDuration::Forever => -1,
+        }
+    }
+}
+
+/// Who may still send danmaku while the room is in silent mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoomSilentMode {
+    /// Turn silent mode off
+    Off,
+    /// Only users at or above this user level
+    Level(u32),
+    /// Only users with a fan medal at or above this level
+    Medal(u32),
+    /// Only guard members
+    Member,
+}
+
+impl RoomSilentMode {
+    fn form(self) -> (&'static str, u32) {
+        match self {
+            RoomSilentMode::Off => ("off", 0),
+            RoomSilentMode::Level(level) => ("level", level),
+            RoomSilentMode::Medal(level) => ("medal", level),
+            RoomSilentMode::Member => ("member", 0),
+        }
+    }
+}
+
+/// An entry of the room's silenced user list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SilencedUser {
+    /// Id of the silence record, needed to lift it
+    pub block_id: u64,
+    pub uid: u64,
+    pub uname: String,
+    /// When the silence ends, as reported by the server
+    pub block_end_time: Option<String>,
+}
+
+/// One page of silenced users
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SilencedUserPage {
+    pub users: Vec<SilencedUser>,
+    /// Total number of silenced users in the room
+    pub total: u64,
+}
+
+/// Why a moderation action failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModerationError {
+    /// Missing credentials, network failure or an error code not specific to moderation
+    Request(LiveApiError),
+    /// The account is not an admin of the room (-403)
+    PermissionDenied,
+    /// The user to unsilence is not in the silenced list
+    NotSilenced(u64),
+}
+
+impl ModerationError {
+    /// Map a moderation API error code to an error
+    pub fn from_code(code: i64, message: &str) -> Self {
+        match code {
+            -403 => ModerationError::PermissionDenied,
+            _ => ModerationError::Request(LiveApiError::from_code(code, message)),
+        }
+    }
+}
+
+impl From<LiveApiError> for ModerationError {
+    fn from(error: LiveApiError) -> Self {
+        ModerationError::Request(error)
+    }
+}
+
+impl fmt::Display for ModerationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModerationError::Request(e) => write!(f, "{}", e),
+            ModerationError::PermissionDenied => {
+                write!(f, "This account is not an admin of the room (-403)")
+            }
+            ModerationError::NotSilenced(uid) => write!(f, "User {} is not silenced", uid),
+        }
+    }
+}
+
+impl std::error::Error for ModerationError {}
+
+/// Check the `code` of a moderation API response and return its `data`
+pub fn check_response(body: &Value) -> Result<&Value, ModerationError> {
+    let (code, message) = response_code(body)?;
+    if code != 0 {
+        return Err(ModerationError::from_code(code, message));
+    }
+    Ok(&body["data"])
+}
+
+/// Parse the `data` of a silenced user list response
+pub fn parse_silenced_users(data: &Value) -> SilencedUserPage {
+    let users = data["data"]
+        .as_array()
+        .map(|list| {
+            list.iter()
+                .map(|entry| SilencedUser {
+                    block_id: entry["id"].as_u64().unwrap_or(0),
+                    uid: entry["tuid"].as_u64().unwrap_or(0),
+                    uname: entry["tname"].as_str().unwrap_or("").to_string(),
+                    block_end_time: entry["block_end_time"].as_str().map(str::to_string),
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    let total = data["total"].as_u64().unwrap_or(users.len() as u64);
+    SilencedUserPage { users, total }
+}
+
+/// Parse the `data` of a shield keyword list response
+pub fn parse_shield_keywords(data: &Value) -> Vec<String> {
+    data["keyword_list"]
+        .as_array()
+        .map(|list| {
+            list.iter()
+                .filter_map(|entry| {
+                    entry
+                        .as_str()
+                        .or_else(|| entry["keyword"].as_str())
+                        .map(str::to_string)
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Moderation actions on the context's room, performed as the context's sending account
+#[derive(Debug, Clone)]
+pub struct ModerationClient {
+    http_client: reqwest::Client,
+}
+
+impl Default for ModerationClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ModerationClient {
+    /// A client sharing the connection pool of danmaku sending
+    pub fn new() -> Self {
+        Self {
+            http_client: live_http_client(),
+        }
+    }
+
+    /// Silence a user in the room
+    pub async fn silence_user(
+        &self,
+        context: &EventContext,
+        uid: u64,
+        duration: SilenceDuration,
+    ) -> Result<(), ModerationError> {
+        self.post(
+            ADD_SILENT_USER_URL,
+            context,
+            vec![
+                ("room_id", context.room_id.to_string()),
+                ("tuid", uid.to_string()),
+                ("msg", String::new()),
+                ("mobile_app", "web".to_string()),
+                ("hour", duration.hours().to_string()),
+            ],
+        )
+        .await?;
+        info!("Silenced user {} in room {}", uid, context.room_id);
+        Ok(())
+    }
+
+    /// Lift a silence by its record id (see [`SilencedUser::block_id`])
+    pub async fn remove_silence(
+        &self,
+        context: &EventContext,
+        block_id: u64,
+    ) -> Result<(), ModerationError> {
+        self.post(
+            DEL_SILENT_USER_URL,
+            context,
+            vec![
+                ("roomid", context.room_id.to_string()),
+                ("id", block_id.to_string()),
+            ],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Unsilence a user, looking up their silence record in the room's list
+    pub async fn unsilence_user(
+        &self,
+        context: &EventContext,
+        uid: u64,
+    ) -> Result<(), ModerationError> {
+        let mut page = 1;
+        loop {
+            let result = self.list_silenced_users(context, page).await?;
+            if let Some(user) = result.users.iter().find(|u| u.uid == uid) {
+                self.remove_silence(context, user.block_id).await?;
+                info!("Unsilenced user {} in room {}", uid, context.room_id);
+                return Ok(());
+            }
+            if result.users.is_empty() || u64::from(page) * 20 >= result.total {
+                return Err(ModerationError::NotSilenced(uid));
+            }
+            page += 1;
+        }
+    }
+
+    /// List silenced users, 20 per page starting at page 1
+    pub async fn list_silenced_users(
+        &self,
+        context: &EventContext,
+        page: u32,
+    ) -> Result<SilencedUserPage, ModerationError> {
+        let data = self
+            .post(
+                SILENT_USER_LIST_URL,
+                context,
+                vec![
+                    ("room_id", context.room_id.to_string()),
+                    ("ps", page.to_string()),
+                ],
+            )
+            .await?;
+        Ok(parse_silenced_users(&data))
+    }
+
+    /// Add a keyword to the room's shield list
+    pub async fn add_shield_keyword(
+        &self,
+        context: &EventContext,
+        keyword: &str,
+    ) -> Result<(), ModerationError> {
+        self.post(
+            ADD_SHIELD_KEYWORD_URL,
+            context,
+            vec![
+                ("room_id", context.room_id.to_string()),
+                ("keyword", keyword.to_string()),
+            ],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Remove a keyword from the room's shield list
+    pub async fn remove_shield_keyword(
+        &self,
+        context: &EventContext,
+        keyword: &str,
+    ) -> Result<(), ModerationError> {
+        self.post(
+            DEL_SHIELD_KEYWORD_URL,
+            context,
+            vec![
+                ("room_id", context.room_id.to_string()),
+                ("keyword", keyword.to_string()),
+            ],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// List the room's shield keywords
+    pub async fn list_shield_keywords(
+        &self,
+        context: &EventContext,
+    ) -> Result<Vec<String>, ModerationError> {
+        let (_, headers) = sending_headers(context)?;
+        let response = self
+            .http_client
+            .get(SHIELD_KEYWORD_LIST_URL)
+            .headers(headers)
+            .query(&[("room_id", context.room_id.to_string())])
+            .send()
+            .await
+            .map_err(|e| LiveApiError::Http(e.to_string()))?;
+        let body = read_json(response).await?;
+        Ok(parse_shield_keywords(check_response(&body)?))
+    }
+
+    /// Switch room-wide silent mode; `minutes` of 0 lasts until turned off
+    pub async fn set_room_silent(
+        &self,
+        context: &EventContext,
+        mode: RoomSilentMode,
+        minutes: u32,
+    ) -> Result<(), ModerationError> {
+        let (kind, level) = mode.form();
+        self.post(
+            ROOM_SILENT_URL,
+            context,
+            vec![
+                ("room_id", context.room_id.to_string()),
+                ("type", kind.to_string()),
+                ("level", level.to_string()),
+                ("minute", minutes.to_string()),
+            ],
+        )
+        .await?;
+        info!("Room {} silent mode set to {:?}", context.room_id, mode);
+        Ok(())
+    }
+
+    /// POST a form with the CSRF token and return the response `data`
+    async fn post(
+        &self,
+        url: &str,
+        context: &EventContext,
+        mut form: Vec<(&str, String)>,
+    ) -> Result<Value, ModerationError> {
+        let (cookies, headers) = sending_headers(context)?;
+        let csrf_token = extract_csrf_token(cookies).ok_or(LiveApiError::MissingCsrf)?;
+        form.push(("csrf_token", csrf_token.clone()));
+        form.push(("csrf", csrf_token));
+
+        debug!("Moderation request: {}", url);
+        let response = self
+            .http_client
+            .post(url)
+            .headers(headers)
+            .form(&form)
+            .send()
+            .await
+            .map_err(|e| LiveApiError::Http(e.to_string()))?;
+        let body = read_json(response).await?;
+        check_response(&body).cloned().inspect_err(|e| {
+            warn!("Moderation request to {} failed: {}", url, e);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_check_response() {
+        let body = json!({"code": 0, "message": "", "data": {"ok": true}});
+        assert_eq!(check_response(&body).unwrap()["ok"], true);
+
+        let error =
+            |code: i64| check_response(&json!({"code": code, "message": "denied"})).unwrap_err();
+        assert_eq!(
+            error(-101),
+            ModerationError::Request(LiveApiError::NotLoggedIn)
+        );
+        assert_eq!(error(-403), ModerationError::PermissionDenied);
+        assert_eq!(
+            error(1200),
+            ModerationError::Request(LiveApiError::Api {
+                code: 1200,
+                message: "denied".to_string()
+            })
+        );
+        assert!(matches!(
+            check_response(&json!("oops")),
+            Err(ModerationError::Request(LiveApiError::Http(_)))
+        ));
+    }
+
+    #[test]
+    fn test_parse_silenced_users() {
+        let data = json!({
+            "data": [
+                {"id": 77, "tuid": 12345, "tname": "spammer", "block_end_time": "2026-10-19 12:00:00"},
+                {"id": 78, "tuid": 54321, "tname": "troll"}
+            ],
+            "total": 2
+        });
+        let page = parse_silenced_users(&data);
+        assert_eq!(page.total, 2);
+        assert_eq!(
+            page.users[0],
+            SilencedUser {
+                block_id: 77,
+                uid: 12345,
+                uname: "spammer".to_string(),
+                block_end_time: Some("2026-10-19 12:00:00".to_string()),
+            }
+        );
+        assert_eq!(page.users[1].block_end_time, None);
+        assert!(parse_silenced_users(&json!({})).users.is_empty());
+    }
+
+    #[test]
+    fn test_parse_shield_keywords() {
+        assert_eq!(
+            parse_shield_keywords(&json!({"keyword_list": ["广告", {"keyword": "刷屏"}]})),
+            vec!["广告".to_string(), "刷屏".to_string()]
+        );
+        assert!(parse_shield_keywords(&json!(null)).is_empty());
+    }
+
+    #[test]
+    fn test_actions_require_csrf() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let client = ModerationClient::new();
+
+        let context = EventContext::new(None, 1);
+        assert_eq!(
+            runtime.block_on(client.silence_user(&context, 1, SilenceDuration::ThisLive)),
+            Err(ModerationError::Request(LiveApiError::MissingCookies))
+        );
+
+        let context = EventContext::new(Some("SESSDATA=abc".to_string()), 1);
+        assert_eq!(
+            runtime.block_on(client.add_shield_keyword(&context, "spam")),
+            Err(ModerationError::Request(LiveApiError::MissingCsrf))
+        );
+    }
+}
@@ -1,14 +1,27 @@
 // In Cargo.toml, ensure you have: client = { path = "../client" }
+use futures::FutureExt;
 use models::BiliMessage;
-use std::sync::Arc;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc::error::SendTimeoutError;
+use tokio::sync::{mpsc, oneshot};
 
+use crate::filter::MessageFilter;
 use crate::models;
 
+/// Re-exported so plugins can implement [`AsyncEventHandler`] without adding the crate
+pub use async_trait::async_trait;
+
 /// Context information passed to event handlers
 #[derive(Debug, Clone)]
 pub struct EventContext {
     /// Bilibili cookies for authentication
     pub cookies: Option<String>,
+    /// Cookies of the account used for sending danmaku, if different from `cookies`
+    pub send_cookies: Option<String>,
     /// Room ID where the event occurred
     pub room_id: u64,
 }
@@ -17,18 +30,322 @@ impl EventContext {
     /// Create a new EventContext with automatic cookie detection
     pub fn new_with_auto_cookies(room_id: u64) -> Self {
         let cookies = crate::auth::get_cookies_or_browser(None);
-        Self { cookies, room_id }
+        Self::new(cookies, room_id)
     }
 
     /// Create a new EventContext with provided cookies
     pub fn new(cookies: Option<String>, room_id: u64) -> Self {
-        Self { cookies, room_id }
+        Self {
+            cookies,
+            send_cookies: None,
+            room_id,
+        }
+    }
+
+    /// Send danmaku as a different account than the one used for the connection
+    pub fn with_send_cookies(mut self, send_cookies: Option<String>) -> Self {
+        self.send_cookies = send_cookies;
+        self
+    }
+
+    /// Cookies to send danmaku with: the sending account, falling back to `cookies`
+    pub fn sending_cookies(&self) -> Option<&str> {
+        self.send_cookies.as_deref().or(self.cookies.as_deref())
     }
 }
 
 /// Trait for event handlers (plugins) that process BiliMessage.
 pub trait EventHandler: Send + Sync {
     fn handle(&self, msg: &BiliMessage, context: &EventContext);
+
+    /// Called once before the first message
+    fn on_start(&self, _context: &EventContext) {}
+
+    /// Called when the connection to the live room is up
+    fn on_connected(&self, _context: &EventContext) {}
+
+    /// Called when the connection to the live room is lost
+    fn on_disconnected(&self, _context: &EventContext) {}
+
+    /// Called on [`Scheduler::shutdown`] after all queued messages, e.g. to flush files
+    fn on_shutdown(&self, _context: &EventContext) {}
+
+    /// Name shown in the handler stats; the type name by default
+    fn name(&self) -> &str {
+        short_type_name(std::any::type_name::<Self>())
+    }
+
+    /// How long the handler may take for one message; the scheduler's default when `None`
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Trait for event handlers that do IO (HTTP, audio, files).
+///
+/// Async handlers run as tasks on the scheduler's shared runtime instead of each
+/// plugin keeping a runtime or thread of its own.
+#[async_trait]
+pub trait AsyncEventHandler: Send + Sync {
+    async fn handle(&self, msg: &BiliMessage, context: &EventContext);
+
+    /// Called once before the first message
+    async fn on_start(&self, _context: &EventContext) {}
+
+    /// Called when the connection to the live room is up
+    async fn on_connected(&self, _context: &EventContext) {}
+
+    /// Called when the connection to the live room is lost
+    async fn on_disconnected(&self, _context: &EventContext) {}
+
+    /// Called on [`Scheduler::shutdown`] after all queued messages, e.g. to finish playback
+    async fn on_shutdown(&self, _context: &EventContext) {}
+
+    /// Name shown in the handler stats; the type name by default
+    fn name(&self) -> &str {
+        short_type_name(std::any::type_name::<Self>())
+    }
+
+    /// How long the handler may take for one message; the scheduler's default when `None`
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// `blivedm::plugins::tts::TtsHandler` -> `TtsHandler`
+fn short_type_name(name: &str) -> &str {
+    let path = name.split('<').next().unwrap_or(name);
+    path.rsplit("::").next().unwrap_or(path)
+}
+
+/// A handler in a stage, either sync or async
+#[derive(Clone)]
+pub enum StageHandler {
+    Sync(Arc<dyn EventHandler>),
+    Async(Arc<dyn AsyncEventHandler>),
+}
+
+impl StageHandler {
+    fn name(&self) -> &str {
+        match self {
+            StageHandler::Sync(handler) => handler.name(),
+            StageHandler::Async(handler) => handler.name(),
+        }
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        match self {
+            StageHandler::Sync(handler) => handler.timeout(),
+            StageHandler::Async(handler) => handler.timeout(),
+        }
+    }
+}
+
+/// What a middleware does with a message
+#[derive(Debug, Clone, PartialEq)]
+pub enum MiddlewareAction {
+    /// Pass the message on unchanged
+    Continue,
+    /// Pass this message on instead
+    Replace(BiliMessage),
+    /// Stop the message; later stages never see it
+    Drop,
+}
+
+/// Trait for middleware that can change or stop messages before later stages see them,
+/// e.g. a spam filter or a sensitive-word masker in front of TTS and the display.
+pub trait Middleware: Send + Sync {
+    fn process(&self, msg: &BiliMessage, context: &EventContext) -> MiddlewareAction;
+}
+
+/// Trait for middleware that does IO, e.g. a translator calling an HTTP API.
+#[async_trait]
+pub trait AsyncMiddleware: Send + Sync {
+    async fn process(&self, msg: &BiliMessage, context: &EventContext) -> MiddlewareAction;
+}
+
+/// A middleware, either sync or async
+#[derive(Clone)]
+pub enum MiddlewareHandler {
+    Sync(Arc<dyn Middleware>),
+    Async(Arc<dyn AsyncMiddleware>),
+}
+
+/// A stage: handlers run side by side, or a middleware deciding what later stages get
+enum Stage {
+    Handlers(Vec<StageEntry>),
+    Middleware(MiddlewareHandler),
+}
+
+/// Messages a handler wants, changeable while the scheduler runs; all when `None`
+type SharedFilter = Arc<RwLock<Option<MessageFilter>>>;
+
+/// A handler registered in a stage, with the messages it wants and its stats
+struct StageEntry {
+    handler: StageHandler,
+    filter: SharedFilter,
+    stats: Arc<Mutex<HandlerStats>>,
+}
+
+/// Invocations, failures and latency of one handler
+#[derive(Debug, Clone, Default)]
+pub struct HandlerStats {
+    /// Handler name, see [`EventHandler::name`]
+    pub name: String,
+    /// Stage the handler belongs to, counted from 0
+    pub stage: usize,
+    /// Messages the handler was called with
+    pub invocations: u64,
+    /// Calls that panicked
+    pub panics: u64,
+    /// Calls that took longer than the handler's timeout
+    pub timeouts: u64,
+    /// Messages the handler did not get because it was disabled or its queue stayed full
+    pub skipped: u64,
+    /// Panics and timeouts since the last call that went well
+    pub consecutive_failures: u32,
+    /// Lifecycle hooks (`on_start`, `on_shutdown`, ...) that panicked or timed out; these
+    /// do not count toward disabling the handler
+    pub hook_failures: u64,
+    /// Time spent in the handler over all calls
+    pub total_latency: Duration,
+    /// Longest call
+    pub max_latency: Duration,
+    /// Whether the handler is disabled, after repeated failures or with
+    /// [`Scheduler::set_enabled`]
+    pub disabled: bool,
+}
+
+impl HandlerStats {
+    /// Panics and timeouts
+    pub fn errors(&self) -> u64 {
+        self.panics + self.timeouts
+    }
+
+    /// Average time per call
+    pub fn average_latency(&self) -> Duration {
+        match u32::try_from(self.invocations) {
+            Ok(0) => Duration::ZERO,
+            Ok(invocations) => self.total_latency / invocations,
+            Err(_) => Duration::ZERO,
+        }
+    }
+
+    /// Whether the handler is enabled and its last call went well
+    pub fn is_healthy(&self) -> bool {
+        !self.disabled && self.consecutive_failures == 0
+    }
+
+    fn record_call(&mut self, latency: Duration) {
+        self.invocations += 1;
+        self.total_latency += latency;
+        self.max_latency = self.max_latency.max(latency);
+    }
+}
+
+/// Shared stats of all handlers of a scheduler, for status displays.
+///
+/// Cloning is cheap; clones see the same stats.
+#[derive(Clone, Default)]
+pub struct SchedulerStats {
+    handlers: Arc<RwLock<Vec<Arc<Mutex<HandlerStats>>>>>,
+}
+
+impl SchedulerStats {
+    /// Stats of all handlers, in stage order
+    pub fn snapshot(&self) -> Vec<HandlerStats> {
+        let handlers = match self.handlers.read() {
+            Ok(handlers) => handlers,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        handlers
+            .iter()
+            .map(|stats| lock_stats(stats).clone())
+            .collect()
+    }
+
+    /// Enable the disabled handlers called `name` again; returns whether there were any
+    pub fn enable(&self, name: &str) -> bool {
+        let handlers = match self.handlers.read() {
+            Ok(handlers) => handlers,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let mut enabled = false;
+        for stats in handlers.iter() {
+            let mut stats = lock_stats(stats);
+            if stats.name == name && stats.disabled {
+                stats.disabled = false;
+                stats.consecutive_failures = 0;
+                enabled = true;
+            }
+        }
+        enabled
+    }
+
+    fn register(&self, name: &str, stage: usize) -> Arc<Mutex<HandlerStats>> {
+        let stats = Arc::new(Mutex::new(HandlerStats {
+            name: name.to_string(),
+            stage,
+            ..HandlerStats::default()
+        }));
+        match self.handlers.write() {
+            Ok(mut handlers) => handlers.push(Arc::clone(&stats)),
+            Err(poisoned) => poisoned.into_inner().push(Arc::clone(&stats)),
+        }
+        stats
+    }
+}
+
+fn lock_stats(stats: &Mutex<HandlerStats>) -> std::sync::MutexGuard<'_, HandlerStats> {
+    match stats.lock() {
+        Ok(stats) => stats,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+/// When a sync handler's current call started; `None` while it waits for work
+type BusySince = Arc<Mutex<Option<Instant>>>;
+
+/// Whether a handler has been in one call for longer than its timeout. Only sync
+/// handlers can be, since async ones are cancelled when they run out of time.
+fn stuck(busy: &BusySince, timeout: Option<Duration>) -> bool {
+    let Some(timeout) = timeout else {
+        return false;
+    };
+    let since = match busy.lock() {
+        Ok(since) => *since,
+        Err(poisoned) => *poisoned.into_inner(),
+    };
+    since.is_some_and(|since| since.elapsed() >= timeout)
+}
+
+fn set_busy(busy: &BusySince, since: Option<Instant>) {
+    match busy.lock() {
+        Ok(mut current) => *current = since,
+        Err(poisoned) => *poisoned.into_inner() = since,
+    }
+}
+
+/// Count a panic or timeout on a message, disabling the handler at the failure limit
+fn record_failure(stats: &mut HandlerStats, name: &str, failure_limit: Option<u32>) {
+    stats.consecutive_failures += 1;
+    if !stats.disabled && failure_limit.is_some_and(|limit| stats.consecutive_failures >= limit) {
+        stats.disabled = true;
+        log::error!(
+            "Handler {} disabled after {} failures in a row",
+            name,
+            stats.consecutive_failures
+        );
+    }
+}
+
+/// Whether a handler with this filter wants the message
+fn wants(filter: &RwLock<Option<MessageFilter>>, msg: &BiliMessage) -> bool {
+    let filter = match filter.read() {
+        Ok(filter) => filter,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    filter.as_ref().is_none_or(|filter| filter.matches(msg))
 }
 
 /// Scheduling mode: Parallel or Sequential.
@@ -37,49 +354,759 @@ pub enum ScheduleMode {
     Sequential,
 }
 
+/// Default capacity of each handler's queue; submitting waits once a handler is this far behind
+pub const DEFAULT_QUEUE_CAPACITY: usize = 1024;
+
+/// Default time a handler may take for one message before its stage moves on without it
+pub const DEFAULT_HANDLER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Lifecycle events, delivered to handlers in order with the messages
+#[derive(Debug, Clone, Copy)]
+enum Hook {
+    Start,
+    Connected,
+    Disconnected,
+    Shutdown,
+}
+
+/// What travels through the stages
+#[derive(Clone)]
+enum Item {
+    Message(Arc<BiliMessage>),
+    Hook(Hook),
+}
+
+/// A message or lifecycle event travelling through the stages
+struct Envelope {
+    item: Item,
+    context: Arc<EventContext>,
+    /// Notified once the last stage is done with it
+    done: Option<oneshot::Sender<()>>,
+}
+
+/// Queue of a handler's worker, with the messages it wants
+struct Worker {
+    name: String,
+    queue: mpsc::Sender<Job>,
+    filter: SharedFilter,
+    stats: Arc<Mutex<HandlerStats>>,
+    timeout: Option<Duration>,
+    busy: BusySince,
+}
+
+/// A message or lifecycle event for one handler's worker
+struct Job {
+    item: Item,
+    context: Arc<EventContext>,
+    done: oneshot::Sender<Outcome>,
+}
+
+/// How a handler's call ended
+enum Outcome {
+    Done,
+    Panicked,
+    TimedOut,
+}
+
+/// A worker's pending call for a message, awaited before the message moves on
+struct Pending {
+    name: String,
+    wait: oneshot::Receiver<Outcome>,
+    stats: Arc<Mutex<HandlerStats>>,
+    timeout: Option<Duration>,
+    busy: BusySince,
+}
+
+/// Settings shared by all workers of a pipeline
+#[derive(Clone, Copy)]
+struct WorkerSettings {
+    capacity: usize,
+    timeout: Option<Duration>,
+    failure_limit: Option<u32>,
+}
+
+/// Running workers for all stages. Dropping it closes the queues; workers finish what
+/// is queued and exit.
+struct Pipeline {
+    input: mpsc::Sender<Envelope>,
+}
+
+impl Pipeline {
+    /// Start one long-lived worker per handler and a dispatcher per stage
+    ///
+    /// A stage hands each message to all of its workers' queues, and passes it on to
+    /// the next stage, in arrival order, once every worker is done with it. Stages
+    /// therefore work on different messages at the same time while each message still
+    /// goes through the stages in order. A middleware stage passes on what its
+    /// middleware returns.
+    /// The handlers' `on_start` hooks run before the first message.
+    fn start(
+        stages: &[Stage],
+        runtime: &Runtime,
+        settings: WorkerSettings,
+        context: Arc<EventContext>,
+    ) -> Self {
+        let capacity = settings.capacity;
+        let (input, mut stage_rx) = mpsc::channel::<Envelope>(capacity);
+        // The channel is empty, so this cannot fail for lack of room
+        let _ = input.try_send(Envelope {
+            item: Item::Hook(Hook::Start),
+            context,
+            done: None,
+        });
+        for stage in stages {
+            let (next_tx, next_rx) = mpsc::channel(capacity);
+            match stage {
+                Stage::Handlers(entries) => {
+                    let workers: Vec<Worker> = entries
+                        .iter()
+                        .map(|entry| {
+                            let timeout = entry.handler.timeout().or(settings.timeout);
+                            let busy = BusySince::default();
+                            Worker {
+                                name: entry.handler.name().to_string(),
+                                queue: start_worker(
+                                    entry.handler.clone(),
+                                    runtime,
+                                    capacity,
+                                    timeout,
+                                    Arc::clone(&entry.stats),
+                                    Arc::clone(&busy),
+                                ),
+                                filter: Arc::clone(&entry.filter),
+                                stats: Arc::clone(&entry.stats),
+                                timeout,
+                                busy,
+                            }
+                        })
+                        .collect();
+                    let (pending_tx, pending_rx) = mpsc::channel(capacity);
+                    runtime.spawn(fan_out(
+                        stage_rx,
+                        workers,
+                        pending_tx,
+                        settings.failure_limit,
+                    ));
+                    runtime.spawn(forward_in_order(
+                        pending_rx,
+                        next_tx,
+                        settings.failure_limit,
+                    ));
+                }
+                Stage::Middleware(middleware) => {
+                    start_middleware(middleware.clone(), runtime, stage_rx, next_tx);
+                }
+            }
+            stage_rx = next_rx;
+        }
+        runtime.spawn(async move {
+            while let Some(envelope) = stage_rx.recv().await {
+                if let Some(done) = envelope.done {
+                    let _ = done.send(());
+                }
+            }
+        });
+        Pipeline { input }
+    }
+}
+
+/// Queue each message for every enabled worker of a stage that wants it, and each
+/// lifecycle event for every worker. A message for a handler stuck in a call past its
+/// timeout is skipped and counted as a timeout, so the stage does not wait for it.
+async fn fan_out(
+    mut rx: mpsc::Receiver<Envelope>,
+    workers: Vec<Worker>,
+    pending: mpsc::Sender<(Envelope, Vec<Pending>)>,
+    failure_limit: Option<u32>,
+) {
+    while let Some(envelope) = rx.recv().await {
+        let mut waits = Vec::with_capacity(workers.len());
+        for worker in &workers {
+            if let Item::Message(msg) = &envelope.item {
+                if !wants(&worker.filter, msg) {
+                    continue;
+                }
+                let mut stats = lock_stats(&worker.stats);
+                if stats.disabled {
+                    stats.skipped += 1;
+                    continue;
+                }
+                if stuck(&worker.busy, worker.timeout) {
+                    log::warn!("Handler {} is stuck, skipping a message", worker.name);
+                    stats.timeouts += 1;
+                    record_failure(&mut stats, &worker.name, failure_limit);
+                    continue;
+                }
+            }
+            let (done, wait) = oneshot::channel();
+            let job = Job {
+                item: envelope.item.clone(),
+                context: Arc::clone(&envelope.context),
+                done,
+            };
+            // A handler stuck past its timeout must not hold up the stage once its queue is full
+            let sent = match worker.timeout {
+                Some(timeout) => match worker.queue.send_timeout(job, timeout).await {
+                    Ok(()) => true,
+                    Err(SendTimeoutError::Timeout(_)) => {
+                        log::warn!(
+                            "Handler {} is too far behind, skipping a message",
+                            worker.name
+                        );
+                        lock_stats(&worker.stats).skipped += 1;
+                        false
+                    }
+                    Err(SendTimeoutError::Closed(_)) => false,
+                },
+                None => worker.queue.send(job).await.is_ok(),
+            };
+            // A worker that has stopped no longer holds up the stage
+            if sent {
+                waits.push(Pending {
+                    name: worker.name.clone(),
+                    wait,
+                    stats: Arc::clone(&worker.stats),
+                    timeout: worker.timeout,
+                    busy: Arc::clone(&worker.busy),
+                });
+            }
+        }
+        if pending.send((envelope, waits)).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Pass messages to the next stage in arrival order once all workers are done or have
+/// run out of time, counting failures and disabling handlers that keep failing on messages.
+/// Messages queued behind a call stuck past its timeout run out of time at once, rather
+/// than each waiting out the timeout again.
+async fn forward_in_order(
+    mut pending: mpsc::Receiver<(Envelope, Vec<Pending>)>,
+    next: mpsc::Sender<Envelope>,
+    failure_limit: Option<u32>,
+) {
+    while let Some((envelope, waits)) = pending.recv().await {
+        let started = tokio::time::Instant::now();
+        for pending in waits {
+            let outcome = match pending.timeout {
+                Some(timeout) => {
+                    let deadline = if stuck(&pending.busy, pending.timeout) {
+                        tokio::time::Instant::now()
+                    } else {
+                        started + timeout
+                    };
+                    tokio::time::timeout_at(deadline, pending.wait)
+                        .await
+                        .unwrap_or(Ok(Outcome::TimedOut))
+                }
+                None => pending.wait.await,
+            };
+            let Ok(outcome) = outcome else {
+                // The worker has stopped
+                continue;
+            };
+            let mut stats = lock_stats(&pending.stats);
+            // Hooks may rightly take long, e.g. to flush on shutdown, so only messages
+            // count toward the failure limit
+            if let Item::Hook(hook) = &envelope.item {
+                if !matches!(outcome, Outcome::Done) {
+                    log::warn!("Handler {} failed in its {:?} hook", pending.name, hook);
+                    stats.hook_failures += 1;
+                }
+                continue;
+            }
+            match outcome {
+                Outcome::Done => {
+                    stats.consecutive_failures = 0;
+                    continue;
+                }
+                Outcome::Panicked => stats.panics += 1,
+                Outcome::TimedOut => {
+                    log::warn!("Handler {} timed out", pending.name);
+                    stats.timeouts += 1;
+                }
+            }
+            record_failure(&mut stats, &pending.name, failure_limit);
+        }
+        if next.send(envelope).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Start a handler's worker: a thread for sync handlers, a task for async ones.
+///
+/// Async handlers are cancelled when they run out of time; sync handlers cannot be, so
+/// they record in `busy` when their current call started, and their stage skips them
+/// while a call runs past the timeout.
+fn start_worker(
+    handler: StageHandler,
+    runtime: &Runtime,
+    capacity: usize,
+    timeout: Option<Duration>,
+    stats: Arc<Mutex<HandlerStats>>,
+    busy: BusySince,
+) -> mpsc::Sender<Job> {
+    let (tx, mut rx) = mpsc::channel::<Job>(capacity);
+    match handler {
+        StageHandler::Sync(handler) => {
+            let spawned = std::thread::Builder::new()
+                .name("blivedm-handler".to_string())
+                .spawn(move || {
+                    while let Some(job) = rx.blocking_recv() {
+                        let started = Instant::now();
+                        set_busy(&busy, Some(started));
+                        let context = &job.context;
+                        let result =
+                            std::panic::catch_unwind(AssertUnwindSafe(|| match &job.item {
+                                Item::Message(msg) => handler.handle(msg, context),
+                                Item::Hook(Hook::Start) => handler.on_start(context),
+                                Item::Hook(Hook::Connected) => handler.on_connected(context),
+                                Item::Hook(Hook::Disconnected) => handler.on_disconnected(context),
+                                Item::Hook(Hook::Shutdown) => handler.on_shutdown(context),
+                            }));
+                        set_busy(&busy, None);
+                        if let Item::Message(_) = job.item {
+                            lock_stats(&stats).record_call(started.elapsed());
+                        }
+                        let outcome = if result.is_err() {
+                            log::error!("Event handler {} panicked", handler.name());
+                            Outcome::Panicked
+                        } else {
+                            Outcome::Done
+                        };
+                        let _ = job.done.send(outcome);
+                    }
+                });
+            if let Err(e) = spawned {
+                log::error!("Failed to start event handler thread: {}", e);
+            }
+        }
+        StageHandler::Async(handler) => {
+            runtime.spawn(async move {
+                while let Some(job) = rx.recv().await {
+                    let started = Instant::now();
+                    let context = &job.context;
+                    let call = match &job.item {
+                        Item::Message(msg) => handler.handle(msg, context),
+                        Item::Hook(Hook::Start) => handler.on_start(context),
+                        Item::Hook(Hook::Connected) => handler.on_connected(context),
+                        Item::Hook(Hook::Disconnected) => handler.on_disconnected(context),
+                        Item::Hook(Hook::Shutdown) => handler.on_shutdown(context),
+                    };
+                    let call = AssertUnwindSafe(call).catch_unwind();
+                    let outcome = match timeout {
+                        Some(timeout) => tokio::time::timeout(timeout, call).await.ok(),
+                        None => Some(call.await),
+                    };
+                    if let Item::Message(_) = job.item {
+                        lock_stats(&stats).record_call(started.elapsed());
+                    }
+                    let outcome = match outcome {
+                        Some(Ok(())) => Outcome::Done,
+                        Some(Err(_)) => {
+                            log::error!("Async event handler {} panicked", handler.name());
+                            Outcome::Panicked
+                        }
+                        None => Outcome::TimedOut,
+                    };
+                    let _ = job.done.send(outcome);
+                }
+            });
+        }
+    }
+    tx
+}
+
+/// Apply a middleware's action to a message; `None` when it is dropped
+fn apply(mut envelope: Envelope, action: MiddlewareAction) -> Option<Envelope> {
+    match action {
+        MiddlewareAction::Continue => Some(envelope),
+        MiddlewareAction::Replace(msg) => {
+            envelope.item = Item::Message(Arc::new(msg));
+            Some(envelope)
+        }
+        MiddlewareAction::Drop => {
+            // Whoever waits on the message is done with it
+            if let Some(done) = envelope.done.take() {
+                let _ = done.send(());
+            }
+            None
+        }
+    }
+}
+
+/// Start a middleware stage: a thread for sync middleware, a task for async ones.
+/// A panicking middleware lets the message through unchanged; lifecycle events always
+/// pass.
+fn start_middleware(
+    middleware: MiddlewareHandler,
+    runtime: &Runtime,
+    mut rx: mpsc::Receiver<Envelope>,
+    next: mpsc::Sender<Envelope>,
+) {
+    match middleware {
+        MiddlewareHandler::Sync(middleware) => {
+            let spawned = std::thread::Builder::new()
+                .name("blivedm-middleware".to_string())
+                .spawn(move || {
+                    while let Some(envelope) = rx.blocking_recv() {
+                        let action = match &envelope.item {
+                            Item::Message(msg) => {
+                                std::panic::catch_unwind(AssertUnwindSafe(|| {
+                                    middleware.process(msg, &envelope.context)
+                                }))
+                                .unwrap_or_else(|_| {
+                                    log::error!("Middleware panicked");
+                                    MiddlewareAction::Continue
+                                })
+                            }
+                            Item::Hook(_) => MiddlewareAction::Continue,
+                        };
+                        if let Some(envelope) = apply(envelope, action)
+                            && next.blocking_send(envelope).is_err()
+                        {
+                            break;
+                        }
+                    }
+                });
+            if let Err(e) = spawned {
+                log::error!("Failed to start middleware thread: {}", e);
+            }
+        }
+        MiddlewareHandler::Async(middleware) => {
+            runtime.spawn(async move {
+                while let Some(envelope) = rx.recv().await {
+                    let action = match &envelope.item {
+                        Item::Message(msg) => {
+                            AssertUnwindSafe(middleware.process(msg, &envelope.context))
+                                .catch_unwind()
+                                .await
+                                .unwrap_or_else(|_| {
+                                    log::error!("Async middleware panicked");
+                                    MiddlewareAction::Continue
+                                })
+                        }
+                        Item::Hook(_) => MiddlewareAction::Continue,
+                    };
+                    if let Some(envelope) = apply(envelope, action)
+                        && next.send(envelope).await.is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+        }
+    }
+}
+
 /// Scheduler struct: manages event handlers and dispatches messages.
+///
+/// Every handler has a long-lived worker with a bounded queue, started on first use.
 pub struct Scheduler {
-    /// Each stage is a Vec of handlers to run in parallel; stages run sequentially.
-    stages: Vec<Vec<Arc<dyn EventHandler>>>,
-    /// Context information for event handlers
-    context: EventContext,
+    /// Each stage is a Vec of handlers to run in parallel, or a middleware; stages run sequentially.
+    stages: Vec<Stage>,
+    /// Context information for event handlers, shared so it can be updated while running
+    context: Arc<RwLock<EventContext>>,
+    /// Runtime that drives async handlers and the stage dispatchers
+    runtime: Arc<Runtime>,
+    /// Capacity of each handler's queue
+    queue_capacity: usize,
+    /// Time a handler may take for one message, unless it sets its own
+    handler_timeout: Option<Duration>,
+    /// Failures in a row after which a handler is disabled
+    failure_limit: Option<u32>,
+    /// Stats of all handlers
+    stats: SchedulerStats,
+    /// Set by [`Scheduler::shutdown`]; later messages are ignored
+    closed: AtomicBool,
+    /// Workers for the current stages, started by the first message
+    pipeline: OnceLock<Pipeline>,
 }
 
 impl Scheduler {
     pub fn new(context: EventContext) -> Self {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .thread_name("blivedm-scheduler")
+            .build()
+            .expect("Failed to create tokio runtime");
         Scheduler {
             stages: Vec::new(),
-            context,
+            context: Arc::new(RwLock::new(context)),
+            runtime: Arc::new(runtime),
+            queue_capacity: DEFAULT_QUEUE_CAPACITY,
+            handler_timeout: Some(DEFAULT_HANDLER_TIMEOUT),
+            failure_limit: None,
+            stats: SchedulerStats::default(),
+            closed: AtomicBool::new(false),
+            pipeline: OnceLock::new(),
         }
     }
 
+    /// Set how many messages each handler may fall behind before submitting waits
+    pub fn with_queue_capacity(mut self, capacity: usize) -> Self {
+        self.queue_capacity = capacity.max(1);
+        self.pipeline = OnceLock::new();
+        self
+    }
+
+    /// Set how long a handler may take for one message before its stage moves on
+    /// without it (default 30 seconds); handlers can set their own with `timeout()`.
+    /// `None` waits for handlers however long they take.
+    pub fn with_handler_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.handler_timeout = timeout;
+        self.pipeline = OnceLock::new();
+        self
+    }
+
+    /// Disable a handler after `failures` panics or timeouts in a row.
+    /// Disabled handlers get no messages until re-enabled with [`SchedulerStats::enable`].
+    pub fn with_failure_limit(mut self, failures: u32) -> Self {
+        self.failure_limit = Some(failures.max(1));
+        self.pipeline = OnceLock::new();
+        self
+    }
+
+    /// Invocations, failures and latency of every handler, e.g. for a status panel
+    pub fn stats(&self) -> SchedulerStats {
+        self.stats.clone()
+    }
+
+    /// The runtime handlers run on, for other tasks that should share it
+    pub fn runtime(&self) -> Arc<Runtime> {
+        Arc::clone(&self.runtime)
+    }
+
+    /// Get a handle to the shared context, e.g. to swap in refreshed cookies.
+    /// Changes apply to all messages triggered afterwards.
+    pub fn context_handle(&self) -> Arc<RwLock<EventContext>> {
+        Arc::clone(&self.context)
+    }
+
     /// Add a new stage (group of handlers to run in parallel)
     pub fn add_stage(&mut self, handlers: Vec<Arc<dyn EventHandler>>) {
-        self.stages.push(handlers);
+        self.add_mixed_stage(handlers.into_iter().map(StageHandler::Sync).collect());
     }
 
     /// Add a single handler as a new sequential stage
     pub fn add_sequential_handler(&mut self, handler: Arc<dyn EventHandler>) {
-        self.stages.push(vec![handler]);
+        self.add_mixed_stage(vec![StageHandler::Sync(handler)]);
+    }
+
+    /// Add a single handler as a new sequential stage, only for messages matching `filter`
+    pub fn add_sequential_handler_with_filter(
+        &mut self,
+        handler: Arc<dyn EventHandler>,
+        filter: MessageFilter,
+    ) {
+        self.add_filtered_stage(vec![(StageHandler::Sync(handler), filter)]);
     }
 
-    /// Trigger all stages with the given BiliMessage.
+    /// Add a new stage of async handlers to run concurrently
+    pub fn add_async_stage(&mut self, handlers: Vec<Arc<dyn AsyncEventHandler>>) {
+        self.add_mixed_stage(handlers.into_iter().map(StageHandler::Async).collect());
+    }
+
+    /// Add a single async handler as a new sequential stage
+    pub fn add_async_handler(&mut self, handler: Arc<dyn AsyncEventHandler>) {
+        self.add_mixed_stage(vec![StageHandler::Async(handler)]);
+    }
+
+    /// Add a single async handler as a new sequential stage, only for messages matching `filter`
+    pub fn add_async_handler_with_filter(
+        &mut self,
+        handler: Arc<dyn AsyncEventHandler>,
+        filter: MessageFilter,
+    ) {
+        self.add_filtered_stage(vec![(StageHandler::Async(handler), filter)]);
+    }
+
+    /// Add a new stage mixing sync and async handlers
+    pub fn add_mixed_stage(&mut self, handlers: Vec<StageHandler>) {
+        self.push_handlers(
+            handlers
+                .into_iter()
+                .map(|handler| (handler, None))
+                .collect(),
+        );
+    }
+
+    /// Add a new stage whose handlers each only get the messages matching their filter.
+    ///
+    /// Messages a handler does not want are never queued for it, and the stage does
+    /// not wait on it for them.
+    pub fn add_filtered_stage(&mut self, handlers: Vec<(StageHandler, MessageFilter)>) {
+        self.push_handlers(
+            handlers
+                .into_iter()
+                .map(|(handler, filter)| (handler, Some(filter)))
+                .collect(),
+        );
+    }
+
+    /// Number of stages, including middleware
+    pub fn stage_count(&self) -> usize {
+        self.stages.len()
+    }
+
+    /// Change which messages a handler gets while the scheduler runs; `None` sends it
+    /// every message. Returns whether there is such a handler.
+    ///
+    /// `stage` counts stages from 0 like [`HandlerStats::stage`], and `index` counts the
+    /// handlers of the stage in the order they were added.
+    pub fn set_filter(&self, stage: usize, index: usize, filter: Option<MessageFilter>) -> bool {
+        let Some(entry) = self.entry(stage, index) else {
+            return false;
+        };
+        match entry.filter.write() {
+            Ok(mut current) => *current = filter,
+            Err(poisoned) => *poisoned.into_inner() = filter,
+        }
+        true
+    }
+
+    /// Enable or disable a handler while the scheduler runs, see [`Scheduler::set_filter`]
+    /// for `stage` and `index`. A disabled handler gets no messages, as after repeated
+    /// failures. Returns whether there is such a handler.
+    pub fn set_enabled(&self, stage: usize, index: usize, enabled: bool) -> bool {
+        let Some(entry) = self.entry(stage, index) else {
+            return false;
+        };
+        let mut stats = lock_stats(&entry.stats);
+        stats.disabled = !enabled;
+        if enabled {
+            stats.consecutive_failures = 0;
+        }
+        true
+    }
+
+    fn entry(&self, stage: usize, index: usize) -> Option<&StageEntry> {
+        match self.stages.get(stage)? {
+            Stage::Handlers(entries) => entries.get(index),
+            Stage::Middleware(_) => None,
+        }
+    }
+
+    /// Add a middleware as a new stage.
+    ///
+    /// Stages added after it get the message it returns, or nothing if it drops the
+    /// message; stages added before it are not affected.
+    pub fn add_middleware(&mut self, middleware: Arc<dyn Middleware>) {
+        self.push_stage(Stage::Middleware(MiddlewareHandler::Sync(middleware)));
+    }
+
+    /// Add an async middleware as a new stage
+    pub fn add_async_middleware(&mut self, middleware: Arc<dyn AsyncMiddleware>) {
+        self.push_stage(Stage::Middleware(MiddlewareHandler::Async(middleware)));
+    }
+
+    fn push_handlers(&mut self, handlers: Vec<(StageHandler, Option<MessageFilter>)>) {
+        let stage = self.stages.len();
+        let entries = handlers
+            .into_iter()
+            .map(|(handler, filter)| StageEntry {
+                stats: self.stats.register(handler.name(), stage),
+                handler,
+                filter: Arc::new(RwLock::new(filter)),
+            })
+            .collect();
+        self.push_stage(Stage::Handlers(entries));
+    }
+
+    fn push_stage(&mut self, stage: Stage) {
+        self.stages.push(stage);
+        // Workers are restarted with the new stage on the next message
+        self.pipeline = OnceLock::new();
+    }
+
+    /// Trigger all stages with the given BiliMessage, blocking until they are done.
+    ///
+    /// Must not be called from within an async context; use [`Scheduler::dispatch`] there.
     pub fn trigger(&self, msg: BiliMessage) {
-        for stage in &self.stages {
-            let mut handles = vec![];
-            for handler in stage {
-                let msg = msg.clone();
-                let context = self.context.clone();
-                let handler = Arc::clone(handler);
-                handles.push(std::thread::spawn(move || {
-                    handler.handle(&msg, &context);
-                }));
-            }
-            // Wait for all handlers in this stage to finish before next stage
-            for handle in handles {
-                let _ = handle.join();
-            }
+        self.runtime.block_on(self.dispatch(msg));
+    }
+
+    /// Run all stages with the given BiliMessage and wait until they are done.
+    ///
+    /// Handlers of a stage run concurrently and each stage waits for the previous one.
+    /// Returns early if a middleware drops the message.
+    pub async fn dispatch(&self, msg: BiliMessage) {
+        let (done, wait) = oneshot::channel();
+        if self.send(msg, Some(done)).await {
+            let _ = wait.await;
+        }
+    }
+
+    /// Queue a message for all stages without waiting for the handlers.
+    ///
+    /// Messages are handled in submission order; this only waits when the first
+    /// stage's queues are full.
+    pub async fn submit(&self, msg: BiliMessage) {
+        self.send(msg, None).await;
+    }
+
+    /// Tell handlers the connection to the live room is up (`on_connected`)
+    pub async fn notify_connected(&self) {
+        if !self.closed.load(Ordering::SeqCst) {
+            self.send_item(Item::Hook(Hook::Connected), None).await;
+        }
+    }
+
+    /// Tell handlers the connection to the live room is lost (`on_disconnected`)
+    pub async fn notify_disconnected(&self) {
+        if !self.closed.load(Ordering::SeqCst) {
+            self.send_item(Item::Hook(Hook::Disconnected), None).await;
+        }
+    }
+
+    /// Stop taking messages, let handlers finish the queued ones and run their
+    /// `on_shutdown` hooks, e.g. to finish TTS playback.
+    ///
+    /// Waits at most `timeout` and returns whether everything finished in time. Must
+    /// not be called from within an async context.
+    pub fn shutdown(&self, timeout: Duration) -> bool {
+        if self.closed.swap(true, Ordering::SeqCst) {
+            return true;
+        }
+        self.runtime.block_on(async {
+            let (done, wait) = oneshot::channel();
+            let drained = async {
+                if self.send_item(Item::Hook(Hook::Shutdown), Some(done)).await {
+                    let _ = wait.await;
+                }
+            };
+            tokio::time::timeout(timeout, drained).await.is_ok()
+        })
+    }
+
+    async fn send(&self, msg: BiliMessage, done: Option<oneshot::Sender<()>>) -> bool {
+        if self.closed.load(Ordering::SeqCst) {
+            return false;
         }
+        self.send_item(Item::Message(Arc::new(msg)), done).await
+    }
+
+    async fn send_item(&self, item: Item, done: Option<oneshot::Sender<()>>) -> bool {
+        let context = Arc::new(match self.context.read() {
+            Ok(context) => context.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        });
+        let envelope = Envelope {
+            item,
+            context: Arc::clone(&context),
+            done,
+        };
+        let pipeline = self.pipeline.get_or_init(|| {
+            let settings = WorkerSettings {
+                capacity: self.queue_capacity,
+                timeout: self.handler_timeout,
+                failure_limit: self.failure_limit,
+            };
+            Pipeline::start(&self.stages, &self.runtime, settings, context)
+        });
+        pipeline.input.send(envelope).await.is_ok()
     }
 }
 
@@ -116,6 +1143,7 @@ mod tests {
         };
         let context = super::EventContext {
             cookies: Some("test_cookies".to_string()),
+            send_cookies: None,
             room_id: 12345,
         };
         let mut scheduler = super::Scheduler::new(context);
@@ -124,7 +1152,10 @@ mod tests {
         // Send a test message
         let test_msg = BiliMessage::Danmu {
             user: "user1".to_string(),
+            uid: 0,
             text: "hello".to_string(),
+            segments: Vec::new(),
+            medal: None,
         };
         tx.send(test_msg.clone()).unwrap();
 
@@ -169,6 +1200,7 @@ mod tests {
 
         let context = super::EventContext {
             cookies: Some("test_cookies".to_string()),
+            send_cookies: None,
             room_id: 12345,
         };
         let mut scheduler = super::Scheduler::new(context);
@@ -179,7 +1211,10 @@ mod tests {
 
         let test_msg = BiliMessage::Danmu {
             user: "user2".to_string(),
+            uid: 0,
             text: "test".to_string(),
+            segments: Vec::new(),
+            medal: None,
         };
         scheduler.trigger(test_msg);
 
@@ -189,4 +1224,605 @@ mod tests {
         // handler3 should be called once (sequential stage)
         assert_eq!(counter3.load(Ordering::SeqCst), 1, "Handler3 not called");
     }
+
+    #[test]
+    fn test_scheduler_context_handle_updates_context() {
+        struct CookieHandler {
+            seen: Arc<Mutex<Option<String>>>,
+        }
+        impl super::EventHandler for CookieHandler {
+            fn handle(&self, _msg: &BiliMessage, context: &super::EventContext) {
+                *self.seen.lock().unwrap() = context.cookies.clone();
+            }
+        }
+
+        let seen = Arc::new(Mutex::new(None));
+        let context = super::EventContext::new(Some("old".to_string()), 12345);
+        let mut scheduler = super::Scheduler::new(context);
+        scheduler.add_sequential_handler(Arc::new(CookieHandler {
+            seen: Arc::clone(&seen),
+        }));
+
+        let msg = BiliMessage::Danmu {
+            user: "user".to_string(),
+            uid: 0,
+            text: "text".to_string(),
+            segments: Vec::new(),
+            medal: None,
+        };
+        scheduler.trigger(msg.clone());
+        assert_eq!(seen.lock().unwrap().as_deref(), Some("old"));
+
+        scheduler.context_handle().write().unwrap().cookies = Some("new".to_string());
+        scheduler.trigger(msg);
+        assert_eq!(seen.lock().unwrap().as_deref(), Some("new"));
+    }
+
+    #[test]
+    fn test_scheduler_runs_async_handlers_in_stage_order() {
+        struct RecordHandler {
+            name: &'static str,
+            delay_ms: u64,
+            order: Arc<Mutex<Vec<&'static str>>>,
+        }
+        #[async_trait::async_trait]
+        impl super::AsyncEventHandler for RecordHandler {
+            async fn handle(&self, _msg: &BiliMessage, _context: &super::EventContext) {
+                tokio::time::sleep(std::time::Duration::from_millis(self.delay_ms)).await;
+                self.order.lock().unwrap().push(self.name);
+            }
+        }
+        struct SyncRecordHandler {
+            order: Arc<Mutex<Vec<&'static str>>>,
+        }
+        impl super::EventHandler for SyncRecordHandler {
+            fn handle(&self, _msg: &BiliMessage, _context: &super::EventContext) {
+                self.order.lock().unwrap().push("sync");
+            }
+        }
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let record = |name, delay_ms| {
+            Arc::new(RecordHandler {
+                name,
+                delay_ms,
+                order: Arc::clone(&order),
+            })
+        };
+        let mut scheduler = super::Scheduler::new(super::EventContext::new(None, 12345));
+        scheduler.add_async_stage(vec![record("slow", 50), record("fast", 0)]);
+        scheduler.add_mixed_stage(vec![
+            super::StageHandler::Sync(Arc::new(SyncRecordHandler {
+                order: Arc::clone(&order),
+            })),
+            super::StageHandler::Async(record("last", 20)),
+        ]);
+
+        let msg = BiliMessage::Danmu {
+            user: "user".to_string(),
+            uid: 0,
+            text: "text".to_string(),
+            segments: Vec::new(),
+            medal: None,
+        };
+        scheduler.trigger(msg.clone());
+        // Handlers in a stage run concurrently; the next stage waits for both
+        assert_eq!(*order.lock().unwrap(), vec!["fast", "slow", "sync", "last"]);
+
+        // dispatch can be awaited from tasks on the scheduler's own runtime
+        let scheduler = Arc::new(scheduler);
+        let runtime = scheduler.runtime();
+        let task_scheduler = Arc::clone(&scheduler);
+        runtime.block_on(async move {
+            tokio::spawn(async move { task_scheduler.dispatch(msg).await })
+                .await
+                .unwrap();
+        });
+        assert_eq!(order.lock().unwrap().len(), 8);
+    }
+
+    #[test]
+    fn test_scheduler_pipelines_submitted_messages_in_order() {
+        struct SeenHandler {
+            seen: Arc<Mutex<Vec<String>>>,
+            /// Texts the previous stage must already have seen
+            before: Option<Arc<Mutex<Vec<String>>>>,
+        }
+        impl super::EventHandler for SeenHandler {
+            fn handle(&self, msg: &BiliMessage, _context: &super::EventContext) {
+                let BiliMessage::Danmu { text, .. } = msg else {
+                    return;
+                };
+                if let Some(before) = &self.before {
+                    assert!(before.lock().unwrap().contains(text));
+                }
+                self.seen.lock().unwrap().push(text.clone());
+            }
+        }
+
+        let first = Arc::new(Mutex::new(Vec::new()));
+        let second = Arc::new(Mutex::new(Vec::new()));
+        let mut scheduler =
+            super::Scheduler::new(super::EventContext::new(None, 1)).with_queue_capacity(4);
+        scheduler.add_sequential_handler(Arc::new(SeenHandler {
+            seen: Arc::clone(&first),
+            before: None,
+        }));
+        scheduler.add_sequential_handler(Arc::new(SeenHandler {
+            seen: Arc::clone(&second),
+            before: Some(Arc::clone(&first)),
+        }));
+
+        let danmu = |i: usize| BiliMessage::Danmu {
+            user: "user".to_string(),
+            uid: 0,
+            text: i.to_string(),
+            segments: Vec::new(),
+            medal: None,
+        };
+        let runtime = scheduler.runtime();
+        runtime.block_on(async {
+            for i in 0..100 {
+                scheduler.submit(danmu(i)).await;
+            }
+            scheduler.dispatch(danmu(100)).await;
+        });
+
+        let expected: Vec<String> = (0..=100).map(|i| i.to_string()).collect();
+        assert_eq!(*first.lock().unwrap(), expected);
+        assert_eq!(*second.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_scheduler_survives_handler_panic() {
+        struct PanicOnce {
+            calls: Arc<AtomicUsize>,
+        }
+        impl super::EventHandler for PanicOnce {
+            fn handle(&self, _msg: &BiliMessage, _context: &super::EventContext) {
+                if self.calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                    panic!("first message");
+                }
+            }
+        }
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut scheduler = super::Scheduler::new(super::EventContext::new(None, 1));
+        scheduler.add_sequential_handler(Arc::new(PanicOnce {
+            calls: Arc::clone(&calls),
+        }));
+        let msg = BiliMessage::Raw(serde_json::json!({"cmd": "TEST"}));
+        scheduler.trigger(msg.clone());
+        scheduler.trigger(msg);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let stats = scheduler.stats().snapshot();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].name, "PanicOnce");
+        assert_eq!(stats[0].invocations, 2);
+        assert_eq!(stats[0].panics, 1);
+        assert!(stats[0].is_healthy());
+    }
+
+    #[test]
+    fn test_scheduler_times_out_and_disables_failing_handlers() {
+        use std::time::{Duration, Instant};
+
+        /// A handler behind a server that never answers; says when it is shut down
+        struct Hanging(std::sync::mpsc::Sender<()>);
+        #[super::async_trait]
+        impl super::AsyncEventHandler for Hanging {
+            async fn handle(&self, _msg: &BiliMessage, _context: &super::EventContext) {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+            }
+
+            async fn on_shutdown(&self, _context: &super::EventContext) {
+                let _ = self.0.send(());
+            }
+        }
+
+        /// A sync handler that blocks for a while and cannot be cancelled
+        struct Slow;
+        impl super::EventHandler for Slow {
+            fn handle(&self, _msg: &BiliMessage, _context: &super::EventContext) {
+                std::thread::sleep(Duration::from_millis(300));
+            }
+
+            fn timeout(&self) -> Option<Duration> {
+                Some(Duration::from_millis(50))
+            }
+        }
+
+        let after = Arc::new(AtomicUsize::new(0));
+        struct Count(Arc<AtomicUsize>);
+        impl super::EventHandler for Count {
+            fn handle(&self, _msg: &BiliMessage, _context: &super::EventContext) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let mut scheduler = super::Scheduler::new(super::EventContext::new(None, 1))
+            .with_handler_timeout(Some(Duration::from_millis(50)))
+            .with_failure_limit(2);
+        let (shut_down, hanging_shut_down) = std::sync::mpsc::channel();
+        scheduler.add_async_handler(Arc::new(Hanging(shut_down)));
+        scheduler.add_sequential_handler(Arc::new(Slow));
+        scheduler.add_sequential_handler(Arc::new(Count(Arc::clone(&after))));
+
+        let msg = BiliMessage::Raw(serde_json::json!({"cmd": "TEST"}));
+        let started = Instant::now();
+        scheduler.trigger(msg.clone());
+        assert!(started.elapsed() < Duration::from_millis(250));
+        scheduler.trigger(msg.clone());
+        scheduler.trigger(msg.clone());
+        assert_eq!(after.load(Ordering::SeqCst), 3);
+
+        let stats = scheduler.stats().snapshot();
+        let hanging = &stats[0];
+        assert_eq!(hanging.name, "Hanging");
+        assert_eq!(hanging.timeouts, 2);
+        assert!(hanging.disabled);
+        assert_eq!(hanging.skipped, 1);
+        assert!(stats[1].timeouts >= 1);
+
+        assert!(scheduler.stats().enable("Hanging"));
+        scheduler.trigger(msg);
+        // The worker records the call when its own timeout fires, which can be just
+        // after the stage stopped waiting; its shutdown hook runs after that
+        assert!(scheduler.shutdown(Duration::from_secs(5)));
+        hanging_shut_down.recv().unwrap();
+        let hanging = &scheduler.stats().snapshot()[0];
+        assert_eq!(hanging.invocations, 3);
+        assert!(!hanging.disabled);
+    }
+
+    #[test]
+    fn test_scheduler_skips_handlers_stuck_past_their_timeout() {
+        use std::time::{Duration, Instant};
+
+        /// A sync handler stuck in its first call until released
+        struct Stuck(Mutex<std::sync::mpsc::Receiver<()>>);
+        impl super::EventHandler for Stuck {
+            fn handle(&self, _msg: &BiliMessage, _context: &super::EventContext) {
+                let _ = self.0.lock().unwrap().recv();
+            }
+        }
+
+        let (release, stuck) = std::sync::mpsc::channel();
+        let mut scheduler = super::Scheduler::new(super::EventContext::new(None, 1))
+            .with_handler_timeout(Some(Duration::from_millis(100)));
+        scheduler.add_sequential_handler(Arc::new(Stuck(Mutex::new(stuck))));
+
+        // Without a failure limit, only the first message waits out the timeout
+        let msg = BiliMessage::Raw(serde_json::json!({"cmd": "TEST"}));
+        let started = Instant::now();
+        for _ in 0..3 {
+            scheduler.runtime().block_on(scheduler.submit(msg.clone()));
+        }
+        scheduler.trigger(msg.clone());
+        for _ in 0..6 {
+            scheduler.trigger(msg.clone());
+        }
+        assert!(started.elapsed() < Duration::from_millis(500));
+        let stats = &scheduler.stats().snapshot()[0];
+        assert_eq!(stats.timeouts, 10);
+        assert!(!stats.disabled);
+
+        // Once it returns, it gets messages again
+        release.send(()).unwrap();
+        drop(release);
+        std::thread::sleep(Duration::from_millis(50));
+        scheduler.trigger(msg);
+        let stats = &scheduler.stats().snapshot()[0];
+        assert_eq!(stats.timeouts, 10);
+        assert_eq!(stats.consecutive_failures, 0);
+    }
+
+    #[test]
+    fn test_scheduler_skips_filtered_handlers() {
+        use crate::filter::{MessageFilter, MessageKind};
+
+        struct CmdHandler {
+            seen: Arc<Mutex<Vec<String>>>,
+        }
+        impl super::EventHandler for CmdHandler {
+            fn handle(&self, msg: &BiliMessage, _context: &super::EventContext) {
+                if let BiliMessage::Raw(json) = msg {
+                    let cmd = json["cmd"].as_str().unwrap_or_default().to_string();
+                    self.seen.lock().unwrap().push(cmd);
+                }
+            }
+        }
+
+        let all = Arc::new(Mutex::new(Vec::new()));
+        let guards = Arc::new(Mutex::new(Vec::new()));
+        let mut scheduler = super::Scheduler::new(super::EventContext::new(None, 1));
+        scheduler.add_filtered_stage(vec![
+            (
+                super::StageHandler::Sync(Arc::new(CmdHandler {
+                    seen: Arc::clone(&all),
+                })),
+                MessageFilter::new(),
+            ),
+            (
+                super::StageHandler::Sync(Arc::new(CmdHandler {
+                    seen: Arc::clone(&guards),
+                })),
+                MessageFilter::new().with_cmds(["GUARD_BUY"]),
+            ),
+        ]);
+        scheduler.add_sequential_handler_with_filter(
+            Arc::new(CmdHandler {
+                seen: Arc::clone(&guards),
+            }),
+            MessageFilter::new().with_kinds([MessageKind::Danmu]),
+        );
+
+        for cmd in ["INTERACT_WORD", "GUARD_BUY", "LIKE_INFO_V3_CLICK"] {
+            scheduler.trigger(BiliMessage::Raw(serde_json::json!({ "cmd": cmd })));
+        }
+        assert_eq!(
+            *all.lock().unwrap(),
+            vec!["INTERACT_WORD", "GUARD_BUY", "LIKE_INFO_V3_CLICK"]
+        );
+        assert_eq!(*guards.lock().unwrap(), vec!["GUARD_BUY"]);
+    }
+
+    #[test]
+    fn test_scheduler_changes_filters_and_enabled_handlers_while_running() {
+        use crate::filter::{MessageFilter, MessageKind};
+
+        let count = Arc::new(AtomicUsize::new(0));
+        struct Count(Arc<AtomicUsize>);
+        impl super::EventHandler for Count {
+            fn handle(&self, _msg: &BiliMessage, _context: &super::EventContext) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        struct Pass;
+        impl super::Middleware for Pass {
+            fn process(
+                &self,
+                _msg: &BiliMessage,
+                _context: &super::EventContext,
+            ) -> super::MiddlewareAction {
+                super::MiddlewareAction::Continue
+            }
+        }
+
+        let mut scheduler = super::Scheduler::new(super::EventContext::new(None, 1));
+        scheduler.add_middleware(Arc::new(Pass));
+        scheduler.add_sequential_handler_with_filter(
+            Arc::new(Count(Arc::clone(&count))),
+            MessageFilter::new().with_kinds([MessageKind::Gift]),
+        );
+        assert_eq!(scheduler.stage_count(), 2);
+        assert!(!scheduler.set_filter(0, 0, None));
+        assert!(!scheduler.set_enabled(1, 1, false));
+
+        let msg = BiliMessage::Raw(serde_json::json!({"cmd": "TEST"}));
+        scheduler.trigger(msg.clone());
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+
+        assert!(scheduler.set_filter(1, 0, None));
+        scheduler.trigger(msg.clone());
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        assert!(scheduler.set_enabled(1, 0, false));
+        scheduler.trigger(msg.clone());
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        assert!(scheduler.stats().snapshot()[0].disabled);
+
+        assert!(scheduler.set_enabled(1, 0, true));
+        scheduler.trigger(msg);
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_scheduler_middleware_replaces_and_drops_messages() {
+        use super::{AsyncMiddleware, Middleware, MiddlewareAction};
+
+        /// Drops spam and masks a sensitive word
+        struct Moderator;
+        impl Middleware for Moderator {
+            fn process(
+                &self,
+                msg: &BiliMessage,
+                _context: &super::EventContext,
+            ) -> MiddlewareAction {
+                let BiliMessage::Raw(json) = msg else {
+                    return MiddlewareAction::Continue;
+                };
+                let text = json["text"].as_str().unwrap_or_default();
+                if text.contains("spam") {
+                    MiddlewareAction::Drop
+                } else if text.contains("bad") {
+                    let masked = text.replace("bad", "***");
+                    MiddlewareAction::Replace(BiliMessage::Raw(
+                        serde_json::json!({ "text": masked }),
+                    ))
+                } else {
+                    MiddlewareAction::Continue
+                }
+            }
+        }
+
+        struct Shout;
+        #[super::async_trait]
+        impl AsyncMiddleware for Shout {
+            async fn process(
+                &self,
+                msg: &BiliMessage,
+                _context: &super::EventContext,
+            ) -> MiddlewareAction {
+                let BiliMessage::Raw(json) = msg else {
+                    return MiddlewareAction::Continue;
+                };
+                let text = json["text"].as_str().unwrap_or_default().to_uppercase();
+                MiddlewareAction::Replace(BiliMessage::Raw(serde_json::json!({ "text": text })))
+            }
+        }
+
+        struct TextHandler {
+            seen: Arc<Mutex<Vec<String>>>,
+        }
+        impl super::EventHandler for TextHandler {
+            fn handle(&self, msg: &BiliMessage, _context: &super::EventContext) {
+                if let BiliMessage::Raw(json) = msg {
+                    let text = json["text"].as_str().unwrap_or_default().to_string();
+                    self.seen.lock().unwrap().push(text);
+                }
+            }
+        }
+
+        let before = Arc::new(Mutex::new(Vec::new()));
+        let after = Arc::new(Mutex::new(Vec::new()));
+        let mut scheduler = super::Scheduler::new(super::EventContext::new(None, 1));
+        scheduler.add_sequential_handler(Arc::new(TextHandler {
+            seen: Arc::clone(&before),
+        }));
+        scheduler.add_middleware(Arc::new(Moderator));
+        scheduler.add_async_middleware(Arc::new(Shout));
+        scheduler.add_sequential_handler(Arc::new(TextHandler {
+            seen: Arc::clone(&after),
+        }));
+
+        for text in ["hi", "buy spam", "bad word"] {
+            scheduler.trigger(BiliMessage::Raw(serde_json::json!({ "text": text })));
+        }
+        assert_eq!(*before.lock().unwrap(), vec!["hi", "buy spam", "bad word"]);
+        assert_eq!(*after.lock().unwrap(), vec!["HI", "*** WORD"]);
+    }
+
+    #[test]
+    fn test_scheduler_lifecycle_hooks_and_shutdown() {
+        use std::time::Duration;
+
+        struct Lifecycle {
+            events: Arc<Mutex<Vec<String>>>,
+        }
+        impl Lifecycle {
+            fn record(&self, event: &str) {
+                self.events.lock().unwrap().push(event.to_string());
+            }
+        }
+        impl super::EventHandler for Lifecycle {
+            fn handle(&self, _msg: &BiliMessage, _context: &super::EventContext) {
+                self.record("message");
+            }
+            fn on_start(&self, _context: &super::EventContext) {
+                self.record("start");
+            }
+            fn on_connected(&self, _context: &super::EventContext) {
+                self.record("connected");
+            }
+            fn on_disconnected(&self, _context: &super::EventContext) {
+                self.record("disconnected");
+            }
+            fn on_shutdown(&self, _context: &super::EventContext) {
+                self.record("shutdown");
+            }
+        }
+
+        /// Like TTS: playback still running when shutdown starts
+        struct Player {
+            finished: Arc<AtomicBool>,
+            playback: Duration,
+        }
+        #[super::async_trait]
+        impl super::AsyncEventHandler for Player {
+            async fn handle(&self, _msg: &BiliMessage, _context: &super::EventContext) {}
+            async fn on_shutdown(&self, _context: &super::EventContext) {
+                tokio::time::sleep(self.playback).await;
+                self.finished.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let finished = Arc::new(AtomicBool::new(false));
+        let mut scheduler = super::Scheduler::new(super::EventContext::new(None, 1));
+        scheduler.add_sequential_handler(Arc::new(Lifecycle {
+            events: Arc::clone(&events),
+        }));
+        scheduler.add_async_handler(Arc::new(Player {
+            finished: Arc::clone(&finished),
+            playback: Duration::from_millis(100),
+        }));
+
+        let msg = BiliMessage::Raw(serde_json::json!({"cmd": "TEST"}));
+        let runtime = scheduler.runtime();
+        runtime.block_on(scheduler.notify_connected());
+        scheduler.trigger(msg.clone());
+        runtime.block_on(scheduler.notify_disconnected());
+        runtime.block_on(scheduler.submit(msg.clone()));
+        assert!(scheduler.shutdown(Duration::from_secs(5)));
+        assert!(finished.load(Ordering::SeqCst));
+
+        // Messages after shutdown are ignored
+        scheduler.trigger(msg);
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                "start",
+                "connected",
+                "message",
+                "disconnected",
+                "message",
+                "shutdown"
+            ]
+        );
+
+        let mut scheduler = super::Scheduler::new(super::EventContext::new(None, 1));
+        scheduler.add_async_handler(Arc::new(Player {
+            finished: Arc::new(AtomicBool::new(false)),
+            playback: Duration::from_secs(10),
+        }));
+        assert!(!scheduler.shutdown(Duration::from_millis(50)));
+    }
+
+    #[test]
+    fn test_scheduler_hook_failures_do_not_disable_handlers() {
+        use std::time::Duration;
+
+        let handled = Arc::new(AtomicUsize::new(0));
+        struct BrokenHooks(Arc<AtomicUsize>);
+        impl super::EventHandler for BrokenHooks {
+            fn handle(&self, _msg: &BiliMessage, _context: &super::EventContext) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+            fn on_connected(&self, _context: &super::EventContext) {
+                panic!("connected hook failed");
+            }
+            fn on_shutdown(&self, _context: &super::EventContext) {
+                panic!("shutdown hook failed");
+            }
+        }
+
+        let mut scheduler =
+            super::Scheduler::new(super::EventContext::new(None, 1)).with_failure_limit(1);
+        scheduler.add_sequential_handler(Arc::new(BrokenHooks(Arc::clone(&handled))));
+
+        let runtime = scheduler.runtime();
+        runtime.block_on(scheduler.notify_connected());
+        scheduler.trigger(BiliMessage::Raw(serde_json::json!({"cmd": "TEST"})));
+        assert_eq!(handled.load(Ordering::SeqCst), 1);
+        assert!(scheduler.shutdown(Duration::from_secs(5)));
+
+        let stats = &scheduler.stats().snapshot()[0];
+        assert_eq!(stats.hook_failures, 2);
+        assert_eq!(stats.panics, 0);
+        assert_eq!(stats.consecutive_failures, 0);
+        assert!(!stats.disabled);
+    }
+
+    #[test]
+    fn test_event_context_sending_cookies() {
+        let context = super::EventContext::new(Some("reader".to_string()), 1);
+        assert_eq!(context.sending_cookies(), Some("reader"));
+
+        let context = context.with_send_cookies(Some("bot".to_string()));
+        assert_eq!(context.sending_cookies(), Some("bot"));
+        assert_eq!(context.cookies.as_deref(), Some("reader"));
+    }
 }
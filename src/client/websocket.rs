@@ -11,14 +11,31 @@ use url::Url;
 use futures_channel::mpsc::Sender;
 use http::Response;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use crate::auth::*;
-use crate::models::{AuthMessage, BiliMessage, DanmuServer, MsgHead};
+use crate::browser_cookies::BrowserSelection;
+use crate::gifts::GiftCatalog;
+use crate::models::{
+    AuthMessage, BiliMessage, CoinType, DanmuServer, FanMedal, GiftValue, MsgHead,
+    parse_danmu_segments,
+};
+
+/// The TLS WebSocket connection to a danmaku server
+pub type DanmuSocket = WebSocket<TlsStream<TcpStream>>;
+
+/// The server's response to the WebSocket handshake
+pub type DanmuHandshake = Response<Option<Vec<u8>>>;
 
 pub struct BiliLiveClient {
-    ws: WebSocket<TlsStream<TcpStream>>,
+    ws: DanmuSocket,
     auth_msg: String,
     ss: Sender<BiliMessage>,
+    gift_catalog: Option<Arc<GiftCatalog>>,
+    /// Kept to establish the connection again in [`BiliLiveClient::reconnect`]
+    cookies: Option<String>,
+    browser: Option<BrowserSelection>,
+    room_id: String,
 }
 
 impl BiliLiveClient {
@@ -29,6 +46,10 @@ impl BiliLiveClient {
             ws,
             auth_msg: serde_json::to_string(&auth).unwrap(),
             ss: r,
+            gift_catalog: None,
+            cookies: Some(cookies.to_string()),
+            browser: None,
+            room_id: room_id.to_string(),
         }
     }
 
@@ -39,15 +60,57 @@ impl BiliLiveClient {
         room_id: &str,
         r: Sender<BiliMessage>,
     ) -> Result<Self, String> {
-        let (v, auth) = init_server_auto(cookies, room_id)?;
-        let (ws, _res) = connect(v["host_list"].clone());
+        Self::new_auto_with_browser(cookies, None, room_id, r)
+    }
+
+    /// Like [`BiliLiveClient::new_auto`], but only detects cookies in the given browser
+    pub fn new_auto_with_browser(
+        cookies: Option<&str>,
+        browser: Option<&BrowserSelection>,
+        room_id: &str,
+        r: Sender<BiliMessage>,
+    ) -> Result<Self, String> {
+        let (v, auth) = init_server_auto_in(cookies, browser, room_id)?;
+        let (ws, _res) = try_connect(v["host_list"].clone())?;
         Ok(BiliLiveClient {
             ws,
             auth_msg: serde_json::to_string(&auth).unwrap(),
             ss: r,
+            gift_catalog: None,
+            cookies: cookies.map(str::to_string),
+            browser: browser.cloned(),
+            room_id: room_id.to_string(),
         })
     }
 
+    /// Replace the cookies used by the next [`BiliLiveClient::reconnect`], e.g. after a refresh
+    pub fn set_cookies(&mut self, cookies: Option<String>) {
+        self.cookies = cookies;
+    }
+
+    /// Establish a new connection to the room, authenticate and send a heartbeat.
+    ///
+    /// Cookies are resolved again, so the connection picks up newly detected cookies.
+    pub fn reconnect(&mut self) -> Result<(), String> {
+        let (v, auth) = init_server_auto_in(
+            self.cookies.as_deref(),
+            self.browser.as_ref(),
+            &self.room_id,
+        )?;
+        let (ws, _res) = try_connect(v["host_list"].clone())?;
+        self.ws = ws;
+        self.auth_msg = serde_json::to_string(&auth).map_err(|e| e.to_string())?;
+        self.send_auth();
+        self.send_heart_beat();
+        Ok(())
+    }
+
+    /// Enrich gift events with prices from a gift catalog
+    pub fn with_gift_catalog(mut self, catalog: Arc<GiftCatalog>) -> Self {
+        self.gift_catalog = Some(catalog);
+        self
+    }
+
     pub fn send_auth(&mut self) {
         let _ = self.ws.send(Message::Binary(make_packet(
             self.auth_msg.as_str(),
@@ -102,6 +165,10 @@ impl BiliLiveClient {
                 let s = String::from_utf8(b.to_vec()).unwrap();
                 let res_json: Value = serde_json::from_str(s.as_str()).unwrap();
                 if let Some(msg) = handle(res_json) {
+                    let msg = match &self.gift_catalog {
+                        Some(catalog) => catalog.enrich(msg),
+                        None => msg,
+                    };
                     let _ = self.ss.try_send(msg);
                 }
             } else {
@@ -128,7 +195,7 @@ impl BiliLiveClient {
                 Err(e) => Err(format!("read msg error: {}", e)),
             }
         } else {
-            Ok(())
+            Err("connection closed".to_string())
         }
     }
 }
@@ -161,11 +228,20 @@ fn find_server(vd: Vec<DanmuServer>) -> (String, String, String) {
 }
 
 pub fn init_server(cookies: &str, room_id: &str) -> (Value, AuthMessage) {
+    try_init_server(cookies, room_id).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Like [`init_server`], but returns an error instead of panicking when a request fails
+pub fn try_init_server(cookies: &str, room_id: &str) -> Result<(Value, AuthMessage), String> {
+    let room_id_num = room_id
+        .parse::<u64>()
+        .map_err(|e| format!("invalid room id {:?}: {}", room_id, e))?;
     let mut auth_map = HashMap::new();
     let mut headers = reqwest::header::HeaderMap::new();
     headers.insert(
         reqwest::header::COOKIE,
-        reqwest::header::HeaderValue::from_str(cookies).unwrap(),
+        reqwest::header::HeaderValue::from_str(cookies)
+            .map_err(|e| format!("invalid cookies: {}", e))?,
     );
     headers.insert(
         reqwest::header::USER_AGENT,
@@ -189,17 +265,21 @@ pub fn init_server(cookies: &str, room_id: &str) -> (Value, AuthMessage) {
         .unwrap_or_else(|| "".to_string());
 
     if !sessdata.is_empty() {
-        let (_, body1) = init_uid(headers.clone());
-        let body1_v: Value = serde_json::from_str(body1.as_str()).unwrap();
+        let (_, body1) = try_init_uid(headers.clone())?;
+        let body1_v: Value = serde_json::from_str(body1.as_str())
+            .map_err(|e| format!("invalid nav response: {}", e))?;
 
         // Check if the authentication was successful
-        if let Some(mid) = body1_v["data"]["mid"].as_i64() {
-            auth_map.insert("uid".to_string(), mid.to_string());
-            log::info!("Successfully authenticated with uid: {}", mid);
-        } else {
-            log::warn!("Authentication failed - SESSDATA may be invalid or expired");
-            log::debug!("Auth response: {}", body1);
-            auth_map.insert("uid".to_string(), "0".to_string());
+        match parse_nav_response(&body1_v) {
+            AuthStatus::LoggedIn(user) => {
+                auth_map.insert("uid".to_string(), user.uid.to_string());
+                log::info!("Successfully authenticated as {}", user.display_label());
+            }
+            AuthStatus::NotLoggedIn { reason } => {
+                log::warn!("Authentication failed - {}", reason);
+                log::debug!("Auth response: {}", body1);
+                auth_map.insert("uid".to_string(), "0".to_string());
+            }
         }
     } else {
         auth_map.insert("uid".to_string(), "0".to_string());
@@ -207,27 +287,39 @@ pub fn init_server(cookies: &str, room_id: &str) -> (Value, AuthMessage) {
     // here the live room id is easily obtained, so we not get it by url.
     auth_map.insert("room_id".to_string(), room_id.to_string());
 
-    let room_id_num = room_id.parse::<u64>().expect("room_id must be a valid u64");
-    let (_, body4) = init_host_server(headers.clone(), room_id_num);
-    let body4_res: Value = serde_json::from_str(body4.as_str()).unwrap();
+    let (_, body4) = try_init_host_server(headers.clone(), room_id_num)?;
+    let body4_res: Value = serde_json::from_str(body4.as_str())
+        .map_err(|e| format!("invalid danmaku server response: {}", e))?;
     let server_info = &body4_res["data"];
-    let token = &body4_res["data"]["token"].as_str().unwrap();
+    let token = body4_res["data"]["token"]
+        .as_str()
+        .ok_or_else(|| format!("no token in danmaku server response: {}", body4))?;
     auth_map.insert("token".to_string(), token.to_string());
 
     let auth_msg = AuthMessage::from(&auth_map);
-    (server_info.clone(), auth_msg)
+    Ok((server_info.clone(), auth_msg))
+}
+
+pub fn connect(v: Value) -> (DanmuSocket, DanmuHandshake) {
+    try_connect(v).unwrap_or_else(|e| panic!("{}", e))
 }
 
-pub fn connect(v: Value) -> (WebSocket<TlsStream<TcpStream>>, Response<Option<Vec<u8>>>) {
+/// Like [`connect`], but returns an error instead of panicking when the connection fails
+pub fn try_connect(v: Value) -> Result<(DanmuSocket, DanmuHandshake), String> {
+    if !v.is_array() {
+        return Err(format!("no danmaku server list: {}", v));
+    }
     let danmu_server = gen_damu_list(&v);
     let (host, url, ws_url) = find_server(danmu_server);
-    let connector: native_tls::TlsConnector = native_tls::TlsConnector::new().unwrap();
-    let stream: TcpStream = TcpStream::connect(url).unwrap();
-    let stream: native_tls::TlsStream<TcpStream> =
-        connector.connect(host.as_str(), stream).unwrap();
-    let (socket, resp) =
-        client(Url::parse(ws_url.as_str()).unwrap(), stream).expect("Can't connect");
-    (socket, resp)
+    let connector: native_tls::TlsConnector =
+        native_tls::TlsConnector::new().map_err(|e| format!("TLS setup failed: {}", e))?;
+    let stream: TcpStream =
+        TcpStream::connect(&url).map_err(|e| format!("Can't connect to {}: {}", url, e))?;
+    let stream: native_tls::TlsStream<TcpStream> = connector
+        .connect(host.as_str(), stream)
+        .map_err(|e| format!("TLS handshake with {} failed: {}", host, e))?;
+    let ws_url = Url::parse(ws_url.as_str()).map_err(|e| e.to_string())?;
+    client(ws_url, stream).map_err(|e| format!("Can't connect: {}", e))
 }
 
 pub enum Operation {
@@ -307,19 +399,16 @@ pub fn decompress(body: &[u8]) -> std::io::Result<Vec<u8>> {
 pub fn handle(json: Value) -> Option<BiliMessage> {
     let category = json["cmd"].as_str().unwrap_or("");
     match category {
-        "DANMU_MSG" => Some(BiliMessage::Danmu {
-            user: json["info"][2][1]
+        "DANMU_MSG" => Some(parse_danmu_msg(&json["info"])),
+        "SEND_GIFT" => Some(parse_send_gift(&json["data"])),
+        "SUPER_CHAT_MESSAGE" => Some(BiliMessage::SuperChat {
+            user: json["data"]["user_info"]["uname"]
                 .as_str()
                 .unwrap_or("<unknown>")
                 .to_string(),
-            text: json["info"][1].as_str().unwrap_or("").to_string(),
-        }),
-        "SEND_GIFT" => Some(BiliMessage::Gift {
-            user: json["info"][2][1]
-                .as_str()
-                .unwrap_or("<unknown>")
-                .to_string(),
-            gift: json["info"][1].as_str().unwrap_or("").to_string(),
+            uid: json["data"]["uid"].as_u64().unwrap_or(0),
+            message: json["data"]["message"].as_str().unwrap_or("").to_string(),
+            price: json["data"]["price"].as_u64().unwrap_or(0),
         }),
         "ONLINE_RANK_COUNT" => Some(BiliMessage::OnlineRankCount {
             count: json["data"]["count"].as_u64().unwrap_or(0),
@@ -330,22 +419,70 @@ pub fn handle(json: Value) -> Option<BiliMessage> {
     }
 }
 
+/// Parse the `info` of a DANMU_MSG message
+///
+/// `info[0][13]` is the sticker of a sticker danmaku, and `info[0][15].extra` a JSON
+/// string holding the inline emotes (`emots`).
+fn parse_danmu_msg(info: &Value) -> BiliMessage {
+    let text = info[1].as_str().unwrap_or("");
+    let extra: Value = info[0][15]["extra"]
+        .as_str()
+        .and_then(|extra| serde_json::from_str(extra).ok())
+        .unwrap_or_default();
+    BiliMessage::Danmu {
+        user: info[2][1].as_str().unwrap_or("<unknown>").to_string(),
+        uid: info[2][0].as_u64().unwrap_or(0),
+        text: text.to_string(),
+        segments: parse_danmu_segments(text, &info[0][13], &extra["emots"]),
+        medal: FanMedal::parse(&info[3]),
+    }
+}
+
+/// Parse the `data` of a SEND_GIFT message
+fn parse_send_gift(data: &Value) -> BiliMessage {
+    let value =
+        CoinType::parse(data["coin_type"].as_str().unwrap_or("")).map(|coin_type| GiftValue {
+            price: data["price"].as_u64().unwrap_or(0),
+            coin_type,
+            blind_box_price: data["blind_gift"]["original_gift_price"].as_u64(),
+        });
+    BiliMessage::Gift {
+        user: data["uname"].as_str().unwrap_or("<unknown>").to_string(),
+        uid: data["uid"].as_u64().unwrap_or(0),
+        gift: data["giftName"].as_str().unwrap_or("").to_string(),
+        gift_id: data["giftId"].as_u64().unwrap_or(0),
+        num: data["num"].as_u64().unwrap_or(1),
+        value,
+    }
+}
+
 /// Enhanced init_server that can automatically detect cookies from browser
 pub fn init_server_auto(
     provided_cookies: Option<&str>,
     room_id: &str,
 ) -> Result<(Value, AuthMessage), String> {
-    // Try to get cookies from provided value or browser cookies
-    let cookies = get_cookies_or_browser(provided_cookies)
-        .ok_or_else(|| "No cookies found in provided value or browser cookies. Please log into bilibili.com in your browser or provide cookies manually.".to_string())?;
+    init_server_auto_in(provided_cookies, None, room_id)
+}
+
+/// Like [`init_server_auto`], but only detects cookies in the given browser selection
+pub fn init_server_auto_in(
+    provided_cookies: Option<&str>,
+    browser: Option<&BrowserSelection>,
+    room_id: &str,
+) -> Result<(Value, AuthMessage), String> {
+    // Use the provided cookies as-is, only fall back to auto-detection without SESSDATA
+    let cookies = match provided_cookies.filter(|c| c.contains("SESSDATA=")) {
+        Some(cookies) => cookies.to_string(),
+        None => get_cookies_or_browser_in(provided_cookies, browser)
+            .ok_or_else(|| "No cookies found in provided value or browser cookies. Please log into bilibili.com in your browser or provide cookies manually.".to_string())?,
+    };
 
     log::info!(
         "Using cookies for authentication: {}...",
         &cookies[..10.min(cookies.len())]
     );
 
-    let result = init_server(&cookies, room_id);
-    Ok(result)
+    try_init_server(&cookies, room_id)
 }
 
 #[cfg(test)]
@@ -367,4 +504,99 @@ mod tests {
         let (tx, _rx) = channel(10);
         let _client = BiliLiveClient::new(&cookies, room_id, tx);
     }
+
+    #[test]
+    fn test_handle_danmu_msg() {
+        let json = serde_json::json!({
+            "cmd": "DANMU_MSG",
+            "info": [[0, 1, 25, 16777215], "hello", [12345, "viewer", 0], [21, "粉丝团", "主播", 6, 1725515]]
+        });
+        assert_eq!(
+            handle(json),
+            Some(BiliMessage::Danmu {
+                user: "viewer".to_string(),
+                uid: 12345,
+                text: "hello".to_string(),
+                segments: Vec::new(),
+                medal: Some(FanMedal {
+                    name: "粉丝团".to_string(),
+                    level: 21,
+                    room_id: 6,
+                }),
+            })
+        );
+    }
+
+    #[test]
+    fn test_handle_danmu_msg_with_emotes() {
+        let extra = serde_json::json!({
+            "content": "hi[dog]",
+            "emots": {"[dog]": {"descript": "[dog]", "emoticon_unique": "emoji_208", "url": "https://i0.hdslb.com/dog.png", "width": 20, "height": 20}}
+        });
+        let mut meta = vec![serde_json::json!(0); 16];
+        meta[13] = serde_json::json!("{}");
+        meta[15] = serde_json::json!({"extra": extra.to_string()});
+        let json = serde_json::json!({
+            "cmd": "DANMU_MSG",
+            "info": [meta, "hi[dog]", [12345, "viewer", 0]]
+        });
+        let Some(BiliMessage::Danmu { segments, .. }) = handle(json) else {
+            panic!("expected a danmu");
+        };
+        assert_eq!(segments.len(), 2);
+        assert!(matches!(&segments[1], crate::models::DanmuSegment::Emote(e) if e.name == "dog"));
+
+        let mut meta = vec![serde_json::json!(0); 16];
+        meta[12] = serde_json::json!(1);
+        meta[13] = serde_json::json!({"emoticon_unique": "upower_1", "url": "https://i0.hdslb.com/zan.png", "width": 183, "height": 162});
+        let json = serde_json::json!({
+            "cmd": "DANMU_MSG",
+            "info": [meta, "赞", [12345, "viewer", 0]]
+        });
+        let Some(BiliMessage::Danmu { segments, .. }) = handle(json) else {
+            panic!("expected a danmu");
+        };
+        assert!(
+            matches!(&segments[..], [crate::models::DanmuSegment::Emote(e)] if e.url.ends_with("zan.png"))
+        );
+    }
+
+    #[test]
+    fn test_handle_send_gift() {
+        let json = serde_json::json!({
+            "cmd": "SEND_GIFT",
+            "data": {
+                "uname": "viewer",
+                "uid": 12345,
+                "giftName": "浪漫城堡",
+                "giftId": 32124,
+                "num": 1,
+                "price": 2000000,
+                "coin_type": "gold",
+                "blind_gift": {"original_gift_id": 32251, "original_gift_price": 15000}
+            }
+        });
+        assert_eq!(
+            handle(json),
+            Some(BiliMessage::Gift {
+                user: "viewer".to_string(),
+                uid: 12345,
+                gift: "浪漫城堡".to_string(),
+                gift_id: 32124,
+                num: 1,
+                value: Some(GiftValue {
+                    price: 2000000,
+                    coin_type: CoinType::Gold,
+                    blind_box_price: Some(15000),
+                }),
+            })
+        );
+    }
+
+    #[test]
+    fn test_try_init_server_rejects_invalid_room_id() {
+        // Checked before any request is made
+        let error = try_init_server("SESSDATA=abc", "not-a-room").unwrap_err();
+        assert!(error.contains("invalid room id"), "{}", error);
+    }
 }
@@ -1,4 +1,11 @@
+use blivedm::client::accounts::{Account, AccountSource};
+use blivedm::client::filter::MessageFilter;
+use blivedm::plugins::external::ExternalPluginConfig;
+use blivedm::plugins::tts::EmoteSpeech;
+#[cfg(feature = "wasm")]
+use blivedm::plugins::wasm::WasmPluginConfig;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 
@@ -10,14 +17,143 @@ pub struct Config {
     pub tts: Option<TtsConfig>,
     #[serde(default)]
     pub auto_reply: Option<AutoReplyConfig>,
+    /// Outbound danmaku queue shared by the TUI and auto replies
+    #[serde(default)]
+    pub sending: Option<SendingConfig>,
     #[serde(default)]
     pub debug: Option<bool>,
+    /// Named accounts, selected with `connection.account` / `connection.send_account`
+    #[serde(default)]
+    pub accounts: Option<BTreeMap<String, AccountConfig>>,
+    /// External process plugins (`[[plugins]]`)
+    #[serde(default)]
+    pub plugins: Option<Vec<ExternalPluginConfig>>,
+    /// Rhai scripts run as handlers
+    #[serde(default)]
+    pub scripts: Option<ScriptsConfig>,
+    /// WebAssembly plugins (`[[wasm_plugins]]`), only run when built with `--features wasm`
+    #[cfg(feature = "wasm")]
+    #[serde(default)]
+    pub wasm_plugins: Option<Vec<WasmPluginConfig>>,
+    /// The scheduler's stages (`[[pipeline.stage]]`); replaces the built-in pipeline
+    #[serde(default)]
+    pub pipeline: Option<PipelineConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct PipelineConfig {
+    /// Stages in the order messages go through them
+    #[serde(default, rename = "stage")]
+    pub stages: Vec<StageConfig>,
+}
+
+/// Plugins that handle each message in parallel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StageConfig {
+    pub plugins: Vec<PluginEntry>,
+}
+
+/// A plugin in a stage, e.g. `{ name = "tts", config = { command = "say" } }`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginEntry {
+    #[serde(flatten)]
+    pub plugin: PluginSpec,
+    /// Messages the plugin gets, instead of the filter in its `config`
+    #[serde(default)]
+    pub filter: Option<MessageFilter>,
+    /// Whether the plugin gets messages (default true); can be changed while running
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Plugin name and its config table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "name", rename_all = "snake_case")]
+pub enum PluginSpec {
+    Terminal,
+    Tts {
+        #[serde(default)]
+        config: Box<TtsConfig>,
+    },
+    AutoReply {
+        #[serde(default)]
+        config: AutoReplyConfig,
+    },
+    External {
+        config: ExternalPluginConfig,
+    },
+    Scripts {
+        #[serde(default)]
+        config: ScriptsConfig,
+    },
+    #[cfg(feature = "wasm")]
+    Wasm {
+        config: WasmPluginConfig,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ConnectionConfig {
     pub cookies: Option<String>,
+    /// Cookies exported from a browser (Netscape cookies.txt or JSON)
+    pub cookies_file: Option<PathBuf>,
     pub room_id: Option<String>,
+    /// Browser (and profile) to read cookies from, e.g. "chrome" or "chrome:Profile 2"
+    pub browser: Option<String>,
+    /// Account (from `[accounts]`) used for the connection
+    pub account: Option<String>,
+    /// Account (from `[accounts]`) used for sending danmaku and auto replies
+    pub send_account: Option<String>,
+    /// Load the room's recent danmaku and super chats on start (default true)
+    pub history: Option<bool>,
+}
+
+/// A named account; exactly one cookie source must be set
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct AccountConfig {
+    pub cookies: Option<String>,
+    pub cookies_file: Option<PathBuf>,
+    /// Credentials file written by `blivedm login --output <PATH>`
+    pub credentials: Option<PathBuf>,
+    /// Browser (and profile), e.g. "chrome:Profile 2"
+    pub browser: Option<String>,
+}
+
+impl AccountConfig {
+    fn to_account(&self, name: &str) -> Result<Account, String> {
+        let mut sources = Vec::new();
+        if let Some(cookies) = &self.cookies {
+            sources.push(AccountSource::Cookies(cookies.clone()));
+        }
+        if let Some(path) = &self.cookies_file {
+            sources.push(AccountSource::CookiesFile(path.clone()));
+        }
+        if let Some(path) = &self.credentials {
+            sources.push(AccountSource::Credentials(path.clone()));
+        }
+        if let Some(browser) = &self.browser {
+            let selection = browser
+                .parse()
+                .map_err(|e| format!("Account '{}': {}", name, e))?;
+            sources.push(AccountSource::Browser(selection));
+        }
+
+        match sources.len() {
+            1 => Ok(Account::new(name, sources.remove(0))),
+            0 => Err(format!(
+                "Account '{}' needs one of cookies, cookies_file, credentials or browser",
+                name
+            )),
+            _ => Err(format!(
+                "Account '{}' must set only one of cookies, cookies_file, credentials or browser",
+                name
+            )),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -36,12 +172,73 @@ pub struct TtsConfig {
     pub ali_model: Option<String>,
     pub ali_voice: Option<String>,
     pub ali_language_type: Option<String>,
+    /// Speak emotes as their description ("speak", default) or leave them out ("skip")
+    pub emotes: Option<EmoteSpeech>,
+    /// Messages to read out; danmaku only when not set
+    pub filter: Option<MessageFilter>,
+}
+
+impl TtsConfig {
+    /// Whether no setting is given
+    pub fn is_empty(&self) -> bool {
+        self.server.is_none()
+            && self.voice.is_none()
+            && self.backend.is_none()
+            && self.quality.is_none()
+            && self.format.is_none()
+            && self.sample_rate.is_none()
+            && self.volume.is_none()
+            && self.command.is_none()
+            && self.args.is_none()
+            && self.ali_api_key.is_none()
+            && self.ali_model.is_none()
+            && self.ali_voice.is_none()
+            && self.ali_language_type.is_none()
+            && self.emotes.is_none()
+            && self.filter.is_none()
+    }
+
+    /// These settings, taking the ones not set here from `fallback`
+    pub fn or(&self, fallback: &TtsConfig) -> TtsConfig {
+        TtsConfig {
+            server: self.server.clone().or_else(|| fallback.server.clone()),
+            voice: self.voice.clone().or_else(|| fallback.voice.clone()),
+            backend: self.backend.clone().or_else(|| fallback.backend.clone()),
+            quality: self.quality.clone().or_else(|| fallback.quality.clone()),
+            format: self.format.clone().or_else(|| fallback.format.clone()),
+            sample_rate: self.sample_rate.or(fallback.sample_rate),
+            volume: self.volume.or(fallback.volume),
+            command: self.command.clone().or_else(|| fallback.command.clone()),
+            args: self.args.clone().or_else(|| fallback.args.clone()),
+            ali_api_key: self
+                .ali_api_key
+                .clone()
+                .or_else(|| fallback.ali_api_key.clone()),
+            ali_model: self
+                .ali_model
+                .clone()
+                .or_else(|| fallback.ali_model.clone()),
+            ali_voice: self
+                .ali_voice
+                .clone()
+                .or_else(|| fallback.ali_voice.clone()),
+            ali_language_type: self
+                .ali_language_type
+                .clone()
+                .or_else(|| fallback.ali_language_type.clone()),
+            emotes: self.emotes.or(fallback.emotes),
+            filter: self.filter.clone().or_else(|| fallback.filter.clone()),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TriggerConfig {
     pub keywords: Vec<String>,
     pub response: String,
+    /// Reply to (@) the user who triggered the response
+    #[serde(default)]
+    pub reply: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -52,6 +249,9 @@ pub struct AutoReplyConfig {
     pub cooldown_seconds: u64,
     #[serde(default)]
     pub triggers: Vec<TriggerConfig>,
+    /// Messages the auto reply plugin sees; all messages when not set
+    #[serde(default)]
+    pub filter: Option<MessageFilter>,
 }
 
 impl Default for AutoReplyConfig {
@@ -60,6 +260,7 @@ impl Default for AutoReplyConfig {
             enabled: false,
             cooldown_seconds: default_cooldown(),
             triggers: vec![],
+            filter: None,
         }
     }
 }
@@ -83,12 +284,78 @@ impl AutoReplyConfig {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SendingConfig {
+    /// Minimum milliseconds between two danmaku
+    #[serde(default = "default_send_interval")]
+    pub interval_ms: u64,
+    /// Maximum characters per danmaku: 20, 30 or 40 depending on your level
+    #[serde(default = "default_max_length")]
+    pub max_length: usize,
+    /// Retries when Bilibili answers "too frequent"
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+}
+
+impl Default for SendingConfig {
+    fn default() -> Self {
+        Self {
+            interval_ms: default_send_interval(),
+            max_length: default_max_length(),
+            max_retries: default_max_retries(),
+        }
+    }
+}
+
+fn default_send_interval() -> u64 {
+    1000
+}
+
+fn default_max_length() -> usize {
+    20
+}
+
+fn default_max_retries() -> u32 {
+    3
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ScriptsConfig {
+    /// Directory with the `*.rhai` scripts (default: `scripts` in the config directory)
+    pub dir: Option<PathBuf>,
+    /// Messages the scripts get; all messages when not set
+    #[serde(default)]
+    pub filter: Option<MessageFilter>,
+}
+
+impl ScriptsConfig {
+    /// Directory the scripts are loaded from (~/.config/blivedm_rs/scripts by default)
+    pub fn dir(&self) -> Option<PathBuf> {
+        self.dir
+            .clone()
+            .or_else(|| dirs::config_dir().map(|dir| dir.join("blivedm_rs").join("scripts")))
+    }
+}
+
+impl SendingConfig {
+    /// Convert to blivedm::client::danmaku::DanmakuQueueConfig
+    pub fn to_queue_config(&self) -> blivedm::client::danmaku::DanmakuQueueConfig {
+        blivedm::client::danmaku::DanmakuQueueConfig {
+            interval: std::time::Duration::from_millis(self.interval_ms),
+            max_length: self.max_length,
+            max_retries: self.max_retries,
+            ..Default::default()
+        }
+    }
+}
+
 impl TriggerConfig {
     /// Convert to blivedm::plugins::auto_reply::TriggerConfig
     pub fn to_plugin_trigger(&self) -> blivedm::plugins::auto_reply::TriggerConfig {
         blivedm::plugins::auto_reply::TriggerConfig {
             keywords: self.keywords.clone(),
             response: self.response.clone(),
+            reply_to_sender: self.reply,
         }
     }
 }
@@ -96,19 +363,7 @@ impl TriggerConfig {
 impl Config {
     /// Load configuration from file with fallback locations
     pub fn load_from_file(config_path: Option<&Path>) -> Result<Self, Box<dyn std::error::Error>> {
-        let config_file = if let Some(path) = config_path {
-            // Use provided path
-            path.to_path_buf()
-        } else {
-            // Try current directory first
-            let current_dir_config = PathBuf::from("config.toml");
-            if current_dir_config.exists() {
-                current_dir_config
-            } else {
-                // Try XDG config directory
-                Self::get_default_config_path()?
-            }
-        };
+        let config_file = Self::config_file_path(config_path)?;
 
         if !config_file.exists() {
             log::debug!("Config file {:?} not found", config_file);
@@ -140,6 +395,25 @@ impl Config {
         Ok(config)
     }
 
+    /// The file [`Config::load_from_file`] reads: `config_path` if given, else
+    /// ./config.toml if it exists, else ~/.config/blivedm_rs/config.toml
+    pub fn config_file_path(
+        config_path: Option<&Path>,
+    ) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        if let Some(path) = config_path {
+            // Use provided path
+            return Ok(path.to_path_buf());
+        }
+        // Try current directory first
+        let current_dir_config = PathBuf::from("config.toml");
+        if current_dir_config.exists() {
+            Ok(current_dir_config)
+        } else {
+            // Try XDG config directory
+            Self::get_default_config_path()
+        }
+    }
+
     /// Get the default configuration file path (~/.config/blivedm_rs/config.toml)
     fn get_default_config_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
         let config_dir = dirs::config_dir()
@@ -156,10 +430,20 @@ impl Config {
         Ok(config_dir.join("config.toml"))
     }
 
+    /// Named accounts from the `[accounts]` section, in name order
+    pub fn accounts(&self) -> Result<Vec<Account>, String> {
+        self.accounts
+            .iter()
+            .flatten()
+            .map(|(name, account)| account.to_account(name))
+            .collect()
+    }
+
     /// Create an example configuration file
     pub fn create_example_config(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
         let example_config = Config {
             connection: None,
+            accounts: None,
             tts: Some(TtsConfig {
                 server: Some("http://localhost:8000".to_string()),
                 voice: None,
@@ -174,6 +458,8 @@ impl Config {
                 ali_model: None,
                 ali_voice: None,
                 ali_language_type: None,
+                emotes: None,
+                filter: None,
             }),
             auto_reply: Some(AutoReplyConfig {
                 enabled: false,
@@ -182,14 +468,23 @@ impl Config {
                     TriggerConfig {
                         keywords: vec!["你好".to_string(), "hello".to_string()],
                         response: "欢迎来到直播间！".to_string(),
+                        reply: false,
                     },
                     TriggerConfig {
                         keywords: vec!["谢谢".to_string(), "thanks".to_string()],
                         response: "不客气～".to_string(),
+                        reply: false,
                     },
                 ],
+                filter: None,
             }),
+            sending: Some(SendingConfig::default()),
             debug: None,
+            plugins: None,
+            scripts: None,
+            #[cfg(feature = "wasm")]
+            wasm_plugins: None,
+            pipeline: None,
         };
 
         let toml_string = toml::to_string_pretty(&example_config)
@@ -205,21 +500,11 @@ impl Config {
     #[allow(clippy::too_many_arguments)]
     pub fn print_effective_config(
         cookies: &Option<String>,
+        cookies_file: &Option<PathBuf>,
         room_id: &str,
-        tts_server: &Option<String>,
-        tts_voice: &Option<String>,
-        tts_backend: &Option<String>,
-        tts_quality: &Option<String>,
-        tts_format: &Option<String>,
-        tts_sample_rate: &Option<u32>,
-        tts_volume: &Option<f32>,
-        tts_command: &Option<String>,
-        tts_args: &Option<String>,
-        ali_api_key: &Option<String>,
-        ali_model: &Option<String>,
-        ali_voice: &Option<String>,
-        ali_language_type: &Option<String>,
-        auto_reply: &Option<AutoReplyConfig>,
+        browser: &Option<String>,
+        tts: Option<&TtsConfig>,
+        auto_reply: Option<&AutoReplyConfig>,
         debug: bool,
     ) {
         println!("=== Effective Configuration ===");
@@ -233,20 +518,28 @@ impl Config {
         } else {
             println!("  cookies: None (will auto-detect)");
         }
+        if let Some(path) = cookies_file {
+            println!("  cookies_file: {:?}", path);
+        }
+        println!(
+            "  browser: {}",
+            browser.as_deref().unwrap_or("all (auto-detect)")
+        );
 
+        let tts = tts.cloned().unwrap_or_default();
         println!("TTS (REST API):");
-        println!("  server: {:?}", tts_server);
-        println!("  voice: {:?}", tts_voice);
-        println!("  backend: {:?}", tts_backend);
-        println!("  quality: {:?}", tts_quality);
-        println!("  format: {:?}", tts_format);
-        println!("  sample_rate: {:?}", tts_sample_rate);
-        println!("  volume: {:?}", tts_volume);
-        println!("  command: {:?}", tts_command);
-        println!("  args: {:?}", tts_args);
+        println!("  server: {:?}", tts.server);
+        println!("  voice: {:?}", tts.voice);
+        println!("  backend: {:?}", tts.backend);
+        println!("  quality: {:?}", tts.quality);
+        println!("  format: {:?}", tts.format);
+        println!("  sample_rate: {:?}", tts.sample_rate);
+        println!("  volume: {:?}", tts.volume);
+        println!("  command: {:?}", tts.command);
+        println!("  args: {:?}", tts.args);
 
         println!("TTS (Alibaba DashScope):");
-        if let Some(key) = ali_api_key {
+        if let Some(key) = &tts.ali_api_key {
             println!(
                 "  api_key: {}...",
                 &key.chars().take(10).collect::<String>()
@@ -254,9 +547,9 @@ impl Config {
         } else {
             println!("  api_key: None");
         }
-        println!("  model: {:?}", ali_model);
-        println!("  voice: {:?}", ali_voice);
-        println!("  language_type: {:?}", ali_language_type);
+        println!("  model: {:?}", tts.ali_model);
+        println!("  voice: {:?}", tts.ali_voice);
+        println!("  language_type: {:?}", tts.ali_language_type);
 
         println!("Auto Reply:");
         if let Some(auto_reply_config) = auto_reply {
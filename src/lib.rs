@@ -6,7 +6,10 @@ pub mod plugins;
 pub mod tui;
 
 // Re-export commonly used items from client
-pub use client::{auth, browser_cookies, get_cookies_or_browser, models, scheduler, websocket};
+pub use client::{
+    accounts, auth, browser_cookies, cookie_refresh, credentials, danmaku, filter,
+    get_cookies_or_browser, gifts, history, login, models, moderation, scheduler, websocket,
+};
 
 // Re-export plugin modules and helpers
 pub use plugins::{
@@ -2,26 +2,53 @@
 // Standalone binary to test integration of the terminal display plugin with the BiliLiveClient
 
 mod config;
+mod pipeline;
 
-use blivedm::client::get_cookies_or_browser;
+use blivedm::client::accounts::{Account, find_account};
+use blivedm::client::auth::{AuthStatus, check_auth_status};
+use blivedm::client::browser_cookies::{BrowserSelection, bilibili_cookies_from_file};
+use blivedm::client::cookie_refresh::refresh_stored_credentials;
+use blivedm::client::credentials::{
+    Credentials, default_credentials_path, load_default_credentials,
+};
+use blivedm::client::danmaku::{DanmakuQueue, DanmakuSender, ReplyTarget, SendOptions};
+use blivedm::client::get_cookies_or_browser_in;
+use blivedm::client::gifts::{DEFAULT_CACHE_MAX_AGE, GiftCatalog};
 use blivedm::client::scheduler::{EventContext, Scheduler};
 use blivedm::client::websocket::BiliLiveClient;
-use blivedm::plugins::terminal_display::TerminalDisplayHandler;
-use blivedm::plugins::tts::TtsHandler;
 use blivedm::tui::{TuiApp, run_tui};
-use clap::{CommandFactory, Parser};
+use clap::{CommandFactory, Parser, Subcommand};
 use clap_complete::{Shell, generate};
 use config::Config;
 use futures::channel::mpsc;
 use futures::stream::StreamExt;
+use pipeline::{LivePipeline, PluginEnv};
 use std::collections::VecDeque;
 use std::env;
-use std::path::PathBuf;
-use std::sync::atomic::AtomicU64;
-use std::sync::{Arc, Mutex};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
 use std::time::Duration;
-use tokio::runtime::Runtime;
+
+/// How often stored login credentials are checked for a refresh
+const COOKIE_REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Panics or timeouts in a row after which a handler stops getting messages
+const HANDLER_FAILURE_LIMIT: u32 = 5;
+
+/// How long handlers get to finish queued work on exit
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the config file is checked for changes
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// First delay before reconnecting after the connection is lost, doubled per failed attempt
+const RECONNECT_MIN_DELAY: Duration = Duration::from_secs(1);
+
+/// Upper bound for the reconnect delay
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(60);
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -37,6 +64,22 @@ struct Args {
     #[arg(long, value_name = "COOKIES")]
     cookies: Option<String>,
 
+    /// Cookies file exported from a browser (Netscape cookies.txt or JSON)
+    #[arg(long, value_name = "PATH")]
+    cookies_file: Option<PathBuf>,
+
+    /// Account (from the [accounts] config section) used for the connection
+    #[arg(long, value_name = "NAME")]
+    account: Option<String>,
+
+    /// Account (from the [accounts] config section) used for sending danmaku and auto replies
+    #[arg(long, value_name = "NAME")]
+    send_account: Option<String>,
+
+    /// Browser to read cookies from, optionally pinned to a profile (e.g. "chrome:Profile 2")
+    #[arg(long, value_name = "BROWSER[:PROFILE]")]
+    browser: Option<String>,
+
     /// Room ID to connect to
     #[arg(long, value_name = "ROOM_ID")]
     room_id: Option<String>,
@@ -101,9 +144,348 @@ struct Args {
     #[arg(long)]
     auto_reply: bool,
 
+    /// Don't load the room's recent danmaku and super chats on start
+    #[arg(long)]
+    no_history: bool,
+
     /// Generate shell completion script (bash, zsh, fish, powershell, elvish)
     #[arg(long, value_name = "SHELL")]
     generate_completion: Option<Shell>,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Log in by scanning a QR code with the Bilibili mobile app and store the cookies
+    Login {
+        /// Where to store the credentials (defaults to ~/.config/blivedm_rs/credentials.json)
+        #[arg(long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+    /// Inspect where cookies are read from
+    Cookies {
+        #[command(subcommand)]
+        command: CookiesCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum CookiesCommand {
+    /// Report, for every browser and profile, whether usable bilibili cookies were found
+    Doctor,
+}
+
+/// Check the login status of the cookies and report it in the TUI
+fn report_auth_status(
+    cookies: &str,
+    logged_in_user: &Arc<Mutex<Option<String>>>,
+    message_buffer: &Arc<Mutex<VecDeque<String>>>,
+) {
+    match check_auth_status(cookies) {
+        Ok(AuthStatus::LoggedIn(user)) => {
+            TuiApp::add_message(
+                message_buffer,
+                format!("[System] Logged in as {}", user.display_label()),
+            );
+            TuiApp::set_logged_in_user(logged_in_user, Some(user.uname));
+        }
+        Ok(AuthStatus::NotLoggedIn { reason }) => {
+            TuiApp::add_message(message_buffer, format!("[System] {}", reason));
+            TuiApp::set_logged_in_user(logged_in_user, None);
+        }
+        Err(e) => {
+            log::warn!("{}", e);
+        }
+    }
+}
+
+/// Backfill the room's recent danmaku; handlers see them as historical.
+///
+/// Runs whenever a connection is established, before that connection's live events.
+fn backfill_history(
+    scheduler: &Scheduler,
+    room_id: &str,
+    cookies: Option<&str>,
+    message_buffer: &Arc<Mutex<VecDeque<String>>>,
+) {
+    let room = room_id.parse::<u64>().unwrap_or(0);
+    match blivedm::client::history::fetch_history(room, cookies, true) {
+        Ok(history) => {
+            for msg in history {
+                scheduler.trigger(msg);
+            }
+        }
+        Err(e) => TuiApp::add_message(
+            message_buffer,
+            format!("[System] Could not load recent history: {}", e),
+        ),
+    }
+}
+
+/// Apply changes to the config file to the running handlers, reporting in the TUI.
+/// A file that does not parse, or needs a restart, leaves the current settings active.
+fn watch_config(
+    path: &Path,
+    mut live_pipeline: LivePipeline,
+    scheduler: &Scheduler,
+    tts_overrides: &config::TtsConfig,
+    auto_reply: bool,
+    message_buffer: &Arc<Mutex<VecDeque<String>>>,
+) {
+    let modified = || fs::metadata(path).and_then(|m| m.modified()).ok();
+    let mut last_modified = modified();
+    loop {
+        thread::sleep(CONFIG_POLL_INTERVAL);
+        let current = modified();
+        if current.is_none() || current == last_modified {
+            continue;
+        }
+        last_modified = current;
+        let message = match Config::load_from_file(Some(path)) {
+            Ok(config) => {
+                let stages = pipeline::stages(&config, tts_overrides, auto_reply);
+                match live_pipeline.apply(scheduler, &stages) {
+                    Ok(()) => format!("[System] Config reloaded from {:?}", path),
+                    Err(e) => format!("[System] Config not reloaded: {}", e),
+                }
+            }
+            Err(e) => format!(
+                "[System] Config reload failed, keeping the previous config: {}",
+                e
+            ),
+        };
+        TuiApp::add_message(message_buffer, message);
+    }
+}
+
+/// Run the QR code login flow and persist the resulting credentials
+fn run_login(output: Option<PathBuf>) {
+    use blivedm::client::login::{QrLoginStatus, login_with_qrcode};
+
+    let path = match output.or_else(default_credentials_path) {
+        Some(path) => path,
+        None => {
+            eprintln!("Unable to determine config directory for storing credentials");
+            std::process::exit(1);
+        }
+    };
+
+    let result = login_with_qrcode(
+        |qrcode, url| {
+            println!("Scan the QR code below with the Bilibili mobile app:");
+            println!("{}", qrcode);
+            println!("Or open this URL on a logged-in device: {}", url);
+        },
+        |status| match status {
+            QrLoginStatus::WaitingForScan => println!("Waiting for scan..."),
+            QrLoginStatus::WaitingForConfirm => {
+                println!("QR code scanned, please confirm the login in the app...")
+            }
+            QrLoginStatus::Expired => println!("QR code expired"),
+            QrLoginStatus::Confirmed(_) => println!("Login confirmed"),
+        },
+    );
+
+    match result.and_then(|credentials| credentials.save(&path).map(|_| credentials)) {
+        Ok(credentials) => {
+            println!(
+                "Logged in as uid {}, credentials saved to {:?}",
+                credentials.dede_user_id, path
+            );
+        }
+        Err(e) => {
+            eprintln!("Login failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Load bilibili cookies from an exported cookies file, exiting on failure
+fn load_cookies_file(path: &Path) -> String {
+    match bilibili_cookies_from_file(path) {
+        Ok(cookies) => {
+            log::info!("Using cookies from {:?}", path);
+            cookies
+        }
+        Err(e) => {
+            eprintln!("Failed to load cookies file: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Whether the credentials file at `path` holds exactly these cookies
+fn stored_credentials_match(path: &Path, cookies: &Option<String>) -> bool {
+    Credentials::load(path).is_ok_and(|c| cookies.as_deref() == Some(c.to_cookie_string().as_str()))
+}
+
+/// Refresh the stored credentials backing `cookies`, if any, before connecting
+fn refresh_before_connecting(
+    cookies: Option<String>,
+    credentials_paths: &[PathBuf],
+) -> Option<String> {
+    let Some(path) = credentials_paths
+        .iter()
+        .find(|path| stored_credentials_match(path, &cookies))
+    else {
+        return cookies;
+    };
+
+    match refresh_stored_credentials(path) {
+        Ok(Some(refreshed)) => {
+            log::info!(
+                "Stored cookies in {:?} were refreshed before connecting",
+                path
+            );
+            Some(refreshed.to_cookie_string())
+        }
+        Ok(None) => cookies,
+        Err(e) => {
+            log::warn!("Cookie refresh check failed: {}", e);
+            cookies
+        }
+    }
+}
+
+/// Resolve the cookies of a configured account, exiting on failure
+fn load_account_cookies(accounts: &[Account], name: &str) -> String {
+    let Some(account) = find_account(accounts, name) else {
+        eprintln!(
+            "Unknown account '{}', check the [accounts] config section",
+            name
+        );
+        std::process::exit(1);
+    };
+    match account.resolve_cookies() {
+        Ok(cookies) => cookies,
+        Err(e) => {
+            eprintln!("Failed to load account '{}': {}", name, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Handle the account switching commands typed into the TUI.
+///
+/// `/accounts` lists the configured accounts, `/account` shows the sending account and
+/// `/account <name>` switches to it (`/account default` sends with the connection's account).
+fn handle_account_command(
+    input: &str,
+    accounts: &[Account],
+    context_handle: &Arc<RwLock<EventContext>>,
+    send_account: &Arc<Mutex<Option<String>>>,
+    message_buffer: &Arc<Mutex<VecDeque<String>>>,
+) {
+    let current = send_account.lock().ok().and_then(|a| a.clone());
+    let name = input.trim_start_matches("/account").trim();
+
+    if input.starts_with("/accounts") {
+        if accounts.is_empty() {
+            TuiApp::add_message(
+                message_buffer,
+                "[System] No accounts configured, add them under [accounts] in the config file"
+                    .to_string(),
+            );
+        }
+        for account in accounts {
+            let marker = if current.as_deref() == Some(account.name.as_str()) {
+                " (sending)"
+            } else {
+                ""
+            };
+            TuiApp::add_message(
+                message_buffer,
+                format!("[System] Account: {}{}", account.name, marker),
+            );
+        }
+        return;
+    }
+
+    if name.is_empty() {
+        TuiApp::add_message(
+            message_buffer,
+            format!(
+                "[System] Sending as: {}",
+                current.as_deref().unwrap_or("connection account")
+            ),
+        );
+        return;
+    }
+
+    let (label, send_cookies) = match find_account(accounts, name) {
+        Some(account) => match account.resolve_cookies() {
+            Ok(cookies) => (Some(account.name.clone()), Some(cookies)),
+            Err(e) => {
+                TuiApp::add_message(
+                    message_buffer,
+                    format!("[System] Failed to switch account: {}", e),
+                );
+                return;
+            }
+        },
+        None if name == "default" => (None, None),
+        None => {
+            TuiApp::add_message(
+                message_buffer,
+                format!("[System] Unknown account '{}', see /accounts", name),
+            );
+            return;
+        }
+    };
+
+    if let Ok(mut context) = context_handle.write() {
+        context.send_cookies = send_cookies;
+    }
+    TuiApp::add_message(
+        message_buffer,
+        format!(
+            "[System] Now sending as: {}",
+            label.as_deref().unwrap_or("connection account")
+        ),
+    );
+    TuiApp::set_send_account(send_account, label);
+}
+
+/// Print a per-browser cookie diagnostics report
+fn run_cookies_doctor(browser: Option<&BrowserSelection>) {
+    use blivedm::client::browser_cookies::diagnose_cookie_sources;
+
+    println!("=== Cookie Sources ===");
+    match default_credentials_path() {
+        Some(path) if path.exists() => match load_default_credentials() {
+            Some(credentials) => println!(
+                "Stored login credentials: uid {} ({:?})",
+                credentials.dede_user_id, path
+            ),
+            None => println!("Stored login credentials: unreadable ({:?})", path),
+        },
+        _ => println!("Stored login credentials: none (run `blivedm login`)"),
+    }
+
+    let reports = diagnose_cookie_sources(browser);
+    for report in &reports {
+        let source = match &report.profile {
+            Some(profile) => format!("{} [{}]", report.browser.name(), profile),
+            None => report.browser.name().to_string(),
+        };
+        let marker = if report.diagnosis.is_ok() {
+            "✓"
+        } else {
+            "✗"
+        };
+        println!("{} {:<32} {}", marker, source, report.diagnosis);
+        if let Some(path) = &report.cookie_db_path {
+            println!("    {}", path.display());
+        }
+    }
+
+    if !reports.iter().any(|r| r.diagnosis.is_ok()) {
+        println!();
+        println!("No usable browser cookies found. Log into bilibili.com in a browser,");
+        println!("run `blivedm login`, or pass cookies with --cookies.");
+    }
 }
 
 fn main() {
@@ -116,6 +498,11 @@ fn main() {
         return;
     }
 
+    if let Some(Commands::Login { output }) = args.command {
+        run_login(output);
+        return;
+    }
+
     // Load configuration from file first
     let config = match Config::load_from_file(args.config.as_deref()) {
         Ok(config) => config,
@@ -124,27 +511,91 @@ fn main() {
             std::process::exit(1);
         }
     };
+    // Watched for changes while running
+    let config_file = Config::config_file_path(args.config.as_deref()).ok();
 
     // Initialize logging with precedence: CLI args > env vars > config file
     let debug_enabled =
         args.debug || env::var("DEBUG").unwrap_or_default() == "1" || config.debug.unwrap_or(false);
 
-    // Load cookies and room_id with precedence: CLI args > env vars > config file > defaults
+    // Pin browser cookie detection with precedence: CLI args > config file
+    let browser = args
+        .browser
+        .or_else(|| config.connection.as_ref().and_then(|c| c.browser.clone()));
+    let browser_selection =
+        browser.as_deref().map(
+            |browser_val| match browser_val.parse::<BrowserSelection>() {
+                Ok(selection) => selection,
+                Err(e) => {
+                    eprintln!("Invalid --browser value: {}", e);
+                    std::process::exit(1);
+                }
+            },
+        );
+
+    let accounts = match config.accounts() {
+        Ok(accounts) => accounts,
+        Err(e) => {
+            eprintln!("Invalid account configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(Commands::Cookies {
+        command: CookiesCommand::Doctor,
+    }) = args.command
+    {
+        run_cookies_doctor(browser_selection.as_ref());
+        return;
+    }
+
+    // Load cookies with precedence: --cookies > --account > Cookie env var > config cookies >
+    // cookies file (--cookies-file, else the config's) > config account
+    let cookies_file = args.cookies_file.clone().or_else(|| {
+        config
+            .connection
+            .as_ref()
+            .and_then(|c| c.cookies_file.clone())
+    });
     let cookies = args
         .cookies
+        .or_else(|| {
+            args.account
+                .as_deref()
+                .map(|name| load_account_cookies(&accounts, name))
+        })
         .or_else(|| {
             env::var("Cookie")
                 .ok()
                 .filter(|s| !s.is_empty() && s != "SESSDATA=dummy_sessdata")
         })
-        .or_else(|| config.connection.as_ref().and_then(|c| c.cookies.clone()));
+        .or_else(|| config.connection.as_ref().and_then(|c| c.cookies.clone()))
+        .or_else(|| cookies_file.as_deref().map(load_cookies_file))
+        .or_else(|| {
+            config
+                .connection
+                .as_ref()
+                .and_then(|c| c.account.as_deref())
+                .map(|name| load_account_cookies(&accounts, name))
+        });
+
+    // Danmaku and auto replies can be sent from a different account than the connection's
+    let send_account = args.send_account.clone().or_else(|| {
+        config
+            .connection
+            .as_ref()
+            .and_then(|c| c.send_account.clone())
+    });
+    let send_cookies = send_account
+        .as_deref()
+        .map(|name| load_account_cookies(&accounts, name));
 
     // If no manual cookies provided, try browser auto-detection
     let cookies = if cookies.is_none() {
         if debug_enabled {
             log::info!("No manual cookies provided, attempting browser auto-detection...");
         }
-        get_cookies_or_browser(None)
+        get_cookies_or_browser_in(None, browser_selection.as_ref())
     } else {
         if debug_enabled {
             log::info!("Using manually provided cookies");
@@ -152,111 +603,63 @@ fn main() {
         cookies
     };
 
+    // Room id with precedence: CLI args > env vars > config file > default
     let room_id = args
         .room_id
         .or_else(|| env::var("ROOM_ID").ok())
         .or_else(|| config.connection.as_ref().and_then(|c| c.room_id.clone()))
         .unwrap_or_else(|| "24779526".to_string());
 
-    // Configure TTS with precedence: CLI args > config file
-    let tts_server = args
-        .tts_server
-        .or_else(|| config.tts.as_ref().and_then(|t| t.server.clone()));
-    let tts_voice = args
-        .tts_voice
-        .or_else(|| config.tts.as_ref().and_then(|t| t.voice.clone()));
-    let tts_backend = args
-        .tts_backend
-        .or_else(|| config.tts.as_ref().and_then(|t| t.backend.clone()));
-    let tts_quality = args
-        .tts_quality
-        .or_else(|| config.tts.as_ref().and_then(|t| t.quality.clone()));
-    let tts_format = args
-        .tts_format
-        .or_else(|| config.tts.as_ref().and_then(|t| t.format.clone()));
-    let tts_sample_rate = args
-        .tts_sample_rate
-        .or_else(|| config.tts.as_ref().and_then(|t| t.sample_rate));
-    let tts_volume = args
-        .tts_volume
-        .or_else(|| config.tts.as_ref().and_then(|t| t.volume));
-    let tts_command = args
-        .tts_command
-        .or_else(|| config.tts.as_ref().and_then(|t| t.command.clone()));
-    let tts_args = args
-        .tts_args
-        .or_else(|| config.tts.as_ref().and_then(|t| t.args.clone()));
-
-    // Configure Alibaba TTS with precedence: CLI args > env vars > config file
-    let ali_api_key = args
-        .ali_api_key
-        .or_else(|| env::var("DASHSCOPE_API_KEY").ok())
-        .or_else(|| config.tts.as_ref().and_then(|t| t.ali_api_key.clone()));
-    let ali_model = args
-        .ali_model
-        .or_else(|| config.tts.as_ref().and_then(|t| t.ali_model.clone()));
-    let ali_voice = args
-        .ali_voice
-        .or_else(|| config.tts.as_ref().and_then(|t| t.ali_voice.clone()));
-    let ali_language_type = args.ali_language_type.or_else(|| {
-        config
-            .tts
-            .as_ref()
-            .and_then(|t| t.ali_language_type.clone())
-    });
+    // TTS command-line options, which also win over a reloaded config file
+    let tts_overrides = config::TtsConfig {
+        server: args.tts_server.clone(),
+        voice: args.tts_voice.clone(),
+        backend: args.tts_backend.clone(),
+        quality: args.tts_quality.clone(),
+        format: args.tts_format.clone(),
+        sample_rate: args.tts_sample_rate,
+        volume: args.tts_volume,
+        command: args.tts_command.clone(),
+        args: args.tts_args.clone(),
+        ali_api_key: args
+            .ali_api_key
+            .clone()
+            .or_else(|| env::var("DASHSCOPE_API_KEY").ok()),
+        ali_model: args.ali_model.clone(),
+        ali_voice: args.ali_voice.clone(),
+        ali_language_type: args.ali_language_type.clone(),
+        ..Default::default()
+    };
 
-    // Configure auto reply with precedence: CLI args > config file
-    let auto_reply_config = if let Some(config_auto_reply) = &config.auto_reply {
-        // Use config file settings, but allow CLI flag to override enabled
-        let mut plugin_config = config_auto_reply.to_plugin_config();
-        if args.auto_reply {
-            plugin_config.enabled = true;
+    // The handlers come from `[[pipeline.stage]]`, or else from the built-in pipeline
+    // made of the [tts], [auto_reply] and plugin sections and the command-line options
+    let stages = pipeline::stages(&config, &tts_overrides, args.auto_reply);
+    if config.pipeline.is_some() {
+        // The options only apply to plugins listed in the pipeline
+        if !tts_overrides.is_empty() && pipeline::first_tts(&stages).is_none() {
+            eprintln!(
+                "Warning: [pipeline] has no tts plugin, the TTS options (--tts-*, --ali-*, DASHSCOPE_API_KEY) are ignored"
+            );
         }
-        plugin_config
-    } else {
-        // No config file section, use defaults with CLI flag
-        let mut default_config = blivedm::plugins::auto_reply::AutoReplyConfig::default();
-        default_config.enabled = args.auto_reply;
-        default_config
-    };
+        if args.auto_reply && pipeline::enabled_auto_reply(&stages).is_none() {
+            eprintln!("Warning: [pipeline] has no auto_reply plugin, --auto-reply is ignored");
+        }
+    }
 
     // If user wants to see config, print and exit
     if args.print_config {
-        // Create a temporary config struct for display that reflects the effective settings
-        let effective_auto_reply = if auto_reply_config.enabled {
-            Some(config::AutoReplyConfig {
-                enabled: auto_reply_config.enabled,
-                cooldown_seconds: auto_reply_config.cooldown_seconds,
-                triggers: auto_reply_config
-                    .triggers
-                    .iter()
-                    .map(|t| config::TriggerConfig {
-                        keywords: t.keywords.clone(),
-                        response: t.response.clone(),
-                    })
-                    .collect(),
-            })
-        } else {
-            None
-        };
-
+        let auto_reply =
+            pipeline::enabled_auto_reply(&stages).map(|auto_reply| config::AutoReplyConfig {
+                enabled: true,
+                ..auto_reply.clone()
+            });
         Config::print_effective_config(
             &cookies,
+            &cookies_file,
             &room_id,
-            &tts_server,
-            &tts_voice,
-            &tts_backend,
-            &tts_quality,
-            &tts_format,
-            &tts_sample_rate,
-            &tts_volume,
-            &tts_command,
-            &tts_args,
-            &ali_api_key,
-            &ali_model,
-            &ali_voice,
-            &ali_language_type,
-            &effective_auto_reply,
+            &browser,
+            pipeline::first_tts(&stages),
+            auto_reply.as_ref(),
             debug_enabled,
         );
         std::process::exit(0);
@@ -269,9 +672,43 @@ fn main() {
             .try_init();
     }
 
+    // Sessions using credentials stored by `blivedm login` (the default file or an
+    // account's credentials file) can renew their cookies
+    let mut credentials_paths: Vec<PathBuf> = accounts
+        .iter()
+        .filter_map(|account| account.credentials_path().map(Path::to_path_buf))
+        .collect();
+    if let Some(path) = default_credentials_path()
+        && (stored_credentials_match(&path, &cookies)
+            || stored_credentials_match(&path, &send_cookies))
+        && !credentials_paths.contains(&path)
+    {
+        credentials_paths.push(path);
+    }
+    let cookies = refresh_before_connecting(cookies, &credentials_paths);
+    let send_cookies = refresh_before_connecting(send_cookies, &credentials_paths);
+
+    // Gift prices for gift events, from the on-disk cache when it is fresh
+    let gift_catalog = match GiftCatalog::load(room_id.parse().unwrap_or(0), DEFAULT_CACHE_MAX_AGE)
+    {
+        Ok(catalog) => Some(Arc::new(catalog)),
+        Err(e) => {
+            log::warn!(
+                "Gift catalog unavailable, gift prices may be missing: {}",
+                e
+            );
+            None
+        }
+    };
+
     // Create client with automatic browser cookie detection
     let (tx, mut rx) = mpsc::channel(64);
-    let mut client = match BiliLiveClient::new_auto(cookies.as_deref(), &room_id, tx) {
+    let mut client = match BiliLiveClient::new_auto_with_browser(
+        cookies.as_deref(),
+        browser_selection.as_ref(),
+        &room_id,
+        tx,
+    ) {
         Ok(client) => {
             log::info!("Successfully created client with automatic cookie detection");
             client
@@ -284,6 +721,9 @@ fn main() {
             std::process::exit(1);
         }
     };
+    if let Some(catalog) = gift_catalog {
+        client = client.with_gift_catalog(catalog);
+    }
     client.send_auth();
     client.send_heart_beat();
     let shared_client: Arc<Mutex<BiliLiveClient>> = Arc::new(Mutex::new(client));
@@ -304,24 +744,6 @@ fn main() {
         }
     });
 
-    let rec_msg: Arc<Mutex<BiliLiveClient>> = Arc::clone(&shared_client);
-    thread::spawn(move || {
-        loop {
-            match rec_msg.lock() {
-                Ok(mut rec_c) => {
-                    if let Err(e) = rec_c.receive() {
-                        log::error!("{}", e);
-                    }
-                }
-                Err(e) => {
-                    eprintln!("Error acquiring lock on stream: {}", e);
-                    break;
-                }
-            }
-            thread::sleep(Duration::from_millis(10)); // instead of 10 microseconds
-        }
-    });
-
     // Set up the scheduler with context and add the terminal display handler
     if debug_enabled {
         match &cookies {
@@ -344,67 +766,52 @@ fn main() {
 
     // Create shared online count for TUI title display
     let online_count: Arc<AtomicU64> = Arc::new(AtomicU64::new(0));
+    // Recent danmaku senders and the one selected for a reply in the TUI
+    let reply_candidates: Arc<Mutex<VecDeque<ReplyTarget>>> = Arc::new(Mutex::new(VecDeque::new()));
+    let reply_target: Arc<Mutex<Option<ReplyTarget>>> = Arc::new(Mutex::new(None));
 
-    let context = EventContext::new(cookies.clone(), room_id.parse::<u64>().unwrap_or(0));
-    let mut scheduler = Scheduler::new(context);
-    let terminal_handler = Arc::new(TerminalDisplayHandler::with_online_count(
-        Arc::clone(&message_buffer),
-        Arc::clone(&online_count),
-    ));
-    scheduler.add_sequential_handler(terminal_handler);
-    if let Some(server_url) = tts_server {
-        // REST API TTS configuration
-        let tts_handler = Arc::new(TtsHandler::new_rest_api_with_volume(
-            server_url,
-            tts_voice,
-            tts_backend,
-            tts_quality,
-            tts_format,
-            tts_sample_rate,
-            tts_volume,
-        ));
-        scheduler.add_sequential_handler(tts_handler);
-        println!("TTS configured with REST API server");
-    } else if let Some(api_key) = ali_api_key {
-        // Alibaba DashScope TTS configuration
-        let model = ali_model.unwrap_or_else(|| "qwen3-tts-flash".to_string());
-        let voice = ali_voice.unwrap_or_else(|| "Cherry".to_string());
-        let tts_handler = Arc::new(TtsHandler::new_ali_tts(
-            api_key,
-            model.clone(),
-            voice.clone(),
-            ali_language_type,
-            tts_volume,
-        ));
-        scheduler.add_sequential_handler(tts_handler);
-        println!(
-            "TTS configured with Alibaba DashScope (model: {}, voice: {})",
-            model, voice
-        );
-    } else if let Some(tts_cmd) = tts_command {
-        // Command-line TTS configuration
-        let cmd_args = tts_args
-            .map(|s| s.split(',').map(|s| s.to_string()).collect())
-            .unwrap_or_default();
-        let tts_handler = Arc::new(TtsHandler::new_command(tts_cmd, cmd_args));
-        scheduler.add_sequential_handler(tts_handler);
-        println!("TTS configured with local command");
-    } else {
-        println!(
-            "No TTS configuration provided. Use --ali-api-key, --tts-server, or --tts-command to enable TTS."
-        );
+    let context = EventContext::new(cookies.clone(), room_id.parse::<u64>().unwrap_or(0))
+        .with_send_cookies(send_cookies);
+    let mut scheduler = Scheduler::new(context).with_failure_limit(HANDLER_FAILURE_LIMIT);
+    let handler_stats = scheduler.stats();
+    // One outbound queue paces and splits danmaku from the TUI and the plugins
+    let sending_config = config.sending.clone().unwrap_or_default();
+    let danmaku_queue = DanmakuQueue::start_on(
+        scheduler.runtime().handle(),
+        DanmakuSender::shared().clone(),
+        sending_config.to_queue_config(),
+    );
+    {
+        let buffer = Arc::clone(&message_buffer);
+        danmaku_queue.set_listener(move |message, result| {
+            if let Err(e) = result {
+                TuiApp::add_message(
+                    &buffer,
+                    format!("[System] Message not sent ({}): {}", message, e),
+                );
+            }
+        });
     }
 
-    // Add auto reply plugin if enabled
-    if auto_reply_config.enabled {
-        let auto_reply_handler = blivedm::plugins::auto_reply_handler(auto_reply_config);
-        scheduler.add_sequential_handler(auto_reply_handler);
-        println!("Auto reply plugin enabled");
-    } else {
+    if config.pipeline.is_none() && pipeline::first_tts(&stages).is_none() {
         println!(
-            "Auto reply plugin disabled. Use --auto-reply or configure in config file to enable."
+            "No TTS configuration provided. Use --ali-api-key, --tts-server, or --tts-command to enable TTS."
         );
     }
+    let plugin_env = PluginEnv {
+        message_buffer: Arc::clone(&message_buffer),
+        online_count: Arc::clone(&online_count),
+        reply_candidates: Arc::clone(&reply_candidates),
+        danmaku_queue: danmaku_queue.clone(),
+        runtime: scheduler.runtime().handle().clone(),
+    };
+    let live_pipeline = match pipeline::build(&mut scheduler, &stages, &plugin_env) {
+        Ok(live_pipeline) => live_pipeline,
+        Err(e) => {
+            eprintln!("Invalid pipeline in config file: {}", e);
+            std::process::exit(1);
+        }
+    };
 
     // Add initial system message to buffer
     TuiApp::add_message(&message_buffer, format!("[System] Bilibili Danmu Client"));
@@ -427,52 +834,301 @@ fn main() {
         );
     }
 
-    // create a thread to process the rx channel messages using tokio runtime and pass to scheduler
-    let rt = Arc::new(Runtime::new().unwrap());
-    let rt_clone = Arc::clone(&rt);
+    // Check the login status in the background and show the user in the TUI title
+    let logged_in_user: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
+    if let Some(cookies_val) = cookies.clone() {
+        let logged_in_user = Arc::clone(&logged_in_user);
+        let buffer = Arc::clone(&message_buffer);
+        thread::spawn(move || report_auth_status(&cookies_val, &logged_in_user, &buffer));
+    }
+
+    // Show the sending account in the TUI title when it differs from the connection's
+    let send_account: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(send_account));
+    if let Ok(Some(name)) = send_account.lock().map(|a| a.clone()) {
+        TuiApp::add_message(&message_buffer, format!("[System] Sending as: {}", name));
+    }
+    if !accounts.is_empty() {
+        TuiApp::add_message(
+            &message_buffer,
+            "[System] Type /accounts to list accounts, /account <name> to switch the sender"
+                .to_string(),
+        );
+    }
+
+    // Periodically renew stored credentials and hand the new cookies to the handlers and client
+    let context_handle = scheduler.context_handle();
+    if !credentials_paths.is_empty() {
+        let context_handle = Arc::clone(&context_handle);
+        let refresh_client = Arc::clone(&shared_client);
+        let logged_in_user = Arc::clone(&logged_in_user);
+        let buffer = Arc::clone(&message_buffer);
+        thread::spawn(move || {
+            loop {
+                thread::sleep(COOKIE_REFRESH_INTERVAL);
+                for path in &credentials_paths {
+                    let old_cookies = Credentials::load(path).ok().map(|c| c.to_cookie_string());
+                    match refresh_stored_credentials(path) {
+                        Ok(Some(refreshed)) => {
+                            let cookie_str = refreshed.to_cookie_string();
+                            // Swap the cookies wherever the old ones were in use
+                            let mut is_connection_account = false;
+                            if let Ok(mut context) = context_handle.write()
+                                && old_cookies.is_some()
+                            {
+                                if context.cookies == old_cookies {
+                                    context.cookies = Some(cookie_str.clone());
+                                    is_connection_account = true;
+                                }
+                                if context.send_cookies == old_cookies {
+                                    context.send_cookies = Some(cookie_str.clone());
+                                }
+                            }
+                            TuiApp::add_message(
+                                &buffer,
+                                format!("[System] Cookies refreshed and saved to {:?}", path),
+                            );
+                            if is_connection_account {
+                                // Reconnects must not use the cookies the refresh invalidated
+                                if let Ok(mut client) = refresh_client.lock() {
+                                    client.set_cookies(Some(cookie_str.clone()));
+                                }
+                                report_auth_status(&cookie_str, &logged_in_user, &buffer);
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(e) => {
+                            TuiApp::add_message(
+                                &buffer,
+                                format!("[System] Cookie refresh failed for {:?}: {}", path, e),
+                            );
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    // Backfill recent danmaku before live events; handlers see them as historical
+    let load_history = !args.no_history
+        && config
+            .connection
+            .as_ref()
+            .and_then(|c| c.history)
+            .unwrap_or(true);
+    if load_history {
+        backfill_history(&scheduler, &room_id, cookies.as_deref(), &message_buffer);
+    }
+
+    // The connection is already up; live events follow the backfilled history
+    let scheduler = Arc::new(scheduler);
+    let rt = scheduler.runtime();
+    rt.block_on(scheduler.notify_connected());
+
+    // Apply changes to the config file without restarting
+    if let Some(path) = config_file {
+        let scheduler = Arc::clone(&scheduler);
+        let buffer = Arc::clone(&message_buffer);
+        let auto_reply = args.auto_reply;
+        thread::spawn(move || {
+            watch_config(
+                &path,
+                live_pipeline,
+                &scheduler,
+                &tts_overrides,
+                auto_reply,
+                &buffer,
+            )
+        });
+    }
+    let rec_msg: Arc<Mutex<BiliLiveClient>> = Arc::clone(&shared_client);
+    let rec_scheduler = Arc::clone(&scheduler);
+    let rec_buffer = Arc::clone(&message_buffer);
+    let rec_room_id = room_id.clone();
+    thread::spawn(move || {
+        let mut connected = true;
+        let mut reconnect_delay = RECONNECT_MIN_DELAY;
+        loop {
+            let received = match rec_msg.lock() {
+                Ok(mut rec_c) => rec_c.receive(),
+                Err(e) => {
+                    eprintln!("Error acquiring lock on stream: {}", e);
+                    break;
+                }
+            };
+            if let Err(e) = received {
+                log::error!("{}", e);
+                if connected {
+                    connected = false;
+                    rec_scheduler
+                        .runtime()
+                        .block_on(rec_scheduler.notify_disconnected());
+                    TuiApp::add_message(
+                        &rec_buffer,
+                        format!("[System] Connection lost ({}), reconnecting...", e),
+                    );
+                }
+
+                // Back off between attempts; the lock is released so heartbeats keep going
+                thread::sleep(reconnect_delay);
+                let reconnected = match rec_msg.lock() {
+                    Ok(mut rec_c) => rec_c.reconnect(),
+                    Err(e) => {
+                        eprintln!("Error acquiring lock on stream: {}", e);
+                        break;
+                    }
+                };
+                match reconnected {
+                    Ok(()) => {
+                        connected = true;
+                        reconnect_delay = RECONNECT_MIN_DELAY;
+                        // Live events of the new connection are only read after the backfill
+                        if load_history {
+                            let cookies = rec_scheduler
+                                .context_handle()
+                                .read()
+                                .ok()
+                                .and_then(|context| context.cookies.clone());
+                            backfill_history(
+                                &rec_scheduler,
+                                &rec_room_id,
+                                cookies.as_deref(),
+                                &rec_buffer,
+                            );
+                        }
+                        rec_scheduler
+                            .runtime()
+                            .block_on(rec_scheduler.notify_connected());
+                        TuiApp::add_message(&rec_buffer, "[System] Reconnected".to_string());
+                    }
+                    Err(e) => {
+                        reconnect_delay = (reconnect_delay * 2).min(RECONNECT_MAX_DELAY);
+                        log::warn!("Reconnect failed: {}, retrying in {:?}", e, reconnect_delay);
+                    }
+                }
+                continue;
+            }
+            thread::sleep(Duration::from_millis(10)); // instead of 10 microseconds
+        }
+    });
+
+    // Process the rx channel messages on the scheduler's runtime
+    let rx_scheduler = Arc::clone(&scheduler);
     rt.spawn(async move {
         while let Some(msg) = rx.next().await {
-            scheduler.trigger(msg);
+            rx_scheduler.submit(msg).await;
         }
     });
 
+    // Ctrl+C outside the TUI's raw mode, or SIGTERM, closes the TUI like /quit
+    let quit_requested = Arc::new(AtomicBool::new(false));
+    let quit_on_signal = Arc::clone(&quit_requested);
+    rt.spawn(async move {
+        wait_for_shutdown_signal().await;
+        quit_on_signal.store(true, Ordering::Relaxed);
+    });
+
     // Create TUI app
     let tui_app = TuiApp::with_online_count(
         Arc::clone(&message_buffer),
         room_id.clone(),
         Arc::clone(&online_count),
-    );
+    )
+    .with_logged_in_user(Arc::clone(&logged_in_user))
+    .with_send_account(Arc::clone(&send_account))
+    .with_queued_messages(danmaku_queue.pending_handle())
+    .with_replies(reply_candidates, Arc::clone(&reply_target))
+    .with_handler_stats(handler_stats.clone())
+    .with_quit_requested(quit_requested);
 
-    let context_for_chat = EventContext::new(cookies.clone(), room_id.parse::<u64>().unwrap_or(0));
     let message_buffer_for_feedback = Arc::clone(&message_buffer);
 
     // Run TUI with message sending callback
     let tui_result = run_tui(tui_app, move |message| {
-        let context_clone = context_for_chat.clone();
-        let rt_for_send = Arc::clone(&rt_clone);
-        let buffer_clone = Arc::clone(&message_buffer_for_feedback);
+        if message.starts_with("/account") {
+            let accounts = accounts.clone();
+            let context_handle = Arc::clone(&context_handle);
+            let send_account = Arc::clone(&send_account);
+            let buffer = Arc::clone(&message_buffer_for_feedback);
+            // Resolving an account may scan browser databases, keep the TUI responsive
+            thread::spawn(move || {
+                handle_account_command(&message, &accounts, &context_handle, &send_account, &buffer)
+            });
+            return;
+        }
 
-        rt_for_send.spawn(async move {
-            if let Err(e) = blivedm::plugins::send_danmaku_message(&message, &context_clone).await {
-                TuiApp::add_message(
-                    &buffer_clone,
-                    format!("[System] Error sending message: {}", e),
-                );
-            }
-        });
+        // `/enable <handler>` re-enables a handler disabled after repeated failures
+        if let Some(name) = message.strip_prefix("/enable ") {
+            let name = name.trim();
+            let feedback = if handler_stats.enable(name) {
+                format!("[System] Handler {} enabled", name)
+            } else {
+                format!("[System] No disabled handler named {}", name)
+            };
+            TuiApp::add_message(&message_buffer_for_feedback, feedback);
+            return;
+        }
+
+        let context_clone = match context_handle.read() {
+            Ok(context) => context.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        };
+        // `/emote <unique id>` sends an emoticon, e.g. `/emote official_147`
+        if let Some(emoticon) = message.strip_prefix("/emote ") {
+            danmaku_queue.enqueue_with_options(
+                emoticon,
+                &context_clone,
+                SendOptions::new().with_emoticon(),
+            );
+            return;
+        }
+
+        let mut options = SendOptions::new();
+        if let Some(target) = TuiApp::take_reply_target(&reply_target) {
+            options = options.with_reply_to(target);
+        }
+        let parts = danmaku_queue.enqueue_with_options(&message, &context_clone, options);
+        if parts > 1 {
+            TuiApp::add_message(
+                &message_buffer_for_feedback,
+                format!("[System] Long message split into {} danmaku", parts),
+            );
+        }
     });
 
     if let Err(e) = tui_result {
         eprintln!("TUI error: {}", e);
     }
 
-    // close the client
-    match shared_client.lock() {
-        Ok(mut _client) => {}
-        Err(e) => {
-            eprintln!("Error acquiring lock on stream: {}", e);
+    // Let handlers finish queued messages and their shutdown hooks, e.g. TTS playback
+    println!("Shutting down...");
+    if !scheduler.shutdown(SHUTDOWN_TIMEOUT) {
+        eprintln!(
+            "Some handlers did not finish within {} seconds",
+            SHUTDOWN_TIMEOUT.as_secs()
+        );
+    }
+}
+
+/// Wait for Ctrl+C or, on Unix, SIGTERM
+async fn wait_for_shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+            }
+            Err(e) => {
+                log::warn!("Cannot listen for SIGTERM: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+            }
         }
     }
-    // wait for the threads to finish
-    thread::sleep(Duration::new(1, 0));
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
 }
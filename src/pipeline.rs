@@ -0,0 +1,871 @@
+// src/pipeline.rs
+//! Building the scheduler's stages from `[[pipeline.stage]]` in the config file, and
+//! applying a changed config file to the running handlers
+
+use crate::config::{
+    AutoReplyConfig, Config, PluginEntry, PluginSpec, ScriptsConfig, StageConfig, TtsConfig,
+};
+use blivedm::client::danmaku::{DanmakuQueue, ReplyTarget};
+use blivedm::client::filter::{MessageFilter, MessageKind};
+use blivedm::client::scheduler::{EventHandler, Scheduler, StageHandler};
+use blivedm::plugins::actions::PluginActions;
+use blivedm::plugins::auto_reply::AutoReplyHandler;
+use blivedm::plugins::external::ExternalPluginHandler;
+use blivedm::plugins::script::load_scripts;
+use blivedm::plugins::terminal_display::TerminalDisplayHandler;
+use blivedm::plugins::tts::{TtsHandler, TtsMode};
+#[cfg(feature = "wasm")]
+use blivedm::plugins::wasm::WasmPluginHandler;
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicU64;
+use std::sync::{Arc, Mutex};
+use tokio::runtime::Handle;
+
+/// What the plugins share with the rest of the app
+pub struct PluginEnv {
+    pub message_buffer: Arc<Mutex<VecDeque<String>>>,
+    pub online_count: Arc<AtomicU64>,
+    pub reply_candidates: Arc<Mutex<VecDeque<ReplyTarget>>>,
+    pub danmaku_queue: DanmakuQueue,
+    /// Runtime TTS requests made on behalf of other plugins run on
+    pub runtime: Handle,
+}
+
+/// Stages from `[[pipeline.stage]]`, or else the built-in pipeline made of the [tts],
+/// [auto_reply] and plugin sections. `tts_overrides` holds the TTS command-line options,
+/// which win over [tts] and the settings of `tts` entries; `auto_reply` is the
+/// `--auto-reply` flag, which enables the auto reply plugin.
+pub fn stages(config: &Config, tts_overrides: &TtsConfig, auto_reply: bool) -> Vec<StageConfig> {
+    if let Some(pipeline) = &config.pipeline {
+        let mut stages = pipeline.stages.clone();
+        for entry in stages.iter_mut().flat_map(|stage| &mut stage.plugins) {
+            match &mut entry.plugin {
+                PluginSpec::Tts { config } => **config = tts_overrides.or(config),
+                PluginSpec::AutoReply { .. } if auto_reply => entry.enabled = true,
+                _ => {}
+            }
+        }
+        return stages;
+    }
+    let tts = tts_overrides.or(&config.tts.clone().unwrap_or_default());
+    let mut auto_reply_config = config.auto_reply.clone().unwrap_or_default();
+    auto_reply_config.enabled |= auto_reply;
+    default_stages(config, tts, auto_reply_config)
+}
+
+/// The pipeline used without `[pipeline]`: terminal display, TTS, auto reply, then
+/// the external plugins, WebAssembly plugins and scripts, each as its own stage
+pub fn default_stages(
+    config: &Config,
+    tts: TtsConfig,
+    auto_reply: AutoReplyConfig,
+) -> Vec<StageConfig> {
+    let mut stages = vec![stage(PluginSpec::Terminal)];
+    if tts_mode(&tts).is_some() {
+        stages.push(stage(PluginSpec::Tts {
+            config: Box::new(tts),
+        }));
+    }
+    // Added while disabled, so that enabling it in the config file needs no restart
+    let enabled = auto_reply.enabled;
+    let mut auto_reply = stage(PluginSpec::AutoReply { config: auto_reply });
+    auto_reply.plugins[0].enabled = enabled;
+    stages.push(auto_reply);
+    for plugin in config.plugins.clone().unwrap_or_default() {
+        stages.push(stage(PluginSpec::External { config: plugin }));
+    }
+    #[cfg(feature = "wasm")]
+    for plugin in config.wasm_plugins.clone().unwrap_or_default() {
+        stages.push(stage(PluginSpec::Wasm { config: plugin }));
+    }
+    // Rhai scripts from the scripts directory, when it exists
+    let scripts = config.scripts.clone().unwrap_or_default();
+    if scripts.dir().is_some_and(|dir| dir.is_dir()) {
+        stages.push(stage(PluginSpec::Scripts { config: scripts }));
+    }
+    stages
+}
+
+/// Settings of the first TTS plugin in the stages, the one other plugins speak through
+pub fn first_tts(stages: &[StageConfig]) -> Option<&TtsConfig> {
+    stages
+        .iter()
+        .flat_map(|stage| &stage.plugins)
+        .find_map(|entry| match &entry.plugin {
+            PluginSpec::Tts { config } => Some(config.as_ref()),
+            _ => None,
+        })
+}
+
+/// Settings of the auto reply plugin when it is in the stages and enabled
+pub fn enabled_auto_reply(stages: &[StageConfig]) -> Option<&AutoReplyConfig> {
+    stages
+        .iter()
+        .flat_map(|stage| &stage.plugins)
+        .find_map(|entry| match &entry.plugin {
+            PluginSpec::AutoReply { config } if entry.enabled => Some(config),
+            _ => None,
+        })
+}
+
+fn stage(plugin: PluginSpec) -> StageConfig {
+    StageConfig {
+        plugins: vec![PluginEntry {
+            plugin,
+            filter: None,
+            enabled: true,
+        }],
+    }
+}
+
+/// Handlers built from the configured stages, kept to apply changes to the config file
+pub struct LivePipeline {
+    entries: Vec<LiveEntry>,
+}
+
+/// The handlers built for a plugin entry
+struct LiveEntry {
+    /// What must stay the same to update the entry in place, see [`entry_key`]
+    key: EntryKey,
+    /// The scheduler stage the handlers are in
+    stage: usize,
+    /// Positions of the handlers in that stage; none if the plugin failed to load
+    handlers: Range<usize>,
+    enabled: bool,
+    plugin: LivePlugin,
+}
+
+/// What identifies a plugin entry across reloads of the config file
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct EntryKey {
+    /// Position of the entry's stage
+    position: usize,
+    /// The plugin, e.g. `tts` or `external`
+    kind: &'static str,
+    /// The name of external and WebAssembly plugins
+    name: Option<String>,
+    /// Settings the handlers were built with
+    settings: RestartSettings,
+}
+
+/// Settings of a plugin that are fixed when its handlers are built, so changing
+/// them needs a restart; everything else is applied while running
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RestartSettings {
+    None,
+    /// The TTS backend; its settings can change, but not the backend itself
+    TtsBackend(Option<&'static str>),
+    External {
+        command: String,
+        args: Vec<String>,
+        restart: bool,
+    },
+    Scripts {
+        dir: Option<PathBuf>,
+    },
+    #[cfg(feature = "wasm")]
+    Wasm {
+        path: PathBuf,
+        fuel: u64,
+        memory_mb: usize,
+    },
+}
+
+/// Handlers whose own settings can change while running
+enum LivePlugin {
+    Tts(Arc<TtsHandler>),
+    AutoReply(Arc<AutoReplyHandler>),
+    Other,
+}
+
+/// Add a stage to the scheduler for each configured stage, with its plugins in parallel
+pub fn build(
+    scheduler: &mut Scheduler,
+    stages: &[StageConfig],
+    env: &PluginEnv,
+) -> Result<LivePipeline, String> {
+    // TTS handlers come first so the other plugins can speak through the first one
+    let mut tts_handlers = Vec::new();
+    for entry in stages.iter().flat_map(|stage| &stage.plugins) {
+        if let PluginSpec::Tts { config } = &entry.plugin {
+            let handler = tts_handler(config)
+                .ok_or("a tts plugin needs one of server, ali_api_key or command")?;
+            tts_handlers.push(handler);
+        }
+    }
+    let mut actions = PluginActions::new()
+        .with_danmaku_queue(env.danmaku_queue.clone())
+        .with_message_buffer(Arc::clone(&env.message_buffer));
+    if let Some(tts) = tts_handlers.first() {
+        actions = actions.with_tts(Arc::clone(tts), env.runtime.clone());
+    }
+    let actions = Arc::new(actions);
+    let mut tts_handlers = tts_handlers.into_iter();
+
+    let mut entries = Vec::new();
+    for (position, stage) in stages.iter().enumerate() {
+        let stage_index = scheduler.stage_count();
+        let mut handlers: Vec<(StageHandler, MessageFilter)> = Vec::new();
+        for entry in &stage.plugins {
+            let filter = entry_filter(entry);
+            let first = handlers.len();
+            let plugin = match &entry.plugin {
+                PluginSpec::Terminal => {
+                    let handler = TerminalDisplayHandler::with_online_count(
+                        Arc::clone(&env.message_buffer),
+                        Arc::clone(&env.online_count),
+                    )
+                    .with_reply_candidates(Arc::clone(&env.reply_candidates));
+                    handlers.push((StageHandler::Sync(Arc::new(handler)), filter));
+                    LivePlugin::Other
+                }
+                PluginSpec::Tts { .. } => {
+                    let handler = tts_handlers.next().ok_or("TTS handlers out of order")?;
+                    handlers.push((StageHandler::Async(handler.clone()), filter));
+                    LivePlugin::Tts(handler)
+                }
+                PluginSpec::AutoReply { config } => {
+                    let handler = Arc::new(
+                        AutoReplyHandler::new(auto_reply_config(config))
+                            .with_queue(env.danmaku_queue.clone()),
+                    );
+                    handlers.push((StageHandler::Async(handler.clone()), filter));
+                    if entry.enabled {
+                        println!("Auto reply plugin enabled");
+                    } else {
+                        println!(
+                            "Auto reply plugin disabled. Use --auto-reply or configure in config file to enable."
+                        );
+                    }
+                    LivePlugin::AutoReply(handler)
+                }
+                PluginSpec::External { config } => {
+                    let handler = ExternalPluginHandler::new(config.clone(), Arc::clone(&actions));
+                    handlers.push((StageHandler::Sync(Arc::new(handler)), filter));
+                    println!("External plugin {} enabled", config.name);
+                    LivePlugin::Other
+                }
+                PluginSpec::Scripts { config } => {
+                    for handler in scripts(config, &actions) {
+                        handlers.push((StageHandler::Sync(handler), filter.clone()));
+                    }
+                    LivePlugin::Other
+                }
+                #[cfg(feature = "wasm")]
+                PluginSpec::Wasm { config } => {
+                    match WasmPluginHandler::load(config.clone(), Arc::clone(&actions)) {
+                        Ok(handler) => {
+                            handlers.push((StageHandler::Sync(Arc::new(handler)), filter));
+                            println!("WASM plugin {} enabled", config.name);
+                        }
+                        Err(e) => eprintln!("{}", e),
+                    }
+                    LivePlugin::Other
+                }
+            };
+            entries.push(LiveEntry {
+                key: entry_key(position, entry),
+                stage: stage_index,
+                handlers: first..handlers.len(),
+                enabled: entry.enabled,
+                plugin,
+            });
+        }
+        if !handlers.is_empty() {
+            scheduler.add_filtered_stage(handlers);
+        }
+    }
+    // Disabled plugins are registered all the same, so they can be enabled later
+    for entry in entries.iter().filter(|entry| !entry.enabled) {
+        for index in entry.handlers.clone() {
+            scheduler.set_enabled(entry.stage, index, false);
+        }
+    }
+    Ok(LivePipeline { entries })
+}
+
+impl LivePipeline {
+    /// Apply changed stages, e.g. from a reloaded config file: filters, enable flags,
+    /// auto reply triggers and cooldown, and TTS settings.
+    ///
+    /// Adding, removing or moving plugins, or changing what a plugin runs, needs a
+    /// restart; then nothing is changed and an error says so.
+    pub fn apply(&mut self, scheduler: &Scheduler, stages: &[StageConfig]) -> Result<(), String> {
+        let entries: Vec<(usize, &PluginEntry)> = stages
+            .iter()
+            .enumerate()
+            .flat_map(|(position, stage)| stage.plugins.iter().map(move |entry| (position, entry)))
+            .collect();
+        let unchanged = entries.len() == self.entries.len()
+            && entries
+                .iter()
+                .zip(&self.entries)
+                .all(|((position, entry), live)| entry_key(*position, entry) == live.key);
+        if !unchanged {
+            return Err(
+                "plugins were added, removed or changed, restart blivedm to apply".to_string(),
+            );
+        }
+
+        for ((_, entry), live) in entries.into_iter().zip(&mut self.entries) {
+            let filter = entry_filter(entry);
+            for index in live.handlers.clone() {
+                scheduler.set_filter(live.stage, index, Some(filter.clone()));
+                // Handlers disabled after failures stay disabled unless the flag changes
+                if entry.enabled != live.enabled {
+                    scheduler.set_enabled(live.stage, index, entry.enabled);
+                }
+            }
+            live.enabled = entry.enabled;
+            match (&live.plugin, &entry.plugin) {
+                (LivePlugin::Tts(handler), PluginSpec::Tts { config }) => {
+                    let mode = tts_mode(config).ok_or("TTS settings missing")?;
+                    handler.set_mode(mode)?;
+                    handler.set_emote_speech(config.emotes.unwrap_or_default());
+                }
+                (LivePlugin::AutoReply(handler), PluginSpec::AutoReply { config }) => {
+                    handler.set_config(auto_reply_config(config));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Messages an entry's plugin gets: the entry's filter wins over the one in the
+/// plugin's own config
+fn entry_filter(entry: &PluginEntry) -> MessageFilter {
+    let default = match &entry.plugin {
+        PluginSpec::Terminal => None,
+        // TTS reads danmaku unless the config routes other messages to it
+        PluginSpec::Tts { config } => Some(
+            config
+                .filter
+                .clone()
+                .unwrap_or_else(|| MessageFilter::new().with_kinds([MessageKind::Danmu])),
+        ),
+        PluginSpec::AutoReply { config } => config.filter.clone(),
+        PluginSpec::External { config } => config.filter.clone(),
+        PluginSpec::Scripts { config } => config.filter.clone(),
+        #[cfg(feature = "wasm")]
+        PluginSpec::Wasm { config } => config.filter.clone(),
+    };
+    entry.filter.clone().or(default).unwrap_or_default()
+}
+
+/// The stage position, kind and name of an entry, with the settings that need a restart
+fn entry_key(position: usize, entry: &PluginEntry) -> EntryKey {
+    let (kind, name, settings) = match &entry.plugin {
+        PluginSpec::Terminal => ("terminal", None, RestartSettings::None),
+        PluginSpec::Tts { config } => {
+            let backend = tts_mode(config).map(|mode| match mode {
+                TtsMode::RestApi { .. } => "rest_api",
+                TtsMode::AliTts { .. } => "dashscope",
+                TtsMode::Command { .. } => "command",
+            });
+            ("tts", None, RestartSettings::TtsBackend(backend))
+        }
+        PluginSpec::AutoReply { .. } => ("auto_reply", None, RestartSettings::None),
+        PluginSpec::External { config } => (
+            "external",
+            Some(config.name.clone()),
+            RestartSettings::External {
+                command: config.command.clone(),
+                args: config.args.clone(),
+                restart: config.restart,
+            },
+        ),
+        PluginSpec::Scripts { config } => (
+            "scripts",
+            None,
+            RestartSettings::Scripts { dir: config.dir() },
+        ),
+        #[cfg(feature = "wasm")]
+        PluginSpec::Wasm { config } => (
+            "wasm",
+            Some(config.name.clone()),
+            RestartSettings::Wasm {
+                path: config.path.clone(),
+                fuel: config.fuel,
+                memory_mb: config.memory_mb,
+            },
+        ),
+    };
+    EntryKey {
+        position,
+        kind,
+        name,
+        settings,
+    }
+}
+
+/// The auto reply plugin's config; listing the plugin in a stage enables it, and the
+/// entry's `enabled` flag switches it off
+fn auto_reply_config(config: &AutoReplyConfig) -> blivedm::plugins::auto_reply::AutoReplyConfig {
+    let mut plugin_config = config.to_plugin_config();
+    plugin_config.enabled = true;
+    plugin_config
+}
+
+/// The TTS handler for a config, or `None` when it sets no server, API key or command
+fn tts_handler(config: &TtsConfig) -> Option<Arc<TtsHandler>> {
+    let mode = tts_mode(config)?;
+    match &mode {
+        TtsMode::RestApi { .. } => println!("TTS configured with REST API server"),
+        TtsMode::AliTts { model, voice, .. } => println!(
+            "TTS configured with Alibaba DashScope (model: {}, voice: {})",
+            model, voice
+        ),
+        TtsMode::Command { .. } => println!("TTS configured with local command"),
+    }
+    Some(Arc::new(
+        TtsHandler::new(mode).with_emote_speech(config.emotes.unwrap_or_default()),
+    ))
+}
+
+/// TTS settings for a config, or `None` when it sets no server, API key or command
+fn tts_mode(config: &TtsConfig) -> Option<TtsMode> {
+    if let Some(server_url) = &config.server {
+        // REST API TTS configuration
+        Some(TtsMode::RestApi {
+            server_url: server_url.clone(),
+            voice: config.voice.clone(),
+            backend: config.backend.clone(),
+            quality: config.quality.clone(),
+            format: config.format.clone(),
+            sample_rate: config.sample_rate,
+            volume: config.volume,
+        })
+    } else if let Some(api_key) = &config.ali_api_key {
+        // Alibaba DashScope TTS configuration
+        Some(TtsMode::AliTts {
+            api_key: api_key.clone(),
+            model: config
+                .ali_model
+                .clone()
+                .unwrap_or_else(|| "qwen3-tts-flash".to_string()),
+            voice: config
+                .ali_voice
+                .clone()
+                .unwrap_or_else(|| "Cherry".to_string()),
+            language_type: config.ali_language_type.clone(),
+            volume: config.volume,
+        })
+    } else {
+        // Command-line TTS configuration
+        config.command.as_ref().map(|tts_cmd| TtsMode::Command {
+            tts_command: tts_cmd.clone(),
+            tts_args: config
+                .args
+                .as_ref()
+                .map(|s| s.split(',').map(|s| s.to_string()).collect())
+                .unwrap_or_default(),
+        })
+    }
+}
+
+/// Handlers for the Rhai scripts in the configured directory
+fn scripts(config: &ScriptsConfig, actions: &Arc<PluginActions>) -> Vec<Arc<dyn EventHandler>> {
+    let Some(dir) = config.dir() else {
+        eprintln!("Cannot find the scripts directory");
+        return Vec::new();
+    };
+    let scripts = match load_scripts(&dir, actions) {
+        Ok(scripts) => scripts,
+        Err(e) => {
+            eprintln!("{}", e);
+            return Vec::new();
+        }
+    };
+    let mut handlers: Vec<Arc<dyn EventHandler>> = Vec::new();
+    for script in scripts {
+        match script {
+            Ok(handler) => {
+                println!("Script {} enabled", handler.name());
+                handlers.push(Arc::new(handler));
+            }
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+    handlers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{PipelineConfig, TriggerConfig};
+    use blivedm::client::danmaku::{DanmakuQueueConfig, DanmakuSender};
+    use blivedm::client::models::BiliMessage;
+    use blivedm::client::scheduler::EventContext;
+    use blivedm::plugins::external::ExternalPluginConfig;
+
+    /// Kinds of the entries in each stage
+    fn kinds(stages: &[StageConfig]) -> Vec<Vec<&'static str>> {
+        stages
+            .iter()
+            .enumerate()
+            .map(|(position, stage)| {
+                stage
+                    .plugins
+                    .iter()
+                    .map(|entry| entry_key(position, entry).kind)
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn external(name: &str) -> ExternalPluginConfig {
+        ExternalPluginConfig {
+            name: name.to_string(),
+            command: "python3".to_string(),
+            args: vec![format!("{}.py", name)],
+            filter: None,
+            restart: true,
+        }
+    }
+
+    #[test]
+    fn test_pipeline_config_parses_stages() {
+        let config: Config = toml::from_str(
+            r#"
+            [[pipeline.stage]]
+            plugins = [{ name = "terminal" }]
+
+            [[pipeline.stage]]
+            [[pipeline.stage.plugins]]
+            name = "tts"
+            enabled = false
+            config = { command = "say", args = "--voice=Mei-Jia", volume = 0.5 }
+            filter = { kinds = ["gift"], min_value = 1.0 }
+
+            [[pipeline.stage.plugins]]
+            name = "external"
+            config = { name = "greeter", command = "python3", args = ["greeter.py"] }
+
+            [[pipeline.stage]]
+            plugins = [
+              { name = "auto_reply", config = { cooldown_seconds = 3, triggers = [{ keywords = ["hi"], response = "hello" }] } },
+              { name = "scripts" },
+            ]
+            "#,
+        )
+        .unwrap();
+        let stages = &config.pipeline.as_ref().unwrap().stages;
+        assert_eq!(
+            kinds(stages),
+            vec![
+                vec!["terminal"],
+                vec!["tts", "external"],
+                vec!["auto_reply", "scripts"]
+            ]
+        );
+
+        let tts = &stages[1].plugins[0];
+        assert!(!tts.enabled);
+        let PluginSpec::Tts { config: tts_config } = &tts.plugin else {
+            panic!("expected a tts entry");
+        };
+        assert_eq!(tts_config.command.as_deref(), Some("say"));
+        assert_eq!(tts_config.args.as_deref(), Some("--voice=Mei-Jia"));
+        assert_eq!(tts_config.volume, Some(0.5));
+        let filter = tts.filter.as_ref().unwrap();
+        assert_eq!(filter.kinds, vec![MessageKind::Gift]);
+        assert_eq!(filter.min_value, Some(1.0));
+
+        let PluginSpec::External { config: greeter } = &stages[1].plugins[1].plugin else {
+            panic!("expected an external entry");
+        };
+        assert_eq!(greeter.name, "greeter");
+        assert!(greeter.restart);
+        assert!(stages[1].plugins[1].enabled);
+
+        let PluginSpec::AutoReply { config: auto_reply } = &stages[2].plugins[0].plugin else {
+            panic!("expected an auto_reply entry");
+        };
+        assert_eq!(auto_reply.cooldown_seconds, 3);
+        assert_eq!(auto_reply.triggers[0].keywords, vec!["hi"]);
+    }
+
+    #[test]
+    fn test_default_stages_order() {
+        let scripts_dir =
+            std::env::temp_dir().join(format!("blivedm_pipeline_scripts_{}", std::process::id()));
+        let mut config = Config {
+            plugins: Some(vec![external("first"), external("second")]),
+            scripts: Some(ScriptsConfig {
+                dir: Some(scripts_dir.clone()),
+                filter: None,
+            }),
+            ..Default::default()
+        };
+
+        // No TTS settings and no scripts directory
+        let stages = default_stages(&config, TtsConfig::default(), AutoReplyConfig::default());
+        assert_eq!(
+            kinds(&stages),
+            vec![
+                vec!["terminal"],
+                vec!["auto_reply"],
+                vec!["external"],
+                vec!["external"]
+            ]
+        );
+        // The auto reply plugin is always there, disabled unless enabled
+        assert!(!stages[1].plugins[0].enabled);
+        assert!(enabled_auto_reply(&stages).is_none());
+
+        std::fs::create_dir_all(&scripts_dir).unwrap();
+        let tts = TtsConfig {
+            command: Some("say".to_string()),
+            ..Default::default()
+        };
+        let auto_reply = AutoReplyConfig {
+            enabled: true,
+            ..Default::default()
+        };
+        config.plugins = Some(vec![external("only")]);
+        let stages = default_stages(&config, tts, auto_reply);
+        std::fs::remove_dir_all(&scripts_dir).unwrap();
+        assert_eq!(
+            kinds(&stages),
+            vec![
+                vec!["terminal"],
+                vec!["tts"],
+                vec!["auto_reply"],
+                vec!["external"],
+                vec!["scripts"]
+            ]
+        );
+        assert!(stages[2].plugins[0].enabled);
+        assert!(enabled_auto_reply(&stages).is_some());
+        assert_eq!(
+            first_tts(&stages).and_then(|tts| tts.command.as_deref()),
+            Some("say")
+        );
+    }
+
+    #[test]
+    fn test_stages_apply_command_line_options_to_pipeline() {
+        let mut disabled = Config {
+            pipeline: Some(PipelineConfig {
+                stages: vec![
+                    stage(PluginSpec::Tts {
+                        config: Box::new(TtsConfig {
+                            command: Some("say".to_string()),
+                            volume: Some(0.5),
+                            ..Default::default()
+                        }),
+                    }),
+                    stage(PluginSpec::AutoReply {
+                        config: AutoReplyConfig::default(),
+                    }),
+                ],
+            }),
+            ..Default::default()
+        };
+        disabled.pipeline.as_mut().unwrap().stages[1].plugins[0].enabled = false;
+        let overrides = TtsConfig {
+            volume: Some(0.8),
+            ..Default::default()
+        };
+
+        // The TTS options win over the entry's settings; the entry keeps the rest
+        let merged = stages(&disabled, &overrides, false);
+        let tts = first_tts(&merged).unwrap();
+        assert_eq!(tts.command.as_deref(), Some("say"));
+        assert_eq!(tts.volume, Some(0.8));
+        assert!(enabled_auto_reply(&merged).is_none());
+
+        // --auto-reply enables a disabled auto_reply entry
+        let merged = stages(&disabled, &TtsConfig::default(), true);
+        assert_eq!(first_tts(&merged).unwrap().volume, Some(0.5));
+        assert!(enabled_auto_reply(&merged).is_some());
+    }
+
+    #[test]
+    fn test_entry_filter_prefers_entry_filter() {
+        let gifts = MessageFilter::new().with_kinds([MessageKind::Gift]);
+        let super_chats = MessageFilter::new().with_kinds([MessageKind::SuperChat]);
+        let mut entry = PluginEntry {
+            plugin: PluginSpec::Tts {
+                config: Box::new(TtsConfig {
+                    command: Some("say".to_string()),
+                    filter: Some(super_chats),
+                    ..Default::default()
+                }),
+            },
+            filter: Some(gifts),
+            enabled: true,
+        };
+        assert_eq!(entry_filter(&entry).kinds, vec![MessageKind::Gift]);
+
+        // Then the plugin config's filter, then TTS reads danmaku
+        entry.filter = None;
+        assert_eq!(entry_filter(&entry).kinds, vec![MessageKind::SuperChat]);
+        if let PluginSpec::Tts { config } = &mut entry.plugin {
+            config.filter = None;
+        }
+        assert_eq!(entry_filter(&entry).kinds, vec![MessageKind::Danmu]);
+
+        // Other plugins get all messages without a filter
+        let mut external_config = external("greeter");
+        external_config.filter = Some(MessageFilter::new().with_kinds([MessageKind::Raw]));
+        let entry = PluginEntry {
+            plugin: PluginSpec::External {
+                config: external_config,
+            },
+            filter: None,
+            enabled: true,
+        };
+        assert_eq!(entry_filter(&entry).kinds, vec![MessageKind::Raw]);
+        let terminal = stage(PluginSpec::Terminal);
+        assert!(entry_filter(&terminal.plugins[0]).kinds.is_empty());
+    }
+
+    fn env(scheduler: &Scheduler) -> PluginEnv {
+        let runtime = scheduler.runtime().handle().clone();
+        PluginEnv {
+            message_buffer: Arc::new(Mutex::new(VecDeque::new())),
+            online_count: Arc::new(AtomicU64::new(0)),
+            reply_candidates: Arc::new(Mutex::new(VecDeque::new())),
+            danmaku_queue: DanmakuQueue::start_on(
+                &runtime,
+                DanmakuSender::new(),
+                DanmakuQueueConfig::default(),
+            ),
+            runtime,
+        }
+    }
+
+    /// Terminal display; REST API TTS for gifts; auto reply
+    fn live_stages() -> Vec<StageConfig> {
+        let mut tts = stage(PluginSpec::Tts {
+            config: Box::new(TtsConfig {
+                server: Some("http://127.0.0.1:9".to_string()),
+                volume: Some(0.5),
+                ..Default::default()
+            }),
+        });
+        tts.plugins[0].filter = Some(MessageFilter::new().with_kinds([MessageKind::Gift]));
+        let auto_reply = stage(PluginSpec::AutoReply {
+            config: AutoReplyConfig {
+                triggers: vec![TriggerConfig {
+                    keywords: vec!["早上好".to_string()],
+                    response: "早".to_string(),
+                    reply: false,
+                }],
+                ..Default::default()
+            },
+        });
+        vec![stage(PluginSpec::Terminal), tts, auto_reply]
+    }
+
+    fn danmu(text: &str) -> BiliMessage {
+        BiliMessage::Danmu {
+            user: "user1".to_string(),
+            uid: 0,
+            text: text.to_string(),
+            segments: Vec::new(),
+            medal: None,
+        }
+    }
+
+    fn tts_volume(pipeline: &LivePipeline) -> Option<f32> {
+        pipeline.entries.iter().find_map(|live| match &live.plugin {
+            LivePlugin::Tts(handler) => match handler.mode() {
+                TtsMode::RestApi { volume, .. } => volume,
+                _ => None,
+            },
+            _ => None,
+        })
+    }
+
+    fn auto_reply_response(pipeline: &LivePipeline) -> Option<String> {
+        pipeline.entries.iter().find_map(|live| match &live.plugin {
+            LivePlugin::AutoReply(handler) => Some(handler.config().triggers[0].response.clone()),
+            _ => None,
+        })
+    }
+
+    fn disabled_stages(scheduler: &Scheduler) -> Vec<usize> {
+        let mut stages: Vec<usize> = scheduler
+            .stats()
+            .snapshot()
+            .into_iter()
+            .filter(|stats| stats.disabled)
+            .map(|stats| stats.stage)
+            .collect();
+        stages.sort();
+        stages
+    }
+
+    #[test]
+    fn test_apply_changes_running_plugins() {
+        let mut scheduler = Scheduler::new(EventContext::new(None, 1));
+        let env = env(&scheduler);
+        let stages = live_stages();
+        let mut pipeline = build(&mut scheduler, &stages, &env).unwrap();
+        scheduler.trigger(danmu("hello"));
+        assert_eq!(env.message_buffer.lock().unwrap().len(), 1);
+
+        let mut changed = stages.clone();
+        changed[0].plugins[0].filter = Some(MessageFilter::new().with_kinds([MessageKind::Gift]));
+        if let PluginSpec::Tts { config } = &mut changed[1].plugins[0].plugin {
+            config.volume = Some(0.8);
+        }
+        if let PluginSpec::AutoReply { config } = &mut changed[2].plugins[0].plugin {
+            config.triggers[0].response = "早上好呀".to_string();
+        }
+        changed[2].plugins[0].enabled = false;
+        pipeline.apply(&scheduler, &changed).unwrap();
+
+        assert_eq!(tts_volume(&pipeline), Some(0.8));
+        assert_eq!(auto_reply_response(&pipeline).as_deref(), Some("早上好呀"));
+        assert_eq!(disabled_stages(&scheduler), vec![2]);
+        // The terminal display now only gets gifts
+        scheduler.trigger(danmu("hello"));
+        assert_eq!(env.message_buffer.lock().unwrap().len(), 1);
+
+        // Enabling it again
+        pipeline.apply(&scheduler, &stages).unwrap();
+        assert!(disabled_stages(&scheduler).is_empty());
+        scheduler.trigger(danmu("hello"));
+        assert_eq!(env.message_buffer.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_apply_rejects_changes_that_need_a_restart() {
+        let mut scheduler = Scheduler::new(EventContext::new(None, 1));
+        let env = env(&scheduler);
+        let stages = live_stages();
+        let mut pipeline = build(&mut scheduler, &stages, &env).unwrap();
+
+        // Changes that could be applied, made along with one that cannot
+        let mut changed = stages.clone();
+        if let PluginSpec::AutoReply { config } = &mut changed[2].plugins[0].plugin {
+            config.triggers[0].response = "早上好呀".to_string();
+        }
+        changed[2].plugins[0].enabled = false;
+
+        let mut added = changed.clone();
+        added.push(stage(PluginSpec::Terminal));
+        let mut reordered = changed.clone();
+        reordered.swap(1, 2);
+        let mut command_tts = changed.clone();
+        if let PluginSpec::Tts { config } = &mut command_tts[1].plugins[0].plugin {
+            config.server = None;
+            config.command = Some("say".to_string());
+        }
+
+        for stages in [added, reordered, command_tts] {
+            let error = pipeline.apply(&scheduler, &stages).unwrap_err();
+            assert!(error.contains("restart"), "{}", error);
+            assert_eq!(tts_volume(&pipeline), Some(0.5));
+            assert_eq!(auto_reply_response(&pipeline).as_deref(), Some("早"));
+            assert!(disabled_stages(&scheduler).is_empty());
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! Actions plugins can take on behalf of their code: send danmaku, speak, show a line
+//! in the TUI and log
+
+use crate::client::danmaku::DanmakuQueue;
+use crate::client::scheduler::EventContext;
+use crate::plugins::tts::TtsHandler;
+use crate::tui::app::TuiApp;
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::runtime::Handle;
+
+/// What plugins running other code (external processes, scripts) can do in the app
+///
+/// Every action is optional; actions that are not set up are logged and ignored.
+#[derive(Clone, Default)]
+pub struct PluginActions {
+    danmaku_queue: Option<DanmakuQueue>,
+    tts: Option<(Arc<TtsHandler>, Handle)>,
+    message_buffer: Option<Arc<Mutex<VecDeque<String>>>>,
+}
+
+impl PluginActions {
+    /// Actions with nothing set up; everything is only logged
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send danmaku through the shared outbound queue
+    pub fn with_danmaku_queue(mut self, danmaku_queue: DanmakuQueue) -> Self {
+        self.danmaku_queue = Some(danmaku_queue);
+        self
+    }
+
+    /// Speak through the TTS handler, making its HTTP requests on `runtime`
+    pub fn with_tts(mut self, tts: Arc<TtsHandler>, runtime: Handle) -> Self {
+        self.tts = Some((tts, runtime));
+        self
+    }
+
+    /// Show lines in the TUI message list
+    pub fn with_message_buffer(mut self, message_buffer: Arc<Mutex<VecDeque<String>>>) -> Self {
+        self.message_buffer = Some(message_buffer);
+        self
+    }
+
+    /// Send a danmaku as the context's sending account
+    pub fn send(&self, plugin: &str, text: &str, context: &EventContext) {
+        match &self.danmaku_queue {
+            Some(queue) => {
+                queue.enqueue(text, context);
+            }
+            None => log::warn!("Plugin {} cannot send danmaku: no danmaku queue", plugin),
+        }
+    }
+
+    /// Speak text after what TTS has queued
+    pub fn speak(&self, plugin: &str, text: &str) {
+        match &self.tts {
+            Some((tts, runtime)) => tts.speak(text, runtime.clone()),
+            None => log::warn!("Plugin {} cannot speak: TTS is not configured", plugin),
+        }
+    }
+
+    /// Show a line in the TUI, e.g. `[Plugin greeter] hello`
+    pub fn display(&self, plugin: &str, text: &str) {
+        match &self.message_buffer {
+            Some(buffer) => TuiApp::add_message(buffer, format!("[Plugin {}] {}", plugin, text)),
+            None => log::info!("[Plugin {}] {}", plugin, text),
+        }
+    }
+
+    /// Log a line at `level` ("error", "warn", "info" or "debug"; "info" otherwise)
+    pub fn log(&self, plugin: &str, level: Option<&str>, text: &str) {
+        let level = match level {
+            Some("error") => log::Level::Error,
+            Some("warn") => log::Level::Warn,
+            Some("debug") => log::Level::Debug,
+            _ => log::Level::Info,
+        };
+        log::log!(level, "[Plugin {}] {}", plugin, text);
+    }
+}
+
+/// Key-value state of a plugin, written to a JSON file on every change
+pub(crate) struct KvStore {
+    path: PathBuf,
+    values: BTreeMap<String, serde_json::Value>,
+}
+
+impl KvStore {
+    /// Values stored in `path`; empty if the file does not exist or is not valid
+    pub(crate) fn load(path: PathBuf) -> Self {
+        let values = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Self { path, values }
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&serde_json::Value> {
+        self.values.get(key)
+    }
+
+    /// Set a value and save the file
+    pub(crate) fn set(&mut self, key: &str, value: serde_json::Value) {
+        self.values.insert(key.to_string(), value);
+        let written = serde_json::to_string_pretty(&self.values)
+            .map_err(|e| e.to_string())
+            .and_then(|json| fs::write(&self.path, json).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            log::warn!("Cannot save plugin values to {:?}: {}", self.path, e);
+        }
+    }
+}
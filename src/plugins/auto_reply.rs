@@ -1,11 +1,12 @@
+use crate::client::danmaku::{
+    DanmakuQueue, DanmakuSendError, DanmakuSender, ReplyTarget, SendOptions, SentDanmaku,
+};
 use crate::client::models::BiliMessage;
-use crate::client::scheduler::{EventContext, EventHandler};
-use log::{debug, error, info, warn};
-use reqwest::header::{HeaderMap, HeaderValue};
-use serde::Serialize;
-use std::sync::{Arc, Mutex};
+use crate::client::scheduler::{AsyncEventHandler, EventContext};
+use async_trait::async_trait;
+use log::{debug, error};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard};
 use std::time::{Duration, Instant};
-use tokio::runtime::Runtime;
 
 /// Configuration for keyword-response triggers
 #[derive(Debug, Clone)]
@@ -14,6 +15,8 @@ pub struct TriggerConfig {
     pub keywords: Vec<String>,
     /// Response message to send
     pub response: String,
+    /// Send the response as a reply (@) to the user who triggered it
+    pub reply_to_sender: bool,
 }
 
 /// Configuration for the auto reply plugin
@@ -36,172 +39,107 @@ impl Default for AutoReplyConfig {
                 TriggerConfig {
                     keywords: vec!["你好".to_string(), "hello".to_string()],
                     response: "欢迎来到直播间！".to_string(),
+                    reply_to_sender: false,
                 },
                 TriggerConfig {
                     keywords: vec!["谢谢".to_string(), "thanks".to_string()],
                     response: "不客气～".to_string(),
+                    reply_to_sender: false,
                 },
             ],
         }
     }
 }
 
-/// Parameters for sending a danmaku message to Bilibili API
-#[derive(Serialize, Debug)]
-struct SendDanmakuRequest {
-    csrf: String,
-    roomid: u64,
-    msg: String,
-    rnd: u64,
-    fontsize: u32,
-    color: u32,
-    mode: u32,
-    bubble: u32,
-    room_type: u32,
-    jumpfrom: u32,
-    reply_mid: u32,
-    reply_attr: u32,
-    reply_uname: String,
-    replay_dmid: String,
-    statistics: String,
-    csrf_token: String,
-}
-
-/// Extract CSRF token from cookies string
-pub fn extract_csrf_token(cookies: &str) -> Option<String> {
-    for cookie in cookies.split(';') {
-        let cookie = cookie.trim();
-        if cookie.starts_with("bili_jct=") {
-            return Some(cookie[9..].to_string());
-        }
-    }
-    None
-}
+pub use crate::client::danmaku::extract_csrf_token;
 
 /// Send a danmaku message to the Bilibili live room
 ///
 /// # Arguments
 /// * `message` - The text message to send
-/// * `context` - Event context containing cookies and room_id
+/// * `context` - Event context containing cookies and room_id; the sending account's
+///   cookies (`send_cookies`) are used when set
 ///
 /// # Returns
-/// Returns Ok(()) on success, or an error if the request fails
+/// Returns the accepted danmaku, or a [`DanmakuSendError`] when the request fails or
+/// the API rejects the message (rate limit, filter, muted, ...)
 pub async fn send_danmaku_message(
     message: &str,
     context: &EventContext,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let cookies = match &context.cookies {
-        Some(cookies) => cookies,
-        None => {
-            return Err("No cookies available for sending danmaku".into());
-        }
-    };
+) -> Result<SentDanmaku, DanmakuSendError> {
+    DanmakuSender::shared().send(message, context).await
+}
 
-    let csrf_token = match extract_csrf_token(cookies) {
-        Some(token) => token,
-        None => {
-            return Err("Could not extract CSRF token from cookies".into());
-        }
-    };
-
-    // Current timestamp
-    let rnd = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
-
-    let request = SendDanmakuRequest {
-        csrf: csrf_token.clone(),
-        roomid: context.room_id,
-        msg: message.to_string(),
-        rnd,
-        fontsize: 25,
-        color: 16777215, // White color
-        mode: 1,         // Scroll mode
-        bubble: 0,
-        room_type: 0,
-        jumpfrom: 0,
-        reply_mid: 0,
-        reply_attr: 0,
-        reply_uname: String::new(),
-        replay_dmid: String::new(),
-        statistics: r#"{"appId":100,"platform":5}"#.to_string(),
-        csrf_token,
-    };
-
-    // Set up headers
-    let mut headers = HeaderMap::new();
-    headers.insert("Cookie", HeaderValue::from_str(cookies)?);
-    headers.insert(
-        "User-Agent",
-        HeaderValue::from_static(
-            "Mozilla/5.0 (X11; Linux x86_64; rv:138.0) Gecko/20100101 Firefox/138.0",
-        ),
-    );
-    headers.insert(
-        "Referer",
-        HeaderValue::from_str(&format!("https://live.bilibili.com/{}", context.room_id))?,
-    );
-
-    debug!("Sending danmaku: {}", message);
-
-    let http_client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(10))
-        .build()?;
-
-    let response = http_client
-        .post("https://api.live.bilibili.com/msg/send")
-        .headers(headers)
-        .form(&request)
-        .send()
-        .await?;
-
-    if response.status().is_success() {
-        info!("Successfully sent danmaku: {}", message);
-        Ok(())
-    } else {
-        let status = response.status();
-        let body = response.text().await.unwrap_or_default();
-        warn!("Failed to send danmaku, status: {}", status);
-        debug!("Response body: {}", body);
-        Err(format!("Failed to send danmaku: {} - {}", status, body).into())
-    }
+/// Send a danmaku message with custom color, mode, emoticon or reply target
+pub async fn send_danmaku_message_with_options(
+    message: &str,
+    context: &EventContext,
+    options: &SendOptions,
+) -> Result<SentDanmaku, DanmakuSendError> {
+    DanmakuSender::shared()
+        .send_with_options(message, context, options)
+        .await
 }
 
 /// Auto reply handler that monitors danmaku for keywords and sends responses
+///
+/// Runs on the scheduler's runtime as an [`AsyncEventHandler`]. Its configuration can be
+/// replaced while it runs with [`AutoReplyHandler::set_config`].
+#[derive(Clone)]
 pub struct AutoReplyHandler {
-    config: AutoReplyConfig,
+    config: Arc<RwLock<AutoReplyConfig>>,
     last_reply: Arc<Mutex<Option<Instant>>>,
-    http_client: reqwest::Client,
-    runtime: Arc<Runtime>,
+    sender: DanmakuSender,
+    queue: Option<DanmakuQueue>,
 }
 
 impl AutoReplyHandler {
     /// Create a new auto reply handler with the given configuration
     pub fn new(config: AutoReplyConfig) -> Self {
-        let http_client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(10))
-            .build()
-            .expect("Failed to create HTTP client");
-
-        let runtime = Arc::new(Runtime::new().expect("Failed to create tokio runtime"));
-
         Self {
-            config,
+            config: Arc::new(RwLock::new(config)),
             last_reply: Arc::new(Mutex::new(None)),
-            http_client,
-            runtime,
+            sender: DanmakuSender::shared().clone(),
+            queue: None,
+        }
+    }
+
+    /// Send replies through an outbound queue shared with other senders
+    pub fn with_queue(mut self, queue: DanmakuQueue) -> Self {
+        self.queue = Some(queue);
+        self
+    }
+
+    /// Replace the triggers, cooldown and enabled flag, e.g. after the config file
+    /// changed; the time of the last reply is kept
+    pub fn set_config(&self, config: AutoReplyConfig) {
+        match self.config.write() {
+            Ok(mut current) => *current = config,
+            Err(poisoned) => *poisoned.into_inner() = config,
+        }
+    }
+
+    /// The triggers, cooldown and enabled flag in use
+    pub fn config(&self) -> AutoReplyConfig {
+        self.current_config().clone()
+    }
+
+    /// The current configuration; a poisoned lock still holds a complete config
+    fn current_config(&self) -> RwLockReadGuard<'_, AutoReplyConfig> {
+        match self.config.read() {
+            Ok(config) => config,
+            Err(poisoned) => poisoned.into_inner(),
         }
     }
 
     /// Check if any keyword matches the message text
-    fn find_matching_trigger(&self, text: &str) -> Option<&TriggerConfig> {
+    fn find_matching_trigger(&self, text: &str) -> Option<TriggerConfig> {
         let text_lower = text.to_lowercase();
 
-        for trigger in &self.config.triggers {
+        for trigger in &self.current_config().triggers {
             for keyword in &trigger.keywords {
                 if text_lower.contains(&keyword.to_lowercase()) {
-                    return Some(trigger);
+                    return Some(trigger.clone());
                 }
             }
         }
@@ -219,12 +157,15 @@ impl AutoReplyHandler {
 
     /// Check if enough time has passed since the last reply
     fn check_cooldown(&self) -> bool {
-        let last_reply = self.last_reply.lock().unwrap();
+        let last_reply = match self.last_reply.lock() {
+            Ok(last_reply) => *last_reply,
+            Err(poisoned) => *poisoned.into_inner(),
+        };
 
-        match *last_reply {
+        match last_reply {
             Some(last_time) => {
                 let elapsed = last_time.elapsed();
-                elapsed >= Duration::from_secs(self.config.cooldown_seconds)
+                elapsed >= Duration::from_secs(self.current_config().cooldown_seconds)
             }
             None => true,
         }
@@ -232,13 +173,10 @@ impl AutoReplyHandler {
 
     /// Update the last reply timestamp
     fn update_last_reply(&self) {
-        let mut last_reply = self.last_reply.lock().unwrap();
-        *last_reply = Some(Instant::now());
-    }
-
-    /// Extract CSRF token from cookies
-    fn extract_csrf_token(&self, cookies: &str) -> Option<String> {
-        extract_csrf_token(cookies)
+        match self.last_reply.lock() {
+            Ok(mut last_reply) => *last_reply = Some(Instant::now()),
+            Err(poisoned) => *poisoned.into_inner() = Some(Instant::now()),
+        }
     }
 
     /// Send a danmaku message to the Bilibili API
@@ -246,93 +184,26 @@ impl AutoReplyHandler {
         &self,
         message: &str,
         context: &EventContext,
-    ) -> Result<(), reqwest::Error> {
-        let cookies = match &context.cookies {
-            Some(cookies) => cookies,
-            None => {
-                warn!("No cookies available for sending danmaku");
-                return Ok(());
-            }
-        };
-
-        let csrf_token = match self.extract_csrf_token(cookies) {
-            Some(token) => token,
-            None => {
-                error!("Could not extract CSRF token from cookies");
-                return Ok(());
-            }
-        };
-
-        // Current timestamp
-        let rnd = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-
-        let request = SendDanmakuRequest {
-            csrf: csrf_token.clone(),
-            roomid: context.room_id,
-            msg: message.to_string(),
-            rnd,
-            fontsize: 25,
-            color: 16777215, // White color
-            mode: 1,         // Scroll mode
-            bubble: 0,
-            room_type: 0,
-            jumpfrom: 0,
-            reply_mid: 0,
-            reply_attr: 0,
-            reply_uname: String::new(),
-            replay_dmid: String::new(),
-            statistics: r#"{"appId":100,"platform":5}"#.to_string(),
-            csrf_token,
-        };
-
-        // Set up headers
-        let mut headers = HeaderMap::new();
-        headers.insert("Cookie", HeaderValue::from_str(cookies).unwrap());
-        headers.insert(
-            "User-Agent",
-            HeaderValue::from_static(
-                "Mozilla/5.0 (X11; Linux x86_64; rv:138.0) Gecko/20100101 Firefox/138.0",
-            ),
-        );
-        headers.insert(
-            "Referer",
-            HeaderValue::from_str(&format!("https://live.bilibili.com/{}", context.room_id))
-                .unwrap(),
-        );
-
-        debug!("Sending danmaku: {}", message);
-
-        let response = self
-            .http_client
-            .post("https://api.live.bilibili.com/msg/send")
-            .headers(headers)
-            .form(&request)
-            .send()
-            .await?;
-
-        if response.status().is_success() {
-            info!("Successfully sent danmaku: {}", message);
-        } else {
-            warn!("Failed to send danmaku, status: {}", response.status());
-            let body = response.text().await.unwrap_or_default();
-            debug!("Response body: {}", body);
-        }
-
-        Ok(())
+        options: &SendOptions,
+    ) -> Result<SentDanmaku, DanmakuSendError> {
+        self.sender
+            .send_with_options(message, context, options)
+            .await
     }
 }
 
-impl EventHandler for AutoReplyHandler {
-    fn handle(&self, msg: &BiliMessage, context: &EventContext) {
-        if !self.config.enabled {
+#[async_trait]
+impl AsyncEventHandler for AutoReplyHandler {
+    async fn handle(&self, msg: &BiliMessage, context: &EventContext) {
+        if !self.current_config().enabled {
             return;
         }
 
         // Only process danmaku messages
-        if let BiliMessage::Danmu { user: _, text } = msg {
+        if let BiliMessage::Danmu {
+            user, uid, text, ..
+        } = msg
+        {
             // Check for keyword match
             if let Some(trigger) = self.find_matching_trigger(text) {
                 // Check cooldown
@@ -342,7 +213,7 @@ impl EventHandler for AutoReplyHandler {
                 }
 
                 // Select response
-                if let Some(response) = self.select_response(trigger) {
+                if let Some(response) = self.select_response(&trigger) {
                     debug!(
                         "Auto reply triggered by '{}', responding with '{}'",
                         text, response
@@ -351,15 +222,26 @@ impl EventHandler for AutoReplyHandler {
                     // Update cooldown
                     self.update_last_reply();
 
-                    // Send the reply asynchronously
-                    let runtime = Arc::clone(&self.runtime);
-                    let _http_client = self.http_client.clone();
+                    let mut options = SendOptions::new();
+                    if trigger.reply_to_sender && *uid != 0 {
+                        options = options.with_reply_to(ReplyTarget::new(*uid, user.clone()));
+                    }
+
+                    if let Some(queue) = &self.queue {
+                        queue.enqueue_with_options(&response, context, options);
+                        return;
+                    }
+
+                    // Send the reply in the background so later stages are not held up
                     let response_msg = response.clone();
                     let context_clone = context.clone();
                     let handler = self.clone();
 
-                    runtime.spawn(async move {
-                        if let Err(e) = handler.send_danmaku(&response_msg, &context_clone).await {
+                    tokio::spawn(async move {
+                        if let Err(e) = handler
+                            .send_danmaku(&response_msg, &context_clone, &options)
+                            .await
+                        {
                             error!("Failed to send auto reply: {}", e);
                         }
                     });
@@ -369,23 +251,11 @@ impl EventHandler for AutoReplyHandler {
     }
 }
 
-// Implement Clone for AutoReplyHandler
-impl Clone for AutoReplyHandler {
-    fn clone(&self) -> Self {
-        Self {
-            config: self.config.clone(),
-            last_reply: Arc::clone(&self.last_reply),
-            http_client: self.http_client.clone(),
-            runtime: Arc::clone(&self.runtime),
-        }
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::client::models::BiliMessage;
-    use crate::client::scheduler::{EventContext, EventHandler};
+    use crate::client::scheduler::{AsyncEventHandler, EventContext};
 
     #[test]
     fn test_keyword_matching() {
@@ -405,8 +275,8 @@ mod tests {
         let config = AutoReplyConfig::default();
         let handler = AutoReplyHandler::new(config);
 
-        let trigger = &handler.config.triggers[0];
-        let response = handler.select_response(trigger);
+        let trigger = handler.config.read().unwrap().triggers[0].clone();
+        let response = handler.select_response(&trigger);
         assert!(response.is_some());
         assert_eq!(response.unwrap(), trigger.response);
     }
@@ -437,42 +307,93 @@ mod tests {
     }
 
     #[test]
-    fn test_csrf_extraction() {
-        let config = AutoReplyConfig::default();
-        let handler = AutoReplyHandler::new(config);
+    fn test_set_config_replaces_triggers_and_cooldown() {
+        let handler = AutoReplyHandler::new(AutoReplyConfig::default());
+        handler.update_last_reply();
+        assert!(!handler.check_cooldown());
 
+        handler.set_config(AutoReplyConfig {
+            enabled: true,
+            cooldown_seconds: 0,
+            triggers: vec![TriggerConfig {
+                keywords: vec!["晚上好".to_string()],
+                response: "晚上好！".to_string(),
+                reply_to_sender: false,
+            }],
+        });
+        assert!(handler.check_cooldown());
+        assert!(handler.find_matching_trigger("hello").is_none());
+        assert_eq!(
+            handler
+                .find_matching_trigger("大家晚上好")
+                .unwrap()
+                .response,
+            "晚上好！"
+        );
+    }
+
+    #[test]
+    fn test_csrf_extraction() {
         let cookies = "SESSDATA=abc123; bili_jct=csrf_token_here; other=value";
-        let csrf = handler.extract_csrf_token(cookies);
+        let csrf = extract_csrf_token(cookies);
         assert_eq!(csrf, Some("csrf_token_here".to_string()));
 
         let cookies_no_csrf = "SESSDATA=abc123; other=value";
-        let csrf = handler.extract_csrf_token(cookies_no_csrf);
+        let csrf = extract_csrf_token(cookies_no_csrf);
         assert_eq!(csrf, None);
     }
 
-    #[test]
-    fn test_event_handler() {
+    #[tokio::test]
+    async fn test_event_handler() {
         let config = AutoReplyConfig {
             enabled: true,
             cooldown_seconds: 0, // No cooldown for testing
             triggers: vec![TriggerConfig {
                 keywords: vec!["test".to_string()],
                 response: "test response".to_string(),
+                reply_to_sender: false,
             }],
         };
         let handler = AutoReplyHandler::new(config);
 
         let context = EventContext {
             cookies: Some("bili_jct=test_csrf; SESSDATA=test".to_string()),
+            send_cookies: None,
             room_id: 12345,
         };
 
         let msg = BiliMessage::Danmu {
             user: "test_user".to_string(),
+            uid: 0,
             text: "this is a test message".to_string(),
+            segments: Vec::new(),
+            medal: None,
         };
 
         // This should trigger the auto reply (but won't actually send due to test environment)
-        handler.handle(&msg, &context);
+        handler.handle(&msg, &context).await;
+        assert!(handler.last_reply.lock().unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_historical_danmaku_ignored() {
+        let config = AutoReplyConfig {
+            enabled: true,
+            cooldown_seconds: 60,
+            ..Default::default()
+        };
+        let handler = AutoReplyHandler::new(config);
+        let msg = BiliMessage::Danmu {
+            user: "test_user".to_string(),
+            uid: 0,
+            text: "hello".to_string(),
+            segments: Vec::new(),
+            medal: None,
+        }
+        .historical();
+
+        handler.handle(&msg, &EventContext::new(None, 12345)).await;
+        // No reply was attempted, so the cooldown has not started
+        assert!(handler.check_cooldown());
     }
 }
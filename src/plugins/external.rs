@@ -0,0 +1,502 @@
+//! Plugins running as external processes (Python, Node, ...) that talk JSON lines over stdio
+//!
+//! Each event is written to the plugin's stdin as one JSON object per line:
+//!
+//! ```text
+//! {"type":"start","context":{"room_id":123,"logged_in":true}}
+//! {"type":"message","message":{"Danmu":{"user":"...","text":"...",...}},"context":{...}}
+//! {"type":"connected","context":{...}}
+//! {"type":"disconnected","context":{...}}
+//! {"type":"shutdown","context":{...}}
+//! ```
+//!
+//! The plugin answers with commands on stdout, one JSON object per line:
+//!
+//! ```text
+//! {"action":"send","text":"欢迎！"}
+//! {"action":"speak","text":"感谢礼物"}
+//! {"action":"display","text":"seen 100 danmaku"}
+//! {"action":"log","level":"warn","text":"something is off"}
+//! ```
+//!
+//! Lines on stderr are logged. Cookies are never passed to the plugin.
+
+use crate::client::filter::MessageFilter;
+use crate::client::models::BiliMessage;
+use crate::client::scheduler::{EventContext, EventHandler};
+use crate::plugins::actions::PluginActions;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// First wait before restarting a crashed plugin; doubled after each quick crash
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+/// Longest wait before restarting a crashed plugin
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+/// A plugin that ran this long before exiting is restarted without delay
+const STABLE_RUN: Duration = Duration::from_secs(60);
+/// How long a plugin gets to exit after its stdin is closed on shutdown
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(3);
+
+/// An external plugin in the config file (`[[plugins]]`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExternalPluginConfig {
+    /// Name shown in logs, the TUI and the handler stats
+    pub name: String,
+    /// Program to run, e.g. "python3"
+    pub command: String,
+    /// Arguments, e.g. ["plugins/greeter.py"]
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Messages the plugin gets; all messages when not set
+    #[serde(default)]
+    pub filter: Option<MessageFilter>,
+    /// Whether the plugin is restarted when it exits or crashes
+    #[serde(default = "default_restart")]
+    pub restart: bool,
+}
+
+fn default_restart() -> bool {
+    true
+}
+
+/// Event context passed to plugins, without cookies
+#[derive(Debug, Serialize)]
+pub(crate) struct PluginContext {
+    room_id: u64,
+    logged_in: bool,
+}
+
+impl From<&EventContext> for PluginContext {
+    fn from(context: &EventContext) -> Self {
+        Self {
+            room_id: context.room_id,
+            logged_in: context.cookies.is_some(),
+        }
+    }
+}
+
+/// An event for a plugin: a line on an external plugin's stdin, or what a WASM plugin's
+/// `handle` gets
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum PluginEvent<'a> {
+    Start {
+        context: PluginContext,
+    },
+    Message {
+        message: &'a BiliMessage,
+        context: PluginContext,
+    },
+    Connected {
+        context: PluginContext,
+    },
+    Disconnected {
+        context: PluginContext,
+    },
+    Shutdown {
+        context: PluginContext,
+    },
+}
+
+/// A line read from the plugin's stdout
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum PluginCommand {
+    /// Send a danmaku as the sending account
+    Send { text: String },
+    /// Speak text with TTS
+    Speak { text: String },
+    /// Show a line in the TUI
+    Display { text: String },
+    /// Write a line to the log
+    Log {
+        #[serde(default)]
+        level: Option<String>,
+        text: String,
+    },
+}
+
+impl PluginCommand {
+    /// Carry out the command
+    pub fn run(&self, plugin: &str, actions: &PluginActions, context: Option<&EventContext>) {
+        match self {
+            PluginCommand::Send { text } => match context {
+                Some(context) => actions.send(plugin, text, context),
+                None => log::warn!("Plugin {} cannot send danmaku before any event", plugin),
+            },
+            PluginCommand::Speak { text } => actions.speak(plugin, text),
+            PluginCommand::Display { text } => actions.display(plugin, text),
+            PluginCommand::Log { level, text } => actions.log(plugin, level.as_deref(), text),
+        }
+    }
+}
+
+/// The running plugin process
+struct Running {
+    child: Child,
+    stdin: ChildStdin,
+    started: Instant,
+}
+
+/// When the plugin may be started again
+#[derive(Default)]
+struct RestartState {
+    /// Crashes in a row that happened shortly after starting
+    quick_crashes: u32,
+    /// When the last process exited
+    exited_at: Option<Instant>,
+    /// Whether the plugin was started before
+    started_once: bool,
+}
+
+/// Runs an external program as a plugin, see the [module docs](self) for the protocol
+///
+/// The program is started on the first event and restarted when it exits, with a
+/// growing delay while it keeps crashing. Events arriving while it is down are dropped.
+pub struct ExternalPluginHandler {
+    config: ExternalPluginConfig,
+    actions: Arc<PluginActions>,
+    /// Context of the latest event, used for commands such as sending danmaku
+    context: Arc<Mutex<Option<EventContext>>>,
+    process: Mutex<Option<Running>>,
+    restart: Mutex<RestartState>,
+    /// Set on shutdown so the plugin is not restarted
+    stopping: AtomicBool,
+}
+
+impl ExternalPluginHandler {
+    /// Create a handler for a configured plugin; the process starts with the first event
+    pub fn new(config: ExternalPluginConfig, actions: Arc<PluginActions>) -> Self {
+        Self {
+            config,
+            actions,
+            context: Arc::new(Mutex::new(None)),
+            process: Mutex::new(None),
+            restart: Mutex::new(RestartState::default()),
+            stopping: AtomicBool::new(false),
+        }
+    }
+
+    /// Write an event to the plugin, starting or restarting it first if needed
+    fn send_event(&self, event: &PluginEvent, context: &EventContext) {
+        if let Ok(mut latest) = self.context.lock() {
+            *latest = Some(context.clone());
+        }
+        let line = match serde_json::to_string(event) {
+            Ok(line) => line,
+            Err(e) => {
+                log::error!("Plugin {}: cannot encode event: {}", self.config.name, e);
+                return;
+            }
+        };
+        let Ok(mut process) = self.process.lock() else {
+            return;
+        };
+        self.check_exited(&mut process);
+        if process.is_none() {
+            *process = self.start();
+        }
+        let Some(running) = process.as_mut() else {
+            return;
+        };
+        let written = writeln!(running.stdin, "{}", line).and_then(|_| running.stdin.flush());
+        if let Err(e) = written {
+            log::warn!("Plugin {}: write failed: {}", self.config.name, e);
+            let mut running = process.take().expect("checked above");
+            let _ = running.child.kill();
+            let _ = running.child.wait();
+            self.record_exit(running.started);
+        }
+    }
+
+    /// Forget the process if it has exited
+    fn check_exited(&self, process: &mut Option<Running>) {
+        let Some(running) = process.as_mut() else {
+            return;
+        };
+        match running.child.try_wait() {
+            Ok(None) => {}
+            Ok(Some(status)) => {
+                log::warn!("Plugin {} exited with {}", self.config.name, status);
+                let started = running.started;
+                *process = None;
+                self.record_exit(started);
+            }
+            Err(e) => log::warn!("Plugin {}: cannot check process: {}", self.config.name, e),
+        }
+    }
+
+    fn record_exit(&self, started: Instant) {
+        if let Ok(mut restart) = self.restart.lock() {
+            if started.elapsed() < STABLE_RUN {
+                restart.quick_crashes += 1;
+            } else {
+                restart.quick_crashes = 0;
+            }
+            restart.exited_at = Some(Instant::now());
+        }
+    }
+
+    /// Start the process unless it may not be (re)started yet
+    fn start(&self) -> Option<Running> {
+        if self.stopping.load(Ordering::SeqCst) {
+            return None;
+        }
+        let mut restart = self.restart.lock().ok()?;
+        if restart.started_once {
+            if !self.config.restart {
+                return None;
+            }
+            let delay = restart_delay(restart.quick_crashes);
+            if restart
+                .exited_at
+                .is_some_and(|exited| exited.elapsed() < delay)
+            {
+                return None;
+            }
+            log::info!("Restarting plugin {}", self.config.name);
+        }
+        restart.started_once = true;
+
+        let mut child = match Command::new(&self.config.command)
+            .args(&self.config.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) => {
+                log::error!(
+                    "Plugin {}: cannot run {}: {}",
+                    self.config.name,
+                    self.config.command,
+                    e
+                );
+                restart.quick_crashes += 1;
+                restart.exited_at = Some(Instant::now());
+                return None;
+            }
+        };
+        let stdin = child.stdin.take()?;
+        if let Some(stdout) = child.stdout.take() {
+            self.read_commands(stdout);
+        }
+        if let Some(stderr) = child.stderr.take() {
+            let name = self.config.name.clone();
+            thread::spawn(move || {
+                for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                    log::warn!("[Plugin {}] {}", name, line);
+                }
+            });
+        }
+        Some(Running {
+            child,
+            stdin,
+            started: Instant::now(),
+        })
+    }
+
+    /// Carry out the commands the plugin writes to stdout until it closes it
+    fn read_commands(&self, stdout: impl Read + Send + 'static) {
+        let name = self.config.name.clone();
+        let actions = Arc::clone(&self.actions);
+        let context = Arc::clone(&self.context);
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<PluginCommand>(&line) {
+                    Ok(command) => {
+                        let context = context.lock().ok().and_then(|c| c.clone());
+                        command.run(&name, &actions, context.as_ref());
+                    }
+                    Err(e) => log::warn!("Plugin {}: bad command {:?}: {}", name, line, e),
+                }
+            }
+        });
+    }
+}
+
+/// Wait before the next start after `quick_crashes` quick crashes in a row
+fn restart_delay(quick_crashes: u32) -> Duration {
+    match quick_crashes {
+        0 => Duration::ZERO,
+        n => RESTART_DELAY
+            .saturating_mul(1 << (n - 1).min(16))
+            .min(MAX_RESTART_DELAY),
+    }
+}
+
+impl EventHandler for ExternalPluginHandler {
+    fn handle(&self, msg: &BiliMessage, context: &EventContext) {
+        let event = PluginEvent::Message {
+            message: msg,
+            context: context.into(),
+        };
+        self.send_event(&event, context);
+    }
+
+    fn on_start(&self, context: &EventContext) {
+        let event = PluginEvent::Start {
+            context: context.into(),
+        };
+        self.send_event(&event, context);
+    }
+
+    fn on_connected(&self, context: &EventContext) {
+        let event = PluginEvent::Connected {
+            context: context.into(),
+        };
+        self.send_event(&event, context);
+    }
+
+    fn on_disconnected(&self, context: &EventContext) {
+        let event = PluginEvent::Disconnected {
+            context: context.into(),
+        };
+        self.send_event(&event, context);
+    }
+
+    /// Tell the plugin, close its stdin and give it a moment to exit before killing it
+    fn on_shutdown(&self, context: &EventContext) {
+        // A plugin that is down is not started just to be told
+        self.stopping.store(true, Ordering::SeqCst);
+        let event = PluginEvent::Shutdown {
+            context: context.into(),
+        };
+        self.send_event(&event, context);
+
+        let Some(Running {
+            mut child, stdin, ..
+        }) = self.process.lock().ok().and_then(|mut p| p.take())
+        else {
+            return;
+        };
+        drop(stdin);
+        let deadline = Instant::now() + SHUTDOWN_GRACE;
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        log::warn!("Plugin {} did not exit, killing it", self.config.name);
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    fn danmu(text: &str) -> BiliMessage {
+        BiliMessage::Danmu {
+            user: "观众".to_string(),
+            uid: 1,
+            text: text.to_string(),
+            segments: Vec::new(),
+            medal: None,
+        }
+    }
+
+    #[test]
+    fn test_plugin_protocol() {
+        let context = EventContext::new(Some("SESSDATA=secret".to_string()), 42);
+        let msg = danmu("你好");
+        let event = PluginEvent::Message {
+            message: &msg,
+            context: (&context).into(),
+        };
+        let line = serde_json::to_string(&event).unwrap();
+        assert!(!line.contains("secret"));
+        let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(json["type"], "message");
+        assert_eq!(json["message"]["Danmu"]["text"], "你好");
+        assert_eq!(json["context"]["room_id"], 42);
+        assert_eq!(json["context"]["logged_in"], true);
+
+        let command: PluginCommand =
+            serde_json::from_str(r#"{"action":"log","level":"warn","text":"hi"}"#).unwrap();
+        assert_eq!(
+            command,
+            PluginCommand::Log {
+                level: Some("warn".to_string()),
+                text: "hi".to_string()
+            }
+        );
+        let command: PluginCommand =
+            serde_json::from_str(r#"{"action":"send","text":"欢迎"}"#).unwrap();
+        assert_eq!(
+            command,
+            PluginCommand::Send {
+                text: "欢迎".to_string()
+            }
+        );
+        assert!(serde_json::from_str::<PluginCommand>(r#"{"action":"explode"}"#).is_err());
+
+        assert_eq!(restart_delay(0), Duration::ZERO);
+        assert_eq!(restart_delay(1), RESTART_DELAY);
+        assert_eq!(restart_delay(3), RESTART_DELAY * 4);
+        assert_eq!(restart_delay(30), MAX_RESTART_DELAY);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_external_plugin_round_trip_and_restart() {
+        let buffer = Arc::new(Mutex::new(VecDeque::new()));
+        let actions = Arc::new(PluginActions::new().with_message_buffer(Arc::clone(&buffer)));
+        // Answers one event, then exits
+        let config = ExternalPluginConfig {
+            name: "echo".to_string(),
+            command: "sh".to_string(),
+            args: vec![
+                "-c".to_string(),
+                r#"read line; echo '{"action":"display","text":"got it"}'"#.to_string(),
+            ],
+            filter: None,
+            restart: true,
+        };
+        let handler = ExternalPluginHandler::new(config, actions);
+        let context = EventContext::new(None, 1);
+
+        let wait_for_lines = |count: usize| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while buffer.lock().unwrap().len() < count && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(20));
+            }
+            buffer.lock().unwrap().len()
+        };
+
+        handler.handle(&danmu("1"), &context);
+        assert_eq!(wait_for_lines(1), 1);
+        assert_eq!(buffer.lock().unwrap()[0], "[Plugin echo] got it");
+
+        // The first restart after a quick exit waits RESTART_DELAY
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while handler.process.lock().unwrap().is_some() && Instant::now() < deadline {
+            handler.handle(&danmu("while exiting"), &context);
+            thread::sleep(Duration::from_millis(20));
+        }
+        thread::sleep(RESTART_DELAY);
+        handler.handle(&danmu("2"), &context);
+        assert_eq!(wait_for_lines(2), 2);
+
+        handler.on_shutdown(&context);
+        assert!(handler.process.lock().unwrap().is_none());
+        handler.handle(&danmu("after shutdown"), &context);
+        assert!(handler.process.lock().unwrap().is_none());
+    }
+}
@@ -1,13 +1,18 @@
+pub mod actions;
 pub mod auto_reply;
+pub mod external;
+pub mod script;
 pub mod terminal_display;
 pub mod tts;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
-use crate::client::scheduler::EventHandler;
+use crate::client::scheduler::{AsyncEventHandler, EventHandler};
 use std::collections::VecDeque;
 use std::sync::{Arc, Mutex};
 
 // Re-export danmaku sending utility for easy access
-pub use auto_reply::send_danmaku_message;
+pub use auto_reply::{send_danmaku_message, send_danmaku_message_with_options};
 
 /// Helper to create the handler as Arc<dyn EventHandler>
 pub fn terminal_display_handler(
@@ -18,13 +23,13 @@ pub fn terminal_display_handler(
     ))
 }
 
-/// Helper to create the TTS handler as Arc<dyn EventHandler>
+/// Helper to create the TTS handler as Arc<dyn AsyncEventHandler>
 /// Uses default Chinese voice settings with REST API
-pub fn tts_handler_default(server_url: String) -> Arc<dyn EventHandler> {
+pub fn tts_handler_default(server_url: String) -> Arc<dyn AsyncEventHandler> {
     Arc::new(tts::TtsHandler::new_rest_api_default(server_url))
 }
 
-/// Helper to create the TTS handler with REST API and custom configuration as Arc<dyn EventHandler>
+/// Helper to create the TTS handler with REST API and custom configuration as Arc<dyn AsyncEventHandler>
 pub fn tts_handler(
     server_url: String,
     voice: Option<String>,
@@ -32,7 +37,7 @@ pub fn tts_handler(
     quality: Option<String>,
     format: Option<String>,
     sample_rate: Option<u32>,
-) -> Arc<dyn EventHandler> {
+) -> Arc<dyn AsyncEventHandler> {
     Arc::new(tts::TtsHandler::new_rest_api(
         server_url,
         voice,
@@ -43,14 +48,17 @@ pub fn tts_handler(
     ))
 }
 
-/// Helper to create the command-based TTS handler as Arc<dyn EventHandler>
+/// Helper to create the command-based TTS handler as Arc<dyn AsyncEventHandler>
 /// For local TTS commands like `say` on macOS or `espeak-ng` on Linux
-pub fn tts_handler_command(tts_command: String, tts_args: Vec<String>) -> Arc<dyn EventHandler> {
+pub fn tts_handler_command(
+    tts_command: String,
+    tts_args: Vec<String>,
+) -> Arc<dyn AsyncEventHandler> {
     Arc::new(tts::TtsHandler::new_command(tts_command, tts_args))
 }
 
-/// Helper to create the auto reply handler as Arc<dyn EventHandler>
-pub fn auto_reply_handler(config: auto_reply::AutoReplyConfig) -> Arc<dyn EventHandler> {
+/// Helper to create the auto reply handler as Arc<dyn AsyncEventHandler>
+pub fn auto_reply_handler(config: auto_reply::AutoReplyConfig) -> Arc<dyn AsyncEventHandler> {
     Arc::new(auto_reply::AutoReplyHandler::new(config))
 }
 
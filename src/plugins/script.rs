@@ -0,0 +1,611 @@
+//! Scripting plugin: small room automations written in [Rhai](https://rhai.rs)
+//!
+//! Each `*.rhai` file in the scripts directory is one handler. A script defines the
+//! functions it needs; all of them are optional:
+//!
+//! ```rhai
+//! fn on_message(msg) {
+//!     if msg.kind == "danmu" && msg.text.contains("你好") {
+//!         let count = kv_get("greeted") ?? 0;
+//!         kv_set("greeted", count + 1);
+//!         send(`你好，${msg.user}`);
+//!     }
+//! }
+//!
+//! fn remind() {
+//!     display("记得关注主播");
+//! }
+//!
+//! every(600, "remind");
+//! ```
+//!
+//! Top-level statements run when the script is loaded, so timers are set up there.
+//! `on_start`, `on_connected`, `on_disconnected` and `on_shutdown` are called for the
+//! handler's lifecycle hooks. Scripts can call:
+//!
+//! - `send(text)`, `speak(text)`, `display(text)`, `log(text)` / `log(level, text)`
+//! - `kv_get(key)` / `kv_set(key, value)`: values kept in `<script>.kv.json` next to the
+//!   script, so they survive restarts; `kv_get` returns `()` for missing keys
+//! - `every(seconds, "function")` / `after(seconds, "function")`: timers
+//!
+//! A script is reloaded when its file changes; timers are set up again and the stored
+//! values are kept.
+
+use crate::client::filter::{MessageKind, message_cmd, value_cny};
+use crate::client::models::BiliMessage;
+use crate::client::scheduler::{EventContext, EventHandler};
+use crate::plugins::actions::{KvStore, PluginActions};
+use rhai::{AST, CallFnOptions, Dynamic, Engine, FLOAT, FuncArgs, INT, Scope};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often timers and the script file are checked
+const TICK: Duration = Duration::from_millis(250);
+/// Limit on the operations of one script call, so a runaway loop cannot hang a handler
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// Load every `*.rhai` file in `dir`, in file name order
+///
+/// Scripts that fail to load are returned as errors so the caller can report them.
+pub fn load_scripts(
+    dir: &Path,
+    actions: &Arc<PluginActions>,
+) -> Result<Vec<Result<ScriptHandler, String>>, String> {
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("Cannot read scripts directory {:?}: {}", dir, e))?;
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "rhai"))
+        .collect();
+    paths.sort();
+    Ok(paths
+        .into_iter()
+        .map(|path| ScriptHandler::load(&path, Arc::clone(actions)))
+        .collect())
+}
+
+/// A timer set up by `every` or `after`
+struct Timer {
+    function: String,
+    due: Instant,
+    /// Interval for `every`; `after` timers run once
+    every: Option<Duration>,
+}
+
+/// The compiled script and the file modification time it was compiled from
+struct LoadedScript {
+    ast: AST,
+    modified: Option<SystemTime>,
+}
+
+/// A Rhai script run as an event handler, see the [module docs](self)
+pub struct ScriptHandler {
+    /// Shared with the thread running timers and hot reload
+    runtime: Arc<ScriptRuntime>,
+}
+
+impl ScriptHandler {
+    /// Compile a script and run its top-level statements
+    pub fn load(path: &Path, actions: Arc<PluginActions>) -> Result<Self, String> {
+        ScriptRuntime::load(path, actions).map(|runtime| Self {
+            runtime: Arc::new(runtime),
+        })
+    }
+}
+
+/// A loaded script with the state its functions use
+struct ScriptRuntime {
+    name: String,
+    path: PathBuf,
+    engine: Engine,
+    actions: Arc<PluginActions>,
+    script: Mutex<LoadedScript>,
+    timers: Arc<Mutex<Vec<Timer>>>,
+    /// Context of the latest event, used by `send`
+    context: Arc<Mutex<Option<EventContext>>>,
+    /// Whether the timer thread was started
+    ticking: AtomicBool,
+    stopping: AtomicBool,
+}
+
+impl ScriptRuntime {
+    fn load(path: &Path, actions: Arc<PluginActions>) -> Result<Self, String> {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.display().to_string());
+        let timers = Arc::new(Mutex::new(Vec::new()));
+        let context = Arc::new(Mutex::new(None));
+        let kv = Arc::new(Mutex::new(KvStore::load(path.with_extension("kv.json"))));
+        let engine = build_engine(&name, &actions, &timers, &context, &kv);
+
+        let modified = modified_time(path);
+        let ast = compile(&engine, path)?;
+        let runtime = Self {
+            name,
+            path: path.to_path_buf(),
+            engine,
+            actions,
+            script: Mutex::new(LoadedScript { ast, modified }),
+            timers,
+            context,
+            ticking: AtomicBool::new(false),
+            stopping: AtomicBool::new(false),
+        };
+        if let Ok(script) = runtime.script.lock() {
+            runtime.run_top_level(&script.ast)?;
+        }
+        Ok(runtime)
+    }
+
+    /// Run the statements outside functions, e.g. setting up timers
+    fn run_top_level(&self, ast: &AST) -> Result<(), String> {
+        self.engine
+            .run_ast(ast)
+            .map_err(|e| format!("Script {} failed: {}", self.name, e))
+    }
+
+    /// Call a script function if the script defines it
+    fn call(&self, function: &str, args: impl FuncArgs) {
+        let Ok(script) = self.script.lock() else {
+            return;
+        };
+        if !script.ast.iter_functions().any(|f| f.name == function) {
+            return;
+        }
+        let options = CallFnOptions::new().eval_ast(false);
+        let result = self.engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut Scope::new(),
+            &script.ast,
+            function,
+            args,
+        );
+        if let Err(e) = result {
+            log::warn!("Script {}: {} failed: {}", self.name, function, e);
+        }
+    }
+
+    /// Call a script hook with the event's context
+    fn hook(&self, function: &str, context: &EventContext) {
+        self.set_context(context);
+        self.call(function, ());
+    }
+
+    fn set_context(&self, context: &EventContext) {
+        if let Ok(mut latest) = self.context.lock() {
+            *latest = Some(context.clone());
+        }
+    }
+
+    /// Reload the script if its file changed; a script that no longer compiles keeps
+    /// running the old version
+    fn reload_if_changed(&self) {
+        let modified = modified_time(&self.path);
+        let Ok(mut script) = self.script.lock() else {
+            return;
+        };
+        if modified == script.modified {
+            return;
+        }
+        script.modified = modified;
+        match compile(&self.engine, &self.path) {
+            Ok(ast) => {
+                if let Ok(mut timers) = self.timers.lock() {
+                    timers.clear();
+                }
+                script.ast = ast;
+                match self.run_top_level(&script.ast) {
+                    Ok(()) => self.actions.display(&self.name, "reloaded"),
+                    Err(e) => self.actions.display(&self.name, &e),
+                }
+            }
+            Err(e) => self.actions.display(&self.name, &e),
+        }
+    }
+
+    /// Call the functions of timers that are due
+    fn run_timers(&self) {
+        let now = Instant::now();
+        let mut due = Vec::new();
+        if let Ok(mut timers) = self.timers.lock() {
+            timers.retain_mut(|timer| {
+                if timer.due > now {
+                    return true;
+                }
+                due.push(timer.function.clone());
+                match timer.every {
+                    Some(every) => {
+                        timer.due = now + every;
+                        true
+                    }
+                    None => false,
+                }
+            });
+        }
+        for function in due {
+            self.call(&function, ());
+        }
+    }
+
+    /// Start the thread running timers and hot reload
+    fn start_ticking(self: &Arc<Self>) {
+        if self.ticking.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        // The thread only holds a weak reference so it ends with the handler
+        let runtime = Arc::downgrade(self);
+        thread::spawn(move || {
+            loop {
+                thread::sleep(TICK);
+                let Some(runtime) = runtime.upgrade() else {
+                    break;
+                };
+                if runtime.stopping.load(Ordering::SeqCst) {
+                    break;
+                }
+                runtime.reload_if_changed();
+                runtime.run_timers();
+            }
+        });
+    }
+}
+
+impl EventHandler for ScriptHandler {
+    fn handle(&self, msg: &BiliMessage, context: &EventContext) {
+        self.runtime.set_context(context);
+        self.runtime.call("on_message", (msg.clone(),));
+    }
+
+    fn on_start(&self, context: &EventContext) {
+        self.runtime.start_ticking();
+        self.runtime.hook("on_start", context);
+    }
+
+    fn on_connected(&self, context: &EventContext) {
+        self.runtime.hook("on_connected", context);
+    }
+
+    fn on_disconnected(&self, context: &EventContext) {
+        self.runtime.hook("on_disconnected", context);
+    }
+
+    fn on_shutdown(&self, context: &EventContext) {
+        self.runtime.hook("on_shutdown", context);
+        self.runtime.stopping.store(true, Ordering::SeqCst);
+    }
+
+    fn name(&self) -> &str {
+        &self.runtime.name
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+fn compile(engine: &Engine, path: &Path) -> Result<AST, String> {
+    let source = fs::read_to_string(path).map_err(|e| format!("Cannot read {:?}: {}", path, e))?;
+    engine
+        .compile(source)
+        .map_err(|e| format!("Cannot compile {:?}: {}", path, e))
+}
+
+/// An engine with the message type and the functions scripts can call
+fn build_engine(
+    name: &str,
+    actions: &Arc<PluginActions>,
+    timers: &Arc<Mutex<Vec<Timer>>>,
+    context: &Arc<Mutex<Option<EventContext>>>,
+    kv: &Arc<Mutex<KvStore>>,
+) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    register_message(&mut engine);
+
+    let plugin = name.to_string();
+    engine.on_print(move |text| log::info!("[Plugin {}] {}", plugin, text));
+
+    let (plugin, actions_, context) = (name.to_string(), Arc::clone(actions), Arc::clone(context));
+    engine.register_fn("send", move |text: &str| {
+        let context = context.lock().ok().and_then(|context| context.clone());
+        match context {
+            Some(context) => actions_.send(&plugin, text, &context),
+            None => log::warn!("Plugin {} cannot send danmaku before any event", plugin),
+        }
+    });
+    let (plugin, actions_) = (name.to_string(), Arc::clone(actions));
+    engine.register_fn("speak", move |text: &str| actions_.speak(&plugin, text));
+    let (plugin, actions_) = (name.to_string(), Arc::clone(actions));
+    engine.register_fn("display", move |text: &str| actions_.display(&plugin, text));
+    let (plugin, actions_) = (name.to_string(), Arc::clone(actions));
+    engine.register_fn("log", move |text: &str| actions_.log(&plugin, None, text));
+    let (plugin, actions_) = (name.to_string(), Arc::clone(actions));
+    engine.register_fn("log", move |level: &str, text: &str| {
+        actions_.log(&plugin, Some(level), text)
+    });
+
+    let kv_ = Arc::clone(kv);
+    engine.register_fn("kv_get", move |key: &str| -> Dynamic {
+        kv_.lock()
+            .ok()
+            .and_then(|kv| kv.get(key).cloned())
+            .and_then(|value| rhai::serde::to_dynamic(value).ok())
+            .unwrap_or(Dynamic::UNIT)
+    });
+    let kv_ = Arc::clone(kv);
+    engine.register_fn(
+        "kv_set",
+        move |key: &str, value: Dynamic| -> Result<(), Box<rhai::EvalAltResult>> {
+            let value: serde_json::Value = rhai::serde::from_dynamic(&value)?;
+            if let Ok(mut kv) = kv_.lock() {
+                kv.set(key, value);
+            }
+            Ok(())
+        },
+    );
+
+    register_timer(&mut engine, timers, "every", true);
+    register_timer(&mut engine, timers, "after", false);
+    engine
+}
+
+/// Register `every`/`after` for whole and fractional seconds; an interval that is not
+/// a number or too large to schedule is a script error
+fn register_timer(engine: &mut Engine, timers: &Arc<Mutex<Vec<Timer>>>, name: &str, repeat: bool) {
+    let add = {
+        let timers = Arc::clone(timers);
+        move |seconds: FLOAT, function: &str| -> Result<(), Box<rhai::EvalAltResult>> {
+            let due = Duration::try_from_secs_f64(seconds.max(0.0))
+                .ok()
+                .and_then(|interval| Some((interval, Instant::now().checked_add(interval)?)));
+            let Some((interval, due)) = due else {
+                return Err(format!("invalid timer interval: {} seconds", seconds).into());
+            };
+            if let Ok(mut timers) = timers.lock() {
+                timers.push(Timer {
+                    function: function.to_string(),
+                    due,
+                    every: repeat.then_some(interval.max(TICK)),
+                });
+            }
+            Ok(())
+        }
+    };
+    let add_ = add.clone();
+    engine.register_fn(name, move |seconds: INT, function: &str| {
+        add_(seconds as FLOAT, function)
+    });
+    engine.register_fn(name, add);
+}
+
+/// Expose [`BiliMessage`] to scripts as `Message`, with a property per field
+///
+/// Fields that do not apply to a message's kind are empty (`""`, `0`).
+fn register_message(engine: &mut Engine) {
+    engine.register_type_with_name::<BiliMessage>("Message");
+    engine.register_get("kind", |msg: &mut BiliMessage| -> String {
+        match MessageKind::of(msg) {
+            Some(MessageKind::Danmu) => "danmu",
+            Some(MessageKind::Gift) => "gift",
+            Some(MessageKind::SuperChat) => "super_chat",
+            Some(MessageKind::OnlineRankCount) => "online_rank_count",
+            Some(MessageKind::Raw) | None => "raw",
+        }
+        .to_string()
+    });
+    engine.register_get("cmd", |msg: &mut BiliMessage| -> String {
+        message_cmd(msg).unwrap_or_default().to_string()
+    });
+    engine.register_get("history", |msg: &mut BiliMessage| msg.is_historical());
+    engine.register_get("user", |msg: &mut BiliMessage| -> String {
+        match msg.inner() {
+            BiliMessage::Danmu { user, .. }
+            | BiliMessage::Gift { user, .. }
+            | BiliMessage::SuperChat { user, .. } => user.clone(),
+            _ => String::new(),
+        }
+    });
+    engine.register_get("uid", |msg: &mut BiliMessage| -> INT {
+        match msg.inner() {
+            BiliMessage::Danmu { uid, .. }
+            | BiliMessage::Gift { uid, .. }
+            | BiliMessage::SuperChat { uid, .. } => *uid as INT,
+            _ => 0,
+        }
+    });
+    engine.register_get("text", |msg: &mut BiliMessage| -> String {
+        match msg.inner() {
+            BiliMessage::Danmu { text, .. } => text.clone(),
+            BiliMessage::SuperChat { message, .. } => message.clone(),
+            _ => String::new(),
+        }
+    });
+    engine.register_get("medal", |msg: &mut BiliMessage| -> String {
+        match msg.inner() {
+            BiliMessage::Danmu {
+                medal: Some(medal), ..
+            } => medal.name.clone(),
+            _ => String::new(),
+        }
+    });
+    engine.register_get("medal_level", |msg: &mut BiliMessage| -> INT {
+        match msg.inner() {
+            BiliMessage::Danmu {
+                medal: Some(medal), ..
+            } => medal.level as INT,
+            _ => 0,
+        }
+    });
+    engine.register_get("gift", |msg: &mut BiliMessage| -> String {
+        match msg.inner() {
+            BiliMessage::Gift { gift, .. } => gift.clone(),
+            _ => String::new(),
+        }
+    });
+    engine.register_get("num", |msg: &mut BiliMessage| -> INT {
+        match msg.inner() {
+            BiliMessage::Gift { num, .. } => *num as INT,
+            _ => 0,
+        }
+    });
+    engine.register_get("value", |msg: &mut BiliMessage| -> FLOAT {
+        value_cny(msg.inner()).unwrap_or_default()
+    });
+    engine.register_get("count", |msg: &mut BiliMessage| -> INT {
+        match msg.inner() {
+            BiliMessage::OnlineRankCount { count, .. } => *count as INT,
+            _ => 0,
+        }
+    });
+    engine.register_get("raw", |msg: &mut BiliMessage| -> Dynamic {
+        match msg.inner() {
+            BiliMessage::Raw(json) => rhai::serde::to_dynamic(json).unwrap_or(Dynamic::UNIT),
+            _ => Dynamic::UNIT,
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::models::FanMedal;
+    use std::collections::VecDeque;
+    use std::fs::File;
+
+    fn danmu(text: &str) -> BiliMessage {
+        BiliMessage::Danmu {
+            user: "alice".to_string(),
+            uid: 42,
+            text: text.to_string(),
+            segments: Vec::new(),
+            medal: Some(FanMedal {
+                name: "粉丝团".to_string(),
+                level: 7,
+                room_id: 1,
+            }),
+        }
+    }
+
+    fn script_dir(test: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("blivedm_script_{}_{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_script_messages_kv_and_timers() {
+        let dir = script_dir("kv");
+        let path = dir.join("greeter.rhai");
+        fs::write(
+            &path,
+            r#"
+            fn on_message(msg) {
+                if msg.kind == "danmu" && msg.text.contains("你好") {
+                    let count = kv_get("greeted") ?? 0;
+                    kv_set("greeted", count + 1);
+                    display(`${msg.user} ${msg.uid} ${msg.medal} ${msg.medal_level} ${count + 1}`);
+                }
+            }
+            fn tick() { display("tick"); }
+            after(0, "tick");
+            "#,
+        )
+        .unwrap();
+        let buffer = Arc::new(Mutex::new(VecDeque::new()));
+        let actions = Arc::new(PluginActions::new().with_message_buffer(Arc::clone(&buffer)));
+        let context = EventContext::new(None, 1);
+
+        let handler = ScriptHandler::load(&path, Arc::clone(&actions)).unwrap();
+        assert_eq!(handler.name(), "greeter.rhai");
+        handler.handle(&danmu("你好"), &context);
+        handler.handle(&danmu("bye"), &context);
+        handler.runtime.run_timers();
+        // `after` timers run once
+        handler.runtime.run_timers();
+        let messages: Vec<String> = buffer.lock().unwrap().drain(..).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "[Plugin greeter.rhai] alice 42 粉丝团 7 1".to_string(),
+                "[Plugin greeter.rhai] tick".to_string(),
+            ]
+        );
+
+        // Stored values survive loading the script again
+        let handler = ScriptHandler::load(&path, actions).unwrap();
+        handler.handle(&danmu("你好"), &context);
+        assert_eq!(
+            buffer.lock().unwrap().back().unwrap(),
+            "[Plugin greeter.rhai] alice 42 粉丝团 7 2"
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_script_rejects_out_of_range_timers() {
+        let dir = script_dir("timers");
+        let actions = Arc::new(PluginActions::new());
+        for (file, line) in [
+            ("float.rhai", r#"after(1e30, "tick");"#),
+            ("int.rhai", r#"every(9223372036854775807, "tick");"#),
+        ] {
+            let path = dir.join(file);
+            fs::write(&path, line).unwrap();
+            let error = ScriptHandler::load(&path, Arc::clone(&actions))
+                .err()
+                .unwrap();
+            assert!(error.contains("invalid timer interval"), "{}", error);
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_script_hot_reload() {
+        let dir = script_dir("reload");
+        let path = dir.join("echo.rhai");
+        fs::write(&path, r#"fn on_message(msg) { display("v1"); }"#).unwrap();
+        fs::write(dir.join("notes.txt"), "not a script").unwrap();
+        let buffer = Arc::new(Mutex::new(VecDeque::new()));
+        let actions = Arc::new(PluginActions::new().with_message_buffer(Arc::clone(&buffer)));
+        let context = EventContext::new(None, 1);
+
+        let handlers = load_scripts(&dir, &actions).unwrap();
+        assert_eq!(handlers.len(), 1);
+        let handler = handlers.into_iter().next().unwrap().unwrap();
+        handler.handle(&danmu("a"), &context);
+
+        let touch = |content: &str, age: u64| {
+            fs::write(&path, content).unwrap();
+            let modified = SystemTime::now() - Duration::from_secs(age);
+            File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        };
+        touch(r#"fn on_message(msg) { display("v2"); }"#, 20);
+        handler.runtime.reload_if_changed();
+        handler.handle(&danmu("b"), &context);
+
+        // A broken script keeps the last working version
+        touch("fn on_message(msg) {", 10);
+        handler.runtime.reload_if_changed();
+        handler.handle(&danmu("c"), &context);
+
+        let messages: Vec<String> = buffer.lock().unwrap().drain(..).collect();
+        assert_eq!(messages[0], "[Plugin echo.rhai] v1");
+        assert_eq!(messages[1], "[Plugin echo.rhai] reloaded");
+        assert_eq!(messages[2], "[Plugin echo.rhai] v2");
+        assert!(messages[3].contains("Cannot compile"));
+        assert_eq!(messages[4], "[Plugin echo.rhai] v2");
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,4 +1,5 @@
-use crate::client::models::BiliMessage;
+use crate::client::danmaku::ReplyTarget;
+use crate::client::models::{BiliMessage, render_danmu};
 use crate::client::scheduler::{EventContext, EventHandler};
 use std::collections::VecDeque;
 use std::sync::atomic::AtomicU64;
@@ -10,6 +11,8 @@ pub struct TerminalDisplayHandler {
     message_buffer: Arc<Mutex<VecDeque<String>>>,
     /// Shared online count for TUI title display
     online_count: Arc<AtomicU64>,
+    /// Recent danmaku senders the TUI can reply to
+    reply_candidates: Option<Arc<Mutex<VecDeque<ReplyTarget>>>>,
 }
 
 impl TerminalDisplayHandler {
@@ -18,6 +21,7 @@ impl TerminalDisplayHandler {
         Self {
             message_buffer,
             online_count: Arc::new(AtomicU64::new(0)),
+            reply_candidates: None,
         }
     }
 
@@ -29,24 +33,72 @@ impl TerminalDisplayHandler {
         Self {
             message_buffer,
             online_count,
+            reply_candidates: None,
         }
     }
-}
 
-impl EventHandler for TerminalDisplayHandler {
-    fn handle(&self, msg: &BiliMessage, _context: &EventContext) {
+    /// Record danmaku senders in a shared list so the TUI can reply to them
+    pub fn with_reply_candidates(
+        mut self,
+        reply_candidates: Arc<Mutex<VecDeque<ReplyTarget>>>,
+    ) -> Self {
+        self.reply_candidates = Some(reply_candidates);
+        self
+    }
+
+    /// Format a message for the buffer, or None if it is not displayed as a line
+    fn format_message(&self, msg: &BiliMessage) -> Option<String> {
         let formatted_msg = match msg {
-            BiliMessage::Danmu { user, text } => {
+            BiliMessage::Danmu {
+                user,
+                uid,
+                text,
+                segments,
+                ..
+            } => {
+                if let Some(candidates) = &self.reply_candidates
+                    && *uid != 0
+                {
+                    crate::tui::app::TuiApp::add_reply_candidate(
+                        candidates,
+                        ReplyTarget::new(*uid, user.clone()),
+                    );
+                }
+                let text = render_danmu(text, segments, |emote| Some(emote.badge()));
                 format!("[Danmu] {}: {}", user, text)
             }
-            BiliMessage::Gift { user, gift } => {
-                format!("[Gift] {} sent a gift: {}", user, gift)
+            BiliMessage::Gift {
+                user,
+                gift,
+                num,
+                value,
+                ..
+            } => {
+                let mut line = format!("[Gift] {} sent a gift: {}", user, gift);
+                if *num > 1 {
+                    line.push_str(&format!(" x{}", num));
+                }
+                if let Some(value) = value.filter(|v| v.is_paid()) {
+                    line.push_str(&format!(" (¥{:.1})", value.paid_cny(*num)));
+                }
+                line
+            }
+            BiliMessage::SuperChat {
+                user,
+                message,
+                price,
+                ..
+            } => {
+                format!("[SC ¥{}] {}: {}", price, user, message)
             }
             BiliMessage::OnlineRankCount { online_count, .. } => {
                 // Update the shared online count for TUI title display
                 crate::tui::app::TuiApp::set_online_count(&self.online_count, *online_count);
                 // Don't add to message buffer - just update the title counter
-                return;
+                return None;
+            }
+            BiliMessage::Historical(inner) => {
+                format!("[History] {}", self.format_message(inner)?)
             }
             BiliMessage::Raw(json) => {
                 format!("[Raw] {}", json["cmd"].as_str().unwrap_or("Unknown"))
@@ -54,16 +106,23 @@ impl EventHandler for TerminalDisplayHandler {
             #[allow(deprecated)]
             BiliMessage::Unsupported => "[Unsupported message type]".to_string(),
         };
+        Some(formatted_msg)
+    }
+}
 
-        // Add message to buffer using the TuiApp helper method
-        crate::tui::app::TuiApp::add_message(&self.message_buffer, formatted_msg);
+impl EventHandler for TerminalDisplayHandler {
+    fn handle(&self, msg: &BiliMessage, _context: &EventContext) {
+        if let Some(formatted_msg) = self.format_message(msg) {
+            // Add message to buffer using the TuiApp helper method
+            crate::tui::app::TuiApp::add_message(&self.message_buffer, formatted_msg);
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::client::models::BiliMessage;
+    use crate::client::models::{BiliMessage, CoinType, DanmuSegment, Emote, GiftValue};
     use crate::client::scheduler::EventHandler;
     use std::collections::VecDeque;
     use std::sync::{Arc, Mutex};
@@ -74,10 +133,14 @@ mod tests {
         let handler = TerminalDisplayHandler::new(Arc::clone(&buffer));
         let msg = BiliMessage::Danmu {
             user: "test_user".to_string(),
+            uid: 42,
             text: "hello world".to_string(),
+            segments: Vec::new(),
+            medal: None,
         };
         let context = EventContext {
             cookies: None,
+            send_cookies: None,
             room_id: 12345,
         };
         handler.handle(&msg, &context);
@@ -87,16 +150,71 @@ mod tests {
         assert_eq!(messages[0], "[Danmu] test_user: hello world");
     }
 
+    #[test]
+    fn test_terminal_display_handler_shows_emote_badges() {
+        let buffer = Arc::new(Mutex::new(VecDeque::new()));
+        let handler = TerminalDisplayHandler::new(Arc::clone(&buffer));
+        let sticker = Emote {
+            name: "赞".to_string(),
+            description: "赞".to_string(),
+            unique_id: Some("upower_1".to_string()),
+            url: "https://i0.hdslb.com/zan.png".to_string(),
+            width: 183,
+            height: 162,
+        };
+        let msg = BiliMessage::Danmu {
+            user: "test_user".to_string(),
+            uid: 42,
+            text: "赞".to_string(),
+            segments: vec![DanmuSegment::Emote(sticker)],
+            medal: None,
+        };
+        handler.handle(&msg, &EventContext::new(None, 12345));
+
+        let messages = buffer.lock().unwrap();
+        assert_eq!(messages[0], "[Danmu] test_user: [赞]");
+    }
+
+    #[test]
+    fn test_terminal_display_handler_records_reply_candidates() {
+        let buffer = Arc::new(Mutex::new(VecDeque::new()));
+        let candidates = Arc::new(Mutex::new(VecDeque::new()));
+        let handler = TerminalDisplayHandler::new(Arc::clone(&buffer))
+            .with_reply_candidates(Arc::clone(&candidates));
+        let context = EventContext::new(None, 12345);
+
+        for (user, uid) in [("alice", 1), ("bob", 2), ("alice", 1), ("hidden", 0)] {
+            let msg = BiliMessage::Danmu {
+                user: user.to_string(),
+                uid,
+                text: "hi".to_string(),
+                segments: Vec::new(),
+                medal: None,
+            };
+            handler.handle(&msg, &context);
+        }
+
+        // Newest last, one entry per user, hidden senders skipped
+        let candidates = candidates.lock().unwrap();
+        let uids: Vec<u64> = candidates.iter().map(|c| c.uid).collect();
+        assert_eq!(uids, vec![2, 1]);
+    }
+
     #[test]
     fn test_terminal_display_handler_adds_gift() {
         let buffer = Arc::new(Mutex::new(VecDeque::new()));
         let handler = TerminalDisplayHandler::new(Arc::clone(&buffer));
         let msg = BiliMessage::Gift {
             user: "gift_user".to_string(),
+            uid: 0,
             gift: "rocket".to_string(),
+            gift_id: 0,
+            num: 1,
+            value: None,
         };
         let context = EventContext {
             cookies: None,
+            send_cookies: None,
             room_id: 12345,
         };
         handler.handle(&msg, &context);
@@ -106,6 +224,48 @@ mod tests {
         assert_eq!(messages[0], "[Gift] gift_user sent a gift: rocket");
     }
 
+    #[test]
+    fn test_terminal_display_handler_shows_gift_value() {
+        let buffer = Arc::new(Mutex::new(VecDeque::new()));
+        let handler = TerminalDisplayHandler::new(Arc::clone(&buffer));
+        let msg = BiliMessage::Gift {
+            user: "gift_user".to_string(),
+            uid: 0,
+            gift: "小花花".to_string(),
+            gift_id: 31036,
+            num: 10,
+            value: Some(GiftValue {
+                price: 100,
+                coin_type: CoinType::Gold,
+                blind_box_price: None,
+            }),
+        };
+        handler.handle(&msg, &EventContext::new(None, 12345));
+
+        let messages = buffer.lock().unwrap();
+        assert_eq!(
+            messages[0],
+            "[Gift] gift_user sent a gift: 小花花 x10 (¥1.0)"
+        );
+    }
+
+    #[test]
+    fn test_terminal_display_handler_marks_history() {
+        let buffer = Arc::new(Mutex::new(VecDeque::new()));
+        let handler = TerminalDisplayHandler::new(Arc::clone(&buffer));
+        let msg = BiliMessage::SuperChat {
+            user: "sc_user".to_string(),
+            uid: 7,
+            message: "great stream".to_string(),
+            price: 30,
+        }
+        .historical();
+        handler.handle(&msg, &EventContext::new(None, 12345));
+
+        let messages = buffer.lock().unwrap();
+        assert_eq!(messages[0], "[History] [SC ¥30] sc_user: great stream");
+    }
+
     #[test]
     fn test_terminal_display_handler_adds_unsupported() {
         let buffer = Arc::new(Mutex::new(VecDeque::new()));
@@ -113,6 +273,7 @@ mod tests {
         let msg = BiliMessage::Unsupported;
         let context = EventContext {
             cookies: None,
+            send_cookies: None,
             room_id: 12345,
         };
         handler.handle(&msg, &context);
@@ -1,5 +1,6 @@
-use crate::client::models::BiliMessage;
-use crate::client::scheduler::{EventContext, EventHandler};
+use crate::client::models::{BiliMessage, render_danmu};
+use crate::client::scheduler::{AsyncEventHandler, EventContext};
+use async_trait::async_trait;
 use base64::{Engine as _, engine::general_purpose};
 use log::{debug, error, info, warn};
 use rodio::{Decoder, OutputStream, Sink};
@@ -7,8 +8,11 @@ use serde::{Deserialize, Serialize};
 use std::io::Cursor;
 use std::process::Command;
 use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, RwLock};
 use std::thread;
 use std::thread::JoinHandle;
+use tokio::runtime::Handle;
+use tokio::sync::oneshot;
 
 #[derive(Serialize, Debug)]
 struct TtsRequest {
@@ -143,6 +147,50 @@ pub enum TtsMode {
     },
 }
 
+/// Text queued for speech, with the runtime to make HTTP requests on
+struct Speech {
+    text: String,
+    runtime: Handle,
+}
+
+/// Work for the TTS worker thread
+enum TtsJob {
+    Speak(Speech),
+    /// Answered once everything queued before it has been spoken
+    Flush(oneshot::Sender<()>),
+}
+
+/// Settings the worker uses for the next text
+fn current_mode(mode: &RwLock<TtsMode>) -> TtsMode {
+    match mode.read() {
+        Ok(mode) => mode.clone(),
+        Err(poisoned) => poisoned.into_inner().clone(),
+    }
+}
+
+/// Next text to speak, answering flush requests on the way
+fn next_speech(receiver: &std::sync::mpsc::Receiver<TtsJob>) -> Option<Speech> {
+    loop {
+        match receiver.recv().ok()? {
+            TtsJob::Speak(speech) => return Some(speech),
+            TtsJob::Flush(done) => {
+                let _ = done.send(());
+            }
+        }
+    }
+}
+
+/// How emotes in danmaku are read aloud
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmoteSpeech {
+    /// Speak the emote's description, e.g. "dog" for `[dog]`
+    #[default]
+    Speak,
+    /// Leave emotes out; sticker-only danmaku are not spoken at all
+    Skip,
+}
+
 /// A plugin that sends Danmaku text to a TTS service and plays the audio sequentially.
 ///
 /// This handler supports two modes:
@@ -150,13 +198,16 @@ pub enum TtsMode {
 ///    decodes it and plays through the system's audio output
 /// 2. Command mode: Uses local command-line TTS programs (like `say` on macOS or `espeak-ng` on Linux)
 ///
-/// Messages are processed sequentially to avoid overlapping audio.
+/// Messages are processed sequentially to avoid overlapping audio. Playback stays on a
+/// dedicated worker thread, while HTTP requests run on the scheduler's runtime.
+/// Settings can be changed while it runs with [`TtsHandler::set_mode`].
 pub struct TtsHandler {
-    /// TTS configuration (either REST API or command-based)
-    #[allow(dead_code)]
-    mode: TtsMode,
+    /// TTS configuration (either REST API or command-based), read by the worker per text
+    mode: Arc<RwLock<TtsMode>>,
     /// Channel sender for queuing TTS messages
-    sender: Sender<String>,
+    sender: Sender<TtsJob>,
+    /// How emotes are read aloud
+    emote_speech: RwLock<EmoteSpeech>,
     /// Background thread handle for TTS processing
     _worker_handle: JoinHandle<()>,
 }
@@ -164,31 +215,111 @@ pub struct TtsHandler {
 impl TtsHandler {
     /// Create a new TTS handler with the specified mode
     pub fn new(mode: TtsMode) -> Self {
-        let (sender, receiver) = mpsc::channel::<String>();
+        let (sender, receiver) = mpsc::channel::<TtsJob>();
 
-        // Clone the mode for the worker thread
-        let mode_clone = mode.clone();
+        // The worker thread shares the mode, so changed settings reach it
+        let worker_kind = mode.clone();
+        let mode = Arc::new(RwLock::new(mode));
+        let worker_mode = Arc::clone(&mode);
 
         // Spawn worker thread to process TTS queue sequentially
-        let worker_handle = thread::spawn(move || match &mode_clone {
+        let worker_handle = thread::spawn(move || match worker_kind {
             TtsMode::RestApi { .. } => {
-                Self::run_rest_api_worker(receiver, mode_clone);
+                Self::run_rest_api_worker(receiver, worker_mode);
             }
             TtsMode::AliTts { .. } => {
-                Self::run_ali_tts_worker(receiver, mode_clone);
+                Self::run_ali_tts_worker(receiver, worker_mode);
             }
             TtsMode::Command { .. } => {
-                Self::run_command_worker(receiver, mode_clone);
+                Self::run_command_worker(receiver, worker_mode);
             }
         });
 
         TtsHandler {
             mode,
             sender,
+            emote_speech: RwLock::new(EmoteSpeech::default()),
             _worker_handle: worker_handle,
         }
     }
 
+    /// Set how emotes in danmaku are read aloud
+    pub fn with_emote_speech(self, emote_speech: EmoteSpeech) -> Self {
+        self.set_emote_speech(emote_speech);
+        self
+    }
+
+    /// Change how emotes are read aloud while the handler runs
+    pub fn set_emote_speech(&self, emote_speech: EmoteSpeech) {
+        match self.emote_speech.write() {
+            Ok(mut current) => *current = emote_speech,
+            Err(poisoned) => *poisoned.into_inner() = emote_speech,
+        }
+    }
+
+    /// Change the settings, e.g. voice or volume, from the next text on.
+    ///
+    /// The backend cannot change while the handler runs: switching between REST API,
+    /// DashScope and command TTS is an error and keeps the current settings.
+    pub fn set_mode(&self, mode: TtsMode) -> Result<(), String> {
+        let mut current = match self.mode.write() {
+            Ok(current) => current,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if std::mem::discriminant(&*current) != std::mem::discriminant(&mode) {
+            return Err("the TTS backend cannot change while running".to_string());
+        }
+        *current = mode;
+        Ok(())
+    }
+
+    /// The settings used for the next text
+    pub fn mode(&self) -> TtsMode {
+        current_mode(&self.mode)
+    }
+
+    /// Queue text to be spoken after what is already queued, e.g. on behalf of another
+    /// plugin; HTTP requests run on `runtime`
+    pub fn speak(&self, text: impl Into<String>, runtime: Handle) {
+        let _ = self.sender.send(TtsJob::Speak(Speech {
+            text: text.into(),
+            runtime,
+        }));
+    }
+
+    /// Text to speak for a message, or None if it is not spoken
+    fn speech_text(&self, msg: &BiliMessage) -> Option<String> {
+        match msg {
+            BiliMessage::Danmu {
+                user,
+                text,
+                segments,
+                ..
+            } => {
+                let emote_speech = match self.emote_speech.read() {
+                    Ok(emote_speech) => *emote_speech,
+                    Err(poisoned) => *poisoned.into_inner(),
+                };
+                let text = render_danmu(text, segments, |emote| match emote_speech {
+                    EmoteSpeech::Speak => Some(emote.description.clone()),
+                    EmoteSpeech::Skip => None,
+                });
+                if text.trim().is_empty() {
+                    return None;
+                }
+                Some(format!("{}说：{}", user, text))
+            }
+            // Gifts and super chats only reach TTS when its filter routes them here
+            BiliMessage::Gift {
+                user, gift, num, ..
+            } => Some(format!("{}送出了{}个{}", user, num, gift)),
+            BiliMessage::SuperChat { user, message, .. } => {
+                Some(format!("{}发送了醒目留言：{}", user, message))
+            }
+            _ => None,
+        }
+    }
+
     /// Create a new TTS handler with REST API using default Chinese voice settings
     pub fn new_rest_api_default(server_url: String) -> Self {
         Self::new_rest_api_default_with_volume(server_url, 1.0)
@@ -289,27 +420,29 @@ impl TtsHandler {
     }
 
     /// Worker thread for REST API TTS processing
-    fn run_rest_api_worker(receiver: std::sync::mpsc::Receiver<String>, mode: TtsMode) {
-        if let TtsMode::RestApi {
-            server_url,
-            voice,
-            backend,
-            quality,
-            format,
-            sample_rate,
-            volume,
-        } = mode
-        {
-            // Create a tokio runtime for HTTP requests
-            let rt = tokio::runtime::Runtime::new().unwrap();
-            let client = reqwest::Client::new();
-
-            // Initialize audio output stream (this will be reused for all audio playback)
-            let (_stream, stream_handle) = OutputStream::try_default().unwrap();
-
-            while let Ok(message) = receiver.recv() {
+    fn run_rest_api_worker(
+        receiver: std::sync::mpsc::Receiver<TtsJob>,
+        mode: Arc<RwLock<TtsMode>>,
+    ) {
+        let client = reqwest::Client::new();
+
+        // Initialize audio output stream (this will be reused for all audio playback)
+        let (_stream, stream_handle) = OutputStream::try_default().unwrap();
+
+        while let Some(job) = next_speech(&receiver) {
+            // Settings are read for every text, so changes apply from the next one
+            if let TtsMode::RestApi {
+                server_url,
+                voice,
+                backend,
+                quality,
+                format,
+                sample_rate,
+                volume,
+            } = current_mode(&mode)
+            {
                 let request = TtsRequest {
-                    text: message,
+                    text: job.text,
                     voice: voice.clone(),
                     backend: backend.clone(),
                     quality: quality.clone(),
@@ -318,7 +451,7 @@ impl TtsHandler {
                 };
 
                 // Make HTTP request to TTS service
-                rt.block_on(async {
+                job.runtime.block_on(async {
                     match client
                         .post(&format!("{}/tts", server_url))
                         .header("Content-Type", "application/json")
@@ -384,36 +517,35 @@ impl TtsHandler {
     }
 
     /// Worker thread for Alibaba DashScope TTS processing with SSE streaming
-    fn run_ali_tts_worker(receiver: std::sync::mpsc::Receiver<String>, mode: TtsMode) {
+    fn run_ali_tts_worker(receiver: std::sync::mpsc::Receiver<TtsJob>, mode: Arc<RwLock<TtsMode>>) {
         use futures::StreamExt;
 
-        if let TtsMode::AliTts {
-            api_key,
-            model,
-            voice,
-            language_type,
-            volume,
-        } = mode
-        {
-            // Create a tokio runtime for HTTP requests
-            let rt = tokio::runtime::Runtime::new().unwrap();
-            let client = reqwest::Client::new();
-
-            // Initialize audio output stream (this will be reused for all audio playback)
-            let (_stream, stream_handle) = OutputStream::try_default().unwrap();
-
-            while let Ok(message) = receiver.recv() {
+        let client = reqwest::Client::new();
+
+        // Initialize audio output stream (this will be reused for all audio playback)
+        let (_stream, stream_handle) = OutputStream::try_default().unwrap();
+
+        while let Some(job) = next_speech(&receiver) {
+            // Settings are read for every text, so changes apply from the next one
+            if let TtsMode::AliTts {
+                api_key,
+                model,
+                voice,
+                language_type,
+                volume,
+            } = current_mode(&mode)
+            {
                 let request = AliTtsRequest {
                     model: model.clone(),
                     input: AliTtsInput {
-                        text: message,
+                        text: job.text,
                         voice: voice.clone(),
                         language_type: language_type.clone(),
                     },
                 };
 
                 // Make HTTP request to DashScope TTS service with SSE
-                rt.block_on(async {
+                job.runtime.block_on(async {
                     match client
                         .post("https://dashscope.aliyuncs.com/api/v1/services/aigc/multimodal-generation/generation")
                         .header("Authorization", format!("Bearer {}", api_key))
@@ -570,20 +702,21 @@ impl TtsHandler {
     }
 
     /// Worker thread for command-line TTS processing
-    fn run_command_worker(receiver: std::sync::mpsc::Receiver<String>, mode: TtsMode) {
-        if let TtsMode::Command {
-            tts_command,
-            tts_args,
-        } = mode
-        {
-            while let Ok(message) = receiver.recv() {
+    fn run_command_worker(receiver: std::sync::mpsc::Receiver<TtsJob>, mode: Arc<RwLock<TtsMode>>) {
+        while let Some(job) = next_speech(&receiver) {
+            // Settings are read for every text, so changes apply from the next one
+            if let TtsMode::Command {
+                tts_command,
+                tts_args,
+            } = current_mode(&mode)
+            {
                 let mut command = Command::new(&tts_command);
                 for arg in &tts_args {
                     command.arg(arg);
                 }
 
                 // Execute TTS command and wait for it to complete
-                match command.arg(&message).status() {
+                match command.arg(&job.text).status() {
                     Ok(status) => {
                         if status.success() {
                             debug!("TTS command completed successfully");
@@ -604,12 +737,19 @@ impl TtsHandler {
     }
 }
 
-impl EventHandler for TtsHandler {
-    fn handle(&self, msg: &BiliMessage, _context: &EventContext) {
-        if let BiliMessage::Danmu { user, text } = msg {
-            let message = format!("{}说：{}", user, text);
-            // Send message to the queue for sequential processing
-            let _ = self.sender.send(message);
+#[async_trait]
+impl AsyncEventHandler for TtsHandler {
+    async fn handle(&self, msg: &BiliMessage, _context: &EventContext) {
+        if let Some(text) = self.speech_text(msg) {
+            self.speak(text, Handle::current());
+        }
+    }
+
+    /// Finish speaking everything queued before the app exits
+    async fn on_shutdown(&self, _context: &EventContext) {
+        let (done, wait) = oneshot::channel();
+        if self.sender.send(TtsJob::Flush(done)).is_ok() {
+            let _ = wait.await;
         }
     }
 }
@@ -618,27 +758,31 @@ impl EventHandler for TtsHandler {
 mod tests {
     use super::*;
     use crate::client::models::BiliMessage;
-    use crate::client::scheduler::EventHandler;
+    use crate::client::scheduler::AsyncEventHandler;
 
-    #[test]
-    fn test_tts_handler_danmu() {
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_tts_handler_danmu() {
         // Test with a mock server URL (won't actually make requests in this test)
         let handler = TtsHandler::new_rest_api_default("http://localhost:8000".to_string());
 
         let text = "您好，欢迎来到直播间。".to_string();
         let msg = BiliMessage::Danmu {
             user: "测试用户".to_string(),
+            uid: 0,
             text: text.clone(),
+            segments: Vec::new(),
+            medal: None,
         };
         let context = EventContext {
             cookies: None,
+            send_cookies: None,
             room_id: 12345,
         };
-        handler.handle(&msg, &context);
+        handler.handle(&msg, &context).await;
     }
 
-    #[test]
-    fn test_tts_handler_custom_config() {
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_tts_handler_custom_config() {
         let handler = TtsHandler::new_rest_api(
             "http://localhost:8000".to_string(),
             Some("zh-CN-XiaoxiaoNeural".to_string()),
@@ -650,17 +794,21 @@ mod tests {
 
         let msg = BiliMessage::Danmu {
             user: "test_user".to_string(),
+            uid: 0,
             text: "hello world".to_string(),
+            segments: Vec::new(),
+            medal: None,
         };
         let context = EventContext {
             cookies: None,
+            send_cookies: None,
             room_id: 12345,
         };
-        handler.handle(&msg, &context);
+        handler.handle(&msg, &context).await;
     }
 
-    #[test]
-    fn test_tts_handler_sequential_processing() {
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_tts_handler_sequential_processing() {
         use std::time::Duration;
 
         // Use default configuration for testing
@@ -676,13 +824,17 @@ mod tests {
         for (user, text) in messages {
             let msg = BiliMessage::Danmu {
                 user: user.to_string(),
+                uid: 0,
                 text: text.to_string(),
+                segments: Vec::new(),
+                medal: None,
             };
             let context = EventContext {
                 cookies: None,
+                send_cookies: None,
                 room_id: 12345,
             };
-            handler.handle(&msg, &context);
+            handler.handle(&msg, &context).await;
         }
 
         // Give the worker thread some time to process the queue
@@ -692,28 +844,32 @@ mod tests {
         // is ensured by the worker thread design
     }
 
-    #[test]
-    fn test_tts_handler_command_mode() {
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_tts_handler_command_mode() {
         // Test command-based TTS (cross-platform using echo)
         let handler = TtsHandler::new_command("echo".to_string(), vec![]);
 
         let msg = BiliMessage::Danmu {
             user: "test_user".to_string(),
+            uid: 0,
             text: "test message".to_string(),
+            segments: Vec::new(),
+            medal: None,
         };
         let context = EventContext {
             cookies: None,
+            send_cookies: None,
             room_id: 12345,
         };
-        handler.handle(&msg, &context);
+        handler.handle(&msg, &context).await;
 
         // Give the worker thread some time to process the message
         std::thread::sleep(std::time::Duration::from_millis(50));
     }
 
     #[cfg(target_os = "macos")]
-    #[test]
-    fn test_tts_handler_macos_voice() {
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_tts_handler_macos_voice() {
         let handler = TtsHandler::new_command(
             "say".to_string(),
             vec!["-v".to_string(), "Mei-Jia".to_string()],
@@ -721,18 +877,22 @@ mod tests {
 
         let msg = BiliMessage::Danmu {
             user: "用户".to_string(),
+            uid: 0,
             text: "你好".to_string(),
+            segments: Vec::new(),
+            medal: None,
         };
         let context = EventContext {
             cookies: None,
+            send_cookies: None,
             room_id: 12345,
         };
-        handler.handle(&msg, &context);
+        handler.handle(&msg, &context).await;
     }
 
     #[cfg(target_os = "linux")]
-    #[test]
-    fn test_tts_handler_linux_voice() {
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_tts_handler_linux_voice() {
         let handler = TtsHandler::new_command(
             "espeak-ng".to_string(),
             vec!["-v".to_string(), "cmn".to_string()],
@@ -740,13 +900,17 @@ mod tests {
 
         let msg = BiliMessage::Danmu {
             user: "用户".to_string(),
+            uid: 0,
             text: "你好".to_string(),
+            segments: Vec::new(),
+            medal: None,
         };
         let context = EventContext {
             cookies: None,
+            send_cookies: None,
             room_id: 12345,
         };
-        handler.handle(&msg, &context);
+        handler.handle(&msg, &context).await;
     }
 
     #[test]
@@ -766,21 +930,25 @@ mod tests {
         assert!(json.contains("edge"));
     }
 
-    #[test]
-    fn test_tts_handler_with_volume() {
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_tts_handler_with_volume() {
         // Test with custom volume setting
         let handler =
             TtsHandler::new_rest_api_default_with_volume("http://localhost:8000".to_string(), 0.5);
 
         let msg = BiliMessage::Danmu {
             user: "test_user".to_string(),
+            uid: 0,
             text: "volume test".to_string(),
+            segments: Vec::new(),
+            medal: None,
         };
         let context = EventContext {
             cookies: None,
+            send_cookies: None,
             room_id: 12345,
         };
-        handler.handle(&msg, &context);
+        handler.handle(&msg, &context).await;
 
         // Test with custom configuration including volume
         let handler_custom = TtsHandler::new_rest_api_with_volume(
@@ -794,29 +962,34 @@ mod tests {
         );
         let context = EventContext {
             cookies: None,
+            send_cookies: None,
             room_id: 12345,
         };
-        handler_custom.handle(&msg, &context);
+        handler_custom.handle(&msg, &context).await;
     }
 
-    #[test]
-    fn test_ali_tts_handler_default() {
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_ali_tts_handler_default() {
         // Test with a mock API key (won't actually make requests in this test)
         let handler = TtsHandler::new_ali_tts_default("test_api_key".to_string());
 
         let msg = BiliMessage::Danmu {
             user: "测试用户".to_string(),
+            uid: 0,
             text: "你好".to_string(),
+            segments: Vec::new(),
+            medal: None,
         };
         let context = EventContext {
             cookies: None,
+            send_cookies: None,
             room_id: 12345,
         };
-        handler.handle(&msg, &context);
+        handler.handle(&msg, &context).await;
     }
 
-    #[test]
-    fn test_ali_tts_handler_custom_config() {
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_ali_tts_handler_custom_config() {
         let handler = TtsHandler::new_ali_tts(
             "test_api_key".to_string(),
             "qwen3-tts-flash".to_string(),
@@ -827,13 +1000,17 @@ mod tests {
 
         let msg = BiliMessage::Danmu {
             user: "test_user".to_string(),
+            uid: 0,
             text: "hello world".to_string(),
+            segments: Vec::new(),
+            medal: None,
         };
         let context = EventContext {
             cookies: None,
+            send_cookies: None,
             room_id: 12345,
         };
-        handler.handle(&msg, &context);
+        handler.handle(&msg, &context).await;
     }
 
     #[test]
@@ -853,4 +1030,101 @@ mod tests {
         assert!(json.contains("Cherry"));
         assert!(json.contains("Chinese"));
     }
+
+    #[test]
+    fn test_emote_speech() {
+        use crate::client::models::{DanmuSegment, Emote};
+
+        let dog = Emote {
+            name: "dog".to_string(),
+            description: "狗头".to_string(),
+            unique_id: None,
+            url: String::new(),
+            width: 20,
+            height: 20,
+        };
+        let msg = BiliMessage::Danmu {
+            user: "观众".to_string(),
+            uid: 0,
+            text: "好[dog]".to_string(),
+            segments: vec![
+                DanmuSegment::Text("好".to_string()),
+                DanmuSegment::Emote(dog.clone()),
+            ],
+            medal: None,
+        };
+        let sticker = BiliMessage::Danmu {
+            user: "观众".to_string(),
+            uid: 0,
+            text: "dog".to_string(),
+            segments: vec![DanmuSegment::Emote(dog)],
+            medal: None,
+        };
+
+        let handler = TtsHandler::new_rest_api_default("http://localhost:8000".to_string());
+        assert_eq!(handler.speech_text(&msg).as_deref(), Some("观众说：好狗头"));
+
+        let handler = handler.with_emote_speech(EmoteSpeech::Skip);
+        assert_eq!(handler.speech_text(&msg).as_deref(), Some("观众说：好"));
+        assert_eq!(handler.speech_text(&sticker), None);
+    }
+
+    #[test]
+    fn test_gift_and_super_chat_speech() {
+        let handler = TtsHandler::new_rest_api_default("http://localhost:8000".to_string());
+        let gift = BiliMessage::Gift {
+            user: "观众".to_string(),
+            uid: 1,
+            gift: "小花花".to_string(),
+            gift_id: 0,
+            num: 3,
+            value: None,
+        };
+        assert_eq!(
+            handler.speech_text(&gift).as_deref(),
+            Some("观众送出了3个小花花")
+        );
+
+        let super_chat = BiliMessage::SuperChat {
+            user: "观众".to_string(),
+            uid: 1,
+            message: "主播加油".to_string(),
+            price: 30,
+        };
+        assert_eq!(
+            handler.speech_text(&super_chat).as_deref(),
+            Some("观众发送了醒目留言：主播加油")
+        );
+        assert_eq!(handler.speech_text(&super_chat.historical()), None);
+    }
+
+    #[test]
+    fn test_set_mode_keeps_the_backend() {
+        let handler = TtsHandler::new_command("true".to_string(), vec![]);
+        assert!(
+            handler
+                .set_mode(TtsMode::Command {
+                    tts_command: "true".to_string(),
+                    tts_args: vec!["-v".to_string(), "Tingting".to_string()],
+                })
+                .is_ok()
+        );
+        assert!(
+            handler
+                .set_mode(TtsMode::RestApi {
+                    server_url: "http://localhost:8000".to_string(),
+                    voice: None,
+                    backend: None,
+                    quality: None,
+                    format: None,
+                    sample_rate: None,
+                    volume: Some(0.5),
+                })
+                .is_err()
+        );
+        match current_mode(&handler.mode) {
+            TtsMode::Command { tts_args, .. } => assert_eq!(tts_args, ["-v", "Tingting"]),
+            mode => panic!("unexpected mode {:?}", mode),
+        }
+    }
 }
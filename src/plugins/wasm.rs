@@ -0,0 +1,502 @@
+//! Sandboxed WebAssembly plugins, run with wasmtime (`--features wasm`)
+//!
+//! A plugin is a core WebAssembly module that exports:
+//!
+//! - `memory`
+//! - `alloc(len: i32) -> i32`: room for an event of `len` bytes
+//! - `handle(ptr: i32, len: i32)`: handle the UTF-8 JSON event at `ptr`
+//!
+//! Events are the same JSON objects external plugins get on stdin, see
+//! [`external`](crate::plugins::external). The plugin may import these functions from
+//! the `blivedm` module; strings are passed as `(ptr, len)` of UTF-8 bytes:
+//!
+//! - `send(ptr, len)`, `speak(ptr, len)`, `display(ptr, len)`
+//! - `log(level, ptr, len)` with level 0 = error, 1 = warn, 2 = info, 3 = debug
+//! - `kv_get(key_ptr, key_len, out_ptr, out_cap) -> i32`: writes the value to `out_ptr`
+//!   and returns its length, or -1 if the key is not set; nothing is written when the
+//!   value is longer than `out_cap`, so the plugin can call again with a larger buffer
+//! - `kv_set(key_ptr, key_len, value_ptr, value_len)`: values are kept in
+//!   `<plugin>.kv.json` next to the module
+//!
+//! Every call gets a fuel budget and the module's memory is capped, so a plugin that
+//! loops forever or allocates without end fails that call instead of hanging the
+//! scheduler. After a failed call the plugin is instantiated again for the next event.
+
+use crate::client::filter::MessageFilter;
+use crate::client::models::BiliMessage;
+use crate::client::scheduler::{EventContext, EventHandler};
+use crate::plugins::actions::{KvStore, PluginActions};
+use crate::plugins::external::PluginEvent;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use wasmtime::{
+    Caller, Config, Engine, Extern, Instance, Linker, Memory, Module, Store, StoreLimits,
+    StoreLimitsBuilder, TypedFunc,
+};
+
+/// Default fuel per call, roughly the number of WebAssembly instructions
+pub const DEFAULT_FUEL: u64 = 10_000_000;
+/// Default memory limit in MiB
+pub const DEFAULT_MEMORY_MB: usize = 16;
+/// Largest string a plugin may pass to a host function (`send`, `display`, `kv_set`, ...), in bytes
+pub const MAX_PAYLOAD: usize = 64 * 1024;
+
+/// A WebAssembly plugin in the config file (`[[wasm_plugins]]`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WasmPluginConfig {
+    /// Name shown in logs, the TUI and the handler stats
+    pub name: String,
+    /// Path of the `.wasm` (or `.wat`) module
+    pub path: PathBuf,
+    /// Messages the plugin gets; all messages when not set
+    #[serde(default)]
+    pub filter: Option<MessageFilter>,
+    /// Fuel for each call
+    #[serde(default = "default_fuel")]
+    pub fuel: u64,
+    /// Largest memory the module may grow to, in MiB
+    #[serde(default = "default_memory_mb")]
+    pub memory_mb: usize,
+}
+
+fn default_fuel() -> u64 {
+    DEFAULT_FUEL
+}
+
+fn default_memory_mb() -> usize {
+    DEFAULT_MEMORY_MB
+}
+
+/// What the host functions can reach
+struct HostState {
+    name: String,
+    actions: Arc<PluginActions>,
+    kv: Arc<Mutex<KvStore>>,
+    /// Context of the event being handled, used by `send`
+    context: Option<EventContext>,
+    limits: StoreLimits,
+}
+
+/// An instantiated plugin
+struct Running {
+    store: Store<HostState>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    handle: TypedFunc<(i32, i32), ()>,
+}
+
+/// Runs a WebAssembly module as a plugin, see the [module docs](self) for its interface
+pub struct WasmPluginHandler {
+    config: WasmPluginConfig,
+    actions: Arc<PluginActions>,
+    kv: Arc<Mutex<KvStore>>,
+    module: Module,
+    linker: Linker<HostState>,
+    running: Mutex<Option<Running>>,
+}
+
+impl WasmPluginHandler {
+    /// Compile a configured plugin; it is instantiated with the first event
+    pub fn load(config: WasmPluginConfig, actions: Arc<PluginActions>) -> Result<Self, String> {
+        let mut engine_config = Config::new();
+        engine_config.consume_fuel(true);
+        let engine =
+            Engine::new(&engine_config).map_err(|e| format!("Cannot create WASM engine: {}", e))?;
+        let module = Module::from_file(&engine, &config.path)
+            .map_err(|e| format!("Cannot load WASM plugin {:?}: {}", config.path, e))?;
+        let linker = host_functions(&engine)
+            .map_err(|e| format!("Cannot set up WASM host functions: {}", e))?;
+        let kv = Arc::new(Mutex::new(KvStore::load(
+            config.path.with_extension("kv.json"),
+        )));
+        Ok(Self {
+            config,
+            actions,
+            kv,
+            module,
+            linker,
+            running: Mutex::new(None),
+        })
+    }
+
+    /// Instantiate the module in a fresh store with the configured limits
+    fn instantiate(&self) -> wasmtime::Result<Running> {
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(self.config.memory_mb * 1024 * 1024)
+            .instances(1)
+            .build();
+        let state = HostState {
+            name: self.config.name.clone(),
+            actions: Arc::clone(&self.actions),
+            kv: Arc::clone(&self.kv),
+            context: None,
+            limits,
+        };
+        let mut store = Store::new(self.module.engine(), state);
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(self.config.fuel)?;
+        let instance: Instance = self.linker.instantiate(&mut store, &self.module)?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| wasmtime::Error::msg("module does not export `memory`"))?;
+        let alloc = instance.get_typed_func(&mut store, "alloc")?;
+        let handle = instance.get_typed_func(&mut store, "handle")?;
+        Ok(Running {
+            store,
+            memory,
+            alloc,
+            handle,
+        })
+    }
+
+    /// Pass an event to the plugin's `handle`
+    fn send_event(&self, event: &PluginEvent, context: &EventContext) {
+        let json = match serde_json::to_vec(event) {
+            Ok(json) => json,
+            Err(e) => {
+                log::error!(
+                    "WASM plugin {}: cannot encode event: {}",
+                    self.config.name,
+                    e
+                );
+                return;
+            }
+        };
+        let Ok(mut running) = self.running.lock() else {
+            return;
+        };
+        if running.is_none() {
+            match self.instantiate() {
+                Ok(instance) => *running = Some(instance),
+                Err(e) => {
+                    log::error!("WASM plugin {}: {}", self.config.name, e);
+                    return;
+                }
+            }
+        }
+        let Some(instance) = running.as_mut() else {
+            return;
+        };
+        instance.store.data_mut().context = Some(context.clone());
+        if let Err(e) = call_handle(instance, &json, self.config.fuel) {
+            // A trap can leave the module's state half updated, so start over
+            log::warn!("WASM plugin {} failed: {}", self.config.name, e);
+            *running = None;
+        }
+    }
+}
+
+fn call_handle(running: &mut Running, json: &[u8], fuel: u64) -> wasmtime::Result<()> {
+    let len = i32::try_from(json.len())?;
+    running.store.set_fuel(fuel)?;
+    let ptr = running.alloc.call(&mut running.store, len)?;
+    running
+        .memory
+        .write(&mut running.store, usize::try_from(ptr)?, json)?;
+    running.handle.call(&mut running.store, (ptr, len))
+}
+
+impl EventHandler for WasmPluginHandler {
+    fn handle(&self, msg: &BiliMessage, context: &EventContext) {
+        let event = PluginEvent::Message {
+            message: msg,
+            context: context.into(),
+        };
+        self.send_event(&event, context);
+    }
+
+    fn on_start(&self, context: &EventContext) {
+        let event = PluginEvent::Start {
+            context: context.into(),
+        };
+        self.send_event(&event, context);
+    }
+
+    fn on_connected(&self, context: &EventContext) {
+        let event = PluginEvent::Connected {
+            context: context.into(),
+        };
+        self.send_event(&event, context);
+    }
+
+    fn on_disconnected(&self, context: &EventContext) {
+        let event = PluginEvent::Disconnected {
+            context: context.into(),
+        };
+        self.send_event(&event, context);
+    }
+
+    fn on_shutdown(&self, context: &EventContext) {
+        let event = PluginEvent::Shutdown {
+            context: context.into(),
+        };
+        self.send_event(&event, context);
+    }
+
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+}
+
+/// The plugin's exported memory
+fn memory(caller: &mut Caller<'_, HostState>) -> wasmtime::Result<Memory> {
+    match caller.get_export("memory") {
+        Some(Extern::Memory(memory)) => Ok(memory),
+        _ => Err(wasmtime::Error::msg("module does not export `memory`")),
+    }
+}
+
+/// Read a UTF-8 string the plugin passed as `(ptr, len)`.
+///
+/// The range is checked against the plugin's memory and [`MAX_PAYLOAD`] before copying.
+fn read_string(caller: &mut Caller<'_, HostState>, ptr: i32, len: i32) -> wasmtime::Result<String> {
+    let memory = memory(caller)?;
+    let (ptr, len) = (usize::try_from(ptr)?, usize::try_from(len)?);
+    if len > MAX_PAYLOAD {
+        return Err(wasmtime::Error::msg(format!(
+            "string of {} bytes exceeds the limit of {} bytes",
+            len, MAX_PAYLOAD
+        )));
+    }
+    let bytes = ptr
+        .checked_add(len)
+        .and_then(|end| memory.data(&*caller).get(ptr..end))
+        .ok_or_else(|| wasmtime::Error::msg("string is outside the plugin's memory"))?;
+    Ok(String::from_utf8(bytes.to_vec())?)
+}
+
+/// The `blivedm` module plugins import
+fn host_functions(engine: &Engine) -> wasmtime::Result<Linker<HostState>> {
+    let mut linker = Linker::new(engine);
+    linker.func_wrap(
+        "blivedm",
+        "send",
+        |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> wasmtime::Result<()> {
+            let text = read_string(&mut caller, ptr, len)?;
+            let state = caller.data();
+            match &state.context {
+                Some(context) => state.actions.send(&state.name, &text, context),
+                None => log::warn!("Plugin {} cannot send danmaku before any event", state.name),
+            }
+            Ok(())
+        },
+    )?;
+    linker.func_wrap(
+        "blivedm",
+        "speak",
+        |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> wasmtime::Result<()> {
+            let text = read_string(&mut caller, ptr, len)?;
+            caller.data().actions.speak(&caller.data().name, &text);
+            Ok(())
+        },
+    )?;
+    linker.func_wrap(
+        "blivedm",
+        "display",
+        |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> wasmtime::Result<()> {
+            let text = read_string(&mut caller, ptr, len)?;
+            caller.data().actions.display(&caller.data().name, &text);
+            Ok(())
+        },
+    )?;
+    linker.func_wrap(
+        "blivedm",
+        "log",
+        |mut caller: Caller<'_, HostState>,
+         level: i32,
+         ptr: i32,
+         len: i32|
+         -> wasmtime::Result<()> {
+            let text = read_string(&mut caller, ptr, len)?;
+            let level = match level {
+                0 => "error",
+                1 => "warn",
+                3 => "debug",
+                _ => "info",
+            };
+            let state = caller.data();
+            state.actions.log(&state.name, Some(level), &text);
+            Ok(())
+        },
+    )?;
+    linker.func_wrap(
+        "blivedm",
+        "kv_get",
+        |mut caller: Caller<'_, HostState>,
+         key_ptr: i32,
+         key_len: i32,
+         out_ptr: i32,
+         out_cap: i32|
+         -> wasmtime::Result<i32> {
+            let key = read_string(&mut caller, key_ptr, key_len)?;
+            let value = caller
+                .data()
+                .kv
+                .lock()
+                .ok()
+                .and_then(|kv| kv.get(&key).cloned());
+            let Some(value) = value else {
+                return Ok(-1);
+            };
+            // Values set by `kv_set` are strings; anything else is passed as JSON
+            let value = match value {
+                serde_json::Value::String(value) => value,
+                value => value.to_string(),
+            };
+            let len = i32::try_from(value.len())?;
+            if len <= out_cap {
+                memory(&mut caller)?.write(
+                    &mut caller,
+                    usize::try_from(out_ptr)?,
+                    value.as_bytes(),
+                )?;
+            }
+            Ok(len)
+        },
+    )?;
+    linker.func_wrap(
+        "blivedm",
+        "kv_set",
+        |mut caller: Caller<'_, HostState>,
+         key_ptr: i32,
+         key_len: i32,
+         value_ptr: i32,
+         value_len: i32|
+         -> wasmtime::Result<()> {
+            let key = read_string(&mut caller, key_ptr, key_len)?;
+            let value = read_string(&mut caller, value_ptr, value_len)?;
+            if let Ok(mut kv) = caller.data().kv.lock() {
+                kv.set(&key, serde_json::Value::String(value));
+            }
+            Ok(())
+        },
+    )?;
+    Ok(linker)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::fs;
+
+    /// A plugin that shows every event, counts them in its stored values, and loops
+    /// forever, grows its memory without end or passes an oversized or out-of-bounds
+    /// string when the event asks for it
+    const PLUGIN: &str = r#"
+        (module
+          (import "blivedm" "display" (func $display (param i32 i32)))
+          (import "blivedm" "kv_get" (func $kv_get (param i32 i32 i32 i32) (result i32)))
+          (import "blivedm" "kv_set" (func $kv_set (param i32 i32 i32 i32)))
+          (memory (export "memory") 1)
+          (data (i32.const 0) "count")
+          (func (export "alloc") (param $len i32) (result i32)
+            i32.const 1024)
+          (func (export "handle") (param $ptr i32) (param $len i32)
+            ;; `"loop"`, `"grow"`, `"big"` and `"edge"` are told apart by their first letter
+            (if (i32.eq (i32.load8_u (i32.add (local.get $ptr) (i32.const 1))) (i32.const 0x6c))
+              (then (loop $forever (br $forever))))
+            (if (i32.eq (i32.load8_u (i32.add (local.get $ptr) (i32.const 1))) (i32.const 0x67))
+              (then (loop $grow
+                (br_if $grow (i32.ne (memory.grow (i32.const 16)) (i32.const -1))))
+                unreachable))
+            (if (i32.eq (i32.load8_u (i32.add (local.get $ptr) (i32.const 1))) (i32.const 0x62))
+              (then (call $display (local.get $ptr) (i32.const 0x7fffffff))))
+            (if (i32.eq (i32.load8_u (i32.add (local.get $ptr) (i32.const 1))) (i32.const 0x65))
+              (then (call $display (i32.const 65530) (i32.const 100))))
+            (call $display (local.get $ptr) (local.get $len))
+            ;; count = kv_get("count") == -1 ? "1" : "2"
+            (if (i32.eq (call $kv_get (i32.const 0) (i32.const 5) (i32.const 16) (i32.const 8)) (i32.const -1))
+              (then (i32.store8 (i32.const 16) (i32.const 0x31)))
+              (else (i32.store8 (i32.const 16) (i32.const 0x32))))
+            (call $kv_set (i32.const 0) (i32.const 5) (i32.const 16) (i32.const 1))))
+    "#;
+
+    fn plugin(dir_name: &str) -> (WasmPluginHandler, Arc<Mutex<VecDeque<String>>>, PathBuf) {
+        let dir =
+            std::env::temp_dir().join(format!("blivedm_wasm_{}_{}", dir_name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("counter.wat");
+        fs::write(&path, PLUGIN).unwrap();
+        let buffer = Arc::new(Mutex::new(VecDeque::new()));
+        let actions = Arc::new(PluginActions::new().with_message_buffer(Arc::clone(&buffer)));
+        let config = WasmPluginConfig {
+            name: "counter".to_string(),
+            path: path.clone(),
+            filter: None,
+            fuel: 1_000_000,
+            memory_mb: 1,
+        };
+        (
+            WasmPluginHandler::load(config, actions).unwrap(),
+            buffer,
+            dir,
+        )
+    }
+
+    /// Call the plugin with a bare JSON string instead of an event
+    fn event(handler: &WasmPluginHandler, text: &str) {
+        let mut running = handler.running.lock().unwrap();
+        if running.is_none() {
+            *running = Some(handler.instantiate().unwrap());
+        }
+        let json = serde_json::to_vec(text).unwrap();
+        if call_handle(running.as_mut().unwrap(), &json, handler.config.fuel).is_err() {
+            *running = None;
+        }
+    }
+
+    #[test]
+    fn test_wasm_plugin_events_and_kv() {
+        let (handler, buffer, dir) = plugin("events");
+        let context = EventContext::new(Some("SESSDATA=secret".to_string()), 7);
+        handler.handle(
+            &BiliMessage::Raw(serde_json::json!({"cmd": "TEST"})),
+            &context,
+        );
+        handler.on_shutdown(&context);
+
+        let messages: Vec<String> = buffer.lock().unwrap().drain(..).collect();
+        assert_eq!(messages.len(), 2);
+        let json = messages[0].strip_prefix("[Plugin counter] ").unwrap();
+        let value: serde_json::Value = serde_json::from_str(json).unwrap();
+        assert_eq!(value["type"], "message");
+        assert_eq!(value["message"]["Raw"]["cmd"], "TEST");
+        assert_eq!(value["context"]["room_id"], 7);
+        assert!(!json.contains("secret"));
+        assert!(messages[1].contains(r#""type":"shutdown""#));
+
+        let stored = fs::read_to_string(dir.join("counter.kv.json")).unwrap();
+        assert!(stored.contains(r#""count": "2""#));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_wasm_plugin_limits() {
+        let (handler, buffer, dir) = plugin("limits");
+
+        // Runs out of fuel instead of hanging
+        event(&handler, "loop");
+        assert!(handler.running.lock().unwrap().is_none());
+        // Cannot grow past the memory limit
+        event(&handler, "grow");
+        assert!(handler.running.lock().unwrap().is_none());
+
+        // Lengths are checked before the host allocates anything
+        event(&handler, "big");
+        assert!(handler.running.lock().unwrap().is_none());
+        event(&handler, "edge");
+        assert!(handler.running.lock().unwrap().is_none());
+        assert!(buffer.lock().unwrap().is_empty());
+
+        // Instantiated again for the next event
+        event(&handler, "ok");
+        assert_eq!(
+            buffer.lock().unwrap().back().unwrap(),
+            r#"[Plugin counter] "ok""#
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,13 +1,18 @@
 // src/tui/app.rs
 //! TUI application state management
 
+use crate::client::danmaku::ReplyTarget;
+use crate::client::scheduler::{HandlerStats, SchedulerStats};
 use std::collections::VecDeque;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 
 /// Maximum number of messages to keep in buffer
 const MAX_MESSAGES: usize = 1000;
 
+/// Maximum number of recent danmaku senders offered for replies
+const MAX_REPLY_CANDIDATES: usize = 50;
+
 /// TUI Application state
 pub struct TuiApp {
     /// Shared message buffer (thread-safe)
@@ -28,6 +33,24 @@ pub struct TuiApp {
     pub online_count: Arc<AtomicU64>,
     /// Whether to show raw event messages
     pub show_raw: bool,
+    /// Label of the logged in user shown in the title (thread-safe, updated after auth checks)
+    pub logged_in_user: Arc<Mutex<Option<String>>>,
+    /// Name of the account used for sending, if different from the connection's
+    pub send_account: Arc<Mutex<Option<String>>>,
+    /// Number of danmaku waiting in the outbound queue
+    pub queued_messages: Arc<AtomicUsize>,
+    /// Recent danmaku senders, newest last (thread-safe, filled by the display handler)
+    pub reply_candidates: Arc<Mutex<VecDeque<ReplyTarget>>>,
+    /// User the next message replies to, read by the send callback
+    pub reply_target: Arc<Mutex<Option<ReplyTarget>>>,
+    /// Position of the selected reply candidate, counted from the newest
+    reply_index: Option<usize>,
+    /// Stats of the scheduler's handlers, shown in the status panel
+    pub handler_stats: Option<SchedulerStats>,
+    /// Whether to show the handler status panel
+    pub show_status: bool,
+    /// Set from outside the TUI (e.g. on SIGTERM) to make it quit
+    pub quit_requested: Arc<AtomicBool>,
 }
 
 impl TuiApp {
@@ -52,6 +75,152 @@ impl TuiApp {
             should_quit: false,
             online_count,
             show_raw: false,
+            logged_in_user: Arc::new(Mutex::new(None)),
+            send_account: Arc::new(Mutex::new(None)),
+            queued_messages: Arc::new(AtomicUsize::new(0)),
+            reply_candidates: Arc::new(Mutex::new(VecDeque::new())),
+            reply_target: Arc::new(Mutex::new(None)),
+            reply_index: None,
+            handler_stats: None,
+            show_status: false,
+            quit_requested: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Use a shared flag that makes the TUI quit when set, e.g. by a signal handler
+    pub fn with_quit_requested(mut self, quit_requested: Arc<AtomicBool>) -> Self {
+        self.quit_requested = quit_requested;
+        self
+    }
+
+    /// Use a shared logged in user label for the title
+    pub fn with_logged_in_user(mut self, logged_in_user: Arc<Mutex<Option<String>>>) -> Self {
+        self.logged_in_user = logged_in_user;
+        self
+    }
+
+    /// Get the logged in user label, if known
+    pub fn get_logged_in_user(&self) -> Option<String> {
+        self.logged_in_user.lock().ok().and_then(|u| u.clone())
+    }
+
+    /// Update the logged in user label (called after auth checks)
+    pub fn set_logged_in_user(logged_in_user: &Arc<Mutex<Option<String>>>, user: Option<String>) {
+        if let Ok(mut current) = logged_in_user.lock() {
+            *current = user;
+        }
+    }
+
+    /// Use a shared sending account name for the title
+    pub fn with_send_account(mut self, send_account: Arc<Mutex<Option<String>>>) -> Self {
+        self.send_account = send_account;
+        self
+    }
+
+    /// Get the name of the sending account, if one is selected
+    pub fn get_send_account(&self) -> Option<String> {
+        self.send_account.lock().ok().and_then(|a| a.clone())
+    }
+
+    /// Update the sending account name (called when switching accounts)
+    pub fn set_send_account(send_account: &Arc<Mutex<Option<String>>>, account: Option<String>) {
+        if let Ok(mut current) = send_account.lock() {
+            *current = account;
+        }
+    }
+
+    /// Use a shared outbound queue counter for the title
+    pub fn with_queued_messages(mut self, queued_messages: Arc<AtomicUsize>) -> Self {
+        self.queued_messages = queued_messages;
+        self
+    }
+
+    /// Get the number of danmaku waiting to be sent
+    pub fn get_queued_messages(&self) -> usize {
+        self.queued_messages.load(Ordering::Relaxed)
+    }
+
+    /// Use the scheduler's handler stats for the status panel
+    pub fn with_handler_stats(mut self, handler_stats: SchedulerStats) -> Self {
+        self.handler_stats = Some(handler_stats);
+        self
+    }
+
+    /// Get the current stats of all handlers
+    pub fn get_handler_stats(&self) -> Vec<HandlerStats> {
+        self.handler_stats
+            .as_ref()
+            .map(SchedulerStats::snapshot)
+            .unwrap_or_default()
+    }
+
+    /// Use shared reply candidates and reply target for replying to recent danmaku
+    pub fn with_replies(
+        mut self,
+        reply_candidates: Arc<Mutex<VecDeque<ReplyTarget>>>,
+        reply_target: Arc<Mutex<Option<ReplyTarget>>>,
+    ) -> Self {
+        self.reply_candidates = reply_candidates;
+        self.reply_target = reply_target;
+        self
+    }
+
+    /// Remember a danmaku sender as a reply candidate (called from event handler)
+    pub fn add_reply_candidate(
+        reply_candidates: &Arc<Mutex<VecDeque<ReplyTarget>>>,
+        target: ReplyTarget,
+    ) {
+        if let Ok(mut candidates) = reply_candidates.lock() {
+            candidates.retain(|c| c.uid != target.uid);
+            candidates.push_back(target);
+            while candidates.len() > MAX_REPLY_CANDIDATES {
+                candidates.pop_front();
+            }
+        }
+    }
+
+    /// Get the user the next message replies to, if one is selected
+    pub fn get_reply_target(&self) -> Option<ReplyTarget> {
+        self.reply_target.lock().ok().and_then(|t| t.clone())
+    }
+
+    /// Take the reply target for a message being sent (called from the send callback)
+    pub fn take_reply_target(
+        reply_target: &Arc<Mutex<Option<ReplyTarget>>>,
+    ) -> Option<ReplyTarget> {
+        reply_target.lock().ok().and_then(|mut t| t.take())
+    }
+
+    /// Select the next older danmaku sender to reply to
+    pub fn select_older_reply(&mut self) {
+        let count = self.reply_candidates.lock().map(|c| c.len()).unwrap_or(0);
+        if count == 0 {
+            return;
+        }
+        let index = match self.reply_index {
+            Some(index) if self.get_reply_target().is_some() => (index + 1).min(count - 1),
+            _ => 0,
+        };
+        self.select_reply(Some(index));
+    }
+
+    /// Select the next newer danmaku sender, or stop replying after the newest
+    pub fn select_newer_reply(&mut self) {
+        let index = match self.reply_index {
+            Some(index) if index > 0 && self.get_reply_target().is_some() => Some(index - 1),
+            _ => None,
+        };
+        self.select_reply(index);
+    }
+
+    fn select_reply(&mut self, index: Option<usize>) {
+        let target = index.and_then(|index| {
+            let candidates = self.reply_candidates.lock().ok()?;
+            candidates.iter().rev().nth(index).cloned()
+        });
+        self.reply_index = target.as_ref().and(index);
+        if let Ok(mut current) = self.reply_target.lock() {
+            *current = target;
         }
     }
 
@@ -183,8 +352,18 @@ impl TuiApp {
         self.should_quit = true;
     }
 
+    /// Whether the application should quit, by the user or from outside
+    pub fn quitting(&self) -> bool {
+        self.should_quit || self.quit_requested.load(Ordering::Relaxed)
+    }
+
     /// Toggle raw message visibility
     pub fn toggle_show_raw(&mut self) {
         self.show_raw = !self.show_raw;
     }
+
+    /// Toggle the handler status panel
+    pub fn toggle_show_status(&mut self) {
+        self.show_status = !self.show_status;
+    }
 }
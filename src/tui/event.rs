@@ -61,10 +61,22 @@ where
                     KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
                         app.toggle_show_raw();
                     }
+                    // Toggle the handler status panel
+                    KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.toggle_show_status();
+                    }
                     KeyCode::Esc => {
                         app.quit();
                     }
 
+                    // Pick a recent danmaku sender to reply to
+                    KeyCode::Tab => {
+                        app.select_older_reply();
+                    }
+                    KeyCode::BackTab => {
+                        app.select_newer_reply();
+                    }
+
                     // Input handling
                     KeyCode::Char(c) => {
                         app.enter_char(c);
@@ -130,7 +142,7 @@ where
             }
         }
 
-        if app.should_quit {
+        if app.quitting() {
             break;
         }
     }
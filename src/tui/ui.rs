@@ -1,6 +1,7 @@
 // src/tui/ui.rs
 //! UI rendering logic for the TUI
 
+use crate::client::scheduler::HandlerStats;
 use crate::tui::app::TuiApp;
 use ratatui::{
     Frame,
@@ -21,10 +22,66 @@ pub fn render(f: &mut Frame, app: &TuiApp) {
         ])
         .split(f.area());
 
-    render_message_list(f, app, chunks[0]);
+    let stats = app.get_handler_stats();
+    if app.show_status && !stats.is_empty() {
+        // Borders and header row around one row per handler
+        let status_height = (stats.len() as u16 + 3).min(chunks[0].height / 2);
+        let message_chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(3), Constraint::Length(status_height)])
+            .split(chunks[0]);
+        render_message_list(f, app, message_chunks[0]);
+        render_status_panel(f, &stats, message_chunks[1]);
+    } else {
+        render_message_list(f, app, chunks[0]);
+    }
     render_input_box(f, app, chunks[1]);
 }
 
+/// Render the handler status panel: calls, failures and latency per handler
+fn render_status_panel(f: &mut Frame, stats: &[HandlerStats], area: Rect) {
+    let mut lines = vec![Line::from(Span::styled(
+        format!(
+            "{:<24} {:>5} {:>8} {:>7} {:>8} {:>7} {:>5} {:>9} {:>9}  state",
+            "handler", "stage", "calls", "panics", "timeouts", "skipped", "hooks", "avg", "max"
+        ),
+        Style::default().fg(Color::Gray),
+    ))];
+    for handler in stats {
+        let (state, color) = if handler.disabled {
+            ("disabled", Color::Red)
+        } else if handler.consecutive_failures > 0 {
+            ("failing", Color::Yellow)
+        } else {
+            ("ok", Color::Green)
+        };
+        lines.push(Line::from(Span::styled(
+            format!(
+                "{:<24} {:>5} {:>8} {:>7} {:>8} {:>7} {:>5} {:>9.1?} {:>9.1?}  {}",
+                handler.name,
+                handler.stage + 1,
+                handler.invocations,
+                handler.panics,
+                handler.timeouts,
+                handler.skipped,
+                handler.hook_failures,
+                handler.average_latency(),
+                handler.max_latency,
+                state
+            ),
+            Style::default().fg(color),
+        )));
+    }
+
+    let paragraph = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" Handlers (Ctrl+S: hide) ")
+            .border_style(Style::default().fg(Color::White)),
+    );
+    f.render_widget(paragraph, area);
+}
+
 /// Render the message list with text wrapping support
 fn render_message_list(f: &mut Frame, app: &TuiApp, area: Rect) {
     let messages = app.get_messages();
@@ -76,11 +133,46 @@ fn render_message_list(f: &mut Frame, app: &TuiApp, area: Rect) {
         String::new()
     };
 
+    let user_display = match app.get_logged_in_user() {
+        Some(user) => format!(" | 👤 {}", user),
+        None => String::new(),
+    };
+
+    let send_display = match app.get_send_account() {
+        Some(account) => format!(" | ✉ {}", account),
+        None => String::new(),
+    };
+
+    let queued = app.get_queued_messages();
+    let queue_display = if queued > 0 {
+        format!(" | 📤 Queued: {}", queued)
+    } else {
+        String::new()
+    };
+
     let raw_indicator = if app.show_raw { "Raw:ON" } else { "Raw:OFF" };
 
+    let unhealthy = app
+        .get_handler_stats()
+        .iter()
+        .filter(|handler| !handler.is_healthy())
+        .count();
+    let health_display = if unhealthy > 0 {
+        format!(" | ⚠ {} handler(s) failing", unhealthy)
+    } else {
+        String::new()
+    };
+
     let title = format!(
-        " Room {}{} | {} | {} ",
-        app.room_id, online_display, scroll_indicator, raw_indicator
+        " Room {}{}{}{}{}{} | {} | {} ",
+        app.room_id,
+        user_display,
+        send_display,
+        online_display,
+        queue_display,
+        health_display,
+        scroll_indicator,
+        raw_indicator
     );
 
     let paragraph = Paragraph::new(visible_lines)
@@ -99,6 +191,10 @@ fn render_message_list(f: &mut Frame, app: &TuiApp, area: Rect) {
 fn get_message_style(msg: &str) -> Style {
     if msg.starts_with("[Danmu]") {
         Style::default().fg(Color::Cyan)
+    } else if msg.starts_with("[History]") {
+        Style::default().fg(Color::DarkGray)
+    } else if msg.starts_with("[SC") {
+        Style::default().fg(Color::LightRed)
     } else if msg.starts_with("[Gift]") {
         Style::default().fg(Color::Yellow)
     } else if msg.starts_with("[Raw]") {
@@ -107,6 +203,8 @@ fn get_message_style(msg: &str) -> Style {
         Style::default().fg(Color::DarkGray)
     } else if msg.starts_with("[System]") {
         Style::default().fg(Color::Green)
+    } else if msg.starts_with("[Plugin") {
+        Style::default().fg(Color::LightBlue)
     } else {
         Style::default()
     }
@@ -150,11 +248,22 @@ fn wrap_text(text: &str, max_width: usize) -> Vec<String> {
 fn render_input_box(f: &mut Frame, app: &TuiApp, area: Rect) {
     let input_text = format!("> {}", app.input);
 
+    let title = match app.get_reply_target() {
+        Some(target) => format!(
+            " Reply to @{} (Enter: send | Tab: older | Shift+Tab: newer/cancel) ",
+            target.uname
+        ),
+        None => {
+            " Input (Enter: send | Tab: reply | ↑↓: scroll | Ctrl+R: toggle raw | Ctrl+S: status | Ctrl+C: exit) "
+                .to_string()
+        }
+    };
+
     let paragraph = Paragraph::new(input_text.as_str())
         .block(
             Block::default()
                 .borders(Borders::ALL)
-                .title(" Input (Enter: send | ↑↓: scroll | Ctrl+R: toggle raw | Ctrl+C: exit) ")
+                .title(title)
                 .border_style(Style::default().fg(Color::Green)),
         )
         .style(Style::default().fg(Color::White));